@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+
+/// A sequence diagram with `participants` actors exchanging `messages`
+/// messages between pseudo-randomly chosen pairs.
+fn sequence_source(participants: usize, messages: usize) -> String {
+    let mut src = String::from("sequenceDiagram\n");
+    for i in 0..participants {
+        writeln!(src, "    participant P{i}").unwrap();
+    }
+    for i in 0..messages {
+        let from = (i * 7) % participants;
+        let to = (i * 13 + 1) % participants;
+        writeln!(src, "    P{from}->>P{to}: message {i}").unwrap();
+    }
+    src
+}
+
+/// A top-down graph where `roots` nodes fan out to `edges` children.
+fn graph_source(roots: usize, edges: usize) -> String {
+    let mut src = String::from("graph TD\n");
+    for i in 0..edges {
+        writeln!(src, "    N{} --> N{}", i % roots, roots + i).unwrap();
+    }
+    src
+}
+
+/// A chain of `entities` entities, each with a few attributes.
+fn er_source(entities: usize) -> String {
+    let mut src = String::from("erDiagram\n");
+    for i in 1..entities {
+        writeln!(src, "    E{} ||--o{{ E{i} : has", i - 1).unwrap();
+    }
+    for i in 0..entities {
+        writeln!(src, "    E{i} {{\n        int id PK\n        string name\n    }}").unwrap();
+    }
+    src
+}
+
+const SEQUENCE_SIZES: &[(usize, usize)] = &[(5, 50), (20, 500), (100, 5_000)];
+const GRAPH_SIZES: &[(usize, usize)] = &[(2, 10), (10, 100), (40, 1_200)];
+const ER_SIZES: &[usize] = &[2, 10, 50];
+
+fn sequence(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sequence");
+    for &(participants, messages) in SEQUENCE_SIZES {
+        let src = sequence_source(participants, messages);
+        let id = format!("{participants}x{messages}");
+        let diagram = ma::parser::parse_diagram(&src).unwrap();
+        let layout = ma::layout::compute(&diagram).unwrap();
+        group.bench_with_input(BenchmarkId::new("parse", &id), &src, |b, src| {
+            b.iter(|| ma::parser::parse_diagram(black_box(src)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("layout", &id), &diagram, |b, diagram| {
+            b.iter(|| ma::layout::compute(black_box(diagram)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("render", &id), &layout, |b, layout| {
+            b.iter(|| ma::renderer::render(black_box(layout)))
+        });
+    }
+    group.finish();
+}
+
+fn graph(c: &mut Criterion) {
+    let mut group = c.benchmark_group("graph");
+    for &(roots, edges) in GRAPH_SIZES {
+        let src = graph_source(roots, edges);
+        let id = format!("{edges} edges");
+        let diagram = ma::graph_parser::parse_graph(&src).unwrap();
+        let layout = ma::graph_layout::compute(&diagram).unwrap();
+        group.bench_with_input(BenchmarkId::new("parse", &id), &src, |b, src| {
+            b.iter(|| ma::graph_parser::parse_graph(black_box(src)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("layout", &id), &diagram, |b, diagram| {
+            b.iter(|| ma::graph_layout::compute(black_box(diagram)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("render", &id), &layout, |b, layout| {
+            b.iter(|| ma::graph_renderer::render(black_box(layout)))
+        });
+    }
+    group.finish();
+}
+
+fn er(c: &mut Criterion) {
+    let mut group = c.benchmark_group("er");
+    for &entities in ER_SIZES {
+        let src = er_source(entities);
+        let id = format!("{entities} entities");
+        let diagram = ma::er_parser::parse_er(&src).unwrap();
+        let layout = ma::er_layout::compute(&diagram).unwrap();
+        group.bench_with_input(BenchmarkId::new("parse", &id), &src, |b, src| {
+            b.iter(|| ma::er_parser::parse_er(black_box(src)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("layout", &id), &diagram, |b, diagram| {
+            b.iter(|| ma::er_layout::compute(black_box(diagram)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("render", &id), &layout, |b, layout| {
+            b.iter(|| ma::er_renderer::render(black_box(layout)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sequence, graph, er);
+criterion_main!(benches);
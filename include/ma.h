@@ -0,0 +1,44 @@
+#ifndef MA_H
+#define MA_H
+
+/* Generated by cbindgen from src/capi.rs. Do not edit by hand. */
+
+#include <stddef.h>
+
+/*
+ Render the NUL-terminated UTF-8 diagram source `input`.
+
+ `max_width` limits the output width in columns; pass 0 for no limit.
+ Returns the rendered text, or NULL on failure. On failure, if `error` is
+ not NULL, `*error` receives a message describing the problem; on success
+ it is set to NULL.
+
+ # Safety
+
+ `input` must be NULL or point to a NUL-terminated string, and `error`
+ must be NULL or point to writable storage for one pointer.
+ */
+char *ma_render(const char *input, size_t max_width, char **error);
+
+/*
+ Like [`ma_render`], but names and labels that don't fit `max_width` are
+ wrapped onto more lines instead of being cut short.
+
+ # Safety
+
+ As for [`ma_render`].
+ */
+char *ma_render_wrapped(const char *input, size_t max_width, char **error);
+
+/*
+ Release a string returned by [`ma_render`] or [`ma_render_wrapped`].
+ Passing NULL is a no-op.
+
+ # Safety
+
+ `s` must be NULL or a pointer returned by this library that has not
+ already been freed.
+ */
+void ma_string_free(char *s);
+
+#endif /* MA_H */
@@ -1,8 +1,21 @@
 #[derive(Debug, Clone, PartialEq)]
 pub struct Diagram {
+    /// Drawn centered above the participant boxes, from a `title` line or
+    /// the frontmatter.
+    pub title: Option<String>,
     pub statements: Vec<Statement>,
+    pub source_lines: SourceLines,
 }
 
+/// The 1-based source line of every statement, block divider (`else`,
+/// `and`, `option`) and `end`, in the order they are written. Empty for
+/// diagrams that weren't parsed from Mermaid text.
+///
+/// Part of a diagram's equality: the same diagram written on other lines
+/// compares unequal.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceLines(pub Vec<usize>);
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     ParticipantDecl(ParticipantDecl),
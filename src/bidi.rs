@@ -0,0 +1,136 @@
+//! Right-to-left text (Hebrew, Arabic, ...) in diagram labels.
+//!
+//! The grid stores text in logical order, so how RTL labels look depends on
+//! the terminal: one that applies the Unicode bidirectional algorithm
+//! reorders them itself, while one that doesn't shows them backwards.
+//! [`RtlMode::Reverse`] writes each RTL run in visual order instead, which
+//! reads correctly on terminals without bidi support and takes the same
+//! number of columns.
+
+/// How right-to-left runs in labels are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RtlMode {
+    /// As typed (logical order), for terminals that reorder RTL text.
+    #[default]
+    Keep,
+    /// Reversed into visual order, with numbers still reading left to right
+    /// and brackets mirrored.
+    Reverse,
+}
+
+impl RtlMode {
+    pub const NAMES: &[&str] = &["keep", "reverse"];
+
+    pub fn named(name: &str) -> Option<RtlMode> {
+        match name {
+            "keep" => Some(RtlMode::Keep),
+            "reverse" => Some(RtlMode::Reverse),
+            _ => None,
+        }
+    }
+
+    /// `text` written in this mode.
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            RtlMode::Keep => text.to_string(),
+            RtlMode::Reverse => reverse_rtl_runs(text),
+        }
+    }
+}
+
+/// Whether `c` is a strong right-to-left character: Hebrew, Arabic and the
+/// other RTL scripts, apart from Arabic-Indic digits.
+pub fn is_rtl(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFC}'
+        | '\u{10800}'..='\u{10FFF}' | '\u{1E800}'..='\u{1EFFF}')
+        && !is_rtl_digit(c)
+}
+
+fn is_rtl_digit(c: char) -> bool {
+    matches!(c, '\u{0660}'..='\u{0669}' | '\u{06F0}'..='\u{06F9}')
+}
+
+fn is_ltr(c: char) -> bool {
+    c.is_alphabetic() && !is_rtl(c)
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit() || is_rtl_digit(c)
+}
+
+/// `text` with each run of right-to-left text in visual order. A run starts
+/// with an RTL character and ends with an RTL character or a number, taking
+/// in the spaces and punctuation between them, and stops at any
+/// left-to-right letter. Closing brackets that pair with an opening one in
+/// the run belong to it too.
+pub fn reverse_rtl_runs(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_rtl(chars[i]) {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        for (j, &c) in chars.iter().enumerate().skip(i + 1) {
+            if is_ltr(c) {
+                break;
+            } else if is_rtl(c) || is_digit(c) {
+                end = j + 1;
+            }
+        }
+        let is_closer = |c: &&char| matches!(c, ')' | ']' | '}');
+        let opened = chars[i..end].iter().filter(|c| matches!(c, '(' | '[' | '{')).count();
+        let unclosed = opened.saturating_sub(chars[i..end].iter().filter(is_closer).count());
+        end += chars[end..].iter().take(unclosed).take_while(is_closer).count();
+        out.extend(visual_order(&chars[i..end]));
+        i = end;
+    }
+    out
+}
+
+/// A run reversed, except that numbers in it still read left to right.
+fn visual_order(run: &[char]) -> Vec<char> {
+    let mut reversed: Vec<char> = run.iter().rev().map(|&c| mirror(c)).collect();
+    for number in reversed.split_mut(|&c| !is_digit(c)) {
+        number.reverse();
+    }
+    reversed
+}
+
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reverses_rtl_runs_only() {
+        assert_eq!(reverse_rtl_runs("hello"), "hello");
+        assert_eq!(reverse_rtl_runs("שלום"), "םולש");
+        assert_eq!(reverse_rtl_runs("say שלום עולם now"), "say םלוע םולש now");
+        assert_eq!(reverse_rtl_runs("שלום<br/>עולם"), "םולש<br/>םלוע");
+    }
+
+    #[test]
+    fn numbers_keep_their_order_and_brackets_mirror() {
+        assert_eq!(reverse_rtl_runs("חדר 12 (ב)"), "(ב) 12 רדח");
+        assert_eq!(reverse_rtl_runs("عدد ١٢٣"), "١٢٣ ددع");
+    }
+}
@@ -0,0 +1,461 @@
+//! Builders for constructing diagrams in code instead of formatting Mermaid
+//! text by hand.
+//!
+//! ```
+//! use ma::builder::SequenceBuilder;
+//!
+//! let diagram = SequenceBuilder::new()
+//!     .participant("A")
+//!     .participant("B")
+//!     .message("A", "B", "hi")
+//!     .reply("B", "A", "hello");
+//! assert!(diagram.to_mermaid().starts_with("sequenceDiagram\n"));
+//! let output = diagram.render().unwrap();
+//! ```
+//!
+//! Each builder can produce its [`Document`], render it directly, or emit
+//! Mermaid source with [`to_mermaid`](crate::to_mermaid).
+
+use std::collections::BTreeMap;
+
+use crate::ast::*;
+use crate::er_ast::*;
+use crate::graph_ast::*;
+use crate::{Document, RenderOptions};
+
+/// Builds a sequence diagram statement by statement. Block bodies are
+/// themselves built with a `SequenceBuilder`.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceBuilder {
+    title: Option<String>,
+    statements: Vec<Statement>,
+}
+
+impl SequenceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draw `title` above the participant boxes. Ignored on block bodies.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn participant(self, id: &str) -> Self {
+        self.push(Statement::ParticipantDecl(ParticipantDecl {
+            id: id.to_string(),
+            alias: None,
+        }))
+    }
+
+    /// Declare participant `id`, displayed as `alias`.
+    pub fn participant_as(self, id: &str, alias: &str) -> Self {
+        self.push(Statement::ParticipantDecl(ParticipantDecl {
+            id: id.to_string(),
+            alias: Some(alias.to_string()),
+        }))
+    }
+
+    /// A solid message with an arrowhead (`->>`).
+    pub fn message(self, from: &str, to: &str, text: &str) -> Self {
+        let arrow = Arrow { line_style: LineStyle::Solid, head: ArrowHead::Arrowhead };
+        self.message_with(from, to, text, arrow)
+    }
+
+    /// A dotted message with an arrowhead (`-->>`).
+    pub fn reply(self, from: &str, to: &str, text: &str) -> Self {
+        let arrow = Arrow { line_style: LineStyle::Dotted, head: ArrowHead::Arrowhead };
+        self.message_with(from, to, text, arrow)
+    }
+
+    pub fn message_with(self, from: &str, to: &str, text: &str, arrow: Arrow) -> Self {
+        self.push(Statement::Message(Message {
+            from: from.to_string(),
+            to: to.to_string(),
+            arrow,
+            text: text.to_string(),
+            activate_target: false,
+            deactivate_source: false,
+        }))
+    }
+
+    pub fn note(self, placement: NotePlacement, text: &str) -> Self {
+        self.push(Statement::Note(Note {
+            placement,
+            text: text.to_string(),
+        }))
+    }
+
+    pub fn activate(self, id: &str) -> Self {
+        self.push(Statement::Activate(id.to_string()))
+    }
+
+    pub fn deactivate(self, id: &str) -> Self {
+        self.push(Statement::Deactivate(id.to_string()))
+    }
+
+    pub fn autonumber(self) -> Self {
+        self.push(Statement::AutoNumber)
+    }
+
+    pub fn loop_block(self, label: &str, body: SequenceBuilder) -> Self {
+        self.push(Statement::Loop(block(label, body)))
+    }
+
+    pub fn opt_block(self, label: &str, body: SequenceBuilder) -> Self {
+        self.push(Statement::Opt(block(label, body)))
+    }
+
+    pub fn break_block(self, label: &str, body: SequenceBuilder) -> Self {
+        self.push(Statement::Break(block(label, body)))
+    }
+
+    pub fn rect(self, label: &str, body: SequenceBuilder) -> Self {
+        self.push(Statement::Rect(block(label, body)))
+    }
+
+    /// An `alt` block whose first `(label, body)` branch is the `alt` and
+    /// the rest are `else` branches. An empty `branches` adds nothing.
+    pub fn alt<'a>(self, branches: impl IntoIterator<Item = (&'a str, SequenceBuilder)>) -> Self {
+        self.divided(Statement::Alt, branches)
+    }
+
+    /// A `par` block of concurrent `(label, body)` branches.
+    pub fn par<'a>(self, branches: impl IntoIterator<Item = (&'a str, SequenceBuilder)>) -> Self {
+        self.divided(Statement::Par, branches)
+    }
+
+    /// A `critical` block followed by its `option` branches.
+    pub fn critical<'a>(
+        self,
+        branches: impl IntoIterator<Item = (&'a str, SequenceBuilder)>,
+    ) -> Self {
+        self.divided(Statement::Critical, branches)
+    }
+
+    fn divided<'a>(
+        self,
+        statement: fn(AltBlock) -> Statement,
+        branches: impl IntoIterator<Item = (&'a str, SequenceBuilder)>,
+    ) -> Self {
+        let mut branches = branches.into_iter();
+        let Some((label, body)) = branches.next() else {
+            return self;
+        };
+        self.push(statement(AltBlock {
+            label: label.to_string(),
+            body: body.statements,
+            else_branches: branches
+                .map(|(label, body)| ElseBranch {
+                    label: label.to_string(),
+                    body: body.statements,
+                })
+                .collect(),
+        }))
+    }
+
+    fn push(mut self, statement: Statement) -> Self {
+        self.statements.push(statement);
+        self
+    }
+
+    pub fn build(&self) -> Document {
+        Document::Sequence(Diagram {
+            title: self.title.clone(),
+            statements: self.statements.clone(),
+            source_lines: SourceLines::default(),
+        })
+    }
+
+    pub fn render(&self) -> Result<String, String> {
+        self.render_with(&RenderOptions::default())
+    }
+
+    pub fn render_with(&self, options: &RenderOptions) -> Result<String, String> {
+        crate::render_document(&self.build(), options)
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        crate::to_mermaid(&self.build())
+    }
+}
+
+fn block(label: &str, body: SequenceBuilder) -> LoopBlock {
+    LoopBlock {
+        label: label.to_string(),
+        body: body.statements,
+    }
+}
+
+/// Builds a flowchart. Nodes mentioned only by edges are plain boxes
+/// labelled with their id.
+#[derive(Debug, Clone)]
+pub struct GraphBuilder {
+    diagram: GraphDiagram,
+}
+
+impl GraphBuilder {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            diagram: GraphDiagram {
+                direction,
+                nodes: Vec::new(),
+                edges: Vec::new(),
+                subgraphs: Vec::new(),
+                redeclared: Vec::new(),
+            },
+        }
+    }
+
+    /// Add node `id`, or change the label and shape of an existing one.
+    pub fn node(mut self, id: &str, label: &str, shape: NodeShape) -> Self {
+        match self.diagram.nodes.iter_mut().find(|n| n.id == id) {
+            Some(existing) => {
+                existing.label = label.to_string();
+                existing.shape = shape;
+            }
+            None => self.diagram.nodes.push(NodeDecl {
+                id: id.to_string(),
+                label: label.to_string(),
+                shape,
+                metadata: BTreeMap::new(),
+            }),
+        }
+        self
+    }
+
+    /// Attach `key` = `value` to node `id`, such as a `tooltip` or `link`.
+    /// It appears in the layout JSON but not in the drawing.
+    pub fn node_metadata(mut self, id: &str, key: &str, value: &str) -> Self {
+        self.add_node(id);
+        if let Some(node) = self.diagram.nodes.iter_mut().find(|n| n.id == id) {
+            node.metadata.insert(key.to_string(), value.to_string());
+        }
+        self
+    }
+
+    /// An arrow (`-->`) from `from` to `to`.
+    pub fn edge(self, from: &str, to: &str) -> Self {
+        self.edge_with(from, to, EdgeType::Arrow, None)
+    }
+
+    /// An arrow (`-->|label|`) from `from` to `to`.
+    pub fn labeled_edge(self, from: &str, to: &str, label: &str) -> Self {
+        self.edge_with(from, to, EdgeType::Arrow, Some(label))
+    }
+
+    pub fn edge_with(
+        mut self,
+        from: &str,
+        to: &str,
+        edge_type: EdgeType,
+        label: Option<&str>,
+    ) -> Self {
+        self.add_node(from);
+        self.add_node(to);
+        self.diagram.edges.push(Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            edge_type,
+            label: label.map(str::to_string),
+            comment: None,
+        });
+        self
+    }
+
+    /// Group `node_ids` in a subgraph titled `label`.
+    pub fn subgraph(mut self, label: &str, node_ids: &[&str]) -> Self {
+        for id in node_ids {
+            self.add_node(id);
+        }
+        self.diagram.subgraphs.push(Subgraph {
+            id: label.replace(' ', "_").to_lowercase(),
+            label: label.to_string(),
+            node_ids: node_ids.iter().map(|id| id.to_string()).collect(),
+        });
+        self
+    }
+
+    fn add_node(&mut self, id: &str) {
+        if !self.diagram.nodes.iter().any(|n| n.id == id) {
+            self.diagram.nodes.push(NodeDecl {
+                id: id.to_string(),
+                label: id.to_string(),
+                shape: NodeShape::Box,
+                metadata: BTreeMap::new(),
+            });
+        }
+    }
+
+    pub fn build(&self) -> Document {
+        Document::Graph(self.diagram.clone())
+    }
+
+    pub fn render(&self) -> Result<String, String> {
+        self.render_with(&RenderOptions::default())
+    }
+
+    pub fn render_with(&self, options: &RenderOptions) -> Result<String, String> {
+        crate::render_document(&self.build(), options)
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        crate::to_mermaid(&self.build())
+    }
+}
+
+/// Builds an ER diagram. Entities are created when first mentioned.
+#[derive(Debug, Clone)]
+pub struct ErBuilder {
+    diagram: ErDiagram,
+}
+
+impl Default for ErBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErBuilder {
+    pub fn new() -> Self {
+        Self {
+            diagram: ErDiagram {
+                entities: Vec::new(),
+                relationships: Vec::new(),
+            },
+        }
+    }
+
+    pub fn entity(mut self, name: &str) -> Self {
+        self.entity_mut(name);
+        self
+    }
+
+    /// Add an attribute to `entity`; `key` is e.g. `PK` or `FK`.
+    pub fn attribute(
+        mut self,
+        entity: &str,
+        attr_type: &str,
+        name: &str,
+        key: Option<&str>,
+    ) -> Self {
+        self.entity_mut(entity).attributes.push(EntityAttribute {
+            attr_type: attr_type.to_string(),
+            name: name.to_string(),
+            key: key.map(str::to_string),
+        });
+        self
+    }
+
+    /// A relationship with `left` cardinality at `from` and `right` at `to`.
+    pub fn relationship(
+        mut self,
+        from: &str,
+        left: Cardinality,
+        right: Cardinality,
+        to: &str,
+        label: &str,
+    ) -> Self {
+        self.entity_mut(from);
+        self.entity_mut(to);
+        self.diagram.relationships.push(Relationship {
+            from: from.to_string(),
+            to: to.to_string(),
+            left_card: left,
+            right_card: right,
+            label: label.to_string(),
+        });
+        self
+    }
+
+    fn entity_mut(&mut self, name: &str) -> &mut Entity {
+        let entities = &mut self.diagram.entities;
+        let index = match entities.iter().position(|e| e.name == name) {
+            Some(i) => i,
+            None => {
+                entities.push(Entity {
+                    name: name.to_string(),
+                    attributes: Vec::new(),
+                });
+                entities.len() - 1
+            }
+        };
+        &mut entities[index]
+    }
+
+    pub fn build(&self) -> Document {
+        Document::Er(self.diagram.clone())
+    }
+
+    pub fn render(&self) -> Result<String, String> {
+        self.render_with(&RenderOptions::default())
+    }
+
+    pub fn render_with(&self, options: &RenderOptions) -> Result<String, String> {
+        crate::render_document(&self.build(), options)
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        crate::to_mermaid(&self.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sequence_builder_matches_parsed_source() {
+        let built = SequenceBuilder::new()
+            .participant_as("A", "Alice")
+            .message("A", "B", "hi")
+            .loop_block("retry", SequenceBuilder::new().reply("B", "A", "again"))
+            .alt([
+                ("ok", SequenceBuilder::new().note(NotePlacement::Over("A".into()), "done")),
+                ("", SequenceBuilder::new().message("A", "B", "no")),
+            ])
+            .build();
+        let parsed = crate::parse(
+            "sequenceDiagram\nparticipant A as Alice\nA->>B: hi\nloop retry\nB-->>A: again\nend\nalt ok\nNote over A: done\nelse\nA->>B: no\nend\n",
+        )
+        .unwrap();
+        let Document::Sequence(parsed) = parsed else { panic!("expected a sequence diagram") };
+        assert_eq!(parsed.source_lines, SourceLines((2..=11).collect()));
+        // A built diagram has no source to point into
+        let parsed = Diagram { source_lines: SourceLines::default(), ..parsed };
+        assert_eq!(built, Document::Sequence(parsed));
+    }
+
+    #[test]
+    fn graph_builder_declares_nodes_from_edges() {
+        let graph = GraphBuilder::new(Direction::LeftRight)
+            .edge("A", "B")
+            .node("B", "Done?", NodeShape::Diamond)
+            .labeled_edge("B", "C", "yes");
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph LR\n    A --> B{Done?}\n    B -->|yes| C\n"
+        );
+        assert_eq!(graph.render(), crate::render(&graph.to_mermaid()));
+    }
+
+    #[test]
+    fn er_builder_collects_attributes() {
+        let er = ErBuilder::new()
+            .attribute("CUSTOMER", "string", "name", None)
+            .relationship(
+                "CUSTOMER",
+                Cardinality::ExactlyOne,
+                Cardinality::ZeroOrMany,
+                "ORDER",
+                "places",
+            );
+        assert_eq!(
+            er.to_mermaid(),
+            "erDiagram\n    CUSTOMER ||--o{ ORDER : places\n    CUSTOMER {\n        string name\n    }\n"
+        );
+        assert!(er.render().unwrap().contains("CUSTOMER"));
+    }
+}
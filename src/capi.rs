@@ -0,0 +1,162 @@
+//! C ABI, enabled with the `capi` feature. The matching header is
+//! `include/ma.h` (regenerate with `cbindgen --config cbindgen.toml`).
+//!
+//! Every string returned by this module is allocated by Rust and must be
+//! released with [`ma_string_free`].
+
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+/// Render the NUL-terminated UTF-8 diagram source `input`.
+///
+/// `max_width` limits the output width in columns; pass 0 for no limit.
+/// Returns the rendered text, or NULL on failure. On failure, if `error` is
+/// not NULL, `*error` receives a message describing the problem; on success
+/// it is set to NULL.
+///
+/// # Safety
+///
+/// `input` must be NULL or point to a NUL-terminated string, and `error`
+/// must be NULL or point to writable storage for one pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ma_render(
+    input: *const c_char,
+    max_width: usize,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    // SAFETY: forwarded from the caller's contract
+    unsafe { render_and_report(input, max_width, false, error) }
+}
+
+/// Like [`ma_render`], but names and labels that don't fit `max_width` are
+/// wrapped onto more lines instead of being cut short.
+///
+/// # Safety
+///
+/// As for [`ma_render`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ma_render_wrapped(
+    input: *const c_char,
+    max_width: usize,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    // SAFETY: forwarded from the caller's contract
+    unsafe { render_and_report(input, max_width, true, error) }
+}
+
+/// Render `input`, storing any error message in `error` as [`ma_render`]
+/// describes.
+///
+/// # Safety
+///
+/// As for [`ma_render`].
+unsafe fn render_and_report(
+    input: *const c_char,
+    max_width: usize,
+    wrap: bool,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    // SAFETY: forwarded from the caller's contract
+    let result = unsafe { render_c_str(input, max_width, wrap) };
+    let (output, message) = match result.and_then(to_c_string) {
+        Ok(output) => (output.into_raw(), ptr::null_mut()),
+        Err(e) => (ptr::null_mut(), to_c_string(e).map_or(ptr::null_mut(), CString::into_raw)),
+    };
+    if !error.is_null() {
+        // SAFETY: the caller guarantees `error` is writable when non-null
+        unsafe { *error = message };
+    } else if !message.is_null() {
+        // SAFETY: `message` was just produced by `CString::into_raw`
+        drop(unsafe { CString::from_raw(message) });
+    }
+    output
+}
+
+/// Release a string returned by [`ma_render`] or [`ma_render_wrapped`].
+/// Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `s` must be NULL or a pointer returned by this library that has not
+/// already been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ma_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the caller guarantees `s` came from `CString::into_raw`
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// # Safety
+///
+/// `input` must be NULL or point to a NUL-terminated string.
+unsafe fn render_c_str(
+    input: *const c_char,
+    max_width: usize,
+    wrap: bool,
+) -> Result<String, String> {
+    if input.is_null() {
+        return Err("input is NULL".to_string());
+    }
+    // SAFETY: checked for NULL above; NUL termination is the caller's contract
+    let input = unsafe { CStr::from_ptr(input) }
+        .to_str()
+        .map_err(|e| format!("input is not valid UTF-8: {e}"))?;
+    let max_width = (max_width > 0).then_some(max_width);
+    crate::render_with(input, &crate::RenderOptions { max_width, wrap, ..Default::default() })
+}
+
+fn to_c_string(text: String) -> Result<CString, String> {
+    CString::new(text).map_err(|_| "output contains a NUL byte".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn take(s: *mut c_char) -> String {
+        let text = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        unsafe { ma_string_free(s) };
+        text
+    }
+
+    #[test]
+    fn render_returns_owned_string() {
+        let input = c"graph LR\n    A --> B\n";
+        let mut error = ptr::dangling_mut();
+        let output = unsafe { ma_render(input.as_ptr(), 0, &mut error) };
+        assert!(error.is_null());
+        assert_eq!(take(output), crate::render("graph LR\n    A --> B\n").unwrap());
+    }
+
+    #[test]
+    fn render_reports_errors() {
+        let mut error = ptr::null_mut();
+        let output = unsafe { ma_render(c"pie".as_ptr(), 0, &mut error) };
+        assert!(output.is_null());
+        let error = take(error);
+        assert!(error.starts_with("unknown diagram type: pie"), "{error}");
+    }
+
+    #[test]
+    fn render_wrapped_wraps_instead_of_cutting() {
+        let source = "graph LR\n    A[a long label] --> B\n";
+        let input = CString::new(source).unwrap();
+        let mut error = ptr::null_mut();
+        let output = unsafe { ma_render_wrapped(input.as_ptr(), 20, &mut error) };
+        assert!(error.is_null());
+        let options =
+            crate::RenderOptions { max_width: Some(20), wrap: true, ..Default::default() };
+        assert_eq!(take(output), crate::render_with(source, &options).unwrap());
+        let cut = unsafe { ma_render(input.as_ptr(), 20, ptr::null_mut()) };
+        assert!(take(cut).contains('…'));
+    }
+
+    #[test]
+    fn null_input_and_error_pointer_are_tolerated() {
+        let output = unsafe { ma_render(ptr::null(), 0, ptr::null_mut()) };
+        assert!(output.is_null());
+        unsafe { ma_string_free(ptr::null_mut()) };
+    }
+}
@@ -0,0 +1,244 @@
+//! Warnings for rendering decisions that lose part of the source.
+
+use std::fmt;
+
+use crate::{Computed, Document, InputFormat, RenderOptions};
+use crate::{dot_parser, er_renderer, format, graph_renderer, layout, plantuml_parser, renderer};
+
+/// Something in the source that the rendering doesn't show as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A participant name shortened to fit `max_width`.
+    TruncatedName { name: String, shown: String },
+    /// A note cut off at the edge of the output.
+    ClippedNote { text: String },
+    /// An edge or relationship with no route to draw it along.
+    DroppedEdge { from: String, to: String },
+    /// An edge or relationship label there was no room to draw.
+    DroppedLabel { from: String, to: String, label: String },
+    /// A source line that was accepted but has no effect on the output.
+    SkippedStatement { line: usize, text: String },
+    /// Input without a diagram header, read as the only type it parses as
+    /// (see [`DiagramType::Detect`](crate::DiagramType::Detect)).
+    AssumedHeader { header: String },
+    /// A flowchart node declared again with a different shape or label,
+    /// written as in the source, when the first declaration is drawn.
+    RedeclaredNode { drawn: String, ignored: String },
+    /// A Markdown ```` ```mermaid ```` block that failed to render and was
+    /// kept as written, by the line of its opening fence.
+    FailedBlock { line: usize, error: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::TruncatedName { name, shown } => {
+                write!(f, "participant `{name}` shortened to `{shown}` to fit the width")
+            }
+            Warning::ClippedNote { text } => write!(f, "note `{text}` is clipped"),
+            Warning::DroppedEdge { from, to } => write!(f, "edge {from} -> {to} is not drawn"),
+            Warning::DroppedLabel { from, to, label } => {
+                write!(f, "label `{label}` on {from} -> {to} is not drawn")
+            }
+            Warning::SkippedStatement { line, text } => {
+                write!(f, "line {line}: `{text}` is not supported and was skipped")
+            }
+            Warning::AssumedHeader { header } => {
+                write!(f, "no diagram header, read as `{header}`")
+            }
+            Warning::RedeclaredNode { drawn, ignored } => {
+                write!(f, "node `{ignored}` declared again, drawn as the first `{drawn}`")
+            }
+            Warning::FailedBlock { line, error } => {
+                write!(f, "mermaid block at line {line} kept as written: {error}")
+            }
+        }
+    }
+}
+
+/// Flowchart statements the graph parser accepts but doesn't draw. Apart
+/// from `linkStyle`, they end up in the node metadata of the layout JSON.
+const SKIPPED_GRAPH_KEYWORDS: &[&str] = &["classDef", "linkStyle", "style", "class", "click"];
+
+pub(crate) fn collect(
+    source: &str,
+    document: &Document,
+    computed: &Computed,
+    options: &RenderOptions,
+) -> Result<Vec<Warning>, String> {
+    let mut warnings = Vec::new();
+    match (document, computed) {
+        (Document::Sequence(diagram), Computed::Sequence(layout)) => {
+            // Wrapped names are shown in full
+            if options.max_width.is_some() && !options.wrap {
+                let full = layout::compute_with(diagram, &options.sequence, None)?;
+                for (p, shown) in full.participants.iter().zip(&layout.participants) {
+                    if p.name != shown.name {
+                        warnings.push(Warning::TruncatedName {
+                            name: p.name.clone(),
+                            shown: shown.name.clone(),
+                        });
+                    }
+                }
+            }
+            for note in renderer::clipped_notes(layout) {
+                warnings.push(Warning::ClippedNote { text: note.text.clone() });
+            }
+        }
+        (Document::Graph(diagram), Computed::Graph(layout)) => {
+            if is_mermaid(source, options.input) {
+                warnings.extend(skipped_lines(source, SKIPPED_GRAPH_KEYWORDS));
+            }
+            for decl in &diagram.redeclared {
+                let Some(node) = diagram.nodes.iter().find(|n| n.id == decl.id) else { continue };
+                warnings.push(Warning::RedeclaredNode {
+                    drawn: format::node(node),
+                    ignored: format::node(decl),
+                });
+            }
+            for edge in graph_renderer::dropped_edges(layout) {
+                warnings.push(Warning::DroppedEdge {
+                    from: edge.from_id.clone(),
+                    to: edge.to_id.clone(),
+                });
+            }
+            for edge in graph_renderer::dropped_labels(layout) {
+                warnings.push(Warning::DroppedLabel {
+                    from: edge.from_id.clone(),
+                    to: edge.to_id.clone(),
+                    label: edge.label.clone().unwrap_or_default(),
+                });
+            }
+        }
+        (Document::Er(_), Computed::Er(layout)) => {
+            for edge in er_renderer::dropped_edges(layout) {
+                let (from, to) = (edge.from.clone(), edge.to.clone());
+                warnings.push(Warning::DroppedEdge { from, to });
+            }
+            for edge in er_renderer::dropped_labels(layout) {
+                warnings.push(Warning::DroppedLabel {
+                    from: edge.from.clone(),
+                    to: edge.to.clone(),
+                    label: edge.label.clone(),
+                });
+            }
+        }
+        _ => {}
+    }
+    Ok(warnings)
+}
+
+/// Whether `source` was read as Mermaid rather than another input syntax.
+fn is_mermaid(source: &str, input: InputFormat) -> bool {
+    match input {
+        InputFormat::Mermaid => true,
+        InputFormat::Dot | InputFormat::PlantUml => false,
+        InputFormat::Auto => {
+            !dot_parser::looks_like_dot(source) && !plantuml_parser::looks_like_plantuml(source)
+        }
+    }
+}
+
+fn skipped_lines(source: &str, keywords: &[&str]) -> Vec<Warning> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let first = line.split_whitespace().next().unwrap_or("");
+            keywords.contains(&first)
+        })
+        .map(|(i, line)| Warning::SkippedStatement { line: i + 1, text: line.trim().to_string() })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_with_diagnostics;
+    use pretty_assertions::assert_eq;
+
+    fn warnings(input: &str, max_width: Option<usize>) -> Vec<Warning> {
+        let options = RenderOptions { max_width, ..RenderOptions::default() };
+        render_with_diagnostics(input, &options).unwrap().1
+    }
+
+    #[test]
+    fn clean_diagrams_have_no_warnings() {
+        assert_eq!(warnings("sequenceDiagram\n    A->>B: hi\n", None), []);
+        assert_eq!(warnings("graph TD\n    A -->|yes| B\n", None), []);
+        assert_eq!(warnings("digraph { style -> x }\n", None), []);
+    }
+
+    #[test]
+    fn sequence_truncation_and_clipping() {
+        let input = "sequenceDiagram\n    Alexandria->>Bartholomew: hi\n    Note left of Alexandria: far too wide\n";
+        assert_eq!(
+            warnings(input, Some(20)),
+            [
+                Warning::TruncatedName { name: "Alexandria".into(), shown: "Alex…".into() },
+                Warning::TruncatedName { name: "Bartholomew".into(), shown: "Bart…".into() },
+                Warning::ClippedNote { text: "far too wide".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn graph_fan_out_labels_and_style_lines() {
+        let input = "graph TD\n    A -->|yes| B & C\n    style A fill:#f9f\n";
+        let found = warnings(input, None);
+        assert_eq!(
+            found,
+            [
+                Warning::SkippedStatement { line: 3, text: "style A fill:#f9f".into() },
+                Warning::DroppedLabel { from: "A".into(), to: "B".into(), label: "yes".into() },
+                Warning::DroppedLabel { from: "A".into(), to: "C".into(), label: "yes".into() },
+            ]
+        );
+        assert_eq!(found[2].to_string(), "label `yes` on A -> C is not drawn");
+    }
+
+    #[test]
+    fn graph_td_cycle_reports_the_edge_back_up() {
+        let input = "graph TD\n    A --> B\n    B --> C\n    C -->|again| A\n";
+        let found = warnings(input, None);
+        assert_eq!(found, [Warning::DroppedEdge { from: "C".into(), to: "A".into() }]);
+        assert_eq!(found[0].to_string(), "edge C -> A is not drawn");
+        assert_eq!(warnings("graph TD\n    A --> B\n    B --> A\n", None), []);
+    }
+
+    #[test]
+    fn graph_lr_cycle_reports_the_edge_back() {
+        let input = "graph LR\n    A --> B\n    B --> C\n    C --> A\n";
+        let dropped = Warning::DroppedEdge { from: "C".into(), to: "A".into() };
+        assert_eq!(warnings(input, None), [dropped]);
+        // Mutual edges share one line with a head at each end
+        let mutual = "graph LR\n    A --> B\n    B --> A\n";
+        let (output, found) = render_with_diagnostics(mutual, &RenderOptions::default()).unwrap();
+        assert_eq!(found, []);
+        assert!(output.contains("│ A │<───>│ B │"), "{output}");
+    }
+
+    #[test]
+    fn er_self_relationship_is_reported() {
+        let input = "erDiagram\n    A ||--o{ A : parent\n";
+        let dropped = Warning::DroppedEdge { from: "A".into(), to: "A".into() };
+        assert_eq!(warnings(input, None), [dropped]);
+    }
+
+    #[test]
+    fn graph_node_declared_again_with_another_shape() {
+        let input = "graph TD\n    A[Start] --> B\n    A{Start?}\n";
+        let found = warnings(input, None);
+        assert_eq!(
+            found,
+            [Warning::RedeclaredNode { drawn: "A[Start]".into(), ignored: "A{Start?}".into() }]
+        );
+        let message = "node `A{Start?}` declared again, drawn as the first `A[Start]`";
+        assert_eq!(found[0].to_string(), message);
+
+        let options = RenderOptions { last_shape_wins: true, ..RenderOptions::default() };
+        let (output, found) = render_with_diagnostics(input, &options).unwrap();
+        assert_eq!(found, []);
+        assert_eq!(output, crate::render("graph TD\n    A{Start?} --> B\n").unwrap());
+    }
+}
@@ -1,7 +1,104 @@
-use unicode_width::UnicodeWidthStr;
+use std::cell::Cell;
 
+use unicode_width::UnicodeWidthChar;
+
+/// How many columns an emoji is taken to fill. Terminals disagree, and when
+/// one draws emoji wider or narrower than measured, boxes around them come
+/// out ragged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmojiWidth {
+    /// As unicode-width says: two columns for most emoji, one for older
+    /// symbols such as `❤` or `☀`.
+    #[default]
+    Auto,
+    /// One column for every emoji.
+    Narrow,
+    /// Two columns for every emoji.
+    Wide,
+}
+
+impl EmojiWidth {
+    pub const NAMES: &[&str] = &["auto", "1", "2"];
+
+    pub fn named(name: &str) -> Option<EmojiWidth> {
+        match name {
+            "auto" => Some(EmojiWidth::Auto),
+            "1" => Some(EmojiWidth::Narrow),
+            "2" => Some(EmojiWidth::Wide),
+            _ => None,
+        }
+    }
+}
+
+thread_local! {
+    static EMOJI_WIDTH: Cell<EmojiWidth> = const { Cell::new(EmojiWidth::Auto) };
+}
+
+/// Run `f` with emoji measured as `width` on this thread.
+pub fn with_emoji_width<T>(width: EmojiWidth, f: impl FnOnce() -> T) -> T {
+    struct Restore(EmojiWidth);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            EMOJI_WIDTH.set(self.0);
+        }
+    }
+    let _restore = Restore(EMOJI_WIDTH.replace(width));
+    f()
+}
+
+/// Columns `s` takes on a canvas, which advances one character at a time:
+/// the sum of its characters' [`char_width`]s. unicode-width measures some
+/// pairs, such as the lam-alef ligature `لا`, as one column for the two.
 pub fn display_width(s: &str) -> usize {
-    UnicodeWidthStr::width(s)
+    s.chars().map(char_width).sum()
+}
+
+/// Columns taken by `c`, following the current [`EmojiWidth`].
+pub fn char_width(c: char) -> usize {
+    match EMOJI_WIDTH.get() {
+        EmojiWidth::Narrow if is_emoji(c) => 1,
+        EmojiWidth::Wide if is_emoji(c) => 2,
+        _ => UnicodeWidthChar::width(c).unwrap_or(1),
+    }
+}
+
+/// Pictographs that terminals may draw as emoji. Box-drawing characters,
+/// arrows and geometric shapes are left out so frames never change width.
+fn is_emoji(c: char) -> bool {
+    matches!(c,
+        '\u{231A}'..='\u{231B}'
+        | '\u{23E9}'..='\u{23FA}'
+        | '\u{2600}'..='\u{27BF}'
+        | '\u{2B05}'..='\u{2B55}'
+        | '\u{1F000}'..='\u{1FAFF}')
+}
+
+/// `s` without the characters that take no column of their own but change
+/// how a terminal draws their neighbours: zero-width joiners and spaces,
+/// variation selectors, bidi marks and other control characters. Their
+/// effect differs between terminals, so measured widths and the drawn row
+/// would drift apart. Tabs and line breaks become spaces.
+pub fn sanitize(s: &str) -> String {
+    s.chars()
+        .filter_map(|c| match c {
+            '\t' | '\n' | '\r' => Some(' '),
+            c if is_invisible(c) => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(c,
+            '\u{00AD}'                     // soft hyphen
+            | '\u{200B}'..='\u{200F}'     // zero-width space, (non-)joiner, LRM/RLM
+            | '\u{202A}'..='\u{202E}'     // bidi embeddings and overrides
+            | '\u{2060}'..='\u{2064}'     // word joiner, invisible operators
+            | '\u{2066}'..='\u{2069}'     // bidi isolates
+            | '\u{FE00}'..='\u{FE0F}'     // variation selectors
+            | '\u{FEFF}'                   // byte order mark
+            | '\u{E0100}'..='\u{E01EF}')  // variation selectors supplement
 }
 
 /// Split text on `<br/>`, `<br>`, `<br />` (case-insensitive).
@@ -44,6 +141,54 @@ pub fn split_br(s: &str) -> Vec<&str> {
     result
 }
 
+/// `name` cut to fit `target_width` columns, ending in `…`.
+pub fn truncate_to_display_width(name: &str, target_width: usize) -> String {
+    if target_width <= 1 {
+        return "…".to_string();
+    }
+    let mut result = String::new();
+    let mut w = 0;
+    for ch in name.chars() {
+        if ch == '…' {
+            continue;
+        }
+        let ch_w = char_width(ch);
+        if w + ch_w >= target_width {
+            break;
+        }
+        result.push(ch);
+        w += ch_w;
+    }
+    result.push('…');
+    result
+}
+
+/// Word-wrap `text` to `width` columns, joining lines with `<br/>`. Words
+/// wider than a line are split between characters.
+pub fn wrap_to_display_width(text: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    for source_line in split_br(text) {
+        let mut line = String::new();
+        for word in source_line.split(' ') {
+            let sep = usize::from(!line.is_empty());
+            if !line.is_empty() && display_width(&line) + sep + display_width(word) > width {
+                lines.push(std::mem::take(&mut line));
+            } else if sep == 1 {
+                line.push(' ');
+            }
+            for ch in word.chars() {
+                let ch_w = char_width(ch);
+                if !line.is_empty() && display_width(&line) + ch_w > width {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(ch);
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("<br/>")
+}
+
 /// Maximum display width among lines split by `<br/>`.
 pub fn multiline_width(s: &str) -> usize {
     split_br(s)
@@ -103,6 +248,51 @@ mod tests {
         assert_eq!(multiline_width("Hi<br/>World"), 5);
     }
 
+    #[test]
+    fn sanitize_drops_invisible_characters() {
+        assert_eq!(sanitize("plain"), "plain");
+        assert_eq!(sanitize("👨\u{200D}👩"), "👨👩");
+        assert_eq!(sanitize("❤\u{FE0F} ok"), "❤ ok");
+        assert_eq!(sanitize("a\tb\u{0007}c\u{200B}d"), "a bcd");
+    }
+
+    #[test]
+    fn sanitized_width_matches_columns_drawn() {
+        for s in ["👨\u{200D}👩", "❤\u{FE0F}", "a\u{0007}b"] {
+            let clean = sanitize(s);
+            let columns: usize = clean
+                .chars()
+                .map(|c| unicode_width::UnicodeWidthChar::width(c).unwrap_or(1))
+                .sum();
+            assert_eq!(display_width(&clean), columns, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn ligature_width_matches_columns_drawn() {
+        use crate::grid::{Canvas, Grid};
+        for s in ["لا", "لا لا", "باتكلا", "a\u{0644}\u{0627}b", "テスト"] {
+            assert_eq!(display_width(s), s.chars().map(char_width).sum::<usize>(), "{s:?}");
+            let mut grid = Grid::new(20, 1);
+            grid.write_str(0, 0, s);
+            grid.set(0, display_width(s), '|');
+            assert_eq!(grid.render(), format!("{s}|"));
+        }
+    }
+
+    #[test]
+    fn emoji_width_overrides_unicode_width() {
+        assert_eq!((display_width("a🎉"), display_width("a❤")), (3, 2));
+        with_emoji_width(EmojiWidth::Narrow, || {
+            assert_eq!((display_width("a🎉"), display_width("a❤")), (2, 2));
+        });
+        with_emoji_width(EmojiWidth::Wide, || {
+            assert_eq!((display_width("a🎉"), display_width("a❤")), (3, 3));
+            assert_eq!(display_width("┌─✓"), 4);
+        });
+        assert_eq!(display_width("a❤"), 2);
+    }
+
     #[test]
     fn line_count_single() {
         assert_eq!(line_count("hello"), 1);
@@ -0,0 +1,434 @@
+//! A subset of Graphviz DOT, parsed into the flowchart AST.
+//!
+//! Supported: `digraph`/`graph` bodies with node and edge statements (edge
+//! chains like `a -> b -> c`), `graph`/`node`/`edge` default attributes,
+//! `rankdir`, and `cluster` subgraphs, which become subgraphs. Other
+//! subgraphs only group statements. Node `tooltip`, `URL`/`href` and `class`
+//! attributes are kept as node metadata; other unknown attributes are ignored.
+
+use winnow::prelude::*;
+use winnow::ascii::multispace1;
+use winnow::combinator::{alt, delimited, opt, peek, preceded, repeat, terminated};
+use winnow::token::{take_until, take_while};
+
+use crate::graph_ast::*;
+use crate::parse_error::syntax_error;
+
+pub fn parse_dot(source: &str) -> Result<GraphDiagram, String> {
+    let mut input = source;
+    match dot_graph(&mut input) {
+        Ok(statements) if skip(&mut input).is_ok() && input.is_empty() => {
+            build(&statements).map_err(|e| format!("DOT graph: {e}"))
+        }
+        _ => Err(syntax_error(Some("DOT graph"), source, input)),
+    }
+}
+
+/// Whether `source` starts like a DOT graph rather than Mermaid: `digraph`,
+/// `strict`, or `graph` with its `{` on the same line.
+pub fn looks_like_dot(source: &str) -> bool {
+    let first_line = source.trim_start().lines().next().unwrap_or("");
+    let keyword = first_line.split(|c: char| c.is_whitespace() || c == '{').next();
+    match keyword {
+        Some("digraph" | "strict") => true,
+        Some("graph") => first_line.contains('{'),
+        _ => false,
+    }
+}
+
+type Attrs = Vec<(String, String)>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum DotStmt {
+    Node(String, Attrs),
+    Edge(Vec<String>, Vec<EdgeOp>, Attrs),
+    /// `graph [...]`, `node [...]` or `edge [...]`
+    Defaults(&'static str, Attrs),
+    /// `key = value` in a graph body
+    Assign(String, String),
+    Subgraph(Option<String>, Vec<DotStmt>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EdgeOp {
+    Directed,
+    Undirected,
+}
+
+/// Whitespace and `//`, `/* */` and `#` comments.
+fn skip(input: &mut &str) -> winnow::ModalResult<()> {
+    repeat(
+        0..,
+        alt((
+            multispace1.void(),
+            ("//", take_while(0.., |c| c != '\n')).void(),
+            ("#", take_while(0.., |c| c != '\n')).void(),
+            ("/*", take_until(0.., "*/"), "*/").void(),
+        )),
+    )
+    .parse_next(input)
+}
+
+fn dot_graph(input: &mut &str) -> winnow::ModalResult<Vec<DotStmt>> {
+    skip.parse_next(input)?;
+    opt(terminated("strict", skip)).parse_next(input)?;
+    alt(("digraph", "graph")).parse_next(input)?;
+    skip.parse_next(input)?;
+    opt(terminated(id, skip)).parse_next(input)?;
+    body.parse_next(input)
+}
+
+/// `{ stmt; stmt ... }`
+fn body(input: &mut &str) -> winnow::ModalResult<Vec<DotStmt>> {
+    "{".parse_next(input)?;
+    let statements = repeat(
+        0..,
+        delimited(skip, statement, (skip, opt(";"))),
+    )
+    .parse_next(input)?;
+    skip.parse_next(input)?;
+    "}".parse_next(input)?;
+    Ok(statements)
+}
+
+fn statement(input: &mut &str) -> winnow::ModalResult<DotStmt> {
+    alt((
+        defaults_stmt,
+        subgraph_stmt,
+        assign_stmt,
+        edge_stmt,
+        (id, attr_lists).map(|(id, attrs)| DotStmt::Node(id, attrs)),
+    ))
+    .parse_next(input)
+}
+
+fn defaults_stmt(input: &mut &str) -> winnow::ModalResult<DotStmt> {
+    let target = alt(("graph".value("graph"), "node".value("node"), "edge".value("edge")))
+        .parse_next(input)?;
+    skip.parse_next(input)?;
+    let attrs = preceded(peek("["), attr_lists).parse_next(input)?;
+    Ok(DotStmt::Defaults(target, attrs))
+}
+
+fn subgraph_stmt(input: &mut &str) -> winnow::ModalResult<DotStmt> {
+    let name = opt(preceded(("subgraph", skip), opt(terminated(id, skip)))).parse_next(input)?;
+    let statements = body.parse_next(input)?;
+    Ok(DotStmt::Subgraph(name.flatten(), statements))
+}
+
+fn assign_stmt(input: &mut &str) -> winnow::ModalResult<DotStmt> {
+    let key = id.parse_next(input)?;
+    (skip, "=", skip).parse_next(input)?;
+    let value = id.parse_next(input)?;
+    Ok(DotStmt::Assign(key, value))
+}
+
+fn edge_stmt(input: &mut &str) -> winnow::ModalResult<DotStmt> {
+    let first = id.parse_next(input)?;
+    let rest: Vec<(EdgeOp, String)> =
+        repeat(1.., (delimited(skip, edge_op, skip), id)).parse_next(input)?;
+    let attrs = attr_lists.parse_next(input)?;
+    let (ops, mut ids): (Vec<EdgeOp>, Vec<String>) = rest.into_iter().unzip();
+    ids.insert(0, first);
+    Ok(DotStmt::Edge(ids, ops, attrs))
+}
+
+fn edge_op(input: &mut &str) -> winnow::ModalResult<EdgeOp> {
+    alt(("->".value(EdgeOp::Directed), "--".value(EdgeOp::Undirected))).parse_next(input)
+}
+
+/// Zero or more `[k=v, ...]` lists, concatenated.
+fn attr_lists(input: &mut &str) -> winnow::ModalResult<Attrs> {
+    let lists: Vec<Attrs> = repeat(0.., preceded(skip, attr_list)).parse_next(input)?;
+    Ok(lists.into_iter().flatten().collect())
+}
+
+fn attr_list(input: &mut &str) -> winnow::ModalResult<Attrs> {
+    "[".parse_next(input)?;
+    let attrs = repeat(
+        0..,
+        delimited(
+            skip,
+            (terminated(id, (skip, "=", skip)), id),
+            (skip, opt(alt((",", ";")))),
+        ),
+    )
+    .parse_next(input)?;
+    skip.parse_next(input)?;
+    "]".parse_next(input)?;
+    Ok(attrs)
+}
+
+/// A bare identifier or number, or a double-quoted string.
+fn id(input: &mut &str) -> winnow::ModalResult<String> {
+    alt((
+        quoted,
+        take_while(1.., |c: char| c.is_alphanumeric() || c == '_' || c == '.')
+            .map(str::to_string),
+    ))
+    .parse_next(input)
+}
+
+fn quoted(input: &mut &str) -> winnow::ModalResult<String> {
+    "\"".parse_next(input)?;
+    let mut text = String::new();
+    loop {
+        let chunk = take_while(0.., |c| c != '"' && c != '\\').parse_next(input)?;
+        text.push_str(chunk);
+        if opt("\"").parse_next(input)?.is_some() {
+            return Ok(text);
+        }
+        "\\".parse_next(input)?;
+        let escaped: char = winnow::token::any.parse_next(input)?;
+        match escaped {
+            // Graphviz line breaks; labels are single-line here
+            'n' | 'l' | 'r' => text.push(' '),
+            c => text.push(c),
+        }
+    }
+}
+
+/// Default attributes in effect within a graph or subgraph body.
+#[derive(Clone, Default)]
+struct Scope {
+    node: Attrs,
+    edge: Attrs,
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attrs.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+fn build(statements: &[DotStmt]) -> Result<GraphDiagram, String> {
+    let mut diagram = GraphDiagram {
+        direction: Direction::TopDown,
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        subgraphs: Vec::new(),
+        redeclared: Vec::new(),
+    };
+    let mut members = Vec::new();
+    collect(statements, &mut Scope::default(), &mut diagram, &mut members, true)?;
+    Ok(diagram)
+}
+
+fn collect(
+    statements: &[DotStmt],
+    scope: &mut Scope,
+    diagram: &mut GraphDiagram,
+    members: &mut Vec<String>,
+    top_level: bool,
+) -> Result<(), String> {
+    for statement in statements {
+        match statement {
+            DotStmt::Assign(key, value) if key == "rankdir" && top_level => {
+                diagram.direction = direction(value)?;
+            }
+            DotStmt::Defaults("graph", attrs) if top_level => {
+                if let Some(value) = attr(attrs, "rankdir") {
+                    diagram.direction = direction(value)?;
+                }
+            }
+            DotStmt::Assign(..) | DotStmt::Defaults("graph", _) => {}
+            DotStmt::Defaults("node", attrs) => scope.node.extend(attrs.iter().cloned()),
+            DotStmt::Defaults(_, attrs) => scope.edge.extend(attrs.iter().cloned()),
+            DotStmt::Node(id, attrs) => {
+                let attrs: Attrs = scope.node.iter().chain(attrs).cloned().collect();
+                let decl = node_decl(id, &attrs);
+                match diagram.nodes.iter_mut().find(|n| n.id == *id) {
+                    Some(existing) => *existing = decl,
+                    None => diagram.nodes.push(decl),
+                }
+                add_member(members, id);
+            }
+            DotStmt::Edge(ids, ops, attrs) => {
+                let attrs: Attrs = scope.edge.iter().chain(attrs).cloned().collect();
+                for id in ids {
+                    if !diagram.nodes.iter().any(|n| n.id == *id) {
+                        diagram.nodes.push(node_decl(id, &scope.node));
+                    }
+                    add_member(members, id);
+                }
+                for (pair, op) in ids.windows(2).zip(ops) {
+                    diagram.edges.push(Edge {
+                        from: pair[0].clone(),
+                        to: pair[1].clone(),
+                        edge_type: edge_type(*op, &attrs),
+                        label: attr(&attrs, "label").map(str::to_string),
+                        comment: None,
+                    });
+                }
+            }
+            DotStmt::Subgraph(name, body) => {
+                let mut inner = Vec::new();
+                collect(body, &mut scope.clone(), diagram, &mut inner, false)?;
+                for id in &inner {
+                    add_member(members, id);
+                }
+                // Only clusters are drawn as boxes in Graphviz
+                let Some(name) = name.as_deref().filter(|n| n.starts_with("cluster")) else {
+                    continue;
+                };
+                let label = body
+                    .iter()
+                    .rev()
+                    .find_map(|s| match s {
+                        DotStmt::Assign(k, v) if k == "label" => Some(v.as_str()),
+                        DotStmt::Defaults("graph", attrs) => attr(attrs, "label"),
+                        _ => None,
+                    })
+                    .unwrap_or(name);
+                diagram.subgraphs.push(Subgraph {
+                    id: label.replace(' ', "_").to_lowercase(),
+                    label: label.to_string(),
+                    node_ids: inner,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn add_member(members: &mut Vec<String>, id: &str) {
+    if !members.iter().any(|m| m == id) {
+        members.push(id.to_string());
+    }
+}
+
+fn direction(rankdir: &str) -> Result<Direction, String> {
+    match rankdir {
+        "TB" => Ok(Direction::TopDown),
+        "LR" => Ok(Direction::LeftRight),
+        other => Err(format!("rankdir={other} is not supported (use TB or LR)")),
+    }
+}
+
+fn node_decl(id: &str, attrs: &[(String, String)]) -> NodeDecl {
+    let shape = match attr(attrs, "shape").unwrap_or("ellipse") {
+        "box" | "rect" | "rectangle" | "square" | "record" | "plaintext" | "plain" | "none" => {
+            NodeShape::Box
+        }
+        "diamond" => NodeShape::Diamond,
+        "circle" | "doublecircle" | "point" => NodeShape::Circle,
+        _ => NodeShape::Round,
+    };
+    let label = attr(attrs, "label").filter(|l| !l.trim().is_empty()).unwrap_or(id);
+    let metadata = [("tooltip", "tooltip"), ("URL", "link"), ("href", "link"), ("class", "class")]
+        .into_iter()
+        .filter_map(|(attr_name, key)| Some((key.to_string(), attr(attrs, attr_name)?.to_string())))
+        .collect();
+    NodeDecl {
+        id: id.to_string(),
+        label: label.to_string(),
+        shape,
+        metadata,
+    }
+}
+
+fn edge_type(op: EdgeOp, attrs: &[(String, String)]) -> EdgeType {
+    let arrow = op == EdgeOp::Directed
+        && attr(attrs, "dir") != Some("none")
+        && attr(attrs, "arrowhead") != Some("none");
+    let thick = attr(attrs, "penwidth").and_then(|w| w.parse::<f32>().ok()).is_some_and(|w| w > 1.0);
+    match (attr(attrs, "style").unwrap_or(""), arrow) {
+        ("dashed" | "dotted", true) => EdgeType::DottedArrow,
+        ("dashed" | "dotted", false) => EdgeType::DottedLink,
+        ("bold", true) => EdgeType::ThickArrow,
+        ("bold", false) => EdgeType::ThickLink,
+        (_, true) if thick => EdgeType::ThickArrow,
+        (_, false) if thick => EdgeType::ThickLink,
+        (_, true) => EdgeType::Arrow,
+        (_, false) => EdgeType::OpenLink,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    fn node(id: &str, label: &str, shape: NodeShape) -> NodeDecl {
+        NodeDecl {
+            id: id.to_string(),
+            label: label.to_string(),
+            shape,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn parse_edge_chain_with_label() {
+        let d = parse_dot("digraph G {\n  a -> b -> c [label=\"x y\"];\n}\n").unwrap();
+        assert_eq!(d.direction, Direction::TopDown);
+        assert_eq!(d.nodes.len(), 3);
+        assert_eq!(d.edges.len(), 2);
+        assert_eq!(d.edges[1].from, "b");
+        assert_eq!(d.edges[1].label.as_deref(), Some("x y"));
+        assert_eq!(d.edges[0].edge_type, EdgeType::Arrow);
+    }
+
+    #[test]
+    fn node_attributes_and_defaults() {
+        let src = "digraph {\n  rankdir=LR\n  node [shape=box]\n  a [label=\"Start\"]\n  b [shape=diamond label=\"Ok?\"]\n  a -> b -> c\n}";
+        let d = parse_dot(src).unwrap();
+        assert_eq!(d.direction, Direction::LeftRight);
+        assert_eq!(
+            d.nodes,
+            vec![
+                node("a", "Start", NodeShape::Box),
+                node("b", "Ok?", NodeShape::Diamond),
+                node("c", "c", NodeShape::Box),
+            ]
+        );
+    }
+
+    #[test]
+    fn node_tooltips_and_links_become_metadata() {
+        let d = parse_dot("digraph {\n  a [tooltip=\"Entry\" URL=\"https://example.com\"]\n}").unwrap();
+        let metadata: Vec<(&str, &str)> =
+            d.nodes[0].metadata.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(metadata, [("link", "https://example.com"), ("tooltip", "Entry")]);
+    }
+
+    #[test]
+    fn undirected_and_styled_edges() {
+        let src = "graph {\n  a -- b [style=dashed]\n  b -- c [penwidth=3]\n  c -> a [arrowhead=none]\n}";
+        let d = parse_dot(src).unwrap();
+        let types: Vec<EdgeType> = d.edges.iter().map(|e| e.edge_type).collect();
+        assert_eq!(types, vec![EdgeType::DottedLink, EdgeType::ThickLink, EdgeType::OpenLink]);
+        assert_eq!(d.nodes[0].shape, NodeShape::Round);
+    }
+
+    #[test]
+    fn clusters_become_subgraphs() {
+        let src = "digraph {\n  subgraph cluster_api {\n    label=\"API Tier\";\n    x; y\n  }\n  subgraph { z }\n  x -> z // comment\n  /* block\n comment */\n}";
+        let d = parse_dot(src).unwrap();
+        assert_eq!(d.subgraphs.len(), 1);
+        assert_eq!(d.subgraphs[0].label, "API Tier");
+        assert_eq!(d.subgraphs[0].id, "api_tier");
+        assert_eq!(d.subgraphs[0].node_ids, vec!["x", "y"]);
+        assert_eq!(d.nodes.len(), 3);
+    }
+
+    #[test]
+    fn unsupported_rankdir_is_an_error() {
+        let err = parse_dot("digraph { rankdir=RL; a -> b }").unwrap_err();
+        assert_eq!(err, "DOT graph: rankdir=RL is not supported (use TB or LR)");
+    }
+
+    #[test]
+    fn syntax_error_reports_position() {
+        let err = parse_dot("digraph {\n  a -> ;\n}").unwrap_err();
+        assert!(err.starts_with("syntax error in DOT graph at line 2:"), "got: {err}");
+    }
+
+    #[test]
+    fn sniffing_tells_dot_from_mermaid() {
+        assert!(looks_like_dot("digraph G {"));
+        assert!(looks_like_dot("  strict digraph {"));
+        assert!(looks_like_dot("graph {\n a -- b\n}"));
+        assert!(!looks_like_dot("graph TD\n    A --> B"));
+        assert!(!looks_like_dot("flowchart LR"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::display_width::{display_width, multiline_width};
+use crate::display_width::{display_width, multiline_width, split_br};
 use crate::er_ast::*;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -20,6 +20,51 @@ pub struct ErNodeLayout {
     pub width: usize,
     pub height: usize,
     pub center_y: usize,
+    pub columns: AttributeColumns,
+}
+
+/// Widths of the type, name and key columns that an entity's attribute
+/// rows are aligned in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AttributeColumns {
+    pub type_width: usize,
+    pub name_width: usize,
+    /// Zero when no attribute has a key.
+    pub key_width: usize,
+}
+
+impl AttributeColumns {
+    fn of(attributes: &[EntityAttribute]) -> Self {
+        let widest = |f: fn(&EntityAttribute) -> usize| attributes.iter().map(f).max().unwrap_or(0);
+        AttributeColumns {
+            type_width: widest(|a| display_width(&a.attr_type)),
+            name_width: widest(|a| display_width(&a.name)),
+            key_width: widest(|a| a.key.as_deref().map_or(0, display_width)),
+        }
+    }
+
+    /// Offset of the name column from the type column.
+    pub fn name_offset(&self) -> usize {
+        self.type_width + 1
+    }
+
+    /// Offset of the key column from the type column.
+    pub fn key_offset(&self) -> usize {
+        self.name_offset() + self.name_width + 1
+    }
+
+    /// Width of an attribute row, with one space between columns.
+    pub fn width(&self) -> usize {
+        match (self.type_width + self.name_width, self.key_width) {
+            (0, _) => 0,
+            (_, 0) => self.name_offset() + self.name_width,
+            (_, key) => self.key_offset() + key,
+        }
+    }
+}
+
+fn box_width(entity: &Entity) -> usize {
+    display_width(&entity.name).max(AttributeColumns::of(&entity.attributes).width()) + 4
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -29,23 +74,32 @@ pub struct ErEdgeLayout {
     pub left_card: Cardinality,
     pub right_card: Cardinality,
     pub label: String,
+    /// The connector's corners as `(x, y)`, from the right border of the
+    /// entity further left to the left border of the other. Empty for
+    /// entities in the same column, which aren't connected.
+    pub points: Vec<(usize, usize)>,
+    /// Where the label's first line starts as `(x, y)`, or `None` when it's
+    /// left out for want of room.
+    pub label_at: Option<(usize, usize)>,
 }
 
 const BOX_HEIGHT: usize = 3;
 const MIN_GAP: usize = 6;
+/// Columns between entities in the grid of those without relationships.
+const GRID_GAP: usize = 2;
 
 pub fn compute(diagram: &ErDiagram) -> Result<ErLayout, String> {
-    compute_with_gap(diagram, MIN_GAP)
+    compute_with_gap(diagram, MIN_GAP, None)
 }
 
 pub fn compute_with_max_width(diagram: &ErDiagram, max_width: usize) -> Result<ErLayout, String> {
-    let layout = compute(diagram)?;
+    let layout = compute_with_gap(diagram, MIN_GAP, Some(max_width))?;
     if layout.width <= max_width {
         return Ok(layout);
     }
 
     for gap in (1..MIN_GAP).rev() {
-        let layout = compute_with_gap(diagram, gap)?;
+        let layout = compute_with_gap(diagram, gap, Some(max_width))?;
         if layout.width <= max_width {
             return Ok(layout);
         }
@@ -54,16 +108,28 @@ pub fn compute_with_max_width(diagram: &ErDiagram, max_width: usize) -> Result<E
     Err(format!("ER diagram too wide for {max_width} columns"))
 }
 
-fn compute_with_gap(diagram: &ErDiagram, min_gap: usize) -> Result<ErLayout, String> {
+/// Entities with relationships are placed in ranks from left to right;
+/// the others follow in a grid below them.
+fn compute_with_gap(
+    diagram: &ErDiagram,
+    min_gap: usize,
+    max_width: Option<usize>,
+) -> Result<ErLayout, String> {
     if diagram.entities.is_empty() {
         return Err("no entities found".to_string());
     }
 
+    let related: HashSet<&str> =
+        diagram.relationships.iter().flat_map(|r| [r.from.as_str(), r.to.as_str()]).collect();
+    let (linked, unlinked): (Vec<&Entity>, Vec<&Entity>) =
+        diagram.entities.iter().partition(|e| related.contains(e.name.as_str()));
+
     let ranks = assign_ranks(diagram);
-    let max_rank = *ranks.values().max().unwrap_or(&0);
+    let entry_rows = entry_rows(diagram, &ranks);
+    let max_rank = linked.iter().map(|e| ranks[e.name.as_str()]).max().unwrap_or(0);
 
     let mut ranks_entities: Vec<Vec<&Entity>> = vec![Vec::new(); max_rank + 1];
-    for entity in &diagram.entities {
+    for entity in linked {
         let rank = ranks[entity.name.as_str()];
         ranks_entities[rank].push(entity);
     }
@@ -74,43 +140,14 @@ fn compute_with_gap(diagram: &ErDiagram, min_gap: usize) -> Result<ErLayout, Str
     for (rank, rank_entities) in ranks_entities.iter().enumerate() {
         let mut y = 0;
         for entity in rank_entities {
-            let attr_width = entity.attributes.iter().map(|a| {
-                let mut w = display_width(&a.attr_type) + 1 + display_width(&a.name);
-                if let Some(ref k) = a.key {
-                    w += 1 + display_width(k);
-                }
-                w
-            }).max().unwrap_or(0);
-            let content_width = display_width(&entity.name).max(attr_width);
-            let w = content_width + 4;
-            let h = if entity.attributes.is_empty() {
-                BOX_HEIGHT
-            } else {
-                BOX_HEIGHT + 1 + entity.attributes.len()
-            };
-            nodes.push(ErNodeLayout {
-                name: entity.name.to_string(),
-                attributes: entity.attributes.clone(),
-                x,
-                y,
-                width: w,
-                height: h,
-                center_y: y + h / 2,
-            });
-            y += h + 1;
+            let rows = entry_rows.get(entity.name.as_str()).copied().unwrap_or(0);
+            let node = entity_node(entity, x, y, rows);
+            y += node.height + 1;
+            nodes.push(node);
         }
 
         if rank < max_rank {
-            let rank_max_width = rank_entities.iter().map(|e| {
-                let attr_width = e.attributes.iter().map(|a| {
-                    let mut w = display_width(&a.attr_type) + 1 + display_width(&a.name);
-                    if let Some(ref k) = a.key {
-                        w += 1 + display_width(k);
-                    }
-                    w
-                }).max().unwrap_or(0);
-                display_width(&e.name).max(attr_width) + 4
-            }).max().unwrap_or(0);
+            let rank_max_width = rank_entities.iter().map(|e| box_width(e)).max().unwrap_or(0);
             let label_gap = diagram
                 .relationships
                 .iter()
@@ -126,20 +163,13 @@ fn compute_with_gap(diagram: &ErDiagram, min_gap: usize) -> Result<ErLayout, Str
         }
     }
 
-    let width = nodes.iter().map(|n| n.x + n.width).max().unwrap_or(0);
-    let height = nodes.iter().map(|n| n.y + n.height).max().unwrap_or(0);
+    let (edges, linked_height) = route(&diagram.relationships, &nodes);
+    let top = if nodes.is_empty() { 0 } else { linked_height + 1 };
+    nodes.extend(grid(&unlinked, top, max_width));
 
-    let edges = diagram
-        .relationships
-        .iter()
-        .map(|r| ErEdgeLayout {
-            from: r.from.clone(),
-            to: r.to.clone(),
-            left_card: r.left_card,
-            right_card: r.right_card,
-            label: r.label.clone(),
-        })
-        .collect();
+    let width = nodes.iter().map(|n| n.x + n.width).max().unwrap_or(0);
+    let height =
+        nodes.iter().map(|n| n.y + n.height).chain([linked_height]).max().unwrap_or(0);
 
     Ok(ErLayout {
         nodes,
@@ -149,6 +179,330 @@ fn compute_with_gap(diagram: &ErDiagram, min_gap: usize) -> Result<ErLayout, Str
     })
 }
 
+/// An entity's box, with blank rows added under its name or attributes
+/// when connectors need more than `entry_rows` on one side.
+fn entity_node(entity: &Entity, x: usize, y: usize, entry_rows: usize) -> ErNodeLayout {
+    let (height, rows) = if entity.attributes.is_empty() {
+        (BOX_HEIGHT, 1)
+    } else {
+        (BOX_HEIGHT + 1 + entity.attributes.len(), 1 + entity.attributes.len())
+    };
+    let center_y = y + height / 2;
+    let height = height + entry_rows.saturating_sub(rows);
+    ErNodeLayout {
+        name: entity.name.to_string(),
+        attributes: entity.attributes.clone(),
+        x,
+        y,
+        width: box_width(entity),
+        height,
+        center_y,
+        columns: AttributeColumns::of(&entity.attributes),
+    }
+}
+
+/// Rows each entity needs on its busier side: one per cardinality drawn
+/// there, since connectors only share a row where their cardinalities match.
+fn entry_rows<'a>(diagram: &'a ErDiagram, ranks: &HashMap<&str, usize>) -> HashMap<&'a str, usize> {
+    let mut sides: HashMap<(&str, bool), Vec<Cardinality>> = HashMap::new();
+    for r in &diagram.relationships {
+        let (Some(&from), Some(&to)) = (ranks.get(r.from.as_str()), ranks.get(r.to.as_str())) else {
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        let ends = [(&r.from, r.left_card, to > from), (&r.to, r.right_card, from > to)];
+        for (name, card, right) in ends {
+            let cards = sides.entry((name.as_str(), right)).or_default();
+            if !cards.contains(&card) {
+                cards.push(card);
+            }
+        }
+    }
+    let mut rows: HashMap<&str, usize> = HashMap::new();
+    for ((name, _), cards) in sides {
+        let needed = rows.entry(name).or_default();
+        *needed = (*needed).max(cards.len());
+    }
+    rows
+}
+
+/// Entities packed row by row from `top`: about √n to a row so the grid
+/// stays compact, and fewer when a row would be wider than `max_width`.
+fn grid(entities: &[&Entity], top: usize, max_width: Option<usize>) -> Vec<ErNodeLayout> {
+    let per_row = (1..).find(|n| n * n >= entities.len()).unwrap_or(1);
+    let mut nodes: Vec<ErNodeLayout> = Vec::new();
+    let (mut x, mut y, mut row_height, mut in_row) = (0, top, 0, 0);
+    for entity in entities {
+        let width = box_width(entity);
+        if in_row > 0 && (in_row == per_row || max_width.is_some_and(|m| x + width > m)) {
+            y += row_height + 1;
+            (x, row_height, in_row) = (0, 0, 0);
+        }
+        let node = entity_node(entity, x, y, 0);
+        x += width + GRID_GAP;
+        row_height = row_height.max(node.height);
+        in_row += 1;
+        nodes.push(node);
+    }
+    nodes
+}
+
+/// Lay out each relationship's connector in turn, and return the height the
+/// entities and connectors take together.
+fn route(relationships: &[Relationship], nodes: &[ErNodeLayout]) -> (Vec<ErEdgeLayout>, usize) {
+    let mut router = Router {
+        boxes: nodes
+            .iter()
+            .flat_map(|n| {
+                (n.y..n.y + n.height).flat_map(move |y| (n.x..n.x + n.width).map(move |x| (x, y)))
+            })
+            .collect(),
+        taken: HashMap::new(),
+    };
+    let find = |name: &str| nodes.iter().find(|n| n.name == name);
+    let edges = relationships
+        .iter()
+        .map(|r| {
+            let (points, label_at) = match (find(&r.from), find(&r.to)) {
+                (Some(a), Some(b)) if a.x != b.x => {
+                    let (from, to) = ((a, r.left_card), (b, r.right_card));
+                    let (left, right) = if a.x < b.x { (from, to) } else { (to, from) };
+                    router.route(left, right, &r.label)
+                }
+                _ => (Vec::new(), None),
+            };
+            ErEdgeLayout {
+                from: r.from.clone(),
+                to: r.to.clone(),
+                left_card: r.left_card,
+                right_card: r.right_card,
+                label: r.label.clone(),
+                points,
+                label_at,
+            }
+        })
+        .collect();
+    (edges, router.height())
+}
+
+/// A connector's corners, and where its label starts if it has room.
+type Route = (Vec<(usize, usize)>, Option<(usize, usize)>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// A cell a connector routed earlier has taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Taken<'a> {
+    /// Part of a straight run, which a run the other way may cross.
+    Run(Axis),
+    /// A corner, crossing or label, which nothing else may use.
+    Fixed,
+    /// In front of the named entity's border, where the cardinality goes:
+    /// shared by every connector leaving or entering that side on that row
+    /// with the same cardinality.
+    Stub(&'a str, Cardinality),
+}
+
+/// An entity a connector ends on, with the cardinality drawn at that end.
+type End<'a> = (&'a ErNodeLayout, Cardinality);
+
+/// Routes connectors through the gaps between entities, around the boxes
+/// and the connectors routed before.
+struct Router<'a> {
+    boxes: HashSet<(usize, usize)>,
+    taken: HashMap<(usize, usize), Taken<'a>>,
+}
+
+impl<'a> Router<'a> {
+    /// The corners of a connector from `left`'s right border to `right`'s
+    /// left one, and where its label starts. A straight run is tried first,
+    /// then one bending once between two rows, then one detouring along a
+    /// row clear of the boxes in the way, below them all if need be.
+    fn route(&mut self, left_end: End<'a>, right_end: End<'a>, label: &str) -> Route {
+        let (left, right) = (left_end.0, right_end.0);
+        let (start, end) = (left.x + left.width - 1, right.x);
+        let mut rows: Vec<(usize, usize)> = side_rows(left)
+            .into_iter()
+            .flat_map(|r1| side_rows(right).into_iter().map(move |r2| (r1, r2)))
+            .collect();
+        rows.sort_by_key(|&(r1, r2)| {
+            (r1.abs_diff(r2), r1.abs_diff(left.center_y), r2.abs_diff(right.center_y))
+        });
+        let fallback = rows.first().map_or(left.center_y, |&(r1, _)| r1);
+        // Bends keep clear of the cardinalities, trying first the columns
+        // next to either entity, where connectors sharing a side meet
+        let (first, last) = (start + STUB, end.saturating_sub(STUB - 1));
+        let mut columns: Vec<usize> = (first..=last).collect();
+        if let Some(column) = columns.pop() {
+            columns.insert(columns.len().min(1), column);
+        }
+
+        let straight =
+            rows.iter().filter(|(r1, r2)| r1 == r2).map(|&(r, _)| vec![(start, r), (end, r)]);
+        let bent = rows.iter().filter(|(r1, r2)| r1 != r2).flat_map(|&(r1, r2)| {
+            columns.iter().map(move |&c| vec![(start, r1), (c, r1), (c, r2), (end, r2)])
+        });
+        let below = self.height();
+        let detours = rows.iter().flat_map(|&(r1, r2)| {
+            let mut middles: Vec<usize> = (0..=below).filter(|&y| y != r1 && y != r2).collect();
+            middles.sort_by_key(|&y| r1.abs_diff(y) + y.abs_diff(r2));
+            let columns = &columns;
+            middles.into_iter().flat_map(move |y| {
+                columns.iter().filter(move |&&c| c < last).map(move |&c| {
+                    vec![(start, r1), (c, r1), (c, y), (last, y), (last, r2), (end, r2)]
+                })
+            })
+        });
+
+        let points = straight
+            .chain(bent)
+            .chain(detours)
+            .find(|points| self.is_clear(points, left_end, right_end))
+            .unwrap_or_else(|| vec![(start, fallback), (end, fallback)]);
+        let label_at = self.place_label(&points, label);
+        self.take(&points, left_end, right_end, label_at, label);
+        (points, label_at)
+    }
+
+    /// The rows the boxes and the connectors so far take.
+    fn height(&self) -> usize {
+        self.boxes.iter().chain(self.taken.keys()).map(|&(_, y)| y + 1).max().unwrap_or(0)
+    }
+
+    fn is_clear(&self, points: &[(usize, usize)], left: End, right: End) -> bool {
+        let (r1, r2) = (points[0].1, points[points.len() - 1].1);
+        cells(points).into_iter().all(|((x, y), axis)| {
+            !self.boxes.contains(&(x, y))
+                && match self.taken.get(&(x, y)) {
+                    None => true,
+                    Some(Taken::Run(run)) => axis.is_some_and(|axis| axis != *run),
+                    Some(Taken::Fixed) => false,
+                    Some(&Taken::Stub(name, card)) => {
+                        (y == r1 && (name, card) == (left.0.name.as_str(), left.1))
+                            || (y == r2 && (name, card) == (right.0.name.as_str(), right.1))
+                    }
+                }
+        })
+    }
+
+    /// Where `label` goes, clear of everything else: centred between the
+    /// cardinalities on a straight run, otherwise on the longest run with
+    /// room for it and a line either side, away from both stubs.
+    fn place_label(&self, points: &[(usize, usize)], label: &str) -> Option<(usize, usize)> {
+        if label.is_empty() {
+            return None;
+        }
+        let lines = split_br(label);
+        let width = multiline_width(label);
+        let top = |y: usize| y.saturating_sub(lines.len() / 2);
+        let clear = |(x, y): (usize, usize)| {
+            lines.iter().enumerate().all(|(i, line)| {
+                let line_x = x + (width - display_width(line)) / 2;
+                (line_x..line_x + display_width(line)).all(|x| {
+                    !self.boxes.contains(&(x, y + i)) && !self.taken.contains_key(&(x, y + i))
+                })
+            })
+        };
+
+        let ((start, r1), (end, r2)) = (points[0], points[points.len() - 1]);
+        if let [_, _] = points {
+            let gap = end - start - 1;
+            let at = (gap > width).then(|| (start + 1 + (gap - width) / 2, top(r1)));
+            return at.filter(|&at| clear(at));
+        }
+        let last = points.len() - 2;
+        let mut runs: Vec<(usize, usize, usize)> = points
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0].1 == pair[1].1)
+            .map(|(i, pair)| {
+                let from = if i == 0 { start + 3 } else { pair[0].0 + 1 };
+                let to = if i == last { end - 2 } else { pair[1].0 };
+                (pair[0].1, from, to.max(from))
+            })
+            .collect();
+        runs.sort_by_key(|&(_, from, to)| std::cmp::Reverse(to - from));
+        runs.into_iter()
+            .filter(|&(_, from, to)| to - from >= width + 2)
+            .map(|(y, from, to)| (y, from + (to - from - width).div_ceil(2)))
+            .filter(|&(y, x)| {
+                let on_exit = y == r1 && x <= start + STUB;
+                let on_entry = y == r2 && x + width + STUB - 1 > end;
+                !on_exit && !on_entry
+            })
+            .map(|(y, x)| (x, top(y)))
+            .find(|&at| clear(at))
+    }
+
+    fn take(
+        &mut self,
+        points: &[(usize, usize)],
+        left: End<'a>,
+        right: End<'a>,
+        label_at: Option<(usize, usize)>,
+        label: &str,
+    ) {
+        let ((start, r1), (end, r2)) = (points[0], points[points.len() - 1]);
+        for ((x, y), axis) in cells(points) {
+            let taken = if y == r1 && x <= start + STUB {
+                Taken::Stub(&left.0.name, left.1)
+            } else if y == r2 && x + STUB > end {
+                Taken::Stub(&right.0.name, right.1)
+            } else {
+                match (axis, self.taken.get(&(x, y))) {
+                    (Some(axis), None) => Taken::Run(axis),
+                    _ => Taken::Fixed,
+                }
+            };
+            self.taken.insert((x, y), taken);
+        }
+        if let Some((x, y)) = label_at {
+            let width = multiline_width(label);
+            for (i, line) in split_br(label).iter().enumerate() {
+                let line_x = x + (width - display_width(line)) / 2;
+                for x in line_x..line_x + display_width(line) {
+                    self.taken.insert((x, y + i), Taken::Fixed);
+                }
+            }
+        }
+    }
+}
+
+/// Columns from an entity's border to the first bend a connector may take:
+/// the cardinality and a line.
+const STUB: usize = 4;
+
+/// Rows a connector can leave or enter `node` on: inside its border, but
+/// not on the line under an entity's name.
+fn side_rows(node: &ErNodeLayout) -> Vec<usize> {
+    let divider = (!node.attributes.is_empty()).then_some(node.y + 2);
+    (node.y + 1..node.y + node.height - 1).filter(|&y| Some(y) != divider).collect()
+}
+
+/// The cells a connector runs through between the two borders, each with
+/// the way it runs there: `None` at corners.
+fn cells(points: &[(usize, usize)]) -> Vec<((usize, usize), Option<Axis>)> {
+    let mut cells = Vec::new();
+    for (i, pair) in points.windows(2).enumerate() {
+        let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+        if i > 0 {
+            cells.push(((ax, ay), None));
+        }
+        if ay == by {
+            cells.extend((ax.min(bx) + 1..ax.max(bx)).map(|x| ((x, ay), Some(Axis::Horizontal))));
+        } else {
+            cells.extend((ay.min(by) + 1..ay.max(by)).map(|y| ((ax, y), Some(Axis::Vertical))));
+        }
+    }
+    cells
+}
+
 fn assign_ranks(diagram: &ErDiagram) -> HashMap<&str, usize> {
     let mut in_edges: HashMap<&str, Vec<&str>> = HashMap::new();
     for entity in &diagram.entities {
@@ -203,7 +557,6 @@ fn compute_rank<'a>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::er_ast::*;
 
     fn entity(name: &str) -> Entity {
         Entity { name: name.to_string(), attributes: Vec::new() }
@@ -266,4 +619,103 @@ mod tests {
             "gap ({gap}) should fit label + connectors"
         );
     }
+
+    #[test]
+    fn connectors_run_along_rows_both_entities_share() {
+        let attribute = |name: &str| EntityAttribute {
+            attr_type: "int".into(),
+            name: name.into(),
+            key: None,
+        };
+        let diagram = ErDiagram {
+            entities: vec![
+                Entity {
+                    name: "A".into(),
+                    attributes: vec![attribute("x"), attribute("y"), attribute("z")],
+                },
+                entity("B"),
+                entity("C"),
+            ],
+            relationships: vec![
+                Relationship {
+                    from: "A".into(),
+                    to: "B".into(),
+                    left_card: Cardinality::ExactlyOne,
+                    right_card: Cardinality::ExactlyOne,
+                    label: "r1".into(),
+                },
+                Relationship {
+                    from: "A".into(),
+                    to: "C".into(),
+                    left_card: Cardinality::ExactlyOne,
+                    right_card: Cardinality::ExactlyOne,
+                    label: "r2".into(),
+                },
+            ],
+        };
+        let layout = compute(&diagram).unwrap();
+        // B's only row is A's name row rather than A's middle one, and C,
+        // stacked below B, is level with A's last attribute
+        assert_eq!(layout.edges[0].points, vec![(8, 1), (19, 1)]);
+        assert_eq!(layout.edges[0].label_at, Some((13, 1)));
+        assert_eq!(layout.edges[1].points, vec![(8, 5), (19, 5)]);
+    }
+
+    #[test]
+    fn entities_without_relationships_form_a_grid() {
+        let diagram = ErDiagram {
+            entities: vec![entity("A"), entity("B"), entity("C"), entity("D"), entity("E")],
+            relationships: vec![Relationship {
+                from: "A".into(),
+                to: "B".into(),
+                left_card: Cardinality::ExactlyOne,
+                right_card: Cardinality::ExactlyOne,
+                label: "r".into(),
+            }],
+        };
+        let position = |layout: &ErLayout, name: &str| {
+            let node = layout.nodes.iter().find(|n| n.name == name).unwrap();
+            (node.x, node.y)
+        };
+        let layout = compute(&diagram).unwrap();
+        assert_eq!(position(&layout, "A"), (0, 0));
+        assert_eq!(position(&layout, "C"), (0, 4));
+        assert_eq!(position(&layout, "D"), (7, 4));
+        assert_eq!(position(&layout, "E"), (0, 8));
+
+        let diagram = ErDiagram {
+            entities: vec![entity("A"), entity("B"), entity("C"), entity("D")],
+            relationships: Vec::new(),
+        };
+        let layout = compute(&diagram).unwrap();
+        assert_eq!(position(&layout, "B"), (7, 0));
+        assert_eq!(position(&layout, "D"), (7, 4));
+        let layout = compute_with_max_width(&diagram, 8).unwrap();
+        assert_eq!(position(&layout, "D"), (0, 12));
+        assert_eq!((layout.width, layout.height), (5, 15));
+    }
+
+    #[test]
+    fn attribute_columns_fit_the_widest_of_each() {
+        let attribute = |attr_type: &str, name: &str, key: Option<&str>| EntityAttribute {
+            attr_type: attr_type.into(),
+            name: name.into(),
+            key: key.map(Into::into),
+        };
+        let diagram = ErDiagram {
+            entities: vec![Entity {
+                name: "USER".into(),
+                attributes: vec![
+                    attribute("int", "id", Some("PK")),
+                    attribute("string", "name", None),
+                ],
+            }],
+            relationships: Vec::new(),
+        };
+        let node = &compute(&diagram).unwrap().nodes[0];
+        let columns = AttributeColumns { type_width: 6, name_width: 4, key_width: 2 };
+        assert_eq!(node.columns, columns);
+        assert_eq!((columns.name_offset(), columns.key_offset()), (7, 12));
+        assert_eq!(node.width, 14 + 4);
+    }
 }
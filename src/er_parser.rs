@@ -1,25 +1,64 @@
 use winnow::prelude::*;
-use winnow::ascii::{line_ending, space0, space1};
-use winnow::combinator::{alt, opt, preceded, repeat};
+use winnow::ascii::{line_ending, multispace0, space0, space1};
+use winnow::combinator::{alt, cut_err, eof, opt, preceded, repeat};
 use winnow::token::take_while;
 
 use crate::er_ast::*;
+use crate::parse_error::{position, syntax_error, syntax_error_while};
+
+const KIND: &str = "ER diagram";
+
+pub fn parse_er(source: &str) -> Result<ErDiagram, String> {
+    let mut input = source;
+    match er_diagram(&mut input) {
+        Ok(d) if input.trim().is_empty() => Ok(d),
+        _ => Err(match error_context(source, input) {
+            Some(doing) => syntax_error_while(Some(KIND), source, input, &doing),
+            None => syntax_error(Some(KIND), source, input),
+        }),
+    }
+}
 
-pub fn parse_er(input: &str) -> Result<ErDiagram, String> {
-    let mut input = input;
-    er_diagram(&mut input).map_err(|_| {
-        let context = input.lines().next().unwrap_or("").trim();
-        let context_display = if context.len() > 40 {
-            format!("{}...", &context[..40])
+/// What the line parsing stopped on was part of: an entity's attributes,
+/// or a relationship between two entities.
+fn error_context(source: &str, remaining: &str) -> Option<String> {
+    let (line, _) = position(source, remaining);
+    let lines: Vec<&str> = source.lines().map(str::trim).collect();
+    let opened = |text: &str| text.strip_suffix('{').map(str::trim_end).map(str::to_string);
+
+    let mut entity = None;
+    for text in &lines[..line - 1] {
+        if let Some(name) = opened(text) {
+            entity = Some(name);
+        } else if text.starts_with('}') {
+            entity = None;
+        }
+    }
+    let text = lines.get(line - 1).copied().unwrap_or("");
+    if let Some(name) = opened(text) {
+        let closed = lines[line..].iter().any(|l| l.starts_with('}'));
+        return Some(if closed {
+            format!("parsing attributes of {name}")
         } else {
-            context.to_string()
-        };
-        format!("syntax error in ER diagram: unexpected `{context_display}`")
-    })
+            format!("parsing attributes of {name}, which have no closing `}}`")
+        });
+    }
+    if let Some(name) = entity {
+        return Some(format!("parsing attributes of {name}"));
+    }
+    match text.split_whitespace().collect::<Vec<_>>()[..] {
+        [from, cardinality, to, ..] if cardinality.contains("--") => {
+            Some(format!("parsing the relationship from {from} to {to}"))
+        }
+        [from, cardinality, ..] if cardinality.contains("--") => {
+            Some(format!("parsing a relationship from {from}"))
+        }
+        _ => None,
+    }
 }
 
-fn er_diagram(input: &mut &str) -> winnow::Result<ErDiagram> {
-    space0.parse_next(input)?;
+fn er_diagram(input: &mut &str) -> winnow::ModalResult<ErDiagram> {
+    multispace0.parse_next(input)?;
     "erDiagram".parse_next(input)?;
     opt(line_ending).parse_next(input)?;
 
@@ -34,6 +73,7 @@ fn er_diagram(input: &mut &str) -> winnow::Result<ErDiagram> {
                 add_entity(&mut entities, &rel.to);
                 relationships.push(rel);
             }
+            ErLine::Entity(name) => add_entity(&mut entities, &name),
             ErLine::EntityBlock(name, attrs) => {
                 if let Some(e) = entities.iter_mut().find(|e| e.name == name) {
                     e.attributes = attrs;
@@ -56,19 +96,22 @@ fn er_diagram(input: &mut &str) -> winnow::Result<ErDiagram> {
 #[derive(Debug)]
 enum ErLine {
     Relationship(Relationship),
+    /// An entity named on a line of its own, without attributes.
+    Entity(String),
     EntityBlock(String, Vec<EntityAttribute>),
 }
 
-fn er_line(input: &mut &str) -> winnow::Result<Option<ErLine>> {
+fn er_line(input: &mut &str) -> winnow::ModalResult<Option<ErLine>> {
     alt((
         entity_block.map(|(name, attrs)| Some(ErLine::EntityBlock(name, attrs))),
         relationship_line.map(|r| Some(ErLine::Relationship(r))),
+        entity_line.map(|name| Some(ErLine::Entity(name))),
         blank_line.map(|_| None),
     ))
     .parse_next(input)
 }
 
-fn blank_line(input: &mut &str) -> winnow::Result<()> {
+fn blank_line(input: &mut &str) -> winnow::ModalResult<()> {
     space0.parse_next(input)?;
     line_ending.parse_next(input)?;
     Ok(())
@@ -83,7 +126,15 @@ fn add_entity(entities: &mut Vec<Entity>, name: &str) {
     }
 }
 
-fn entity_block<'s>(input: &mut &'s str) -> winnow::Result<(String, Vec<EntityAttribute>)> {
+fn entity_line(input: &mut &str) -> winnow::ModalResult<String> {
+    space0.parse_next(input)?;
+    let name = er_identifier.parse_next(input)?;
+    space0.parse_next(input)?;
+    alt((line_ending.void(), eof.void())).parse_next(input)?;
+    Ok(name.to_string())
+}
+
+fn entity_block(input: &mut &str) -> winnow::ModalResult<(String, Vec<EntityAttribute>)> {
     space0.parse_next(input)?;
     let name = er_identifier.parse_next(input)?;
     space0.parse_next(input)?;
@@ -104,14 +155,14 @@ fn entity_block<'s>(input: &mut &'s str) -> winnow::Result<(String, Vec<EntityAt
         if let Ok(()) = blank_line(input) {
             continue;
         }
-        let attr = entity_attribute.parse_next(input)?;
+        let attr = cut_err(entity_attribute).parse_next(input)?;
         attrs.push(attr);
     }
 
     Ok((name.to_string(), attrs))
 }
 
-fn entity_attribute(input: &mut &str) -> winnow::Result<EntityAttribute> {
+fn entity_attribute(input: &mut &str) -> winnow::ModalResult<EntityAttribute> {
     space0.parse_next(input)?;
     let attr_type = er_identifier.parse_next(input)?;
     space1.parse_next(input)?;
@@ -126,11 +177,11 @@ fn entity_attribute(input: &mut &str) -> winnow::Result<EntityAttribute> {
     })
 }
 
-fn er_identifier<'s>(input: &mut &'s str) -> winnow::Result<&'s str> {
+fn er_identifier<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
     take_while(1.., |c: char| c.is_alphanumeric() || c == '_' || c == '-').parse_next(input)
 }
 
-fn relationship_line(input: &mut &str) -> winnow::Result<Relationship> {
+fn relationship_line(input: &mut &str) -> winnow::ModalResult<Relationship> {
     space0.parse_next(input)?;
     let from = er_identifier.parse_next(input)?;
     space1.parse_next(input)?;
@@ -153,7 +204,7 @@ fn relationship_line(input: &mut &str) -> winnow::Result<Relationship> {
     })
 }
 
-fn cardinality(input: &mut &str) -> winnow::Result<(Cardinality, Cardinality)> {
+fn cardinality(input: &mut &str) -> winnow::ModalResult<(Cardinality, Cardinality)> {
     let left_str: &str =
         take_while(1.., |c: char| c == '|' || c == 'o' || c == '{' || c == '}')
             .parse_next(input)?;
@@ -190,6 +241,40 @@ fn parse_right_cardinality(s: &str) -> Cardinality {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_error_reports_line_number() {
+        let input = "erDiagram\n    A ||--o{ B : has\n    A ?? B\n";
+        let err = parse_er(input).unwrap_err();
+        assert!(err.starts_with("syntax error in ER diagram at line 3:"), "got: {err}");
+    }
+
+    #[test]
+    fn parse_error_says_which_entity_or_relationship() {
+        let first_line =
+            |input: &str| parse_er(input).unwrap_err().lines().next().unwrap().to_string();
+        assert_eq!(
+            first_line("erDiagram\n    A {\n        string name\n        int\n    }\n"),
+            "syntax error in ER diagram at line 4:12: unexpected `int` while parsing attributes of A"
+        );
+        assert!(
+            first_line("erDiagram\n    A {\n        int id\n")
+                .ends_with("while parsing attributes of A, which have no closing `}`")
+        );
+        assert!(
+            first_line("erDiagram\n    A ||--o{ B has\n")
+                .ends_with("while parsing the relationship from A to B")
+        );
+    }
+
+    #[test]
+    fn parse_entities_without_relationships() {
+        let input = "erDiagram\n    A\n    B {\n    }\n    C {\n        int id\n    }\n    A";
+        let diagram = parse_er(input).unwrap();
+        let names: Vec<&str> = diagram.entities.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["A", "B", "C"]);
+        assert!(diagram.relationships.is_empty());
+    }
+
     #[test]
     fn parse_er_identifier_simple() {
         let mut input = "CUSTOMER rest";
@@ -1,60 +1,61 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 use crate::display_width::{display_width, multiline_width, split_br};
 use crate::er_ast::Cardinality;
 use crate::er_layout::*;
+use crate::grid::{AnsiGrid, Canvas, Grid};
+use crate::style::Theme;
 
-struct Grid {
-    cells: Vec<Vec<char>>,
-    width: usize,
-    height: usize,
+pub fn render(layout: &ErLayout) -> String {
+    render_with(layout, &Theme::default())
 }
 
-impl Grid {
-    fn new(width: usize, height: usize) -> Self {
-        Self {
-            cells: vec![vec![' '; width]; height],
-            width,
-            height,
-        }
-    }
+/// Render with the given border theme for entity boxes.
+pub fn render_with(layout: &ErLayout, theme: &Theme) -> String {
+    draw(layout, theme).render()
+}
 
-    fn set(&mut self, row: usize, col: usize, ch: char) {
-        if row < self.height && col < self.width {
-            if self.cells[row][col] == '\0' && col > 0 && self.cells[row][col - 1] != '\0' {
-                self.cells[row][col - 1] = ' ';
-            }
-            self.cells[row][col] = ch;
-        }
-    }
+/// Render for a terminal, with the lines dimmed by ANSI escapes.
+pub fn render_ansi(layout: &ErLayout, theme: &Theme) -> String {
+    let mut grid = AnsiGrid::new(layout.width, layout.height);
+    draw_on(&mut grid, layout, theme);
+    grid.render()
+}
 
-    fn write_str(&mut self, row: usize, col: usize, s: &str) {
-        let mut offset = 0;
-        for ch in s.chars() {
-            self.set(row, col + offset, ch);
-            let w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(1);
-            for j in 1..w {
-                self.set(row, col + offset + j, '\0');
-            }
-            offset += w;
-        }
-    }
+/// Write the rendering to `out` row by row.
+pub fn render_to<W: std::io::Write>(
+    layout: &ErLayout,
+    theme: &Theme,
+    out: &mut W,
+) -> std::io::Result<()> {
+    draw(layout, theme).write_to(out)
+}
 
-    fn render(&self) -> String {
-        self.cells
-            .iter()
-            .map(|row| {
-                let line: String = row.iter().filter(|&&ch| ch != '\0').collect();
-                line.trim_end().to_string()
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
-    }
+/// Relationships whose label the renderer leaves out for want of room along
+/// their connector.
+pub fn dropped_labels(layout: &ErLayout) -> Vec<&ErEdgeLayout> {
+    layout
+        .edges
+        .iter()
+        .filter(|edge| !edge.label.is_empty() && edge.label_at.is_none() && !edge.points.is_empty())
+        .collect()
 }
 
-pub fn render(layout: &ErLayout) -> String {
+/// Relationships the renderer leaves out: those of an entity with itself or
+/// with another in the same column, which have no connector.
+pub fn dropped_edges(layout: &ErLayout) -> Vec<&ErEdgeLayout> {
+    layout.edges.iter().filter(|edge| edge.points.is_empty()).collect()
+}
+
+fn draw(layout: &ErLayout, theme: &Theme) -> Grid {
     let mut grid = Grid::new(layout.width, layout.height);
+    draw_on(&mut grid, layout, theme);
+    grid
+}
 
+/// Draw onto a canvas of at least `layout.width` by `layout.height` cells.
+pub fn draw_on(grid: &mut impl Canvas, layout: &ErLayout, theme: &Theme) {
     let node_map: HashMap<&str, &ErNodeLayout> = layout
         .nodes
         .iter()
@@ -62,108 +63,117 @@ pub fn render(layout: &ErLayout) -> String {
         .collect();
 
     for node in &layout.nodes {
-        draw_box(&mut grid, node);
+        draw_box(grid, theme, node);
     }
 
     for edge in &layout.edges {
         if let (Some(from), Some(to)) = (node_map.get(edge.from.as_str()), node_map.get(edge.to.as_str())) {
-            draw_er_edge(&mut grid, from, to, &edge.label, edge.left_card, edge.right_card);
+            if to.x > from.x {
+                draw_er_edge(grid, theme, edge, to, edge.left_card, edge.right_card);
+            } else if from.x > to.x {
+                // Relationship pointing back to an earlier rank: draw it mirrored
+                draw_er_edge(grid, theme, edge, from, edge.right_card, edge.left_card);
+            }
         }
     }
-
-    grid.render()
 }
 
-fn draw_box(grid: &mut Grid, node: &ErNodeLayout) {
+fn draw_box(grid: &mut impl Canvas, theme: &Theme, node: &ErNodeLayout) {
     let x = node.x;
     let y = node.y;
     let w = node.width;
 
-    grid.set(y, x, '┌');
-    for col in (x + 1)..(x + w - 1) {
-        grid.set(y, col, '─');
-    }
-    grid.set(y, x + w - 1, '┐');
-
-    grid.set(y + 1, x, '│');
+    grid.rect(x, y, w, node.height, theme);
     grid.write_str(y + 1, x + 2, &node.name);
-    grid.set(y + 1, x + w - 1, '│');
 
-    if node.attributes.is_empty() {
-        grid.set(y + 2, x, '└');
-        for col in (x + 1)..(x + w - 1) {
-            grid.set(y + 2, col, '─');
-        }
-        grid.set(y + 2, x + w - 1, '┘');
-    } else {
+    if !node.attributes.is_empty() {
         // Separator line
         let sep_y = y + 2;
-        grid.set(sep_y, x, '├');
-        for col in (x + 1)..(x + w - 1) {
-            grid.set(sep_y, col, '─');
-        }
-        grid.set(sep_y, x + w - 1, '┤');
+        grid.set(sep_y, x, theme.divider_left);
+        grid.hline(sep_y, x + 1, x + w - 1, theme.horizontal);
+        grid.set(sep_y, x + w - 1, theme.divider_right);
 
-        // Attribute rows
+        let columns = &node.columns;
         for (i, attr) in node.attributes.iter().enumerate() {
             let row = sep_y + 1 + i;
-            grid.set(row, x, '│');
-            let text = if let Some(ref key) = attr.key {
-                format!("{} {} {}", attr.attr_type, attr.name, key)
-            } else {
-                format!("{} {}", attr.attr_type, attr.name)
-            };
-            grid.write_str(row, x + 2, &text);
-            grid.set(row, x + w - 1, '│');
-        }
-
-        // Bottom border
-        let bottom_y = sep_y + 1 + node.attributes.len();
-        grid.set(bottom_y, x, '└');
-        for col in (x + 1)..(x + w - 1) {
-            grid.set(bottom_y, col, '─');
+            grid.write_str(row, x + 2, &attr.attr_type);
+            grid.write_str(row, x + 2 + columns.name_offset(), &attr.name);
+            if let Some(key) = &attr.key {
+                grid.write_str(row, x + 2 + columns.key_offset(), key);
+            }
         }
-        grid.set(bottom_y, x + w - 1, '┘');
     }
 }
 
 fn draw_er_edge(
-    grid: &mut Grid,
-    from: &ErNodeLayout,
+    grid: &mut impl Canvas,
+    theme: &Theme,
+    edge: &ErEdgeLayout,
     to: &ErNodeLayout,
-    label: &str,
     left_card: Cardinality,
     right_card: Cardinality,
 ) {
-    let from_right = from.x + from.width;
-    let to_left = to.x;
-    let row = from.center_y;
+    let points = &edge.points;
+    let (Some(&(from_border, from_row)), Some(&(to_left, to_row))) = (points.first(), points.last())
+    else {
+        return;
+    };
 
-    for col in from_right..to_left {
-        grid.set(row, col, '─');
+    // Merge so the line forms junctions with any it meets
+    for (i, pair) in points.windows(2).enumerate() {
+        let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+        if ay == by {
+            for col in ax.min(bx) + 1..ax.max(bx) {
+                grid.set_merge(ay, col, '─');
+            }
+        } else {
+            for row in ay.min(by) + 1..ay.max(by) {
+                grid.set_merge(row, ax, '│');
+            }
+        }
+        if let Some(&next) = points.get(i + 2) {
+            grid.set_merge(by, bx, corner(pair[0], pair[1], next));
+        }
+    }
+    grid.set(from_row, from_border, theme.connector_right);
+    if to_row > to.y && to_row + 1 < to.y + to.height {
+        grid.set(to_row, to_left, theme.connector_left);
     }
 
     let left_sym = left_cardinality_str(left_card);
-    grid.write_str(row, from_right, left_sym);
+    grid.write_str(from_row, from_border + 1, left_sym);
 
     let right_sym = right_cardinality_str(right_card);
     if to_left >= 2 {
-        grid.write_str(row, to_left - 2, right_sym);
+        grid.write_str(to_row, to_left - 2, right_sym);
     }
 
-    let gap = to_left - from_right;
-    let lines = split_br(label);
-    let max_w = multiline_width(label);
-    if gap > max_w {
-        let label_col = from_right + (gap - max_w) / 2;
-        let start_row = if lines.len() > 1 { row.saturating_sub(lines.len() / 2) } else { row };
-        for (i, line) in lines.iter().enumerate() {
+    if let Some((label_col, label_row)) = edge.label_at {
+        let max_w = multiline_width(&edge.label);
+        for (i, line) in split_br(&edge.label).iter().enumerate() {
             let line_col = label_col + (max_w - display_width(line)) / 2;
-            grid.write_str(start_row + i, line_col, line);
+            grid.write_str(label_row + i, line_col, line);
         }
     }
 }
 
+/// The corner at `at` of a connector coming from `from` and going on to
+/// `to`.
+fn corner(from: (usize, usize), at: (usize, usize), to: (usize, usize)) -> char {
+    let toward = |(x, y): (usize, usize)| match (x.cmp(&at.0), y.cmp(&at.1)) {
+        (Ordering::Less, _) => 'l',
+        (Ordering::Greater, _) => 'r',
+        (_, Ordering::Less) => 'u',
+        _ => 'd',
+    };
+    match (toward(from), toward(to)) {
+        ('l', 'd') | ('d', 'l') => '┐',
+        ('l', 'u') | ('u', 'l') => '┘',
+        ('r', 'd') | ('d', 'r') => '┌',
+        _ => '└',
+    }
+}
+
 fn left_cardinality_str(card: Cardinality) -> &'static str {
     match card {
         Cardinality::ExactlyOne => "||",
@@ -209,7 +219,7 @@ mod tests {
         let output = render(&layout);
         let expected = "\
 ┌───┐          ┌───┐
-│ A │||──r1──||│ B │
+│ A ├||──r1──||┤ B │
 └───┘          └───┘";
         assert_eq!(output, expected);
     }
@@ -263,4 +273,85 @@ mod tests {
         assert!(output.contains("places"), "should contain places label");
         assert!(output.contains("contains"), "should contain contains label");
     }
+
+    fn relationship(from: &str, to: &str, label: &str) -> Relationship {
+        Relationship {
+            from: from.into(),
+            to: to.into(),
+            left_card: Cardinality::ExactlyOne,
+            right_card: Cardinality::ZeroOrMany,
+            label: label.into(),
+        }
+    }
+
+    #[test]
+    fn render_connector_bends_to_an_entity_stacked_below() {
+        let attribute = |name: &str| EntityAttribute {
+            attr_type: "int".into(),
+            name: name.into(),
+            key: None,
+        };
+        let diagram = ErDiagram {
+            entities: vec![
+                entity("A"),
+                Entity { name: "B".into(), attributes: vec![attribute("x"), attribute("y")] },
+                entity("C"),
+            ],
+            relationships: vec![relationship("A", "B", "has"), relationship("A", "C", "owns")],
+        };
+        let layout = er_layout::compute(&diagram).unwrap();
+        let expected = "\
+┌───┐            ┌───────┐
+│ A ├||─┬has───o{┤ B     │
+└───┘   │        ├───────┤
+        │        │ int x │
+        │        │ int y │
+        │        └───────┘
+        │
+        │        ┌───┐
+        └─owns─o{┤ C │
+                 └───┘";
+        assert_eq!(render(&layout), expected);
+        assert!(dropped_labels(&layout).is_empty());
+    }
+
+    #[test]
+    fn render_connector_detours_around_entities_in_between() {
+        let diagram = ErDiagram {
+            entities: vec![entity("A"), entity("B"), entity("C")],
+            relationships: vec![
+                relationship("A", "B", "r1"),
+                relationship("B", "C", "r2"),
+                relationship("A", "C", "skips"),
+            ],
+        };
+        let layout = er_layout::compute(&diagram).unwrap();
+        let expected = "\
+┌───┐          ┌───┐          ┌───┐
+│ A ├||─┬r1──o{┤ B ├||──r2─┬o{┤ C │
+└───┘   │      └───┘       │  └───┘
+        └───────skips──────┘";
+        assert_eq!(render(&layout), expected);
+    }
+
+    #[test]
+    fn render_relationships_into_one_entity_keep_their_cardinalities() {
+        let mut contains = relationship("ORDER", "LINE", "contains");
+        contains.right_card = Cardinality::OneOrMany;
+        let diagram = ErDiagram {
+            entities: vec![entity("CUSTOMER"), entity("ORDER"), entity("LINE")],
+            relationships: vec![
+                relationship("CUSTOMER", "ORDER", "places"),
+                contains,
+                relationship("CUSTOMER", "LINE", "direct"),
+            ],
+        };
+        let layout = er_layout::compute(&diagram).unwrap();
+        let expected = "\
+┌──────────┐              ┌───────┐                ┌──────┐
+│ CUSTOMER ├||─┬places──o{┤ ORDER ├||──contains──|{┤ LINE │
+└──────────┘   │          └───────┘             ┌o{┤      │
+               └─────────────direct─────────────┘  └──────┘";
+        assert_eq!(render(&layout), expected);
+    }
 }
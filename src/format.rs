@@ -0,0 +1,515 @@
+//! Canonical Mermaid source for a parsed [`Document`], used by `ma fmt`.
+//!
+//! Statements are indented four spaces per block level. Runs of consecutive
+//! sequence messages and ER relationships are aligned on their arrows and
+//! colons, and ER attribute columns are aligned within each entity.
+
+use crate::Document;
+use crate::ast::*;
+use crate::display_width::display_width;
+use crate::er_ast::*;
+use crate::graph_ast::*;
+
+const INDENT: &str = "    ";
+
+/// Pretty-print `document` as Mermaid source ending in a newline. A custom
+/// document, whose syntax only its handler knows, comes out empty.
+pub fn format_document(document: &Document) -> String {
+    let mut out = String::new();
+    match document {
+        Document::Sequence(diagram) => {
+            out.push_str("sequenceDiagram\n");
+            if let Some(title) = &diagram.title {
+                push_line(&mut out, 1, &format!("title {title}"));
+            }
+            format_statements(&mut out, &diagram.statements, 1);
+        }
+        Document::Graph(diagram) => format_graph(&mut out, diagram),
+        Document::Er(diagram) => format_er(&mut out, diagram),
+        Document::Custom(_) => {}
+    }
+    out
+}
+
+fn push_line(out: &mut String, depth: usize, line: &str) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+/// `text` followed by spaces up to `width` display columns.
+fn pad(text: &str, width: usize) -> String {
+    let fill = width.saturating_sub(display_width(text));
+    format!("{text}{}", " ".repeat(fill))
+}
+
+fn format_statements(out: &mut String, statements: &[Statement], depth: usize) {
+    let mut i = 0;
+    while i < statements.len() {
+        let run = statements[i..]
+            .iter()
+            .take_while(|s| matches!(s, Statement::Message(_)))
+            .count();
+        if run > 0 {
+            let messages: Vec<&Message> = statements[i..i + run]
+                .iter()
+                .filter_map(|s| match s {
+                    Statement::Message(m) => Some(m),
+                    _ => None,
+                })
+                .collect();
+            format_messages(out, &messages, depth);
+            i += run;
+        } else {
+            format_statement(out, &statements[i], depth);
+            i += 1;
+        }
+    }
+}
+
+/// Messages aligned on their arrows and colons.
+fn format_messages(out: &mut String, messages: &[&Message], depth: usize) {
+    let arrows: Vec<String> = messages.iter().map(|m| message_arrow(m)).collect();
+    let froms: Vec<String> = messages.iter().map(|m| participant_ref(&m.from)).collect();
+    let tos: Vec<String> = messages.iter().map(|m| participant_ref(&m.to)).collect();
+    let from_width = froms.iter().map(|s| display_width(s)).max().unwrap_or(0);
+    let arrow_width = arrows.iter().map(|a| a.len()).max().unwrap_or(0);
+    let to_width = tos.iter().map(|s| display_width(s)).max().unwrap_or(0);
+    for (i, m) in messages.iter().enumerate() {
+        let line = format!(
+            "{} {} {}: {}",
+            pad(&froms[i], from_width),
+            pad(&arrows[i], arrow_width),
+            pad(&tos[i], to_width),
+            m.text
+        );
+        push_line(out, depth, &line);
+    }
+}
+
+fn message_arrow(m: &Message) -> String {
+    let line = match m.arrow.line_style {
+        LineStyle::Solid => "-",
+        LineStyle::Dotted => "--",
+    };
+    let head = match m.arrow.head {
+        ArrowHead::Arrowhead => ">>",
+        ArrowHead::None => ">",
+        ArrowHead::Cross => "x",
+        ArrowHead::Open => ")",
+    };
+    let modifier = if m.activate_target {
+        "+"
+    } else if m.deactivate_source {
+        "-"
+    } else {
+        ""
+    };
+    format!("{line}{head}{modifier}")
+}
+
+fn format_statement(out: &mut String, statement: &Statement, depth: usize) {
+    match statement {
+        Statement::ParticipantDecl(decl) => push_line(out, depth, &participant(decl)),
+        Statement::Create(decl) => push_line(out, depth, &format!("create {}", participant(decl))),
+        Statement::Message(m) => format_messages(out, &[m], depth),
+        Statement::Note(note) => {
+            let placement = match &note.placement {
+                NotePlacement::RightOf(id) => format!("right of {}", participant_ref(id)),
+                NotePlacement::LeftOf(id) => format!("left of {}", participant_ref(id)),
+                NotePlacement::Over(id) => format!("over {}", participant_ref(id)),
+                NotePlacement::OverTwo(a, b) => {
+                    format!("over {},{}", participant_ref(a), participant_ref(b))
+                }
+            };
+            push_line(out, depth, &format!("Note {placement}: {}", note.text));
+        }
+        Statement::Activate(id) => {
+            push_line(out, depth, &format!("activate {}", participant_ref(id)))
+        }
+        Statement::Deactivate(id) => {
+            push_line(out, depth, &format!("deactivate {}", participant_ref(id)))
+        }
+        Statement::Destroy(id) => {
+            push_line(out, depth, &format!("destroy {}", participant_ref(id)))
+        }
+        Statement::AutoNumber => push_line(out, depth, "autonumber"),
+        Statement::Loop(block) => format_block(out, "loop", block, depth),
+        Statement::Opt(block) => format_block(out, "opt", block, depth),
+        Statement::Break(block) => format_block(out, "break", block, depth),
+        Statement::Rect(block) => format_block(out, "rect", block, depth),
+        Statement::Alt(block) => format_divided_block(out, ("alt", "else"), block, depth),
+        Statement::Par(block) => format_divided_block(out, ("par", "and"), block, depth),
+        Statement::Critical(block) => {
+            format_divided_block(out, ("critical", "option"), block, depth)
+        }
+    }
+}
+
+fn participant(decl: &ParticipantDecl) -> String {
+    let id = participant_ref(&decl.id);
+    match &decl.alias {
+        // a quoted id can't be followed by a free-text alias
+        Some(alias) if id != decl.id => format!("participant \"{alias}\" as {id}"),
+        Some(alias) => format!("participant {id} as {alias}"),
+        None => format!("participant {id}"),
+    }
+}
+
+/// A participant id as the sequence parser reads it back: bare if it's an
+/// identifier (which may continue with `-` and `.`), quoted otherwise.
+fn participant_ref(id: &str) -> String {
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    if id.starts_with(word) && id.chars().all(|c| word(c) || matches!(c, '-' | '.')) {
+        id.to_string()
+    } else {
+        format!("\"{id}\"")
+    }
+}
+
+fn format_block(out: &mut String, keyword: &str, block: &LoopBlock, depth: usize) {
+    push_line(out, depth, &format!("{keyword} {}", block.label));
+    format_statements(out, &block.body, depth + 1);
+    push_line(out, depth, "end");
+}
+
+fn format_divided_block(
+    out: &mut String,
+    (keyword, divider): (&str, &str),
+    block: &AltBlock,
+    depth: usize,
+) {
+    push_line(out, depth, &format!("{keyword} {}", block.label));
+    format_statements(out, &block.body, depth + 1);
+    for branch in &block.else_branches {
+        push_line(out, depth, &format!("{divider} {}", branch.label));
+        format_statements(out, &branch.body, depth + 1);
+    }
+    push_line(out, depth, "end");
+}
+
+/// Decide how a link between items `from` and `to` declares them, given
+/// which items (by position) are already declared.
+///
+/// Items are ordered by first mention, and a link mentions `from` before
+/// `to`. Undeclared items that must precede the link's endpoints are passed
+/// to `declare` to be written on their own lines. Returns whether `from`
+/// and `to` should carry their declaration inline.
+fn declare_endpoints(
+    declared: &mut [bool],
+    from: usize,
+    to: usize,
+    mut declare: impl FnMut(usize),
+) -> (bool, bool) {
+    let mut endpoints: Vec<usize> = [from, to].into_iter().filter(|&i| !declared[i]).collect();
+    endpoints.dedup();
+    let Some(&last) = endpoints.iter().max() else {
+        return (false, false);
+    };
+    let pending: Vec<usize> = (0..=last).filter(|&i| !declared[i]).collect();
+    // Prefer declaring both endpoints inline, then either one
+    let candidates = [&endpoints[..], &endpoints[..1], &endpoints[endpoints.len() - 1..]];
+    let inline = candidates
+        .into_iter()
+        .find(|c| pending.ends_with(c))
+        .unwrap_or_default();
+    for &i in &pending[..pending.len() - inline.len()] {
+        declare(i);
+    }
+    for &i in &pending {
+        declared[i] = true;
+    }
+    (inline.contains(&from), to != from && inline.contains(&to))
+}
+
+fn format_graph(out: &mut String, diagram: &GraphDiagram) {
+    let direction = match diagram.direction {
+        Direction::TopDown => "TD",
+        Direction::LeftRight => "LR",
+    };
+    out.push_str(&format!("graph {direction}\n"));
+
+    let index = |id: &str| diagram.nodes.iter().position(|n| n.id == id);
+    let mut declared = vec![false; diagram.nodes.len()];
+    for edge in &diagram.edges {
+        let (Some(from), Some(to)) = (index(&edge.from), index(&edge.to)) else {
+            continue;
+        };
+        let (inline_from, inline_to) = declare_endpoints(&mut declared, from, to, |i| {
+            push_line(out, 1, &node(&diagram.nodes[i]));
+        });
+        let endpoint = |i: usize, inline: bool| {
+            let decl = &diagram.nodes[i];
+            if inline { node(decl) } else { decl.id.clone() }
+        };
+        let mut line = format!(
+            "{} {} {}",
+            endpoint(from, inline_from),
+            edge_link(edge),
+            endpoint(to, inline_to)
+        );
+        if let Some(comment) = &edge.comment {
+            line.push_str(&format!(" %% {comment}"));
+        }
+        push_line(out, 1, &line);
+    }
+    for (decl, _) in diagram.nodes.iter().zip(&declared).filter(|(_, d)| !**d) {
+        push_line(out, 1, &node(decl));
+    }
+    // After the first declarations, so they stay the ones drawn
+    for decl in &diagram.redeclared {
+        push_line(out, 1, &node(decl));
+    }
+    for decl in &diagram.nodes {
+        for line in annotations(decl) {
+            push_line(out, 1, &line);
+        }
+    }
+
+    for subgraph in &diagram.subgraphs {
+        let (id, label) = (&subgraph.id, &subgraph.label);
+        if *id == label.replace(' ', "_").to_lowercase() {
+            push_line(out, 1, &format!("subgraph {label}"));
+        } else {
+            push_line(out, 1, &format!("subgraph {id} [{label}]"));
+        }
+        for id in &subgraph.node_ids {
+            push_line(out, 2, id);
+        }
+        push_line(out, 1, "end");
+    }
+}
+
+/// `class`, `style` and `click` lines for the node metadata Mermaid can
+/// express. Other keys, and a tooltip without a link or callback, are lost.
+fn annotations(decl: &NodeDecl) -> Vec<String> {
+    let id = &decl.id;
+    let meta = |key: &str| decl.metadata.get(key);
+    let mut lines: Vec<String> = meta("class")
+        .into_iter()
+        .flat_map(|classes| classes.split(' '))
+        .map(|class| format!("class {id} {class}"))
+        .collect();
+    lines.extend(meta("style").map(|style| format!("style {id} {style}")));
+    let action = match (meta("link"), meta("callback")) {
+        (Some(link), _) => format!("href \"{link}\""),
+        (None, Some(callback)) => callback.clone(),
+        (None, None) => return lines,
+    };
+    let mut click = format!("click {id} {action}");
+    if let Some(tooltip) = meta("tooltip") {
+        click.push_str(&format!(" \"{tooltip}\""));
+    }
+    if let Some(target) = meta("target") {
+        click.push_str(&format!(" {target}"));
+    }
+    lines.push(click);
+    lines
+}
+
+/// A node reference carrying its shape and label, or the bare id when that
+/// is what the label defaults to.
+pub(crate) fn node(decl: &NodeDecl) -> String {
+    let (open, close) = match decl.shape {
+        NodeShape::Box if decl.label == decl.id => return decl.id.clone(),
+        NodeShape::Box => ("[", "]"),
+        NodeShape::Round => ("(", ")"),
+        NodeShape::Diamond => ("{", "}"),
+        NodeShape::Circle => ("((", "))"),
+    };
+    let needs_quotes = decl.shape != NodeShape::Circle
+        && (decl.label.contains(close) || decl.label.starts_with('"'));
+    if needs_quotes {
+        format!("{}{open}\"{}\"{close}", decl.id, decl.label)
+    } else {
+        format!("{}{open}{}{close}", decl.id, decl.label)
+    }
+}
+
+fn edge_link(edge: &Edge) -> String {
+    let link = match edge.edge_type {
+        EdgeType::Arrow => "-->",
+        EdgeType::OpenLink => "---",
+        EdgeType::DottedArrow => "-.->",
+        EdgeType::DottedLink => "-.-",
+        EdgeType::ThickArrow => "==>",
+        EdgeType::ThickLink => "===",
+    };
+    match &edge.label {
+        Some(label) => format!("{link}|{}|", edge_label(label)),
+        None => link.to_string(),
+    }
+}
+
+/// An edge label with the characters that would end or re-quote it
+/// written as entities.
+fn edge_label(label: &str) -> String {
+    let label = label.replace('|', "&#124;");
+    match label.strip_prefix('"') {
+        Some(rest) => format!("&quot;{rest}"),
+        None => label,
+    }
+}
+
+fn format_er(out: &mut String, diagram: &ErDiagram) {
+    out.push_str("erDiagram\n");
+
+    let index = |name: &str| diagram.entities.iter().position(|e| e.name == name);
+    let mut declared = vec![false; diagram.entities.len()];
+    // Entities whose attribute block has been written
+    let mut written = vec![false; diagram.entities.len()];
+    let mut run: Vec<&Relationship> = Vec::new();
+    for rel in &diagram.relationships {
+        let (Some(from), Some(to)) = (index(&rel.from), index(&rel.to)) else {
+            continue;
+        };
+        declare_endpoints(&mut declared, from, to, |i| {
+            format_relationships(out, &std::mem::take(&mut run));
+            format_entity(out, &diagram.entities[i]);
+            written[i] = true;
+        });
+        run.push(rel);
+    }
+    format_relationships(out, &run);
+
+    // Entities nothing declared yet all follow the declared ones, so one pass
+    // in order keeps the entity order
+    for (i, entity) in diagram.entities.iter().enumerate() {
+        if !written[i] && (!entity.attributes.is_empty() || !declared[i]) {
+            format_entity(out, entity);
+        }
+    }
+}
+
+/// Relationships aligned on their cardinalities and colons.
+fn format_relationships(out: &mut String, relationships: &[&Relationship]) {
+    let from_width = relationships.iter().map(|r| display_width(&r.from)).max().unwrap_or(0);
+    let to_width = relationships.iter().map(|r| display_width(&r.to)).max().unwrap_or(0);
+    for rel in relationships {
+        let left = match rel.left_card {
+            Cardinality::ExactlyOne => "||",
+            Cardinality::ZeroOrOne => "o|",
+            Cardinality::OneOrMany => "}|",
+            Cardinality::ZeroOrMany => "}o",
+        };
+        let right = match rel.right_card {
+            Cardinality::ExactlyOne => "||",
+            Cardinality::ZeroOrOne => "|o",
+            Cardinality::OneOrMany => "|{",
+            Cardinality::ZeroOrMany => "o{",
+        };
+        let line = format!(
+            "{} {left}--{right} {} : {}",
+            pad(&rel.from, from_width),
+            pad(&rel.to, to_width),
+            rel.label
+        );
+        push_line(out, 1, &line);
+    }
+}
+
+/// An entity's attribute block with its type, name and key columns aligned.
+fn format_entity(out: &mut String, entity: &Entity) {
+    push_line(out, 1, &format!("{} {{", entity.name));
+    let type_width = entity.attributes.iter().map(|a| display_width(&a.attr_type)).max();
+    let name_width = entity.attributes.iter().map(|a| display_width(&a.name)).max();
+    for attr in &entity.attributes {
+        let line = format!(
+            "{} {} {}",
+            pad(&attr.attr_type, type_width.unwrap_or(0)),
+            pad(&attr.name, name_width.unwrap_or(0)),
+            attr.key.as_deref().unwrap_or("")
+        );
+        push_line(out, 2, &line);
+    }
+    push_line(out, 1, "}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use pretty_assertions::assert_eq;
+
+    fn fmt(input: &str) -> String {
+        format_document(&parse(input).unwrap())
+    }
+
+    #[test]
+    fn sequence_messages_align_on_arrows_and_colons() {
+        let input = "sequenceDiagram\nAlice->>John: Hello\n  John-->>+Alice :Hi\nloop Every minute\nJohn-)Alice: Ping\nend\n";
+        assert_eq!(
+            fmt(input),
+            "sequenceDiagram\n    Alice ->>   John : Hello\n    John  -->>+ Alice: Hi\n    loop Every minute\n        John -) Alice: Ping\n    end\n"
+        );
+    }
+
+    #[test]
+    fn sequence_divided_blocks_and_notes() {
+        let input = "sequenceDiagram\nparticipant A as Alice\nalt ok\nNote over A,B: n\nelse\nA->>B:\nend\n";
+        assert_eq!(
+            fmt(input),
+            "sequenceDiagram\n    participant A as Alice\n    alt ok\n        Note over A,B: n\n    else\n        A ->> B:\n    end\n"
+        );
+    }
+
+    #[test]
+    fn graph_declares_nodes_inline_at_first_use() {
+        let input = "graph LR\nA[Start]-->B{Ok?}\nB-->|yes| C(\"Done (ok)\")\nB --> A\n";
+        assert_eq!(
+            fmt(input),
+            "graph LR\n    A[Start] --> B{Ok?}\n    B -->|yes| C(\"Done (ok)\")\n    B --> A\n"
+        );
+    }
+
+    #[test]
+    fn graph_keeps_node_order_and_subgraphs() {
+        let input = "graph TD\nsubgraph Group One\nX\nend\nA --> X\nY((y))\n";
+        assert_eq!(
+            fmt(input),
+            "graph TD\n    X\n    A --> X\n    Y((y))\n    subgraph Group One\n        X\n    end\n"
+        );
+    }
+
+    #[test]
+    fn er_aligns_relationships_and_attributes() {
+        let input = "erDiagram\nCUSTOMER ||--o{ ORDER : places\nORDER ||--|{ LINE : contains\nCUSTOMER {\nstring name\nint id PK\n}\n";
+        assert_eq!(
+            fmt(input),
+            "erDiagram\n    CUSTOMER ||--o{ ORDER : places\n    ORDER    ||--|{ LINE  : contains\n    CUSTOMER {\n        string name\n        int    id   PK\n    }\n"
+        );
+    }
+
+    #[test]
+    fn er_declares_entities_that_precede_their_relationships() {
+        let input = "erDiagram\nB {\nint id\n}\nA ||--|| B : r\n";
+        assert_eq!(
+            fmt(input),
+            "erDiagram\n    B {\n        int id\n    }\n    A ||--|| B : r\n"
+        );
+    }
+
+    #[test]
+    fn graph_node_metadata_becomes_class_style_and_click_lines() {
+        let input = "graph TD\nA:::warn\nstyle A fill:#f96\nclick A call notify() \"Tip\"\n";
+        assert_eq!(
+            fmt(input),
+            "graph TD\n    A\n    class A warn\n    style A fill:#f96\n    click A notify() \"Tip\"\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for input in [
+            "sequenceDiagram\nA->>B: hi\ncritical c\nB-xA: no\noption o\nB--)A: yes\nend\n",
+            "graph TD\nA --> B & C\nC -.->|x| A\n",
+            "erDiagram\nA }|--o| B : r\n",
+        ] {
+            let once = fmt(input);
+            assert_eq!(fmt(&once), once);
+            assert_eq!(parse(&once).unwrap(), parse(input).unwrap());
+        }
+    }
+}
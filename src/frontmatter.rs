@@ -0,0 +1,62 @@
+//! YAML frontmatter before a Mermaid diagram's header:
+//!
+//! ```text
+//! ---
+//! title: Checkout
+//! ---
+//! sequenceDiagram
+//! ```
+//!
+//! Only a top-level `title` is read. Other keys, such as `config`, are
+//! ignored.
+
+use std::borrow::Cow;
+
+/// The frontmatter's `title`, and `input` with the frontmatter lines
+/// blanked so line numbers in errors still match the source. Input without
+/// a closed frontmatter block comes back unchanged.
+pub(crate) fn strip(input: &str) -> (Option<String>, Cow<'_, str>) {
+    let lines: Vec<&str> = input.split('\n').collect();
+    let Some(open) = lines.iter().position(|line| !line.trim().is_empty()) else {
+        return (None, Cow::Borrowed(input));
+    };
+    let is_fence = |line: &&str| line.trim_end() == "---";
+    if !is_fence(&lines[open]) {
+        return (None, Cow::Borrowed(input));
+    }
+    let Some(close) = lines[open + 1..].iter().position(is_fence).map(|i| open + 1 + i) else {
+        return (None, Cow::Borrowed(input));
+    };
+
+    let title = lines[open + 1..close].iter().find_map(|line| {
+        let value = line.strip_prefix("title:")?.trim();
+        let unquoted = ['"', '\'']
+            .iter()
+            .find_map(|&q| value.strip_prefix(q).and_then(|v| v.strip_suffix(q)));
+        Some(unquoted.unwrap_or(value).to_string())
+    });
+    let body = lines[close + 1..].join("\n");
+    let blanked = "\n".repeat(close + 1) + &body;
+    (title.filter(|title| !title.is_empty()), Cow::Owned(blanked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn strip_reads_the_title_and_keeps_line_numbers() {
+        let input = "---\ntitle: \"Checkout\"\nconfig:\n  title: nested\n---\nsequenceDiagram\n";
+        let (title, body) = strip(input);
+        assert_eq!(title.as_deref(), Some("Checkout"));
+        assert_eq!(body, "\n\n\n\n\nsequenceDiagram\n");
+    }
+
+    #[test]
+    fn strip_leaves_input_without_closed_frontmatter_alone() {
+        for input in ["sequenceDiagram\n", "---\ntitle: open\nsequenceDiagram\n", ""] {
+            assert_eq!(strip(input), (None, Cow::Borrowed(input)));
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Direction {
     TopDown,
@@ -10,6 +12,23 @@ pub struct GraphDiagram {
     pub nodes: Vec<NodeDecl>,
     pub edges: Vec<Edge>,
     pub subgraphs: Vec<Subgraph>,
+    /// Later declarations that give an already shaped or labelled node a
+    /// different shape or label, in source order. The drawing keeps the
+    /// first unless they are applied with [`apply_redeclared`](Self::apply_redeclared).
+    pub redeclared: Vec<NodeDecl>,
+}
+
+impl GraphDiagram {
+    /// Give each node the shape and label of its last declaration, as
+    /// Mermaid does, instead of its first.
+    pub fn apply_redeclared(&mut self) {
+        for decl in std::mem::take(&mut self.redeclared) {
+            if let Some(node) = self.nodes.iter_mut().find(|n| n.id == decl.id) {
+                node.shape = decl.shape;
+                node.label = decl.label;
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,6 +51,9 @@ pub struct NodeDecl {
     pub id: String,
     pub label: String,
     pub shape: NodeShape,
+    /// Attributes that don't change the drawing, such as `tooltip`, `link`,
+    /// `class` or `style`, kept for tools built on the layout.
+    pub metadata: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -40,6 +62,9 @@ pub struct Edge {
     pub to: String,
     pub edge_type: EdgeType,
     pub label: Option<String>,
+    /// A trailing `%%` comment on the edge's line, such as a weight, kept
+    /// for tools built on the AST. The drawing ignores it.
+    pub comment: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -1,6 +1,9 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::display_width::{display_width, line_count, multiline_width};
+use crate::display_width::{
+    display_width, line_count, multiline_width, split_br, truncate_to_display_width,
+    wrap_to_display_width,
+};
 use crate::graph_ast::*;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -11,6 +14,43 @@ pub struct GraphLayout {
     pub width: usize,
     pub height: usize,
     pub direction: Direction,
+    pub adjacency: Adjacency,
+}
+
+/// Non-self-loop edges grouped by endpoint, as indices into
+/// [`GraphLayout::nodes`]. A node appears once per edge, so parallel edges
+/// are counted separately.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Adjacency {
+    children: HashMap<String, Vec<usize>>,
+    parents: HashMap<String, Vec<usize>>,
+}
+
+impl Adjacency {
+    pub fn new(nodes: &[NodeLayout], edges: &[EdgeLayout]) -> Self {
+        let index: HashMap<&str, usize> =
+            nodes.iter().enumerate().map(|(i, n)| (n.id.as_str(), i)).collect();
+        let mut adjacency = Adjacency::default();
+        for edge in edges.iter().filter(|e| e.from_id != e.to_id) {
+            if let Some(&to) = index.get(edge.to_id.as_str()) {
+                adjacency.children.entry(edge.from_id.clone()).or_default().push(to);
+            }
+            if let Some(&from) = index.get(edge.from_id.as_str()) {
+                adjacency.parents.entry(edge.to_id.clone()).or_default().push(from);
+            }
+        }
+        adjacency
+    }
+
+    /// Targets of the edges leaving `id`.
+    pub fn children(&self, id: &str) -> &[usize] {
+        self.children.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Sources of the edges entering `id`.
+    pub fn parents(&self, id: &str) -> &[usize] {
+        self.parents.get(id).map_or(&[], Vec::as_slice)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -33,6 +73,8 @@ pub struct NodeLayout {
     pub height: usize,
     pub center_x: usize,
     pub center_y: usize,
+    /// The node's [`NodeDecl::metadata`], which the renderer ignores.
+    pub metadata: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -45,28 +87,77 @@ pub struct EdgeLayout {
 
 const SUBGRAPH_GAP: usize = 3;
 
+/// The horizontal gaps a graph is laid out with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Spacing {
+    /// Between neighbouring nodes of a TD rank, and between the parts of a
+    /// TD graph placed side by side.
+    node_gap: usize,
+    /// Between LR ranks; a gap an edge label crosses is never narrower than
+    /// the label, or twice it where one node has several labelled edges
+    /// across.
+    lr_gap: usize,
+    /// Between subgraphs.
+    subgraph_gap: usize,
+}
+
+impl Spacing {
+    const DEFAULT: Spacing =
+        Spacing { node_gap: TD_NODE_GAP, lr_gap: LR_GAP, subgraph_gap: SUBGRAPH_GAP };
+
+    /// Every gap a column narrower, stopping at its minimum, or `None` once
+    /// all of them are there.
+    fn narrower(self) -> Option<Spacing> {
+        let narrower = Spacing {
+            node_gap: self.node_gap.saturating_sub(1),
+            lr_gap: self.lr_gap.saturating_sub(1).max(1),
+            subgraph_gap: self.subgraph_gap.saturating_sub(1).max(1),
+        };
+        (narrower != self).then_some(narrower)
+    }
+}
+
 pub fn compute(diagram: &GraphDiagram) -> Result<GraphLayout, String> {
+    compute_within(diagram, Spacing::DEFAULT, None, None)
+}
+
+/// Fail on an edge between nodes the diagram doesn't declare. Parsed
+/// diagrams declare every node they mention, but ones built by hand may not.
+fn check_edge_nodes(diagram: &GraphDiagram) -> Result<(), String> {
+    let ids: HashSet<&str> = diagram.nodes.iter().map(|n| n.id.as_str()).collect();
+    for edge in &diagram.edges {
+        for id in [&edge.from, &edge.to] {
+            if !ids.contains(id.as_str()) {
+                return Err(format!(
+                    "unknown node '{id}' referenced by edge {} -> {}",
+                    edge.from, edge.to
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The layout with `spacing`, subgraph titles cut to `title_cap` (see
+/// [`layout_with_subgraphs`]) and disconnected parts wrapped to fit
+/// `max_width`, when given.
+fn compute_within(
+    diagram: &GraphDiagram,
+    spacing: Spacing,
+    title_cap: Option<usize>,
+    max_width: Option<usize>,
+) -> Result<GraphLayout, String> {
     if diagram.nodes.is_empty() {
         return Err("no nodes found".to_string());
     }
+    check_edge_nodes(diagram)?;
 
     if !diagram.subgraphs.is_empty() {
-        return layout_with_subgraphs(diagram);
+        return layout_with_subgraphs(diagram, spacing, title_cap, max_width);
     }
 
-    let ranks = assign_ranks(diagram);
-    let max_rank = *ranks.values().max().unwrap_or(&0);
-
-    let mut ranks_nodes: Vec<Vec<&NodeDecl>> = vec![Vec::new(); max_rank + 1];
-    for node in &diagram.nodes {
-        let rank = ranks[&node.id];
-        ranks_nodes[rank].push(node);
-    }
-
-    let mut node_layouts = match diagram.direction {
-        Direction::TopDown => layout_td(&ranks_nodes),
-        Direction::LeftRight => layout_lr(&ranks_nodes, &ranks, &diagram.edges),
-    };
+    let mut node_layouts =
+        layout_components(diagram, spacing.node_gap, spacing.lr_gap, max_width);
 
     let edges: Vec<EdgeLayout> = diagram
         .edges
@@ -88,37 +179,19 @@ pub fn compute(diagram: &GraphDiagram) -> Result<GraphLayout, String> {
         height = height.max(sg.y + sg.height);
     }
 
-    // Self-loop nodes need extra space: arm (2 cols) + label width to the right,
-    // and 1 row below the node for the return arrow
-    for edge in &diagram.edges {
-        if edge.from == edge.to {
-            if let Some(nl) = node_layouts.iter().find(|n| n.id == edge.from) {
-                let label_w = edge
-                    .label
-                    .as_ref()
-                    .map(|l| display_width(l))
-                    .unwrap_or(0);
-                let needed_right = nl.x + nl.width + 2 + label_w;
-                width = width.max(needed_right);
-                let needed_bottom = nl.y + nl.height + 1;
-                height = height.max(needed_bottom);
-            }
-        }
+    for nl in &node_layouts {
+        let (right, bottom) = extent_with_self_loops(nl, &diagram.edges);
+        width = width.max(right);
+        height = height.max(bottom);
     }
 
     // Cross-rank fan-in edges: reserve gutter column width.
+    let adjacency = Adjacency::new(&node_layouts, &edges);
     let max_right = node_layouts.iter().map(|n| n.x + n.width).max().unwrap_or(0);
-    let has_cross_rank_fan_in = diagram.edges.iter().any(|edge| {
-        if edge.from == edge.to {
-            return false;
-        }
-        let parents: Vec<&NodeLayout> = diagram
-            .edges
-            .iter()
-            .filter(|e| e.to == edge.to && e.from != e.to)
-            .filter_map(|e| node_layouts.iter().find(|n| n.id == e.from))
-            .collect();
-        parents.len() > 1 && !parents.windows(2).all(|w| w[0].y == w[1].y)
+    let has_cross_rank_fan_in = node_layouts.iter().any(|node| {
+        let parents = adjacency.parents(&node.id);
+        parents.len() > 1
+            && !parents.windows(2).all(|w| node_layouts[w[0]].y == node_layouts[w[1]].y)
     });
     if has_cross_rank_fan_in {
         width = width.max(max_right + 2);
@@ -131,10 +204,81 @@ pub fn compute(diagram: &GraphDiagram) -> Result<GraphLayout, String> {
         width,
         height,
         direction: diagram.direction.clone(),
+        adjacency,
     })
 }
 
-fn layout_with_subgraphs(diagram: &GraphDiagram) -> Result<GraphLayout, String> {
+/// A subgraph, or nodes outside any subgraph, laid out on its own with its
+/// top-left corner at the origin, ready to be placed.
+struct Block {
+    nodes: Vec<NodeLayout>,
+    frame: Option<SubgraphLayout>,
+    width: usize,
+    height: usize,
+}
+
+impl Block {
+    fn new(nodes: Vec<NodeLayout>, frame: Option<SubgraphLayout>) -> Block {
+        let mut width = nodes.iter().map(|n| n.x + n.width).max().unwrap_or(0);
+        let mut height = nodes.iter().map(|n| n.y + n.height).max().unwrap_or(0);
+        if let Some(frame) = &frame {
+            width = width.max(frame.width);
+            height = height.max(frame.height);
+        }
+        Block { nodes, frame, width, height }
+    }
+
+    fn move_to(&mut self, x: usize, y: usize) {
+        for node in &mut self.nodes {
+            node.x += x;
+            node.y += y;
+            node.center_x += x;
+            node.center_y += y;
+        }
+        if let Some(frame) = &mut self.frame {
+            frame.x += x;
+            frame.y += y;
+        }
+    }
+
+    /// One more row between a subgraph's top border and its contents.
+    fn pad_top(&mut self) {
+        if let Some(frame) = &mut self.frame {
+            frame.height += 1;
+            self.height = self.height.max(frame.height);
+            for node in &mut self.nodes {
+                node.y += 1;
+                node.center_y += 1;
+            }
+        }
+    }
+}
+
+/// Each subgraph laid out on its own, followed by the nodes outside any
+/// subgraph, the blocks one beside the other `subgraph_gap` apart. In TD
+/// graphs they wrap onto a new row past `max_width`, see [`stack_blocks`].
+/// Titles wider than `title_cap` are cut short, though never below the width
+/// of their subgraph's contents.
+/// One past `node`'s right column and bottom row, taking in the loop of any
+/// edge from it to itself: the arm (2 columns) and label to the right, and
+/// the return arrow on the row below.
+fn extent_with_self_loops(node: &NodeLayout, edges: &[Edge]) -> (usize, usize) {
+    let (mut right, mut bottom) = (node.x + node.width, node.y + node.height);
+    for edge in edges.iter().filter(|e| e.from == node.id && e.to == node.id) {
+        let label_w = edge.label.as_ref().map(|l| display_width(l)).unwrap_or(0);
+        right = right.max(node.x + node.width + 2 + label_w);
+        bottom = bottom.max(node.y + node.height + 1);
+    }
+    (right, bottom)
+}
+
+fn layout_with_subgraphs(
+    diagram: &GraphDiagram,
+    spacing: Spacing,
+    title_cap: Option<usize>,
+    max_width: Option<usize>,
+) -> Result<GraphLayout, String> {
+    let Spacing { node_gap, lr_gap, subgraph_gap } = spacing;
     let node_to_subgraph: HashMap<String, usize> = diagram
         .subgraphs
         .iter()
@@ -142,130 +286,120 @@ fn layout_with_subgraphs(diagram: &GraphDiagram) -> Result<GraphLayout, String>
         .flat_map(|(i, sg)| sg.node_ids.iter().map(move |id| (id.clone(), i)))
         .collect();
 
-    // Build mini-diagrams for each subgraph
-    let mut sg_groups: Vec<GraphDiagram> = Vec::new();
-    for sg in &diagram.subgraphs {
-        let nodes: Vec<NodeDecl> = diagram
-            .nodes
-            .iter()
-            .filter(|n| sg.node_ids.contains(&n.id))
-            .cloned()
-            .collect();
-        let edges: Vec<Edge> = diagram
-            .edges
-            .iter()
-            .filter(|e| sg.node_ids.contains(&e.from) && sg.node_ids.contains(&e.to))
-            .cloned()
-            .collect();
-        sg_groups.push(GraphDiagram {
-            direction: diagram.direction.clone(),
-            nodes,
-            edges,
-            subgraphs: vec![],
-        });
-    }
-
-    // Collect bare nodes (not in any subgraph)
-    let bare_nodes: Vec<&NodeDecl> = diagram
-        .nodes
-        .iter()
-        .filter(|n| !node_to_subgraph.contains_key(&n.id))
-        .collect();
-    let bare_edges: Vec<&Edge> = diagram
-        .edges
-        .iter()
-        .filter(|e| {
-            !node_to_subgraph.contains_key(&e.from) && !node_to_subgraph.contains_key(&e.to)
-        })
-        .collect();
-
-    // Layout each subgraph independently
-    let mut all_nodes: Vec<NodeLayout> = Vec::new();
-    let mut sg_layouts: Vec<SubgraphLayout> = Vec::new();
-    let mut x_offset: usize = 0;
-
-    for (i, sg_diagram) in sg_groups.iter().enumerate() {
-        if sg_diagram.nodes.is_empty() {
-            continue;
-        }
-
-        let ranks = assign_ranks(sg_diagram);
+    let layout_nodes = |part: &GraphDiagram| {
+        let ranks = assign_ranks(part);
         let max_rank = *ranks.values().max().unwrap_or(&0);
         let mut ranks_nodes: Vec<Vec<&NodeDecl>> = vec![Vec::new(); max_rank + 1];
-        for node in &sg_diagram.nodes {
-            let rank = ranks[&node.id];
-            ranks_nodes[rank].push(node);
+        for node in &part.nodes {
+            ranks_nodes[ranks[&node.id]].push(node);
+        }
+        match diagram.direction {
+            Direction::TopDown => {
+                layout_td_with_gap(&ranks_nodes, &ranks, &part.edges, node_gap)
+            }
+            Direction::LeftRight => {
+                layout_lr_with_gap(&ranks_nodes, &ranks, &part.edges, lr_gap)
+            }
         }
+    };
 
-        let mut node_layouts = match diagram.direction {
-            Direction::TopDown => layout_td(&ranks_nodes),
-            Direction::LeftRight => layout_lr(&ranks_nodes, &ranks, &sg_diagram.edges),
+    let mut blocks: Vec<Block> = Vec::new();
+    for sg in &diagram.subgraphs {
+        let part = GraphDiagram {
+            direction: diagram.direction.clone(),
+            nodes: diagram
+                .nodes
+                .iter()
+                .filter(|n| sg.node_ids.contains(&n.id))
+                .cloned()
+                .collect(),
+            edges: diagram
+                .edges
+                .iter()
+                .filter(|e| sg.node_ids.contains(&e.from) && sg.node_ids.contains(&e.to))
+                .cloned()
+                .collect(),
+            subgraphs: vec![],
+            redeclared: vec![],
         };
+        if part.nodes.is_empty() {
+            continue;
+        }
 
         // Apply subgraph padding
-        let sg = &diagram.subgraphs[i];
+        let mut node_layouts = layout_nodes(&part);
         for nl in &mut node_layouts {
-            nl.x += x_offset + SUBGRAPH_PAD_LEFT;
+            nl.x += SUBGRAPH_PAD_LEFT;
             nl.y += SUBGRAPH_PAD_TOP;
-            nl.center_x += x_offset + SUBGRAPH_PAD_LEFT;
+            nl.center_x += SUBGRAPH_PAD_LEFT;
             nl.center_y += SUBGRAPH_PAD_TOP;
         }
 
-        let content_right = node_layouts
-            .iter()
-            .map(|n| n.x + n.width)
-            .max()
-            .unwrap_or(0);
-        let content_bottom = node_layouts
-            .iter()
-            .map(|n| n.y + n.height)
-            .max()
-            .unwrap_or(0);
-
-        let content_width = content_right - x_offset + SUBGRAPH_PAD_RIGHT;
-        let title_width = display_width(&sg.label) + SUBGRAPH_TITLE_DECOR;
-        let sg_width = content_width.max(title_width);
-        let sg_height = content_bottom + SUBGRAPH_PAD_BOTTOM;
-
-        sg_layouts.push(SubgraphLayout {
-            label: sg.label.clone(),
-            x: x_offset,
+        // Self-loops stay inside the frame rather than running along it
+        let extents = node_layouts.iter().map(|n| extent_with_self_loops(n, &part.edges));
+        let content_right = extents.clone().map(|(right, _)| right).max().unwrap_or(0);
+        let content_bottom = extents.map(|(_, bottom)| bottom).max().unwrap_or(0);
+
+        let content_width = content_right + SUBGRAPH_PAD_RIGHT;
+        let room =
+            title_cap.map(|cap| cap.max(content_width.saturating_sub(SUBGRAPH_TITLE_DECOR)));
+        let label = match room {
+            Some(room) if display_width(&sg.label) > room => {
+                truncate_to_display_width(&sg.label, room)
+            }
+            _ => sg.label.clone(),
+        };
+        let title_width = display_width(&label) + SUBGRAPH_TITLE_DECOR;
+        let frame = SubgraphLayout {
+            label,
+            x: 0,
             y: 0,
-            width: sg_width,
-            height: sg_height,
-        });
-
-        all_nodes.extend(node_layouts);
-        x_offset += sg_width + SUBGRAPH_GAP;
-    }
-
-    // Layout bare nodes
-    if !bare_nodes.is_empty() {
-        let bare_diagram = GraphDiagram {
-            direction: diagram.direction.clone(),
-            nodes: bare_nodes.into_iter().cloned().collect(),
-            edges: bare_edges.into_iter().cloned().collect(),
-            subgraphs: vec![],
+            width: content_width.max(title_width),
+            height: content_bottom + SUBGRAPH_PAD_BOTTOM,
         };
-        let ranks = assign_ranks(&bare_diagram);
-        let max_rank = *ranks.values().max().unwrap_or(&0);
-        let mut ranks_nodes: Vec<Vec<&NodeDecl>> = vec![Vec::new(); max_rank + 1];
-        for node in &bare_diagram.nodes {
-            let rank = ranks[&node.id];
-            ranks_nodes[rank].push(node);
-        }
+        blocks.push(Block::new(node_layouts, Some(frame)));
+    }
 
-        let mut node_layouts = match diagram.direction {
-            Direction::TopDown => layout_td(&ranks_nodes),
-            Direction::LeftRight => layout_lr(&ranks_nodes, &ranks, &bare_diagram.edges),
-        };
+    // Nodes outside any subgraph
+    let bare = GraphDiagram {
+        direction: diagram.direction.clone(),
+        nodes: diagram
+            .nodes
+            .iter()
+            .filter(|n| !node_to_subgraph.contains_key(&n.id))
+            .cloned()
+            .collect(),
+        edges: diagram
+            .edges
+            .iter()
+            .filter(|e| {
+                !node_to_subgraph.contains_key(&e.from) && !node_to_subgraph.contains_key(&e.to)
+            })
+            .cloned()
+            .collect(),
+        subgraphs: vec![],
+        redeclared: vec![],
+    };
+    if !bare.nodes.is_empty() {
+        blocks.push(Block::new(layout_nodes(&bare), None));
+    }
 
-        for nl in &mut node_layouts {
-            nl.x += x_offset;
-            nl.center_x += x_offset;
+    match diagram.direction {
+        Direction::TopDown => stack_blocks(&mut blocks, &diagram.edges, subgraph_gap, max_width),
+        Direction::LeftRight => {
+            let mut x = 0;
+            for block in &mut blocks {
+                block.move_to(x, 0);
+                x += block.width + subgraph_gap;
+            }
         }
+    }
 
-        all_nodes.extend(node_layouts);
+    let mut all_nodes: Vec<NodeLayout> = Vec::new();
+    let mut sg_layouts: Vec<SubgraphLayout> = Vec::new();
+    for block in blocks {
+        all_nodes.extend(block.nodes);
+        sg_layouts.extend(block.frame);
     }
 
     let edges: Vec<EdgeLayout> = diagram
@@ -286,6 +420,7 @@ fn layout_with_subgraphs(diagram: &GraphDiagram) -> Result<GraphLayout, String>
         height = height.max(sg.y + sg.height);
     }
 
+    let adjacency = Adjacency::new(&all_nodes, &edges);
     Ok(GraphLayout {
         nodes: all_nodes,
         edges,
@@ -293,16 +428,84 @@ fn layout_with_subgraphs(diagram: &GraphDiagram) -> Result<GraphLayout, String>
         width,
         height,
         direction: diagram.direction.clone(),
+        adjacency,
     })
 }
 
+/// Place TD blocks side by side in declaration order, wrapping onto a new
+/// row past `max_width`. A block on a later row sits under the node the
+/// first edge into it comes from, where there is room, and a subgraph
+/// entered from a row above gets an extra row on top for the arrow heads.
+fn stack_blocks(
+    blocks: &mut [Block],
+    edges: &[Edge],
+    gap: usize,
+    max_width: Option<usize>,
+) {
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut x = 0;
+    for i in 0..blocks.len() {
+        let fits = |x: usize| max_width.is_none_or(|max| x + blocks[i].width <= max);
+        if x > 0 && !fits(x) {
+            rows.push(Vec::new());
+            x = 0;
+        }
+        let above = &rows[..rows.len() - 1];
+        let wanted = edges.iter().find_map(|e| {
+            let to = blocks[i].nodes.iter().find(|n| n.id == e.to)?;
+            let from = above
+                .iter()
+                .flatten()
+                .flat_map(|&j| &blocks[j].nodes)
+                .find(|n| n.id == e.from)?;
+            from.center_x.checked_sub(to.center_x)
+        });
+        if let Some(wanted) = wanted.filter(|&w| w > x && fits(w)) {
+            x = wanted;
+        }
+        blocks[i].move_to(x, 0);
+        x += blocks[i].width + gap;
+        rows.last_mut().unwrap().push(i);
+    }
+
+    let mut row_of = vec![0; blocks.len()];
+    for (r, row) in rows.iter().enumerate() {
+        for &i in row {
+            row_of[i] = r;
+        }
+    }
+    let block_of: HashMap<&str, usize> = blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(i, b)| b.nodes.iter().map(move |n| (n.id.as_str(), i)))
+        .collect();
+    let entered: HashSet<usize> = edges
+        .iter()
+        .filter_map(|e| Some((*block_of.get(e.from.as_str())?, *block_of.get(e.to.as_str())?)))
+        .filter(|&(from, to)| row_of[from] < row_of[to])
+        .map(|(_, to)| to)
+        .collect();
+    for i in entered {
+        blocks[i].pad_top();
+    }
+
+    let mut y = 0;
+    for row in &rows {
+        for &i in row {
+            blocks[i].move_to(0, y);
+        }
+        y = row.iter().map(|&i| y + blocks[i].height + TD_RANK_SPACING).max().unwrap_or(y);
+    }
+}
+
 fn assign_ranks(diagram: &GraphDiagram) -> HashMap<String, usize> {
+    let back_edges = back_edges(diagram);
     let mut in_edges: HashMap<String, Vec<String>> = HashMap::new();
     for node in &diagram.nodes {
         in_edges.entry(node.id.clone()).or_default();
     }
     for edge in &diagram.edges {
-        if edge.from == edge.to {
+        if edge.from == edge.to || back_edges.contains(&(edge.from.as_str(), edge.to.as_str())) {
             continue;
         }
         in_edges
@@ -323,6 +526,44 @@ fn assign_ranks(diagram: &GraphDiagram) -> HashMap<String, usize> {
     ranks
 }
 
+/// Edges that close a cycle, found walking forward from each node in the
+/// order they were declared. Ranking without them puts the node where a
+/// cycle is entered first, so `A --> B` plus `B --> A` ranks A above B.
+fn back_edges(diagram: &GraphDiagram) -> HashSet<(&str, &str)> {
+    let mut out_edges: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in diagram.edges.iter().filter(|e| e.from != e.to) {
+        out_edges.entry(edge.from.as_str()).or_default().push(edge.to.as_str());
+    }
+    let mut back = HashSet::new();
+    let mut done: HashSet<&str> = HashSet::new();
+    for node in &diagram.nodes {
+        let mut on_path: Vec<&str> = Vec::new();
+        walk_forward(&node.id, &out_edges, &mut on_path, &mut done, &mut back);
+    }
+    back
+}
+
+fn walk_forward<'a>(
+    id: &'a str,
+    out_edges: &HashMap<&'a str, Vec<&'a str>>,
+    on_path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+    back: &mut HashSet<(&'a str, &'a str)>,
+) {
+    if !done.insert(id) {
+        return;
+    }
+    on_path.push(id);
+    for &next in out_edges.get(id).into_iter().flatten() {
+        if on_path.contains(&next) {
+            back.insert((id, next));
+        } else {
+            walk_forward(next, out_edges, on_path, done, back);
+        }
+    }
+    on_path.pop();
+}
+
 fn compute_rank(
     id: &str,
     in_edges: &HashMap<String, Vec<String>>,
@@ -362,79 +603,195 @@ const TD_NODE_GAP: usize = 3;
 const LR_GAP: usize = 5;
 const LR_NODE_VERTICAL_GAP: usize = 2;
 
+/// The layout narrowed to fit `max_width`: disconnected parts and
+/// subgraphs wrap onto new rows first, then subgraph titles are cut down,
+/// then every gap shrinks a column at a time, then the longest node and
+/// edge labels are cut a column at a time. Fails, saying how wide the
+/// narrowest layout is, when even that doesn't fit.
 pub fn compute_with_max_width(
     diagram: &GraphDiagram,
     max_width: usize,
 ) -> Result<GraphLayout, String> {
-    let layout = compute(diagram)?;
+    compute_fitted(diagram, max_width, false)
+}
+
+/// Like [`compute_with_max_width`], but node labels are wrapped onto more
+/// lines a column at a time instead of being cut short with `…`. Edge
+/// labels are still cut.
+pub fn compute_wrapped(diagram: &GraphDiagram, max_width: usize) -> Result<GraphLayout, String> {
+    compute_fitted(diagram, max_width, true)
+}
+
+fn compute_fitted(
+    diagram: &GraphDiagram,
+    max_width: usize,
+    wrap: bool,
+) -> Result<GraphLayout, String> {
+    let layout = compute_within(diagram, Spacing::DEFAULT, None, Some(max_width))?;
     if layout.width <= max_width {
         return Ok(layout);
     }
 
-    // Subgraph case: no gap reduction fallback (already laid out independently)
-    if !diagram.subgraphs.is_empty() {
-        return Err(format!("graph diagram too wide for {max_width} columns"));
+    // Subgraph titles, as far as their contents' width
+    let longest = diagram.subgraphs.iter().map(|sg| display_width(&sg.label)).max();
+    for cap in (0..longest.unwrap_or(0)).rev() {
+        let layout = compute_within(diagram, Spacing::DEFAULT, Some(cap), Some(max_width))?;
+        if layout.width <= max_width {
+            return Ok(layout);
+        }
     }
-
-    // Try with progressively smaller gaps
-    let ranks = assign_ranks(diagram);
-    let max_rank = *ranks.values().max().unwrap_or(&0);
-    let mut ranks_nodes: Vec<Vec<&NodeDecl>> = vec![Vec::new(); max_rank + 1];
-    for node in &diagram.nodes {
-        let rank = ranks[&node.id];
-        ranks_nodes[rank].push(node);
+    let title_cap = Some(0);
+
+    let mut spacing = Spacing::DEFAULT;
+    while let Some(narrower) = spacing.narrower() {
+        spacing = narrower;
+        let layout = compute_within(diagram, spacing, title_cap, Some(max_width))?;
+        if layout.width <= max_width {
+            return Ok(layout);
+        }
     }
 
-    for node_gap in (0..TD_NODE_GAP).rev() {
-        for lr_gap in (1..LR_GAP).rev() {
-            let mut node_layouts = match diagram.direction {
-                Direction::TopDown => layout_td_with_gap(&ranks_nodes, node_gap),
-                Direction::LeftRight => {
-                    layout_lr_with_gap(&ranks_nodes, &ranks, &diagram.edges, lr_gap)
+    let full = diagram;
+    let mut diagram = diagram.clone();
+    loop {
+        let layout = compute_within(&diagram, spacing, title_cap, Some(max_width))?;
+        if layout.width <= max_width {
+            return Ok(layout);
+        }
+        // Node labels are rewrapped from their full text, so earlier breaks
+        // don't pile up
+        let full_labels = full.nodes.iter().map(|n| Some(&n.label).filter(|_| wrap));
+        let nodes = diagram.nodes.iter_mut().map(|n| &mut n.label).zip(full_labels);
+        let edges = diagram.edges.iter_mut().filter_map(|e| e.label.as_mut());
+        let labels = nodes.chain(edges.map(|label| (label, None)));
+        let longest = labels.max_by_key(|(label, _)| multiline_width(label));
+        let Some((label, full_label)) = longest.filter(|(label, _)| multiline_width(label) > 2)
+        else {
+            return Err(format!(
+                "graph diagram requires at least {} columns, but max_width is {max_width}",
+                layout.width
+            ));
+        };
+        let width = multiline_width(label) - 1;
+        if let Some(full_label) = full_label {
+            *label = wrap_to_display_width(full_label, width);
+            continue;
+        }
+        *label = split_br(label)
+            .into_iter()
+            .map(|line| {
+                if display_width(line) > width {
+                    truncate_to_display_width(line, width)
+                } else {
+                    line.to_string()
                 }
-            };
+            })
+            .collect::<Vec<_>>()
+            .join("<br/>");
+    }
+}
 
-            let edges: Vec<EdgeLayout> = diagram
-                .edges
-                .iter()
-                .map(|e| EdgeLayout {
-                    from_id: e.from.clone(),
-                    to_id: e.to.clone(),
-                    edge_type: e.edge_type,
-                    label: e.label.clone(),
-                })
-                .collect();
+/// Lay out each connected part of `diagram` on its own, then pack the parts
+/// in the order they were declared: side by side in TD graphs, wrapping
+/// onto a new row past `max_width`, and one below the other in LR graphs.
+fn layout_components(
+    diagram: &GraphDiagram,
+    node_gap: usize,
+    lr_gap: usize,
+    max_width: Option<usize>,
+) -> Vec<NodeLayout> {
+    let (gap_x, gap_y) = match diagram.direction {
+        Direction::TopDown => (node_gap, TD_RANK_SPACING),
+        Direction::LeftRight => (lr_gap, LR_NODE_VERTICAL_GAP),
+    };
+    let mut layouts = Vec::new();
+    let (mut x, mut y, mut bottom) = (0, 0, 0);
+    for component in components(diagram) {
+        let ranks = assign_ranks(&component);
+        let max_rank = *ranks.values().max().unwrap_or(&0);
+        let mut ranks_nodes: Vec<Vec<&NodeDecl>> = vec![Vec::new(); max_rank + 1];
+        for node in &component.nodes {
+            ranks_nodes[ranks[&node.id]].push(node);
+        }
+        let mut nodes = match diagram.direction {
+            Direction::TopDown => {
+                layout_td_with_gap(&ranks_nodes, &ranks, &component.edges, node_gap)
+            }
+            Direction::LeftRight => {
+                layout_lr_with_gap(&ranks_nodes, &ranks, &component.edges, lr_gap)
+            }
+        };
 
-            let subgraphs = compute_subgraph_layouts(&diagram.subgraphs, &mut node_layouts);
+        let width = nodes.iter().map(|n| n.x + n.width).max().unwrap_or(0);
+        let fits = diagram.direction == Direction::TopDown
+            && max_width.is_none_or(|max| x + width <= max);
+        if x > 0 && !fits {
+            (x, y) = (0, bottom + gap_y);
+        }
+        for node in &mut nodes {
+            node.x += x;
+            node.y += y;
+            node.center_x += x;
+            node.center_y += y;
+        }
+        x += width + gap_x;
+        bottom = nodes.iter().map(|n| n.y + n.height).fold(bottom, usize::max);
+        layouts.extend(nodes);
+    }
+    layouts
+}
 
-            let mut width = node_layouts.iter().map(|n| n.x + n.width).max().unwrap_or(0);
-            let mut height = node_layouts.iter().map(|n| n.y + n.height).max().unwrap_or(0);
-            for sg in &subgraphs {
-                width = width.max(sg.x + sg.width);
-                height = height.max(sg.y + sg.height);
-            }
+/// The parts of `diagram` that no edge connects to each other, in the order
+/// of their first nodes.
+fn components(diagram: &GraphDiagram) -> Vec<GraphDiagram> {
+    let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in &diagram.edges {
+        neighbours.entry(&edge.from).or_default().push(&edge.to);
+        neighbours.entry(&edge.to).or_default().push(&edge.from);
+    }
 
-            if width <= max_width {
-                return Ok(GraphLayout {
-                    nodes: node_layouts,
-                    edges,
-                    subgraphs,
-                    width,
-                    height,
-                    direction: diagram.direction.clone(),
-                });
+    let mut component: HashMap<&str, usize> = HashMap::new();
+    let mut count = 0;
+    for node in &diagram.nodes {
+        if component.contains_key(node.id.as_str()) {
+            continue;
+        }
+        let mut stack = vec![node.id.as_str()];
+        while let Some(id) = stack.pop() {
+            if component.insert(id, count).is_none() {
+                stack.extend(neighbours.get(id).into_iter().flatten());
             }
         }
+        count += 1;
     }
 
-    Err(format!("graph diagram too wide for {max_width} columns"))
-}
-
-fn layout_td(ranks_nodes: &[Vec<&NodeDecl>]) -> Vec<NodeLayout> {
-    layout_td_with_gap(ranks_nodes, TD_NODE_GAP)
+    (0..count)
+        .map(|c| GraphDiagram {
+            direction: diagram.direction.clone(),
+            nodes: diagram
+                .nodes
+                .iter()
+                .filter(|n| component.get(n.id.as_str()) == Some(&c))
+                .cloned()
+                .collect(),
+            edges: diagram
+                .edges
+                .iter()
+                .filter(|e| component.get(e.from.as_str()) == Some(&c))
+                .cloned()
+                .collect(),
+            subgraphs: vec![],
+            redeclared: vec![],
+        })
+        .collect()
 }
 
-fn layout_td_with_gap(ranks_nodes: &[Vec<&NodeDecl>], node_gap: usize) -> Vec<NodeLayout> {
+fn layout_td_with_gap(
+    ranks_nodes: &[Vec<&NodeDecl>],
+    ranks: &HashMap<String, usize>,
+    edges: &[Edge],
+    node_gap: usize,
+) -> Vec<NodeLayout> {
     let mut layouts = Vec::new();
 
     let mut rank_widths: Vec<usize> = Vec::new();
@@ -486,6 +843,7 @@ fn layout_td_with_gap(ranks_nodes: &[Vec<&NodeDecl>], node_gap: usize) -> Vec<No
                 height: h,
                 center_x: x + w / 2,
                 center_y: y + h / 2,
+                metadata: node.metadata.clone(),
             });
             x += w + node_gap;
         }
@@ -493,15 +851,93 @@ fn layout_td_with_gap(ranks_nodes: &[Vec<&NodeDecl>], node_gap: usize) -> Vec<No
         y += rank_heights[rank] + TD_RANK_SPACING;
     }
 
+    align_td_ranks(&mut layouts, ranks, edges, node_gap);
     layouts
 }
 
-fn layout_lr(
-    ranks_nodes: &[Vec<&NodeDecl>],
+/// Shift the nodes of each rank sideways toward the nodes they connect to,
+/// so children sit under their own parent rather than wherever centering
+/// the rank against the widest one put them. A pass down the ranks follows
+/// the parents, then a pass up re-centers parents over their children.
+/// Nodes keep their order within a rank and stay `gap` apart.
+fn align_td_ranks(
+    layouts: &mut [NodeLayout],
     ranks: &HashMap<String, usize>,
     edges: &[Edge],
-) -> Vec<NodeLayout> {
-    layout_lr_with_gap(ranks_nodes, ranks, edges, LR_GAP)
+    gap: usize,
+) {
+    let rank_of = |id: &str| ranks.get(id).copied().unwrap_or(0);
+    let max_rank = layouts.iter().map(|n| rank_of(&n.id)).max().unwrap_or(0);
+    let passes = (1..=max_rank).map(|r| (r, true)).chain((0..max_rank).rev().map(|r| (r, false)));
+    for (rank, down) in passes {
+        let members: Vec<usize> =
+            (0..layouts.len()).filter(|&i| rank_of(&layouts[i].id) == rank).collect();
+        let wanted: Vec<i64> = members
+            .iter()
+            .map(|&i| {
+                let node = &layouts[i];
+                let centers: Vec<usize> = edges
+                    .iter()
+                    .filter_map(|e| match down {
+                        true if e.to == node.id && rank_of(&e.from) < rank => Some(&e.from),
+                        false if e.from == node.id && rank_of(&e.to) > rank => Some(&e.to),
+                        _ => None,
+                    })
+                    .filter_map(|id| layouts.iter().find(|n| n.id == *id))
+                    .map(|n| n.center_x)
+                    .collect();
+                match centers.len() {
+                    0 => node.x as i64,
+                    n => (centers.iter().sum::<usize>() / n) as i64 - (node.width / 2) as i64,
+                }
+            })
+            .collect();
+        let widths: Vec<usize> = members.iter().map(|&i| layouts[i].width).collect();
+        let mut min_x = 0;
+        for (&i, x) in members.iter().zip(spread(&wanted, &widths, gap)) {
+            // A group pushed past the left edge keeps its spacing
+            let x = x.max(min_x as i64) as usize;
+            layouts[i].x = x;
+            layouts[i].center_x = x + layouts[i].width / 2;
+            min_x = x + layouts[i].width + gap;
+        }
+    }
+    let left = layouts.iter().map(|n| n.x).min().unwrap_or(0);
+    for node in layouts.iter_mut() {
+        node.x -= left;
+        node.center_x -= left;
+    }
+}
+
+/// Left edges for boxes of `widths`, in order and at least `gap` apart,
+/// each as close as it can get to its `wanted` left edge. Boxes that would
+/// overlap move as a group, centered on where they want to be together.
+fn spread(wanted: &[i64], widths: &[usize], gap: usize) -> Vec<i64> {
+    // Each group: first box, number of boxes, sum of wanted group lefts, span
+    let mut groups: Vec<(usize, i64, i64, i64)> = Vec::new();
+    for (i, (&want, &width)) in wanted.iter().zip(widths).enumerate() {
+        let mut group = (i, 1, want, width as i64);
+        while let Some(&(first, count, sum, span)) = groups.last() {
+            let left = sum.div_euclid(count);
+            let span_gap = span + gap as i64;
+            if left + span_gap <= group.2.div_euclid(group.1) {
+                break;
+            }
+            groups.pop();
+            let sum = sum + group.2 - group.1 * span_gap;
+            group = (first, count + group.1, sum, span_gap + group.3);
+        }
+        groups.push(group);
+    }
+    let mut lefts = Vec::with_capacity(wanted.len());
+    for (first, count, sum, _) in groups {
+        let mut x = sum.div_euclid(count);
+        for width in &widths[first..first + count as usize] {
+            lefts.push(x);
+            x += (width + gap) as i64;
+        }
+    }
+    lefts
 }
 
 fn layout_lr_with_gap(
@@ -510,7 +946,7 @@ fn layout_lr_with_gap(
     edges: &[Edge],
     min_gap: usize,
 ) -> Vec<NodeLayout> {
-    let mut layouts = Vec::new();
+    let mut layouts: Vec<NodeLayout> = Vec::new();
     let mut rank_x = 0;
 
     for (rank, rank_nodes) in ranks_nodes.iter().enumerate() {
@@ -524,6 +960,11 @@ fn layout_lr_with_gap(
         for node in rank_nodes {
             let w = box_width(&node.label, node.shape);
             let h = box_height(&node.label, node.shape);
+            // Line up with the predecessors when there is room, rather than
+            // at the top of the rank, so edges from a low parent stay short
+            if let Some(center) = predecessor_center(&layouts, edges, &node.id) {
+                y = y.max(center.saturating_sub(h / 2));
+            }
             layouts.push(NodeLayout {
                 id: node.id.clone(),
                 label: node.label.clone(),
@@ -534,18 +975,31 @@ fn layout_lr_with_gap(
                 height: h,
                 center_x: rank_x + w / 2,
                 center_y: y + h / 2,
+                metadata: node.metadata.clone(),
             });
             y += h + LR_NODE_VERTICAL_GAP;
         }
 
         if rank + 1 < ranks_nodes.len() {
-            let label_gap = edges
+            let labelled: Vec<(&str, usize)> = edges
                 .iter()
                 .filter(|e| {
                     ranks.get(&e.from) == Some(&rank)
                         && ranks.get(&e.to) == Some(&(rank + 1))
                 })
-                .filter_map(|e| e.label.as_ref().map(|l| display_width(l) + 2))
+                .filter_map(|e| Some((e.from.as_str(), display_width(e.label.as_ref()?))))
+                .collect();
+            // Several labels from one node are drawn past the middle of the
+            // gap, where the lines to their targets split up
+            let label_gap = labelled
+                .iter()
+                .map(|&(from, width)| {
+                    if labelled.iter().filter(|&&(f, _)| f == from).count() > 1 {
+                        2 * width + 5
+                    } else {
+                        width + 2
+                    }
+                })
                 .max()
                 .unwrap_or(0);
             let gap = min_gap.max(label_gap);
@@ -556,6 +1010,18 @@ fn layout_lr_with_gap(
     layouts
 }
 
+/// The average center row of the already placed nodes with an edge into
+/// `id`, if there are any.
+fn predecessor_center(placed: &[NodeLayout], edges: &[Edge], id: &str) -> Option<usize> {
+    let centers: Vec<usize> = edges
+        .iter()
+        .filter(|e| e.to == id && e.from != id)
+        .filter_map(|e| placed.iter().find(|n| n.id == e.from))
+        .map(|n| n.center_y)
+        .collect();
+    (!centers.is_empty()).then(|| centers.iter().sum::<usize>() / centers.len())
+}
+
 fn box_width(label: &str, shape: NodeShape) -> usize {
     let base = multiline_width(label) + 4;
     match shape {
@@ -666,6 +1132,18 @@ mod tests {
         assert_eq!(ranks["C"], 1);
     }
 
+    #[test]
+    fn adjacency_skips_self_loops() {
+        let diagram = parse_graph("graph TD\n    A --> B\n    A --> C\n    A --> A\n    B --> C\n").unwrap();
+        let layout = compute(&diagram).unwrap();
+        let ids = |idx: &[usize]| -> Vec<&str> {
+            idx.iter().map(|&i| layout.nodes[i].id.as_str()).collect()
+        };
+        assert_eq!(ids(layout.adjacency.children("A")), vec!["B", "C"]);
+        assert_eq!(ids(layout.adjacency.parents("C")), vec!["A", "B"]);
+        assert!(layout.adjacency.parents("A").is_empty());
+    }
+
     #[test]
     fn layout_td_two_nodes() {
         let diagram = parse_graph("graph TD\n    A[Start] --> B[End]\n").unwrap();
@@ -689,6 +1167,38 @@ mod tests {
         assert_eq!(a.y, b.y, "single row in LR");
     }
 
+    #[test]
+    fn layout_lr_children_line_up_with_their_parent() {
+        let input = "graph LR\n    A --> B\n    A --> C\n    C --> D\n    C --> E\n    B --> F\n    E --> F\n";
+        let diagram = parse_graph(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+        let center = |id: &str| layout.nodes.iter().find(|n| n.id == id).unwrap().center_y;
+
+        assert_eq!(center("D"), center("C"), "first child level with its parent");
+        assert!(center("E") > center("D"), "siblings keep their order");
+        assert_eq!(center("F"), (center("B") + center("E")) / 2);
+    }
+
+    #[test]
+    fn layout_td_children_sit_under_their_own_parent() {
+        let input = "graph TD\n    P --> A[a wide child]\n    P --> B[another wide one]\n    Q --> C\n";
+        let diagram = parse_graph(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+        let node = |id: &str| layout.nodes.iter().find(|n| n.id == id).unwrap();
+
+        assert_eq!(node("C").center_x, node("Q").center_x);
+        let (a, b) = (node("A"), node("B"));
+        assert!(a.x + a.width + TD_NODE_GAP <= b.x && b.x + b.width + TD_NODE_GAP <= node("C").x);
+        assert!(node("P").center_x.abs_diff((a.center_x + b.center_x) / 2) <= 1);
+    }
+
+    #[test]
+    fn layout_cycle_ranks_from_where_it_is_entered() {
+        let diagram = parse_graph("graph TD\n    A --> B\n    B --> C\n    C --> A\n").unwrap();
+        let ranks = assign_ranks(&diagram);
+        assert_eq!((ranks["A"], ranks["B"], ranks["C"]), (0, 1, 2));
+    }
+
     #[test]
     fn layout_td_fan_out_side_by_side() {
         let diagram = parse_graph("graph TD\n    A --> B\n    A --> C\n").unwrap();
@@ -716,6 +1226,105 @@ mod tests {
         assert!(c.y > a.y, "C below parents");
     }
 
+    #[test]
+    fn layout_packs_disconnected_parts() {
+        let input = "graph TD\n    A[Long start node] --> B\n    B --> C\n    D --> E\n    F\n";
+        let diagram = parse_graph(input).unwrap();
+        let position = |layout: &GraphLayout, id: &str| {
+            let node = layout.nodes.iter().find(|n| n.id == id).unwrap();
+            (node.x, node.y)
+        };
+
+        let layout = compute(&diagram).unwrap();
+        assert_eq!(position(&layout, "B"), (7, 5));
+        assert_eq!(position(&layout, "D"), (22, 0));
+        assert_eq!(position(&layout, "E"), (22, 5));
+        assert_eq!(position(&layout, "F"), (30, 0));
+
+        let layout = compute_with_max_width(&diagram, 30).unwrap();
+        assert_eq!(position(&layout, "E"), (22, 5));
+        assert_eq!(position(&layout, "F"), (0, 15));
+        assert_eq!((layout.width, layout.height), (27, 18));
+    }
+
+    #[test]
+    fn layout_subgraphs_under_max_width_wrap_then_cut_titles_then_gaps() {
+        let input = "graph TD\n    subgraph A very long subgraph title\n    X --> Y\n    end\n    subgraph Short\n    Z\n    end\n";
+        let diagram = parse_graph(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+        assert_eq!(layout.width, 46);
+        assert_eq!(layout.subgraphs[1].y, 0);
+
+        let layout = compute_with_max_width(&diagram, 30).unwrap();
+        assert!(layout.width <= 30, "{}", layout.width);
+        let [long, short] = &layout.subgraphs[..] else { panic!() };
+        assert_eq!(long.label, "A very long subgraph ti…");
+        assert_eq!(short.label, "Short");
+        assert_eq!((short.x, short.y), (0, long.height + TD_RANK_SPACING));
+
+        let layout = compute_with_max_width(&diagram, 9).unwrap();
+        assert_eq!(layout.subgraphs[0].label, "A …");
+        assert!(compute_with_max_width(&diagram, 8).is_err());
+
+        let diagram = parse_graph("graph TD\n    subgraph Fan\n    A --> B & C\n    end\n").unwrap();
+        assert_eq!(compute(&diagram).unwrap().width, 17);
+        let layout = compute_with_max_width(&diagram, 15).unwrap();
+        let b = layout.nodes.iter().find(|n| n.id == "B").unwrap();
+        let c = layout.nodes.iter().find(|n| n.id == "C").unwrap();
+        assert_eq!(c.x - (b.x + b.width), 1);
+    }
+
+    #[test]
+    fn layout_under_max_width_shrinks_gaps_then_cuts_labels() {
+        let input = "graph LR\n    A[Start here] -->|go| B[Process it]\n    B --> C[Finish]\n";
+        let diagram = parse_graph(input).unwrap();
+        let node = |layout: &GraphLayout, id: &str| {
+            let node = layout.nodes.iter().find(|n| n.id == id).unwrap();
+            (node.x, node.label.clone())
+        };
+        assert_eq!(compute(&diagram).unwrap().width, 48);
+
+        // The labelled gap keeps room for its label while the other shrinks
+        let layout = compute_with_max_width(&diagram, 45).unwrap();
+        assert_eq!(node(&layout, "B"), (18, "Process it".to_string()));
+        assert_eq!(node(&layout, "C"), (35, "Finish".to_string()));
+
+        let layout = compute_with_max_width(&diagram, 32).unwrap();
+        assert_eq!(layout.width, 32);
+        assert_eq!(node(&layout, "A").1, "Star…");
+        assert_eq!(layout.edges[0].label.as_deref(), Some("go"));
+
+        let error = compute_with_max_width(&diagram, 20).unwrap_err();
+        assert_eq!(error, "graph diagram requires at least 23 columns, but max_width is 20");
+    }
+
+    #[test]
+    fn layout_wrapped_under_max_width_wraps_node_labels_from_their_full_text() {
+        let input = "graph LR\n    A[Start here] -->|go| B[Process it]\n    B --> C[Finish]\n";
+        let diagram = parse_graph(input).unwrap();
+        let label = |layout: &GraphLayout, id: &str| {
+            layout.nodes.iter().find(|n| n.id == id).unwrap().label.clone()
+        };
+
+        let layout = compute_wrapped(&diagram, 32).unwrap();
+        assert!(layout.width <= 32, "{}", layout.width);
+        assert_eq!(label(&layout, "A"), "Start<br/>here");
+        assert_eq!(label(&layout, "B"), "Proce<br/>ss it");
+
+        let layout = compute_wrapped(&diagram, 26).unwrap();
+        assert!(layout.width <= 26, "{}", layout.width);
+        assert_eq!(label(&layout, "A"), "Sta<br/>rt<br/>her<br/>e");
+        assert_eq!(label(&layout, "C"), "Fin<br/>ish");
+        assert_eq!(layout.edges[0].label.as_deref(), Some("go"));
+    }
+
+    #[test]
+    fn layout_edge_to_undeclared_node_is_an_error() {
+        let mut diagram = parse_graph("graph TD\n    A --> B\n").unwrap();
+        diagram.nodes.retain(|n| n.id == "A");
+        assert_eq!(compute(&diagram).unwrap_err(), "unknown node 'B' referenced by edge A -> B");
+    }
+
     #[test]
     fn layout_box_dimensions() {
         let diagram = parse_graph("graph TD\n    A[Hello]\n").unwrap();
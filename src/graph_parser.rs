@@ -1,25 +1,24 @@
+use std::collections::{BTreeMap, HashMap};
+
 use winnow::prelude::*;
-use winnow::ascii::{line_ending, space0, space1};
-use winnow::combinator::{alt, opt, repeat};
+use winnow::ascii::{line_ending, multispace0, space0, space1, till_line_ending};
+use winnow::combinator::{alt, cut_err, delimited, eof, fail, opt, preceded, repeat, separated};
 use winnow::token::{take_until, take_while};
 
 use crate::graph_ast::*;
-
-pub fn parse_graph(input: &str) -> Result<GraphDiagram, String> {
-    let mut input = input;
-    graph_diagram(&mut input).map_err(|_| {
-        let context = input.lines().next().unwrap_or("").trim();
-        let context_display = if context.len() > 40 {
-            format!("{}...", &context[..40])
-        } else {
-            context.to_string()
-        };
-        format!("syntax error in graph diagram: unexpected `{context_display}`")
-    })
+use crate::parse_error::{syntax_error, unclosed_block};
+
+pub fn parse_graph(source: &str) -> Result<GraphDiagram, String> {
+    let mut input = source;
+    match graph_diagram(&mut input) {
+        Ok(d) if input.trim().is_empty() => Ok(d),
+        _ => Err(unclosed_block(Some("graph diagram"), &["subgraph"], false, source, input)
+            .unwrap_or_else(|| syntax_error(Some("graph diagram"), source, input))),
+    }
 }
 
-fn graph_diagram(input: &mut &str) -> winnow::Result<GraphDiagram> {
-    space0.parse_next(input)?;
+fn graph_diagram(input: &mut &str) -> winnow::ModalResult<GraphDiagram> {
+    multispace0.parse_next(input)?;
     alt(("graph", "flowchart")).parse_next(input)?;
     space1.parse_next(input)?;
     let direction = direction.parse_next(input)?;
@@ -28,43 +27,59 @@ fn graph_diagram(input: &mut &str) -> winnow::Result<GraphDiagram> {
     let mut nodes: Vec<NodeDecl> = Vec::new();
     let mut edges: Vec<Edge> = Vec::new();
     let mut subgraphs: Vec<Subgraph> = Vec::new();
+    let mut annotations: Vec<Annotation> = Vec::new();
+    let mut redeclared: Vec<NodeDecl> = Vec::new();
 
     let lines: Vec<Option<GraphLine>> = repeat(0.., graph_line).parse_next(input)?;
     for line in lines.into_iter().flatten() {
-        collect_line(line, &mut nodes, &mut edges, &mut subgraphs);
+        collect_line(
+            line,
+            &mut nodes,
+            &mut redeclared,
+            &mut edges,
+            &mut subgraphs,
+            &mut annotations,
+        );
     }
+    resolve_subgraph_refs(&mut nodes, &mut edges, &mut subgraphs);
+    redeclared.retain(|decl| nodes.iter().any(|n| n.id == decl.id));
+    apply_annotations(&mut nodes, annotations);
 
     Ok(GraphDiagram {
         direction,
         nodes,
         edges,
         subgraphs,
+        redeclared,
     })
 }
 
 fn collect_line(
     line: GraphLine,
     nodes: &mut Vec<NodeDecl>,
+    redeclared: &mut Vec<NodeDecl>,
     edges: &mut Vec<Edge>,
     subgraphs: &mut Vec<Subgraph>,
+    annotations: &mut Vec<Annotation>,
 ) {
     match line {
         GraphLine::Edge(edge, from_decl, to_decl) => {
-            add_node(nodes, from_decl);
-            add_node(nodes, to_decl);
+            add_node(nodes, redeclared, from_decl);
+            add_node(nodes, redeclared, to_decl);
             edges.push(edge);
         }
         GraphLine::Edges(items) => {
             for (edge, from_decl, to_decl) in items {
-                add_node(nodes, from_decl);
-                add_node(nodes, to_decl);
+                add_node(nodes, redeclared, from_decl);
+                add_node(nodes, redeclared, to_decl);
                 edges.push(edge);
             }
         }
         GraphLine::Node(decl) => {
-            add_node(nodes, decl);
+            add_node(nodes, redeclared, decl);
         }
-        GraphLine::SubgraphBlock(label, inner_lines) => {
+        GraphLine::Annotation(annotation) => annotations.push(annotation),
+        GraphLine::SubgraphBlock(id, label, inner_lines) => {
             let mut sg_node_ids: Vec<String> = Vec::new();
             for inner in inner_lines {
                 match &inner {
@@ -91,11 +106,10 @@ fn collect_line(
                             sg_node_ids.push(decl.id.clone());
                         }
                     }
-                    GraphLine::SubgraphBlock(_, _) => {}
+                    GraphLine::SubgraphBlock(..) | GraphLine::Annotation(_) => {}
                 }
-                collect_line(inner, nodes, edges, subgraphs);
+                collect_line(inner, nodes, redeclared, edges, subgraphs, annotations);
             }
-            let id = label.replace(' ', "_").to_lowercase();
             subgraphs.push(Subgraph {
                 id,
                 label,
@@ -105,9 +119,113 @@ fn collect_line(
     }
 }
 
-fn add_node(nodes: &mut Vec<NodeDecl>, decl: NodeDecl) {
-    if !nodes.iter().any(|n| n.id == decl.id) {
+/// Point edges that name a subgraph by its id at the subgraph's first node,
+/// which stands in for its frame, and drop the nodes those names declared.
+fn resolve_subgraph_refs(
+    nodes: &mut Vec<NodeDecl>,
+    edges: &mut [Edge],
+    subgraphs: &mut [Subgraph],
+) {
+    let anchors: HashMap<String, String> = subgraphs
+        .iter()
+        .filter(|sg| !sg.node_ids.contains(&sg.id))
+        .filter_map(|sg| Some((sg.id.clone(), sg.node_ids.first()?.clone())))
+        .filter(|(id, _)| nodes.iter().any(|n| &n.id == id))
+        .collect();
+    if anchors.is_empty() {
+        return;
+    }
+    for edge in edges {
+        for end in [&mut edge.from, &mut edge.to] {
+            if let Some(anchor) = anchors.get(end.as_str()) {
+                *end = anchor.clone();
+            }
+        }
+    }
+    nodes.retain(|n| !anchors.contains_key(&n.id));
+    for sg in subgraphs {
+        sg.node_ids.retain(|id| !anchors.contains_key(id));
+    }
+}
+
+/// Add `decl`, or merge it into the node it names again. A bare `A` changes
+/// nothing, a first shape or label replaces a bare one, and a later
+/// different one goes to `redeclared`.
+fn add_node(nodes: &mut Vec<NodeDecl>, redeclared: &mut Vec<NodeDecl>, decl: NodeDecl) {
+    let is_bare = |decl: &NodeDecl| decl.shape == NodeShape::Box && decl.label == decl.id;
+    let Some(existing) = nodes.iter_mut().find(|n| n.id == decl.id) else {
         nodes.push(decl);
+        return;
+    };
+    for (key, value) in &decl.metadata {
+        add_metadata(&mut existing.metadata, key, value);
+    }
+    if is_bare(&decl) || (decl.shape, &decl.label) == (existing.shape, &existing.label) {
+        return;
+    }
+    if is_bare(existing) {
+        existing.shape = decl.shape;
+        existing.label = decl.label;
+    } else {
+        redeclared.push(NodeDecl { metadata: BTreeMap::new(), ..decl });
+    }
+}
+
+/// Attach annotations to the nodes they name, once every node is known.
+/// Styles from a node's `classDef`s come before its own `style`.
+fn apply_annotations(nodes: &mut [NodeDecl], annotations: Vec<Annotation>) {
+    let mut class_styles: HashMap<String, String> = HashMap::new();
+    for annotation in annotations {
+        match annotation {
+            Annotation::Nodes(ids, entries) => {
+                for node in nodes.iter_mut().filter(|n| ids.contains(&n.id)) {
+                    for (key, value) in &entries {
+                        add_metadata(&mut node.metadata, key, value);
+                    }
+                }
+            }
+            Annotation::ClassDef(names, style) => {
+                for name in names {
+                    class_styles.insert(name, style.clone());
+                }
+            }
+        }
+    }
+    for node in nodes {
+        let Some(classes) = node.metadata.get("class") else {
+            continue;
+        };
+        let mut styles: Vec<&str> =
+            classes.split(' ').filter_map(|c| class_styles.get(c)).map(String::as_str).collect();
+        if styles.is_empty() {
+            continue;
+        }
+        styles.extend(node.metadata.get("style").map(String::as_str));
+        let style = styles.join(",");
+        node.metadata.insert("style".to_string(), style);
+    }
+}
+
+/// Set `key` to `value`, except that `class` names and `style` properties
+/// accumulate.
+fn add_metadata(metadata: &mut BTreeMap<String, String>, key: &str, value: &str) {
+    let separator = match key {
+        "class" => ' ',
+        "style" => ',',
+        _ => {
+            metadata.insert(key.to_string(), value.to_string());
+            return;
+        }
+    };
+    match metadata.get_mut(key) {
+        Some(existing) if key == "class" && existing.split(' ').any(|c| c == value) => {}
+        Some(existing) => {
+            existing.push(separator);
+            existing.push_str(value);
+        }
+        None => {
+            metadata.insert(key.to_string(), value.to_string());
+        }
     }
 }
 
@@ -116,10 +234,21 @@ enum GraphLine {
     Edge(Edge, NodeDecl, NodeDecl),
     Edges(Vec<(Edge, NodeDecl, NodeDecl)>),
     Node(NodeDecl),
-    SubgraphBlock(String, Vec<GraphLine>),
+    /// Id, title and the lines inside.
+    SubgraphBlock(String, String, Vec<GraphLine>),
+    Annotation(Annotation),
+}
+
+/// Node metadata from `click`, `class`, `style` and `classDef` lines.
+#[derive(Debug)]
+enum Annotation {
+    /// Entries for each of the named nodes.
+    Nodes(Vec<String>, Vec<(&'static str, String)>),
+    /// Class names and the style they stand for.
+    ClassDef(Vec<String>, String),
 }
 
-fn graph_line(input: &mut &str) -> winnow::Result<Option<GraphLine>> {
+fn graph_line(input: &mut &str) -> winnow::ModalResult<Option<GraphLine>> {
     space0.parse_next(input)?;
 
     if input.is_empty() {
@@ -128,6 +257,8 @@ fn graph_line(input: &mut &str) -> winnow::Result<Option<GraphLine>> {
 
     let result = alt((
         blank_line.map(|_| None),
+        comment_line.map(|_| None),
+        annotation_line.map(Some),
         style_line.map(|_| None),
         subgraph_block.map(Some),
         edge_line.map(Some),
@@ -139,13 +270,21 @@ fn graph_line(input: &mut &str) -> winnow::Result<Option<GraphLine>> {
     Ok(result)
 }
 
-fn subgraph_block(input: &mut &str) -> winnow::Result<GraphLine> {
+/// `subgraph Title`, or `subgraph id [Title]` to refer to the subgraph by
+/// an id other than its title, followed by lines up to `end`.
+fn subgraph_block(input: &mut &str) -> winnow::ModalResult<GraphLine> {
     "subgraph".parse_next(input)?;
     space1.parse_next(input)?;
-    let label = take_while(1.., |c: char| c != '\n' && c != '\r')
+    let titled = opt((identifier, space0, bracketed_label, space0, alt((line_ending, eof))))
         .parse_next(input)?;
-    let label = label.trim_end().to_string();
-    opt(line_ending).parse_next(input)?;
+    let (id, label) = match titled {
+        Some((id, _, label, _, _)) => (id.to_string(), label),
+        None => {
+            let label = rest_of_line.parse_next(input)?;
+            opt(line_ending).parse_next(input)?;
+            (label.replace(' ', "_").to_lowercase(), label)
+        }
+    };
 
     let mut inner_lines: Vec<GraphLine> = Vec::new();
     loop {
@@ -156,21 +295,32 @@ fn subgraph_block(input: &mut &str) -> winnow::Result<GraphLine> {
             break;
         }
         if input.is_empty() {
-            break;
+            return cut_err(fail).parse_next(input);
         }
-        if let Some(line) = graph_line(input)? {
+        if let Some(line) = cut_err(graph_line).parse_next(input)? {
             inner_lines.push(line);
         }
     }
 
-    Ok(GraphLine::SubgraphBlock(label, inner_lines))
+    Ok(GraphLine::SubgraphBlock(id, label, inner_lines))
 }
 
-fn blank_line(input: &mut &str) -> winnow::Result<()> {
+fn blank_line(input: &mut &str) -> winnow::ModalResult<()> {
     line_ending.void().parse_next(input)
 }
 
-fn style_line(input: &mut &str) -> winnow::Result<()> {
+fn comment_line(input: &mut &str) -> winnow::ModalResult<()> {
+    ("%%", till_line_ending, opt(line_ending)).void().parse_next(input)
+}
+
+/// A `%%` comment after the statement on a line, without the `%%`.
+fn trailing_comment(input: &mut &str) -> winnow::ModalResult<Option<String>> {
+    space0.parse_next(input)?;
+    let comment = opt(preceded("%%", till_line_ending)).parse_next(input)?;
+    Ok(comment.map(|text| text.trim().to_string()))
+}
+
+fn style_line(input: &mut &str) -> winnow::ModalResult<()> {
     alt(("classDef", "linkStyle", "style", "class")).parse_next(input)?;
     space1.parse_next(input)?;
     let _ = take_while(0.., |c: char| c != '\n' && c != '\r').parse_next(input)?;
@@ -178,7 +328,81 @@ fn style_line(input: &mut &str) -> winnow::Result<()> {
     Ok(())
 }
 
-fn direction(input: &mut &str) -> winnow::Result<Direction> {
+fn annotation_line(input: &mut &str) -> winnow::ModalResult<GraphLine> {
+    let annotation = alt((click, class_def, class_assignment, node_style)).parse_next(input)?;
+    space0.parse_next(input)?;
+    opt(line_ending).parse_next(input)?;
+    Ok(GraphLine::Annotation(annotation))
+}
+
+/// `click id "url" "tooltip" _blank`, `click id href "url"`, or
+/// `click id callback "tooltip"`.
+fn click(input: &mut &str) -> winnow::ModalResult<Annotation> {
+    ("click", space1).parse_next(input)?;
+    let id = identifier.parse_next(input)?;
+    space1.parse_next(input)?;
+    let action = alt((
+        preceded(opt(("href", space1)), quoted_text).map(|url| ("link", url)),
+        preceded(opt(("call", space1)), word).map(|f| ("callback", f.to_string())),
+    ))
+    .parse_next(input)?;
+    let mut entries = vec![action];
+    if let Some(tooltip) = opt(preceded(space1, quoted_text)).parse_next(input)? {
+        entries.push(("tooltip", tooltip));
+    }
+    if let Some(target) = opt(preceded(space1, word)).parse_next(input)? {
+        entries.push(("target", target.to_string()));
+    }
+    Ok(Annotation::Nodes(vec![id.to_string()], entries))
+}
+
+/// `classDef name1,name2 fill:#f9f,stroke:#333`
+fn class_def(input: &mut &str) -> winnow::ModalResult<Annotation> {
+    ("classDef", space1).parse_next(input)?;
+    let names = identifiers.parse_next(input)?;
+    space1.parse_next(input)?;
+    let style = rest_of_line.parse_next(input)?;
+    Ok(Annotation::ClassDef(names, style))
+}
+
+/// `class id1,id2 name`
+fn class_assignment(input: &mut &str) -> winnow::ModalResult<Annotation> {
+    ("class", space1).parse_next(input)?;
+    let ids = identifiers.parse_next(input)?;
+    space1.parse_next(input)?;
+    let name = identifier.parse_next(input)?;
+    Ok(Annotation::Nodes(ids, vec![("class", name.to_string())]))
+}
+
+/// `style id fill:#f9f,stroke:#333`
+fn node_style(input: &mut &str) -> winnow::ModalResult<Annotation> {
+    ("style", space1).parse_next(input)?;
+    let id = identifier.parse_next(input)?;
+    space1.parse_next(input)?;
+    let style = rest_of_line.parse_next(input)?;
+    Ok(Annotation::Nodes(vec![id.to_string()], vec![("style", style)]))
+}
+
+fn identifiers(input: &mut &str) -> winnow::ModalResult<Vec<String>> {
+    separated(1.., identifier.map(str::to_string), (space0, ",", space0)).parse_next(input)
+}
+
+fn quoted_text(input: &mut &str) -> winnow::ModalResult<String> {
+    delimited('"', take_while(0.., |c: char| c != '"' && c != '\n'), '"')
+        .map(str::to_string)
+        .parse_next(input)
+}
+
+fn word<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
+    take_while(1.., |c: char| !c.is_whitespace()).parse_next(input)
+}
+
+fn rest_of_line(input: &mut &str) -> winnow::ModalResult<String> {
+    let text = take_while(1.., |c: char| c != '\n' && c != '\r').parse_next(input)?;
+    Ok(text.trim_end().to_string())
+}
+
+fn direction(input: &mut &str) -> winnow::ModalResult<Direction> {
     alt((
         "TD".value(Direction::TopDown),
         "TB".value(Direction::TopDown),
@@ -187,22 +411,24 @@ fn direction(input: &mut &str) -> winnow::Result<Direction> {
     .parse_next(input)
 }
 
-fn identifier<'s>(input: &mut &'s str) -> winnow::Result<&'s str> {
+fn identifier<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
     take_while(1.., |c: char| c.is_alphanumeric() || c == '_').parse_next(input)
 }
 
-fn node_ref(input: &mut &str) -> winnow::Result<NodeDecl> {
+fn node_ref(input: &mut &str) -> winnow::ModalResult<NodeDecl> {
     let id = identifier.parse_next(input)?;
     let shape_label = opt(shape_label).parse_next(input)?;
     let (shape, label) = shape_label.unwrap_or_else(|| (NodeShape::Box, id.to_string()));
+    let class = opt(preceded(":::", identifier)).parse_next(input)?;
     Ok(NodeDecl {
         id: id.to_string(),
         label,
         shape,
+        metadata: class.map(|c| ("class".to_string(), c.to_string())).into_iter().collect(),
     })
 }
 
-fn shape_label(input: &mut &str) -> winnow::Result<(NodeShape, String)> {
+fn shape_label(input: &mut &str) -> winnow::ModalResult<(NodeShape, String)> {
     alt((
         circle_label.map(|l| (NodeShape::Circle, l)),
         round_label.map(|l| (NodeShape::Round, l)),
@@ -212,7 +438,10 @@ fn shape_label(input: &mut &str) -> winnow::Result<(NodeShape, String)> {
     .parse_next(input)
 }
 
-fn quoted_inner(quote: char, closer: char) -> impl FnMut(&mut &str) -> winnow::Result<String> {
+fn quoted_inner(
+    quote: char,
+    (opener, closer): (char, char),
+) -> impl FnMut(&mut &str) -> winnow::ModalResult<String> {
     move |input: &mut &str| {
         if input.starts_with(quote) {
             let _q: char = winnow::token::any.parse_next(input)?;
@@ -221,41 +450,72 @@ fn quoted_inner(quote: char, closer: char) -> impl FnMut(&mut &str) -> winnow::R
             let _q2: char = winnow::token::any.parse_next(input)?;
             Ok(result)
         } else {
-            let text = take_while(1.., move |c: char| c != closer).parse_next(input)?;
+            let text = alt((
+                balanced(opener, closer),
+                take_while(1.., move |c: char| c != closer),
+            ))
+            .parse_next(input)?;
             Ok(text.to_string())
         }
     }
 }
 
-fn round_label(input: &mut &str) -> winnow::Result<String> {
+/// Text up to the `closer` that matches an already-consumed `opener`, so
+/// labels like `B[list [a]]` keep their inner brackets.
+fn balanced<'s>(
+    opener: char,
+    closer: char,
+) -> impl FnMut(&mut &'s str) -> winnow::ModalResult<&'s str> {
+    move |input: &mut &'s str| {
+        let mut depth = 0;
+        for (i, c) in input.char_indices() {
+            if c == '\n' {
+                break;
+            } else if c == opener {
+                depth += 1;
+            } else if c == closer && depth > 0 {
+                depth -= 1;
+            } else if c == closer {
+                if i == 0 {
+                    break;
+                }
+                return Ok(input.next_slice(i));
+            }
+        }
+        Err(winnow::error::ParserError::from_input(input))
+    }
+}
+
+fn round_label(input: &mut &str) -> winnow::ModalResult<String> {
     "(".parse_next(input)?;
-    let text = quoted_inner('"', ')').parse_next(input)?;
+    let text = quoted_inner('"', ('(', ')')).parse_next(input)?;
     ")".parse_next(input)?;
     Ok(text)
 }
 
-fn diamond_label(input: &mut &str) -> winnow::Result<String> {
+fn diamond_label(input: &mut &str) -> winnow::ModalResult<String> {
     "{".parse_next(input)?;
-    let text = quoted_inner('"', '}').parse_next(input)?;
+    let text = quoted_inner('"', ('{', '}')).parse_next(input)?;
     "}".parse_next(input)?;
     Ok(text)
 }
 
-fn circle_label(input: &mut &str) -> winnow::Result<String> {
+fn circle_label(input: &mut &str) -> winnow::ModalResult<String> {
     "((".parse_next(input)?;
-    let text = take_while(1.., |c: char| c != ')').parse_next(input)?;
+    let text = alt((balanced('(', ')'), take_while(1.., |c: char| c != ')')))
+        .parse_next(input)?;
     "))".parse_next(input)?;
     Ok(text.to_string())
 }
 
-fn bracketed_label(input: &mut &str) -> winnow::Result<String> {
+fn bracketed_label(input: &mut &str) -> winnow::ModalResult<String> {
     "[".parse_next(input)?;
-    let text = quoted_inner('"', ']').parse_next(input)?;
+    let text = quoted_inner('"', ('[', ']')).parse_next(input)?;
     "]".parse_next(input)?;
     Ok(text)
 }
 
-fn edge_type(input: &mut &str) -> winnow::Result<EdgeType> {
+fn edge_type(input: &mut &str) -> winnow::ModalResult<EdgeType> {
     alt((
         "-.->".value(EdgeType::DottedArrow),
         "-.-".value(EdgeType::DottedLink),
@@ -267,17 +527,72 @@ fn edge_type(input: &mut &str) -> winnow::Result<EdgeType> {
     .parse_next(input)
 }
 
-fn edge_label(input: &mut &str) -> winnow::Result<String> {
-    "|".parse_next(input)?;
-    let text = take_while(1.., |c: char| c != '|').parse_next(input)?;
+/// `|text|`, or `|"text"|` when the text itself contains `|`. Entities
+/// like `&#124;` and `&quot;` are decoded.
+fn edge_label(input: &mut &str) -> winnow::ModalResult<String> {
     "|".parse_next(input)?;
-    Ok(text.to_string())
+    let text = alt((
+        ("\"", take_while(1.., |c: char| c != '"' && c != '\n'), "\"|").map(|(_, t, _)| t),
+        (take_while(1.., |c: char| c != '|' && c != '\n'), "|").map(|(t, _)| t),
+    ))
+    .parse_next(input)?;
+    Ok(decode_entities(text))
+}
+
+/// Replace HTML character references (`&#124;`, `&#x7C;`, `&quot;`) and
+/// Mermaid's `#124;`/`#quot;` entity codes with the characters they stand
+/// for. Anything else, such as a bare `&`, is kept as written.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['&', '#']) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let skip = usize::from(after.starts_with("&#"));
+        let decoded = after[1 + skip..].split_once(';').and_then(|(code, _)| {
+            let c = entity_char(code)?;
+            Some((c, 1 + skip + code.len() + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &after[len..];
+            }
+            None => {
+                out.push_str(&after[..1]);
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The character for an entity body: a decimal or `x` hex code point, or
+/// one of the common names.
+fn entity_char(code: &str) -> Option<char> {
+    if let Some(hex) = code.strip_prefix(['x', 'X']) {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+    if code.starts_with(|c: char| c.is_ascii_digit()) {
+        return code.parse().ok().and_then(char::from_u32);
+    }
+    match code {
+        "quot" => Some('"'),
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => None,
+    }
 }
 
-fn edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
+fn edge_line(input: &mut &str) -> winnow::ModalResult<GraphLine> {
     let from = node_ref.parse_next(input)?;
     space0.parse_next(input)?;
     let et = edge_type.parse_next(input)?;
+    space0.parse_next(input)?;
     let label = opt(edge_label).parse_next(input)?;
     space0.parse_next(input)?;
     let first_to = node_ref.parse_next(input)?;
@@ -291,6 +606,7 @@ fn edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
         space0.parse_next(input)?;
         extra_targets.push(node_ref.parse_next(input)?);
     }
+    let comment = trailing_comment.parse_next(input)?;
     opt(line_ending).parse_next(input)?;
 
     if extra_targets.is_empty() {
@@ -299,6 +615,7 @@ fn edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
             to: first_to.id.clone(),
             edge_type: et,
             label,
+            comment,
         };
         Ok(GraphLine::Edge(edge, from, first_to))
     } else {
@@ -308,6 +625,7 @@ fn edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
                 to: first_to.id.clone(),
                 edge_type: et,
                 label: label.clone(),
+                comment: comment.clone(),
             },
             from.clone(),
             first_to,
@@ -319,6 +637,7 @@ fn edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
                     to: target.id.clone(),
                     edge_type: et,
                     label: label.clone(),
+                    comment: comment.clone(),
                 },
                 from.clone(),
                 target,
@@ -328,7 +647,7 @@ fn edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
     }
 }
 
-fn alt_edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
+fn alt_edge_line(input: &mut &str) -> winnow::ModalResult<GraphLine> {
     let from = node_ref.parse_next(input)?;
     space0.parse_next(input)?;
     "-- ".parse_next(input)?;
@@ -339,19 +658,21 @@ fn alt_edge_line(input: &mut &str) -> winnow::Result<GraphLine> {
     .parse_next(input)?;
     space0.parse_next(input)?;
     let to = node_ref.parse_next(input)?;
+    let comment = trailing_comment.parse_next(input)?;
     opt(line_ending).parse_next(input)?;
 
-    let label = label_text.trim().to_string();
+    let label = decode_entities(label_text.trim());
     let edge = Edge {
         from: from.id.clone(),
         to: to.id.clone(),
         edge_type: et,
         label: Some(label),
+        comment,
     };
     Ok(GraphLine::Edge(edge, from, to))
 }
 
-fn node_line(input: &mut &str) -> winnow::Result<GraphLine> {
+fn node_line(input: &mut &str) -> winnow::ModalResult<GraphLine> {
     let decl = node_ref.parse_next(input)?;
     opt(line_ending).parse_next(input)?;
     Ok(GraphLine::Node(decl))
@@ -362,6 +683,17 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn parse_error_reports_line_and_column() {
+        let input = "graph TD\n    A --> B\n    A -> ??\n";
+        let err = parse_graph(input).unwrap_err();
+        assert!(
+            err.starts_with("syntax error in graph diagram at line 3:7:"),
+            "got: {err}"
+        );
+        assert!(err.contains("3 |     A -> ??"), "snippet: {err}");
+    }
+
     #[test]
     fn parse_direction_td() {
         let mut input = "TD";
@@ -478,6 +810,35 @@ mod tests {
         assert_eq!(diagram.edges[0].label, Some("text".to_string()));
     }
 
+    #[test]
+    fn parse_edge_label_quotes_and_entities() {
+        let input = "graph TD\n    A -->|say \"hi\"| B\n    B -->|\"a|b\"| C\n    C -->|x &#124; y #quot;z#quot; &amp; R&D| D\n    D -- 1 &lt; 2 --> E\n";
+        let diagram = parse_graph(input).unwrap();
+        let labels: Vec<&str> =
+            diagram.edges.iter().map(|e| e.label.as_deref().unwrap()).collect();
+        assert_eq!(labels, ["say \"hi\"", "a|b", "x | y \"z\" & R&D", "1 < 2"]);
+    }
+
+    #[test]
+    fn parse_edge_comments_and_spaced_labels() {
+        let input = "graph TD\n    %% setup\n    A --> |yes| B %% weight: 3\n    B --> C & D %%fan\n    C -- go --> D\n";
+        let diagram = parse_graph(input).unwrap();
+        let edges: Vec<(Option<&str>, Option<&str>)> = diagram
+            .edges
+            .iter()
+            .map(|e| (e.label.as_deref(), e.comment.as_deref()))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                (Some("yes"), Some("weight: 3")),
+                (None, Some("fan")),
+                (None, Some("fan")),
+                (Some("go"), None),
+            ]
+        );
+    }
+
     #[test]
     fn parse_edge_no_label() {
         let input = "graph TD\n    A --> B\n";
@@ -619,6 +980,22 @@ mod tests {
         assert_eq!(a_nodes[0].label, "Start");
     }
 
+    #[test]
+    fn parse_keeps_first_shape_and_records_later_ones() {
+        let input = "graph TD\n    A --> B\n    B{Decision?}\n    B\n    B{Decision?}\n    \
+                     B(Round)\n    B[Box]\n";
+        let shaped = |n: &NodeDecl| (n.shape, n.label.clone());
+        let mut diagram = parse_graph(input).unwrap();
+        // The shape given after a bare mention isn't a redeclaration
+        assert_eq!(shaped(&diagram.nodes[1]), (NodeShape::Diamond, "Decision?".into()));
+        let later: Vec<_> = diagram.redeclared.iter().map(shaped).collect();
+        assert_eq!(later, [(NodeShape::Round, "Round".into()), (NodeShape::Box, "Box".into())]);
+
+        diagram.apply_redeclared();
+        assert_eq!(shaped(&diagram.nodes[1]), (NodeShape::Box, "Box".into()));
+        assert_eq!(diagram.redeclared, []);
+    }
+
     #[test]
     fn parse_subgraph_basic() {
         let input = "graph TD\n    subgraph Backend\n        A --> B\n    end\n";
@@ -658,6 +1035,15 @@ mod tests {
         assert_eq!(diagram.nodes[0].shape, NodeShape::Box);
     }
 
+    #[test]
+    fn parse_nested_unquoted_labels() {
+        let input = "graph TD\n    A(Compute (fast path)) --> B[list [a] [b]]\n    B --> C{ok {y}?}\n    C --> D((x (y)))\n    D --> E[a [ b]\n";
+        let diagram = parse_graph(input).unwrap();
+        let labels: Vec<&str> = diagram.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels, ["Compute (fast path)", "list [a] [b]", "ok {y}?", "x (y)", "a [ b"]);
+        assert_eq!(diagram.nodes[3].shape, NodeShape::Circle);
+    }
+
     #[test]
     fn parse_quoted_round_label() {
         let input = "graph TD\n    A(\"(inner) text\")\n";
@@ -683,4 +1069,56 @@ mod tests {
         assert_eq!(diagram.nodes.len(), 3);
         assert_eq!(diagram.edges.len(), 2);
     }
+
+    #[test]
+    fn parse_subgraph_id_with_title() {
+        let input = "graph TD\n    subgraph api [Public API]\n        A --> B\n    end\n    subgraph db[\"Data [v2]\"]\n        C\n    end\n    C --> api\n";
+        let diagram = parse_graph(input).unwrap();
+        let ids: Vec<(&str, &str)> =
+            diagram.subgraphs.iter().map(|sg| (sg.id.as_str(), sg.label.as_str())).collect();
+        assert_eq!(ids, vec![("api", "Public API"), ("db", "Data [v2]")]);
+
+        // An edge to the subgraph's id goes to its first node
+        let node_ids: Vec<&str> = diagram.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(node_ids, ["A", "B", "C"]);
+        assert_eq!((diagram.edges[1].from.as_str(), diagram.edges[1].to.as_str()), ("C", "A"));
+
+        let diagram = parse_graph("graph TD\n    subgraph One Two\n    A\n    end\n").unwrap();
+        assert_eq!(diagram.subgraphs[0].id, "one_two");
+    }
+
+    #[test]
+    fn parse_unclosed_subgraph_is_an_error() {
+        let err = parse_graph("graph TD\n    subgraph One\n    A --> B\n").unwrap_err();
+        assert!(
+            err.starts_with("unclosed `subgraph` in graph diagram started at line 2:5:"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn parse_click_class_and_style_metadata() {
+        let input = "graph TD\n    A:::warn --> B\n    classDef warn fill:#f96\n    class B warn\n    style A stroke:#333\n    click A href \"https://example.com\" \"Open docs\" _blank\n    click B notify\n";
+        let diagram = parse_graph(input).unwrap();
+        let metadata = |i: usize| -> Vec<(String, String)> {
+            diagram.nodes[i].metadata.clone().into_iter().collect()
+        };
+        let pairs = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+            items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(
+            metadata(0),
+            pairs(&[
+                ("class", "warn"),
+                ("link", "https://example.com"),
+                ("style", "fill:#f96,stroke:#333"),
+                ("target", "_blank"),
+                ("tooltip", "Open docs"),
+            ])
+        );
+        assert_eq!(
+            metadata(1),
+            pairs(&[("callback", "notify"), ("class", "warn"), ("style", "fill:#f96")])
+        );
+    }
 }
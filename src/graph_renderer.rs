@@ -3,139 +3,222 @@ use std::collections::HashMap;
 use crate::display_width::{display_width, split_br};
 use crate::graph_ast::{Direction, EdgeType, NodeShape};
 use crate::graph_layout::*;
+use crate::grid::{AnsiGrid, Canvas, Grid};
+use crate::style::Theme;
 
-struct Grid {
-    cells: Vec<Vec<char>>,
-    width: usize,
-    height: usize,
+pub fn render(layout: &GraphLayout) -> String {
+    render_with(layout, &Theme::default())
 }
 
-impl Grid {
-    fn new(width: usize, height: usize) -> Self {
-        Self {
-            cells: vec![vec![' '; width]; height],
-            width,
-            height,
-        }
-    }
+/// Render with the given border theme for rectangle nodes and subgraphs.
+pub fn render_with(layout: &GraphLayout, theme: &Theme) -> String {
+    draw(layout, theme).render()
+}
 
-    fn set(&mut self, row: usize, col: usize, ch: char) {
-        if row < self.height && col < self.width {
-            if self.cells[row][col] == '\0' && col > 0 && self.cells[row][col - 1] != '\0' {
-                self.cells[row][col - 1] = ' ';
-            }
-            self.cells[row][col] = ch;
-        }
-    }
+/// Render for a terminal, with the lines dimmed by ANSI escapes.
+pub fn render_ansi(layout: &GraphLayout, theme: &Theme) -> String {
+    let mut grid = AnsiGrid::new(layout.width, layout.height);
+    draw_on(&mut grid, layout, theme);
+    grid.render()
+}
+
+/// Write the rendering to `out` row by row.
+pub fn render_to<W: std::io::Write>(
+    layout: &GraphLayout,
+    theme: &Theme,
+    out: &mut W,
+) -> std::io::Result<()> {
+    draw(layout, theme).write_to(out)
+}
 
-    fn write_str(&mut self, row: usize, col: usize, s: &str) {
-        let mut offset = 0;
-        for ch in s.chars() {
-            self.set(row, col + offset, ch);
-            let w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(1);
-            for j in 1..w {
-                self.set(row, col + offset + j, '\0');
+/// Labelled edges whose label the renderer leaves out: fan-out and fan-in
+/// edges drawn as a shared bus, LR labels with no row or gap to sit in, and
+/// TD edges within a rank whose
+/// label is wider than the gap they cross, and the second label of a pair of
+/// mutual edges drawn as one line. Edges left out altogether are in
+/// [`dropped_edges`] instead. Mirrors the checks in the `draw_*` functions
+/// below.
+pub fn dropped_labels(layout: &GraphLayout) -> Vec<&EdgeLayout> {
+    let node_map: HashMap<&str, &NodeLayout> =
+        layout.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    layout
+        .edges
+        .iter()
+        .filter(|edge| edge.label.is_some() && edge.from_id != edge.to_id)
+        .filter(|edge| {
+            let (Some(&(mut from)), Some(&(mut to))) =
+                (node_map.get(edge.from_id.as_str()), node_map.get(edge.to_id.as_str()))
+            else {
+                return false;
+            };
+            if let Some(reverse) = reverse_edge(layout, edge)
+                && !runs_forward(layout, from, to)
+            {
+                // Drawn on its reverse's line, which shows only one label
+                if reverse.label.is_some() {
+                    return true;
+                }
+                (from, to) = (to, from);
             }
-            offset += w;
-        }
-    }
+            match layout.direction {
+                Direction::TopDown if side_by_side(from, to) => {
+                    let width = display_width(edge.label.as_deref().unwrap_or_default());
+                    side_route(layout, from, to)
+                        .is_none_or(|(row, start, end)| row == 0 || start + width > end)
+                }
+                Direction::TopDown if to.y <= from.y + from.height => false,
+                Direction::TopDown => {
+                    let parents = beyond(layout, to, layout.adjacency.parents(&to.id), false);
+                    beyond(layout, from, layout.adjacency.children(&from.id), true).len() > 1
+                        || (parents.len() > 1
+                            && parents.windows(2).all(|w| {
+                                layout.nodes[w[0]].y == layout.nodes[w[1]].y
+                            }))
+                }
+                Direction::LeftRight => {
+                    let from_right = from.x + from.width;
+                    if to.x <= from_right {
+                        false
+                    } else if lr_label_at_target(layout, from, to) {
+                        to.center_y == 0
+                    } else if from.center_y == 0 {
+                        true
+                    } else if from.center_y == to.center_y {
+                        false
+                    } else {
+                        let mid_col = from_right + (to.x - from_right) / 2;
+                        mid_col <= from_right
+                    }
+                }
+            }
+        })
+        .collect()
+}
 
-    fn set_merge(&mut self, row: usize, col: usize, ch: char) {
-        if row < self.height && col < self.width {
-            let existing = self.cells[row][col];
-            let merged = merge_box_drawing(existing, ch);
-            self.set(row, col, merged);
-        }
-    }
+/// Edges the renderer leaves out for want of a route: TD edges back up to
+/// an earlier rank and LR edges back into one, unless drawn along their
+/// reverse. Mirrors `draw_td_edge` and `draw_lr_edge`.
+pub fn dropped_edges(layout: &GraphLayout) -> Vec<&EdgeLayout> {
+    let node_map: HashMap<&str, &NodeLayout> =
+        layout.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    layout
+        .edges
+        .iter()
+        .filter(|edge| {
+            let (Some(from), Some(to)) =
+                (node_map.get(edge.from_id.as_str()), node_map.get(edge.to_id.as_str()))
+            else {
+                return false;
+            };
+            let back = match layout.direction {
+                Direction::TopDown => !side_by_side(from, to) && to.y <= from.y + from.height,
+                Direction::LeftRight => to.x <= from.x + from.width,
+            };
+            edge.from_id != edge.to_id && reverse_edge(layout, edge).is_none() && back
+        })
+        .collect()
+}
 
-    fn render(&self) -> String {
-        self.cells
-            .iter()
-            .map(|row| {
-                let line: String = row.iter().filter(|&&ch| ch != '\0').collect();
-                line.trim_end().to_string()
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
-    }
+fn draw(layout: &GraphLayout, theme: &Theme) -> Grid {
+    let mut grid = Grid::new(layout.width, layout.height);
+    draw_on(&mut grid, layout, theme);
+    grid
 }
 
-pub fn render(layout: &GraphLayout) -> String {
+/// Draw onto a canvas of at least `layout.width` by `layout.height` cells.
+pub fn draw_on(grid: &mut impl Canvas, layout: &GraphLayout, theme: &Theme) {
     match layout.direction {
-        Direction::TopDown => render_td(layout),
-        Direction::LeftRight => render_lr(layout),
+        Direction::TopDown => draw_td(grid, layout, theme),
+        Direction::LeftRight => draw_lr(grid, layout, theme),
     }
 }
 
-fn render_td(layout: &GraphLayout) -> String {
-    let mut grid = Grid::new(layout.width, layout.height);
+fn draw_td(grid: &mut impl Canvas, layout: &GraphLayout, theme: &Theme) {
     let node_map: HashMap<&str, &NodeLayout> =
         layout.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
 
     for sg in &layout.subgraphs {
-        draw_subgraph(&mut grid, sg);
+        draw_subgraph(grid, theme, sg);
     }
 
     for node in &layout.nodes {
-        draw_node(&mut grid, node);
+        draw_node(grid, theme, node);
     }
 
     // Draw non-self-loop edges first, then self-loops on top
     // so self-loop labels aren't overwritten by cross-rank routing
+    let mut reverse_heads = Vec::new();
     for edge in &layout.edges {
         if edge.from_id == edge.to_id {
             continue;
         }
-        let from = node_map[edge.from_id.as_str()];
-        let to = node_map[edge.to_id.as_str()];
-        draw_td_edge(&mut grid, from, to, edge, layout);
+        let (Some(&from), Some(&to)) =
+            (node_map.get(edge.from_id.as_str()), node_map.get(edge.to_id.as_str()))
+        else {
+            continue;
+        };
+        let Some((edge, reverse)) = with_reverse(layout, edge, from, to) else { continue };
+        let routed = draw_td_edge(grid, theme, from, to, &edge, layout);
+        if routed && reverse.is_some_and(|r| has_arrow_head(r.edge_type)) {
+            reverse_heads.push((from, to));
+        }
+    }
+    // After every edge, so a fan-out from the same node doesn't cover them
+    for (from, to) in reverse_heads {
+        draw_td_reverse_head(grid, from, to, layout);
     }
     for edge in &layout.edges {
         if edge.from_id != edge.to_id {
             continue;
         }
-        let from = node_map[edge.from_id.as_str()];
-        draw_td_self_loop(&mut grid, from, edge);
+        let Some(&from) = node_map.get(edge.from_id.as_str()) else { continue };
+        draw_td_self_loop(grid, theme, from, edge, layout);
     }
-
-    grid.render()
 }
 
-fn render_lr(layout: &GraphLayout) -> String {
-    let mut grid = Grid::new(layout.width, layout.height);
+fn draw_lr(grid: &mut impl Canvas, layout: &GraphLayout, theme: &Theme) {
     let node_map: HashMap<&str, &NodeLayout> =
         layout.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
 
     for sg in &layout.subgraphs {
-        draw_subgraph(&mut grid, sg);
+        draw_subgraph(grid, theme, sg);
     }
 
     for node in &layout.nodes {
-        draw_node(&mut grid, node);
+        draw_node(grid, theme, node);
     }
 
+    let mut reverse_heads = Vec::new();
     for edge in &layout.edges {
         if edge.from_id == edge.to_id {
             continue;
         }
-        let from = node_map[edge.from_id.as_str()];
-        let to = node_map[edge.to_id.as_str()];
-        draw_lr_edge(&mut grid, from, to, edge);
+        let (Some(&from), Some(&to)) =
+            (node_map.get(edge.from_id.as_str()), node_map.get(edge.to_id.as_str()))
+        else {
+            continue;
+        };
+        let Some((edge, reverse)) = with_reverse(layout, edge, from, to) else { continue };
+        draw_lr_edge(grid, theme, from, to, &edge, layout);
+        if reverse.is_some_and(|r| has_arrow_head(r.edge_type)) && to.x > from.x + from.width {
+            reverse_heads.push((from.center_y, from.x + from.width));
+        }
+    }
+    for (row, col) in reverse_heads {
+        grid.set(row, col, '<');
     }
     for edge in &layout.edges {
         if edge.from_id != edge.to_id {
             continue;
         }
-        let from = node_map[edge.from_id.as_str()];
-        draw_td_self_loop(&mut grid, from, edge);
+        let Some(&from) = node_map.get(edge.from_id.as_str()) else { continue };
+        draw_td_self_loop(grid, theme, from, edge, layout);
     }
-
-    grid.render()
 }
 
-fn draw_node(grid: &mut Grid, node: &NodeLayout) {
+fn draw_node(grid: &mut impl Canvas, theme: &Theme, node: &NodeLayout) {
     match node.shape {
-        NodeShape::Box => draw_box(grid, node.x, node.y, node.width, node.height, &node.label),
+        NodeShape::Box => draw_box(grid, theme, node.x, node.y, node.width, node.height, &node.label),
         NodeShape::Round | NodeShape::Circle => {
             draw_round(grid, node.x, node.y, node.width, node.height, &node.label)
         }
@@ -145,85 +228,31 @@ fn draw_node(grid: &mut Grid, node: &NodeLayout) {
     }
 }
 
-fn draw_subgraph(grid: &mut Grid, sg: &SubgraphLayout) {
+fn draw_subgraph(grid: &mut impl Canvas, theme: &Theme, sg: &SubgraphLayout) {
     let x = sg.x;
     let y = sg.y;
-    let w = sg.width;
-    let h = sg.height;
 
-    grid.set(y, x, '┌');
-    grid.set(y, x + 1, '─');
+    grid.rect(x, y, sg.width, sg.height, theme);
     grid.set(y, x + 2, ' ');
     grid.write_str(y, x + 3, &sg.label);
     grid.set(y, x + 3 + display_width(&sg.label), ' ');
-    for col in (x + 4 + display_width(&sg.label))..(x + w - 1) {
-        grid.set(y, col, '─');
-    }
-    grid.set(y, x + w - 1, '┐');
-
-    for row in (y + 1)..(y + h - 1) {
-        grid.set(row, x, '│');
-        grid.set(row, x + w - 1, '│');
-    }
-
-    grid.set(y + h - 1, x, '└');
-    for col in (x + 1)..(x + w - 1) {
-        grid.set(y + h - 1, col, '─');
-    }
-    grid.set(y + h - 1, x + w - 1, '┘');
 }
 
-fn draw_box(grid: &mut Grid, x: usize, y: usize, width: usize, height: usize, label: &str) {
-    let lines = split_br(label);
-
-    grid.set(y, x, '┌');
-    for col in (x + 1)..(x + width - 1) {
-        grid.set(y, col, '─');
+fn draw_box(grid: &mut impl Canvas, theme: &Theme, x: usize, y: usize, width: usize, height: usize, label: &str) {
+    grid.rect(x, y, width, height, theme);
+    for (i, line) in split_br(label).iter().enumerate() {
+        write_centered(grid, y + 1 + i, x, width, line);
     }
-    grid.set(y, x + width - 1, '┐');
-
-    for (i, line) in lines.iter().enumerate() {
-        let row = y + 1 + i;
-        grid.set(row, x, '│');
-        grid.write_str(row, x + 2, line);
-        grid.set(row, x + width - 1, '│');
-    }
-
-    let bottom = y + height - 1;
-    grid.set(bottom, x, '└');
-    for col in (x + 1)..(x + width - 1) {
-        grid.set(bottom, col, '─');
-    }
-    grid.set(bottom, x + width - 1, '┘');
 }
 
-fn draw_round(grid: &mut Grid, x: usize, y: usize, width: usize, height: usize, label: &str) {
-    let lines = split_br(label);
-
-    grid.set(y, x, '╭');
-    for col in (x + 1)..(x + width - 1) {
-        grid.set(y, col, '─');
-    }
-    grid.set(y, x + width - 1, '╮');
-
-    let inner = width - 2;
-    for (i, line) in lines.iter().enumerate() {
-        let row = y + 1 + i;
-        grid.set(row, x, '│');
-        let pad_left = (inner - display_width(line)) / 2;
-        grid.write_str(row, x + 1 + pad_left, line);
-        grid.set(row, x + width - 1, '│');
-    }
-
-    let bottom = y + height - 1;
-    grid.set(bottom, x, '╰');
-    for col in (x + 1)..(x + width - 1) {
-        grid.set(bottom, col, '─');
+fn draw_round(grid: &mut impl Canvas, x: usize, y: usize, width: usize, height: usize, label: &str) {
+    grid.rect(x, y, width, height, &Theme::ROUNDED);
+    for (i, line) in split_br(label).iter().enumerate() {
+        write_centered(grid, y + 1 + i, x, width, line);
     }
-    grid.set(bottom, x + width - 1, '╯');
 }
 
-fn draw_diamond(grid: &mut Grid, x: usize, y: usize, width: usize, height: usize, label: &str) {
+fn draw_diamond(grid: &mut impl Canvas, x: usize, y: usize, width: usize, height: usize, label: &str) {
     let lines = split_br(label);
 
     // Top border (inset by 2, no corners)
@@ -239,7 +268,7 @@ fn draw_diamond(grid: &mut Grid, x: usize, y: usize, width: usize, height: usize
     for (i, line) in lines.iter().enumerate() {
         let row = y + 2 + i;
         grid.set(row, x, '│');
-        grid.write_str(row, x + 2, line);
+        write_centered(grid, row, x, width, line);
         grid.set(row, x + width - 1, '│');
     }
 
@@ -255,52 +284,11 @@ fn draw_diamond(grid: &mut Grid, x: usize, y: usize, width: usize, height: usize
     }
 }
 
-const DIR_L: u8 = 1;
-const DIR_R: u8 = 2;
-const DIR_U: u8 = 4;
-const DIR_D: u8 = 8;
-
-fn box_connections(ch: char) -> u8 {
-    match ch {
-        '─' | '═' | '╌' => DIR_L | DIR_R,
-        '│' | '║' | '┊' => DIR_U | DIR_D,
-        '┌' => DIR_R | DIR_D,
-        '┐' => DIR_L | DIR_D,
-        '└' => DIR_R | DIR_U,
-        '┘' => DIR_L | DIR_U,
-        '┬' => DIR_L | DIR_R | DIR_D,
-        '┴' => DIR_L | DIR_R | DIR_U,
-        '├' => DIR_U | DIR_D | DIR_R,
-        '┤' => DIR_U | DIR_D | DIR_L,
-        '┼' => DIR_L | DIR_R | DIR_U | DIR_D,
-        _ => 0,
-    }
-}
-
-fn connections_to_char(conn: u8) -> Option<char> {
-    match conn {
-        c if c == DIR_L | DIR_R => Some('─'),
-        c if c == DIR_U | DIR_D => Some('│'),
-        c if c == DIR_R | DIR_D => Some('┌'),
-        c if c == DIR_L | DIR_D => Some('┐'),
-        c if c == DIR_R | DIR_U => Some('└'),
-        c if c == DIR_L | DIR_U => Some('┘'),
-        c if c == DIR_L | DIR_R | DIR_D => Some('┬'),
-        c if c == DIR_L | DIR_R | DIR_U => Some('┴'),
-        c if c == DIR_U | DIR_D | DIR_R => Some('├'),
-        c if c == DIR_U | DIR_D | DIR_L => Some('┤'),
-        c if c == DIR_L | DIR_R | DIR_U | DIR_D => Some('┼'),
-        _ => None,
-    }
-}
-
-fn merge_box_drawing(existing: char, new_char: char) -> char {
-    let ec = box_connections(existing);
-    let nc = box_connections(new_char);
-    if ec == 0 {
-        return new_char;
-    }
-    connections_to_char(ec | nc).unwrap_or(new_char)
+/// Write one line of a node label on `row`, centered between the borders of
+/// a shape `width` wide starting at column `x`.
+fn write_centered(grid: &mut impl Canvas, row: usize, x: usize, width: usize, line: &str) {
+    let pad_left = (width - 2).saturating_sub(display_width(line)) / 2;
+    grid.write_str(row, x + 1 + pad_left, line);
 }
 
 fn td_vertical_connector(edge_type: EdgeType) -> char {
@@ -318,11 +306,28 @@ fn has_arrow_head(edge_type: EdgeType) -> bool {
     )
 }
 
-fn is_subgraph_border_row(layout: &GraphLayout, row: usize) -> bool {
-    layout
-        .subgraphs
-        .iter()
-        .any(|sg| row == sg.y || row == sg.y + sg.height - 1)
+/// The subgraph whose top or bottom border runs through (`row`, `col`).
+fn subgraph_border_at(layout: &GraphLayout, row: usize, col: usize) -> Option<&SubgraphLayout> {
+    layout.subgraphs.iter().find(|sg| {
+        let (bottom, right) = (sg.y + sg.height - 1, sg.x + sg.width - 1);
+        let on_top_or_bottom = (row == sg.y || row == bottom) && (sg.x..=right).contains(&col);
+        let on_side = (col == sg.x || col == right) && (sg.y..=bottom).contains(&row);
+        on_top_or_bottom || on_side
+    })
+}
+
+/// Draw one cell of an edge's line. Where the line crosses or meets a
+/// subgraph's frame it joins the frame, as a tee or a cross, leaving the
+/// subgraph's title alone.
+fn set_edge_cell(grid: &mut impl Canvas, layout: &GraphLayout, row: usize, col: usize, ch: char) {
+    let on_title = |sg: &SubgraphLayout| {
+        row == sg.y && (sg.x + 2..=sg.x + 3 + display_width(&sg.label)).contains(&col)
+    };
+    match subgraph_border_at(layout, row, col) {
+        None => grid.set(row, col, ch),
+        Some(sg) if on_title(sg) => {}
+        Some(_) => grid.set_merge(row, col, ch),
+    }
 }
 
 fn route_crosses_node(
@@ -344,7 +349,7 @@ fn route_crosses_node(
 }
 
 fn draw_td_single_edge_route(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
     from_cx: usize,
     to_cx: usize,
     from_below: usize,
@@ -356,9 +361,16 @@ fn draw_td_single_edge_route(
     let vert = td_vertical_connector(edge_type);
 
     let route_start = if let Some(ref label) = edge.label {
+        // Below the border of a subgraph the edge leaves
+        let label_row = (from_below..to_above)
+            .find(|&row| subgraph_border_at(layout, row, from_cx).is_none())
+            .unwrap_or(from_below);
+        for row in from_below..label_row {
+            set_edge_cell(grid, layout, row, from_cx, vert);
+        }
         let label_col = from_cx.saturating_sub(display_width(label) / 2);
-        grid.write_str(from_below, label_col, label);
-        from_below + 1
+        grid.write_str(label_row, label_col, label);
+        label_row + 1
     } else {
         from_below
     };
@@ -375,28 +387,24 @@ fn draw_td_single_edge_route(
     if from_cx == to_cx && from_col_clear {
         // Straight down
         for row in route_start..to_above {
-            if !is_subgraph_border_row(layout, row) {
-                grid.set(row, from_cx, vert);
-            }
+            set_edge_cell(grid, layout, row, from_cx, vert);
         }
     } else if from_col_clear && to_above > route_start {
         // Source column is clear: route down at from_cx, turn at to_above row.
         // The turn shares the to_above row with the arrow head.
         for row in route_start..to_above {
-            if !is_subgraph_border_row(layout, row) {
-                grid.set(row, from_cx, vert);
-            }
+            set_edge_cell(grid, layout, row, from_cx, vert);
         }
         // Draw horizontal + corner at to_above (▼ overwrites to_cx later)
         if from_cx < to_cx {
             grid.set_merge(to_above, from_cx, '└');
             for col in (from_cx + 1)..to_cx {
-                grid.set(to_above, col, '─');
+                set_edge_cell(grid, layout, to_above, col, '─');
             }
         } else {
             grid.set_merge(to_above, from_cx, '┘');
             for col in (to_cx + 1)..from_cx {
-                grid.set(to_above, col, '─');
+                set_edge_cell(grid, layout, to_above, col, '─');
             }
         }
     } else if !from_col_clear && to_above > route_start {
@@ -412,14 +420,14 @@ fn draw_td_single_edge_route(
             .unwrap_or(from_cx)
             + 1;
 
-        if gutter_col < grid.width {
-            for col in (from_cx + 1)..=gutter_col {
-                grid.set(route_start, col, '─');
+        if gutter_col < grid.width() {
+            for col in (from_cx + 1)..gutter_col {
+                set_edge_cell(grid, layout, route_start, col, '─');
             }
-            grid.set(route_start, gutter_col, '┐');
+            set_edge_cell(grid, layout, route_start, gutter_col, '┐');
 
             for row in (route_start + 1)..to_above {
-                grid.set(row, gutter_col, vert);
+                set_edge_cell(grid, layout, row, gutter_col, vert);
             }
 
             let (turn, a, b) = if to_cx < gutter_col {
@@ -429,57 +437,197 @@ fn draw_td_single_edge_route(
             };
             grid.set_merge(to_above, gutter_col, turn);
             for col in a..b {
-                grid.set(to_above, col, '─');
+                set_edge_cell(grid, layout, to_above, col, '─');
             }
         }
     } else if edge.label.is_none() && from_cx != to_cx && to_above > from_below {
         // No label, original L-shaped routing at midpoint
         let mid_row = from_below + (to_above - from_below) / 2;
         for row in from_below..mid_row {
-            if !is_subgraph_border_row(layout, row) {
-                grid.set(row, from_cx, vert);
-            }
+            set_edge_cell(grid, layout, row, from_cx, vert);
         }
         let (left, right) = if from_cx < to_cx {
-            grid.set(mid_row, from_cx, '└');
-            grid.set(mid_row, to_cx, '┐');
+            set_edge_cell(grid, layout, mid_row, from_cx, '└');
+            set_edge_cell(grid, layout, mid_row, to_cx, '┐');
             (from_cx + 1, to_cx)
         } else {
-            grid.set(mid_row, from_cx, '┘');
-            grid.set(mid_row, to_cx, '┌');
+            set_edge_cell(grid, layout, mid_row, from_cx, '┘');
+            set_edge_cell(grid, layout, mid_row, to_cx, '┌');
             (to_cx + 1, from_cx)
         };
         for col in left..right {
-            grid.set(mid_row, col, '─');
+            set_edge_cell(grid, layout, mid_row, col, '─');
         }
         for row in (mid_row + 1)..to_above {
-            if !is_subgraph_border_row(layout, row) {
-                grid.set(row, to_cx, vert);
-            }
+            set_edge_cell(grid, layout, row, to_cx, vert);
         }
     }
     // else: label + arrow only (no intermediate routing)
 
-    if !is_subgraph_border_row(layout, to_above) {
-        if has_arrow_head(edge_type) {
-            grid.set(to_above, to_cx, '▼');
-        } else {
-            grid.set(to_above, to_cx, vert);
+    let head = if has_arrow_head(edge_type) { '▼' } else { vert };
+    set_edge_cell(grid, layout, to_above, to_cx, head);
+}
+
+/// The edge from `edge`'s target back to its source, if there is one.
+fn reverse_edge<'a>(layout: &'a GraphLayout, edge: &EdgeLayout) -> Option<&'a EdgeLayout> {
+    layout.edges.iter().find(|e| e.from_id == edge.to_id && e.to_id == edge.from_id)
+}
+
+/// Whether an edge from `from` to `to` runs with the layout: down in TD or
+/// right in LR, and within a rank, right in TD or down in LR.
+fn runs_forward(layout: &GraphLayout, from: &NodeLayout, to: &NodeLayout) -> bool {
+    match layout.direction {
+        Direction::TopDown => (to.y, to.x) > (from.y, from.x),
+        Direction::LeftRight => (to.x, to.y) > (from.x, from.y),
+    }
+}
+
+/// `edge` as it should be drawn, with the reverse edge it is drawn together
+/// with. Mutual edges (`A --> B` plus `B --> A`) share one line, drawn
+/// along the forward edge with a head at each end and the first label
+/// either has; `None` for the edge that is drawn by its reverse.
+fn with_reverse<'a>(
+    layout: &'a GraphLayout,
+    edge: &EdgeLayout,
+    from: &NodeLayout,
+    to: &NodeLayout,
+) -> Option<(EdgeLayout, Option<&'a EdgeLayout>)> {
+    let reverse = reverse_edge(layout, edge);
+    match reverse {
+        Some(_) if !runs_forward(layout, from, to) => None,
+        Some(r) => {
+            let label = edge.label.clone().or(r.label.clone());
+            Some((EdgeLayout { label, ..edge.clone() }, reverse))
         }
+        None => Some((edge.clone(), None)),
     }
 }
 
-fn draw_td_self_loop(grid: &mut Grid, node: &NodeLayout, edge: &EdgeLayout) {
+/// The head of a reverse edge drawn along a TD edge: on the source's bottom
+/// border, or at the source end of an edge within a rank.
+fn draw_td_reverse_head(grid: &mut impl Canvas, from: &NodeLayout, to: &NodeLayout, layout: &GraphLayout) {
+    if side_by_side(from, to) {
+        if let Some((row, start, end)) = side_route(layout, from, to) {
+            let (col, head) = if to.x > from.x { (start, '<') } else { (end - 1, '>') };
+            grid.set(row, col, head);
+        }
+    } else if to.y > from.y + from.height {
+        grid.set(from.y + from.height - 1, from.center_x, '▲');
+    }
+}
+
+/// Whether `a` and `b` share a row, as nodes of the same rank do.
+fn side_by_side(a: &NodeLayout, b: &NodeLayout) -> bool {
+    a.y < b.y + b.height && b.y < a.y + a.height
+}
+
+/// The nodes of `neighbours` wholly below `node`, or above it when `below`
+/// is false: the ones a fan-out or fan-in bus reaches. Edges within a rank
+/// or back up to an earlier one are drawn on their own.
+fn beyond(
+    layout: &GraphLayout,
+    node: &NodeLayout,
+    neighbours: &[usize],
+    below: bool,
+) -> Vec<usize> {
+    let beyond = |other: &NodeLayout| match below {
+        true => other.y >= node.y + node.height,
+        false => other.y + other.height <= node.y,
+    };
+    neighbours.iter().copied().filter(|&i| beyond(&layout.nodes[i])).collect()
+}
+
+/// Where a TD edge between nodes of the same rank runs: the middle of the
+/// rows where both have a straight side, or failing that of the rows they
+/// share, and the columns between their outlines on that row. Nodes that
+/// aren't neighbours have no route around the ones between them yet.
+fn side_route(
+    layout: &GraphLayout,
+    from: &NodeLayout,
+    to: &NodeLayout,
+) -> Option<(usize, usize, usize)> {
+    let (top, bottom) = (from.y.max(to.y), (from.y + from.height).min(to.y + to.height));
+    let (from_sides, to_sides) = (straight_sides(from), straight_sides(to));
+    let (side_top, side_bottom) =
+        (from_sides.start.max(to_sides.start), from_sides.end.min(to_sides.end));
+    let row = if side_top < side_bottom {
+        (side_top + side_bottom - 1) / 2
+    } else {
+        (top + bottom - 1) / 2
+    };
+    let (left, right) = if from.x < to.x { (from, to) } else { (to, from) };
+    let (start, end) = (border_col(left, row, true) + 1, border_col(right, row, false));
+    let blocked =
+        (start..end).any(|col| route_crosses_node(layout, col, row, row + 1, &from.id, &to.id));
+    (start < end && !blocked).then_some((row, start, end))
+}
+
+/// The rows on which `node`'s left and right sides are straight: between
+/// the corners, or a diamond's slopes.
+fn straight_sides(node: &NodeLayout) -> std::ops::Range<usize> {
+    let inset = if node.shape == NodeShape::Diamond { 2 } else { 1 };
+    node.y + inset..(node.y + node.height).saturating_sub(inset)
+}
+
+/// The column of `node`'s outline on its left or right side on `row`,
+/// where an edge coming in from the side meets it. Diamonds narrow on their
+/// slopes and their top and bottom lines.
+fn border_col(node: &NodeLayout, row: usize, right: bool) -> usize {
+    let bottom = node.y + node.height - 1;
+    let inset = match node.shape {
+        NodeShape::Diamond if row == node.y || row == bottom => 2,
+        NodeShape::Diamond if row == node.y + 1 || row + 1 == bottom => 1,
+        _ => 0,
+    };
+    if right { node.x + node.width - 1 - inset } else { node.x + inset }
+}
+
+/// A TD edge between nodes of the same rank, straight across the gap
+/// between their boxes. Its label goes above it when the gap is wide enough.
+fn draw_td_side_edge(
+    grid: &mut impl Canvas,
+    from: &NodeLayout,
+    to: &NodeLayout,
+    edge: &EdgeLayout,
+    layout: &GraphLayout,
+) {
+    let Some((row, start, end)) = side_route(layout, from, to) else { return };
+    let horiz = lr_horizontal_connector(edge.edge_type);
+    for col in start..end {
+        grid.set_merge(row, col, horiz);
+    }
+    if has_arrow_head(edge.edge_type) {
+        let (col, head) = if to.x > from.x { (end - 1, '>') } else { (start, '<') };
+        grid.set(row, col, head);
+    }
+    if let Some(ref label) = edge.label
+        && display_width(label) <= end - start
+        && row > 0
+    {
+        let label_col = start + (end - start - display_width(label)) / 2;
+        grid.write_str(row - 1, label_col, label);
+    }
+}
+
+fn draw_td_self_loop(
+    grid: &mut impl Canvas,
+    theme: &Theme,
+    node: &NodeLayout,
+    edge: &EdgeLayout,
+    layout: &GraphLayout,
+) {
     let right_col = node.x + node.width - 1;
     let arm_col = right_col + 1;
     let loop_col = right_col + 2;
-    let mid_row = node.y + 1; // text row where ├ goes
+    // First text row, where ├ goes
+    let mid_row = straight_sides(node).start;
     let from_below = node.y + node.height;
 
     // ├─┐ on the text row
-    grid.set(mid_row, right_col, '├');
-    grid.set(mid_row, arm_col, '─');
-    grid.set(mid_row, loop_col, '┐');
+    let connector = if node.shape == NodeShape::Box { theme.connector_right } else { '├' };
+    grid.set(mid_row, right_col, connector);
+    set_edge_cell(grid, layout, mid_row, arm_col, '─');
+    set_edge_cell(grid, layout, mid_row, loop_col, '┐');
 
     // label to the right of the arm
     if let Some(ref label) = edge.label {
@@ -488,28 +636,40 @@ fn draw_td_self_loop(grid: &mut Grid, node: &NodeLayout, edge: &EdgeLayout) {
 
     // │ going down
     for row in (mid_row + 1)..from_below {
-        grid.set(row, loop_col, '│');
+        set_edge_cell(grid, layout, row, loop_col, '│');
     }
 
     // ◄─┘ on the from_below row, right of center_x
     let return_col = node.center_x + 1;
     grid.set(from_below, return_col, '◄');
     for col in (return_col + 1)..loop_col {
-        grid.set(from_below, col, '─');
+        set_edge_cell(grid, layout, from_below, col, '─');
     }
-    grid.set(from_below, loop_col, '┘');
+    set_edge_cell(grid, layout, from_below, loop_col, '┘');
 }
 
+/// Draw a TD edge, returning whether its line reaches `to`: edges back up
+/// to an earlier rank, and fan-out or fan-in edges past a rank in between,
+/// aren't routed yet.
 fn draw_td_edge(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
+    theme: &Theme,
     from: &NodeLayout,
     to: &NodeLayout,
     edge: &EdgeLayout,
     layout: &GraphLayout,
-) {
+) -> bool {
     if from.id == to.id {
-        draw_td_self_loop(grid, from, edge);
-        return;
+        draw_td_self_loop(grid, theme, from, edge, layout);
+        return true;
+    }
+    if side_by_side(from, to) {
+        draw_td_side_edge(grid, from, to, edge, layout);
+        return true;
+    }
+    // Edges back up to an earlier rank (cycles) have no route yet
+    if to.y <= from.y + from.height {
+        return false;
     }
 
     let edge_type = edge.edge_type;
@@ -519,49 +679,33 @@ fn draw_td_edge(
     let from_below = from.y + from.height;
     let to_above = to.y - 1;
 
-    grid.set(bottom_row, from_cx, '┬');
+    let connector = if from.shape == NodeShape::Box { theme.connector_down } else { '┬' };
+    grid.set(bottom_row, from_cx, connector);
 
-    let sibling_count = layout
-        .edges
-        .iter()
-        .filter(|e| e.from_id == from.id && e.from_id != e.to_id)
-        .count();
-    let parent_count = layout
-        .edges
-        .iter()
-        .filter(|e| e.to_id == to.id && e.from_id != e.to_id)
-        .count();
+    let children = beyond(layout, from, layout.adjacency.children(&from.id), true);
+    let parents = beyond(layout, to, layout.adjacency.parents(&to.id), false);
+    // A bus only joins the ranks on either side of it
+    let next_rank = !layout.nodes.iter().any(|n| n.y >= from_below && n.y + n.height <= to.y);
 
-    if sibling_count > 1 {
-        let child_centers: Vec<usize> = layout
-            .edges
-            .iter()
-            .filter(|e| e.from_id == from.id && e.from_id != e.to_id)
-            .filter_map(|e| layout.nodes.iter().find(|n| n.id == e.to_id))
-            .map(|n| n.center_x)
-            .collect();
-        let min_cx = *child_centers.iter().min().unwrap();
-        let max_cx = *child_centers.iter().max().unwrap();
-
-        grid.set(from_below, min_cx, '┌');
+    if children.len() > 1 {
+        let child_centers = children.iter().map(|&i| layout.nodes[i].center_x);
+        let min_cx = child_centers.clone().min().unwrap();
+        let max_cx = child_centers.max().unwrap();
+
+        set_edge_cell(grid, layout, from_below, min_cx, '┌');
         for col in (min_cx + 1)..max_cx {
-            grid.set(from_below, col, '─');
+            set_edge_cell(grid, layout, from_below, col, '─');
         }
-        grid.set(from_below, max_cx, '┐');
-        grid.set(from_below, from_cx, '┴');
+        set_edge_cell(grid, layout, from_below, max_cx, '┐');
+        set_edge_cell(grid, layout, from_below, from_cx, '┴');
 
         if has_arrow_head(edge_type) {
             grid.set(to_above, to_cx, '▼');
         } else {
             grid.set(to_above, to_cx, td_vertical_connector(edge_type));
         }
-    } else if parent_count > 1 {
-        let parents: Vec<&NodeLayout> = layout
-            .edges
-            .iter()
-            .filter(|e| e.to_id == to.id && e.from_id != e.to_id)
-            .filter_map(|e| layout.nodes.iter().find(|n| n.id == e.from_id))
-            .collect();
+    } else if parents.len() > 1 {
+        let parents: Vec<&NodeLayout> = parents.iter().map(|&i| &layout.nodes[i]).collect();
         let all_same_y = parents.windows(2).all(|w| w[0].y == w[1].y);
 
         if all_same_y {
@@ -569,12 +713,12 @@ fn draw_td_edge(
             let min_cx = *parent_centers.iter().min().unwrap();
             let max_cx = *parent_centers.iter().max().unwrap();
 
-            grid.set(from_below, min_cx, '└');
+            set_edge_cell(grid, layout, from_below, min_cx, '└');
             for col in (min_cx + 1)..max_cx {
-                grid.set(from_below, col, '─');
+                set_edge_cell(grid, layout, from_below, col, '─');
             }
-            grid.set(from_below, max_cx, '┘');
-            grid.set(from_below, to_cx, '┬');
+            set_edge_cell(grid, layout, from_below, max_cx, '┘');
+            set_edge_cell(grid, layout, from_below, to_cx, '┬');
 
             if has_arrow_head(edge_type) {
                 grid.set(to_above, to_cx, '▼');
@@ -585,10 +729,27 @@ fn draw_td_edge(
             draw_td_single_edge_route(
                 grid, from_cx, to_cx, from_below, to_above, edge, layout,
             );
+            return true;
         }
     } else {
         draw_td_single_edge_route(grid, from_cx, to_cx, from_below, to_above, edge, layout);
+        return true;
     }
+    next_rank
+}
+
+/// Whether the label of an LR edge from `from` to `to` goes over the
+/// target's end of the line: when `from` has more than one labelled edge
+/// into `to`'s rank, which would otherwise all share the row above `from`.
+/// The layout leaves room for these past the line's vertical segment.
+fn lr_label_at_target(layout: &GraphLayout, from: &NodeLayout, to: &NodeLayout) -> bool {
+    let labelled = layout.edges.iter().filter(|e| {
+        e.label.is_some()
+            && e.from_id == from.id
+            && e.to_id != from.id
+            && layout.nodes.iter().any(|n| n.id == e.to_id && n.x == to.x)
+    });
+    labelled.count() > 1
 }
 
 fn lr_horizontal_connector(edge_type: EdgeType) -> char {
@@ -600,19 +761,37 @@ fn lr_horizontal_connector(edge_type: EdgeType) -> char {
 }
 
 fn draw_lr_edge(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
+    theme: &Theme,
     from: &NodeLayout,
     to: &NodeLayout,
     edge: &EdgeLayout,
+    layout: &GraphLayout,
 ) {
     if from.id == to.id {
-        draw_td_self_loop(grid, from, edge);
+        draw_td_self_loop(grid, theme, from, edge, layout);
         return;
     }
 
     let from_right = from.x + from.width;
+    // Edges back into an earlier rank (cycles) have no route yet
+    if to.x <= from_right {
+        return;
+    }
     let to_left = to.x;
+    let label_at_target = lr_label_at_target(layout, from, to);
     let horiz = lr_horizontal_connector(edge.edge_type);
+    let mid_col = from_right + (to_left - from_right) / 2;
+
+    if label_at_target
+        && let Some(ref label) = edge.label
+        && to.center_y > 0
+    {
+        // Past the vertical segment, a column clear of it and of the target
+        let room = to_left.saturating_sub(mid_col + 3);
+        let label_col = mid_col + 2 + room.saturating_sub(display_width(label)) / 2;
+        grid.write_str(to.center_y - 1, label_col, label);
+    }
 
     if from.center_y == to.center_y {
         // Straight horizontal
@@ -623,7 +802,9 @@ fn draw_lr_edge(
         if has_arrow_head(edge.edge_type) {
             grid.set(row, to_left - 1, '>');
         }
-        if let Some(ref label) = edge.label {
+        if let Some(ref label) = edge.label
+            && !label_at_target
+        {
             let gap = to_left - from_right;
             let label_col = from_right + (gap.saturating_sub(display_width(label))) / 2;
             if row > 0 {
@@ -632,12 +813,11 @@ fn draw_lr_edge(
         }
     } else {
         // L-shaped routing: horizontal → corner → vertical → corner → horizontal
-        let mid_col = from_right + (to_left - from_right) / 2;
         let vert = td_vertical_connector(edge.edge_type);
 
         // Horizontal from source to midpoint
         for col in from_right..mid_col {
-            grid.set(from.center_y, col, horiz);
+            set_edge_cell(grid, layout, from.center_y, col, horiz);
         }
 
         // Corners and vertical segment
@@ -657,14 +837,16 @@ fn draw_lr_edge(
 
         // Horizontal from midpoint to target
         for col in (mid_col + 1)..to_left {
-            grid.set(to.center_y, col, horiz);
+            set_edge_cell(grid, layout, to.center_y, col, horiz);
         }
         if has_arrow_head(edge.edge_type) {
             grid.set(to.center_y, to_left - 1, '>');
         }
 
         // Label on the source-side horizontal segment
-        if let Some(ref label) = edge.label {
+        if let Some(ref label) = edge.label
+            && !label_at_target
+        {
             let gap = mid_col.saturating_sub(from_right);
             if gap > 0 {
                 let label_col = from_right + (gap.saturating_sub(display_width(label))) / 2;
@@ -715,6 +897,19 @@ mod tests {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn render_multiline_labels_centered_in_every_shape() {
+        let input = "graph TD\n    A[one<br/>three]\n    B(a<br/>bcd)\n    C{x<br/>yz}\n";
+        let expected = "\
+┌───────┐   ╭─────╮     ──
+│  one  │   │  a  │    ╱  ╲
+│ three │   │ bcd │   │ x  │
+└───────┘   ╰─────╯   │ yz │
+                       ╲  ╱
+                        ──";
+        assert_eq!(render_input(input), expected);
+    }
+
     #[test]
     fn render_td_single_node() {
         let output = render_input("graph TD\n    A[Hello]\n");
@@ -740,6 +935,70 @@ mod tests {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn render_td_same_rank_edge_across() {
+        let input = "graph TD\n    subgraph one\n    B\n    end\n    subgraph two\n    C\n    end\n    C -->|go| B\n";
+        let expected = "\
+┌─ one ─┐   ┌─ two ─┐
+│ ┌───┐ │go │ ┌───┐ │
+│ │ B │<┼───┼─│ C │ │
+│ └───┘ │   │ └───┘ │
+└───────┘   └───────┘";
+        assert_eq!(render_input(input), expected);
+
+        let diagram = parse_graph(&input.replace("go", "a long label")).unwrap();
+        let layout = crate::graph_layout::compute(&diagram).unwrap();
+        assert_eq!(dropped_labels(&layout).len(), 1);
+    }
+
+    #[test]
+    fn render_td_edge_into_wrapped_subgraph() {
+        let input = "graph TD\n    subgraph one\n    A[Start] --> B\n    end\n    subgraph two\n    C\n    end\n    B --> C\n";
+        let diagram = parse_graph(input).unwrap();
+        let layout = crate::graph_layout::compute_with_max_width(&diagram, 16).unwrap();
+        let expected = "\
+┌─ one ─────┐
+│ ┌───────┐ │
+│ │ Start │ │
+│ └───┬───┘ │
+│     │     │
+│     ▼     │
+│   ┌───┐   │
+│   │ B │   │
+│   └─┬─┘   │
+└─────┼─────┘
+      │
+      │
+  ┌─ two ─┐
+  │   ▼   │
+  │ ┌───┐ │
+  │ │ C │ │
+  │ └───┘ │
+  └───────┘";
+        assert_eq!(render(&layout), expected);
+    }
+
+    #[test]
+    fn render_mutual_edges_as_one_double_headed_line() {
+        let expected = "\
+┌───┐
+│ A │
+└─▲─┘
+ ack
+  ▼
+┌───┐
+│ B │
+└───┘";
+        assert_eq!(render_input("graph TD\n    A --> B\n    B -->|ack| A\n"), expected);
+
+        let output = render_input("graph LR\n    A --> B\n    B --> A\n");
+        assert_eq!(output.lines().nth(1), Some("│ A │<───>│ B │"));
+
+        // A fan-out edge past a rank isn't drawn, so neither is its reverse
+        let output = render_input("graph TD\n    A-->B\n    A-->C\n    B-->C\n    C-->A\n");
+        assert_eq!(output.lines().nth(2), Some("└─┬─┘"));
+    }
+
     #[test]
     fn render_lr_linear_chain() {
         let output = render_input("graph LR\n    A[Start] --> B[End]\n");
@@ -821,6 +1080,24 @@ mod tests {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn render_lr_labels_of_a_fan_out_over_each_target() {
+        let input = "graph LR\n    A -->|yes| B\n    A -->|no| C\n";
+        let output = render_input(input);
+        let expected = "\
+┌───┐       yes ┌───┐
+│ A │─────┬────>│ B │
+└───┘     │     └───┘
+          │
+          │
+          │ no  ┌───┐
+          └────>│ C │
+                └───┘";
+        assert_eq!(output, expected);
+        let layout = crate::graph_layout::compute(&parse_graph(input).unwrap()).unwrap();
+        assert!(dropped_labels(&layout).is_empty());
+    }
+
     #[test]
     fn render_td_dotted_arrow() {
         let output = render_input("graph TD\n    A -.-> B\n");
@@ -958,6 +1235,50 @@ mod tests {
         assert!(last_line.contains('┘'), "last line has bottom-right corner");
     }
 
+    #[test]
+    fn render_edges_join_the_subgraph_frames_they_cross() {
+        let input = "graph TD\n    subgraph one\n    A --> B\n    end\n    \
+                     subgraph two\n    C --> D\n    end\n    C --> B\n";
+        let lines: Vec<String> = render_input(input).lines().map(str::to_string).collect();
+        assert_eq!(lines[4], "│   ┌───┼───┼───┴   │");
+
+        let input = "graph LR\n    subgraph one\n    A\n    end\n    \
+                     subgraph two\n    B\n    C\n    end\n    A --> B\n    A --> C\n";
+        let lines: Vec<String> = render_input(input).lines().map(str::to_string).collect();
+        assert_eq!(lines[2], "│ │ A │─┼─┬─┼>│ B │ │");
+        assert_eq!(lines[7], "          └─┼>│ C │ │");
+    }
+
+    #[test]
+    fn render_edges_join_heavy_and_double_subgraph_frames() {
+        let input = "graph TD\n    subgraph one [Store]\n        A[State]\n    end\n    \
+                     subgraph two [Backend]\n        B[API]\n    end\n    A --> B\n";
+        let layout = crate::graph_layout::compute(&parse_graph(input).unwrap()).unwrap();
+        let row = |theme| render_with(&layout, &theme).lines().nth(2).unwrap().to_string();
+        assert_eq!(row(Theme::HEAVY), "┃ ┃ State ┃─╂───╂>┃ API ┃   ┃");
+        assert_eq!(row(Theme::DOUBLE), "║ ║ State ║─╫───╫>║ API ║   ║");
+
+        // Where box drawing has no mixed glyph, the junction is all double
+        let input = "graph TD\n    subgraph one\n        A\n    end\n    \
+                     subgraph two\n        B --> C\n    end\n    A --> C\n";
+        let layout = crate::graph_layout::compute(&parse_graph(input).unwrap()).unwrap();
+        let output = render_with(&layout, &Theme::DOUBLE);
+        assert_eq!(output.lines().nth(4), Some("╚═══╧═══╩───╫───┬   ║"));
+    }
+
+    #[test]
+    fn render_self_loop_inside_its_subgraph() {
+        let output = render_input("graph TD\n    subgraph one\n    A --> A\n    end\n");
+        let expected = "\
+┌─ one ───┐
+│ ┌───┐   │
+│ │ A ├─┐ │
+│ └───┘ │ │
+│    ◄──┘ │
+└─────────┘";
+        assert_eq!(output, expected);
+    }
+
     #[test]
     fn render_lr_fan_out_edges_reach_targets() {
         let output = render_input("graph LR\n    A --> B\n    A --> C\n");
@@ -1049,6 +1370,19 @@ mod tests {
         assert!(output.contains("retry"), "label rendered");
     }
 
+    #[test]
+    fn render_edges_meet_a_diamond_on_its_outline() {
+        let output = render_input("graph TD\n    A{d} --> A\n");
+        assert_eq!(output, "  ─\n ╱ ╲\n│ d ├─┐\n ╲ ╱  │\n  ─   │\n   ◄──┘");
+
+        // A box and a diamond have no straight side on a shared row, so the
+        // edge runs on to the diamond's slope
+        let input = "graph TD\n  subgraph one\n    A[box]\n  end\n  \
+                     subgraph two\n    B{d}\n  end\n  A --> B\n";
+        let lines: Vec<String> = render_input(input).lines().map(str::to_string).collect();
+        assert_eq!(lines[2], "│ │ box │─┼───┼─>╱ ╲  │");
+    }
+
     #[test]
     fn render_lr_fan_out_has_vertical_routing() {
         let output = render_input("graph LR\n    A --> B\n    A --> C\n");
@@ -1059,4 +1393,21 @@ mod tests {
             || output.contains('┌');
         assert!(has_corner, "L-shaped routing should have corners:\n{output}");
     }
+
+    #[test]
+    fn render_skips_edges_to_nodes_the_layout_lacks() {
+        for direction in ["TD", "LR"] {
+            let input = format!("graph {direction}\n    A -->|go| B\n    B --> B\n");
+            let diagram = parse_graph(&input).unwrap();
+            let mut layout = crate::graph_layout::compute(&diagram).unwrap();
+            let expected = render(&layout);
+            for edge in &mut layout.edges {
+                edge.from_id = format!("{}x", edge.from_id);
+            }
+            let without_b = render(&layout);
+            assert!(!without_b.contains('◄') && !without_b.contains("go"), "{without_b}");
+            assert!(dropped_labels(&layout).is_empty());
+            assert_ne!(without_b, expected);
+        }
+    }
 }
@@ -0,0 +1,527 @@
+use crate::style::{self, Theme};
+
+/// The text [`Canvas`] all renderers draw on by default.
+///
+/// Wide characters occupy their base cell plus `'\0'` continuation cells,
+/// which are dropped when the grid is turned back into text. The grid
+/// remembers which cells hold label text, written with
+/// [`Canvas::write_str`]; boxes, lines and arrow heads are set cell by
+/// cell. A grid created inside [`style::with_ascii_only`] turns those
+/// glyphs into their ASCII stand-ins when it is read back, and keeps the
+/// labels as written.
+pub struct Grid {
+    cells: Vec<Vec<char>>,
+    text: Vec<Vec<bool>>,
+    width: usize,
+    height: usize,
+    ascii_only: bool,
+}
+
+/// A drawing surface of character cells, written to by the renderers'
+/// `draw_on` functions.
+///
+/// Only the four cell accessors are required; lines, boxes and text are
+/// drawn through them, so another output format gets every diagram type by
+/// implementing these. Wide characters are written as their base cell
+/// followed by `'\0'` continuation cells.
+pub trait Canvas {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    /// Character at a cell, or `None` outside the canvas.
+    fn get(&self, row: usize, col: usize) -> Option<char>;
+
+    /// Set one cell; writes outside the canvas are ignored.
+    fn set(&mut self, row: usize, col: usize, ch: char);
+
+    /// Write `s` from `col` on. A wide character that would only partly fit
+    /// before the right edge is left out, along with the rest of `s`.
+    fn write_str(&mut self, row: usize, col: usize, s: &str) {
+        let mut offset = 0;
+        for ch in s.chars() {
+            let w = crate::display_width::char_width(ch);
+            if w > 1 && col + offset + w > self.width() {
+                break;
+            }
+            self.set(row, col + offset, ch);
+            for j in 1..w {
+                self.set(row, col + offset + j, '\0');
+            }
+            offset += w;
+        }
+    }
+
+    /// Set a box-drawing cell, joining it with a line already there
+    /// (e.g. `─` over `│` becomes `┼`).
+    fn set_merge(&mut self, row: usize, col: usize, ch: char) {
+        if let Some(existing) = self.get(row, col) {
+            self.set(row, col, merge_box_drawing(existing, ch));
+        }
+    }
+
+    /// Fill columns `from..to` of `row` with `ch`.
+    fn hline(&mut self, row: usize, from: usize, to: usize, ch: char) {
+        for col in from..to {
+            self.set(row, col, ch);
+        }
+    }
+
+    /// Fill rows `from..to` of `col` with `ch`.
+    fn vline(&mut self, col: usize, from: usize, to: usize, ch: char) {
+        for row in from..to {
+            self.set(row, col, ch);
+        }
+    }
+
+    /// Draw a rectangle outline with its top-left corner at (`y`, `x`).
+    /// The interior is left untouched.
+    fn rect(&mut self, x: usize, y: usize, width: usize, height: usize, theme: &Theme) {
+        if width < 2 || height < 2 {
+            return;
+        }
+        let right = x + width - 1;
+        let bottom = y + height - 1;
+        self.set(y, x, theme.top_left);
+        self.hline(y, x + 1, right, theme.horizontal);
+        self.set(y, right, theme.top_right);
+        self.vline(x, y + 1, bottom, theme.vertical);
+        self.vline(right, y + 1, bottom, theme.vertical);
+        self.set(bottom, x, theme.bottom_left);
+        self.hline(bottom, x + 1, right, theme.horizontal);
+        self.set(bottom, right, theme.bottom_right);
+    }
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            cells: vec![vec![' '; width]; height],
+            text: vec![vec![false; width]; height],
+            width,
+            height,
+            ascii_only: style::is_ascii_only(),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        self.lines().join("\n")
+    }
+
+    /// Rendered rows with continuation cells dropped and trailing spaces trimmed.
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.height)
+            .map(|row| {
+                let line: String = self.shown(row).map(|(ch, _)| ch).collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// The characters of `row` as drawn, without continuation cells, each
+    /// with whether it is label text.
+    fn shown(&self, row: usize) -> impl Iterator<Item = (char, bool)> + '_ {
+        self.cells[row].iter().zip(&self.text[row]).filter(|(ch, _)| **ch != '\0').map(
+            |(&ch, &is_text)| match (self.ascii_only, is_text) {
+                (false, _) => (ch, is_text),
+                (true, false) => (style::to_ascii(ch), false),
+                (true, true) => (style::text_to_ascii(ch), true),
+            },
+        )
+    }
+
+    /// Write rows one at a time, without assembling the whole output.
+    pub fn write_to<W: std::io::Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let mut line = String::with_capacity(self.width);
+        for i in 0..self.height {
+            line.clear();
+            line.extend(self.shown(i).map(|(ch, _)| ch));
+            if i > 0 {
+                out.write_all(b"\n")?;
+            }
+            out.write_all(line.trim_end().as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl Canvas for Grid {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn get(&self, row: usize, col: usize) -> Option<char> {
+        self.cells.get(row).and_then(|r| r.get(col)).copied()
+    }
+
+    /// Overwriting either cell of a wide character blanks the other, so the
+    /// row keeps its width.
+    fn set(&mut self, row: usize, col: usize, ch: char) {
+        if row < self.height && col < self.width {
+            let cells = &mut self.cells[row];
+            if cells[col] == '\0' && col > 0 && cells[col - 1] != '\0' {
+                cells[col - 1] = ' ';
+            }
+            if cells[col] != '\0' {
+                for cell in cells[col + 1..].iter_mut().take_while(|c| **c == '\0') {
+                    *cell = ' ';
+                }
+            }
+            cells[col] = ch;
+            self.text[row][col] = false;
+        }
+    }
+
+    fn write_str(&mut self, row: usize, col: usize, s: &str) {
+        let mut offset = 0;
+        for ch in s.chars() {
+            let w = crate::display_width::char_width(ch);
+            if w > 1 && col + offset + w > self.width {
+                break;
+            }
+            self.set(row, col + offset, ch);
+            for j in 1..w {
+                self.set(row, col + offset + j, '\0');
+            }
+            if let Some(text) = self.text.get_mut(row) {
+                for cell in text.iter_mut().skip(col + offset).take(w) {
+                    *cell = true;
+                }
+            }
+            offset += w;
+        }
+    }
+}
+
+/// Escape starting a dimmed run of line glyphs.
+const ANSI_DIM: &str = "\x1b[2m";
+/// Escape ending one.
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// A [`Canvas`] for terminals: a [`Grid`] that renders every glyph but
+/// label text dimmed with ANSI escapes.
+///
+/// Every line closes its own escape, so lines can be printed or paged
+/// separately.
+pub struct AnsiGrid {
+    grid: Grid,
+}
+
+impl AnsiGrid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { grid: Grid::new(width, height) }
+    }
+
+    pub fn render(&self) -> String {
+        self.lines().join("\n")
+    }
+
+    /// Rendered rows, trimmed like [`Grid::lines`], with each run of line
+    /// glyphs wrapped in a dim escape.
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.grid.height)
+            .map(|row| {
+                let shown: Vec<(char, bool)> = self.grid.shown(row).collect();
+                let end = shown.iter().rposition(|&(ch, _)| ch != ' ').map_or(0, |i| i + 1);
+                let mut line = String::with_capacity(end);
+                let mut dim = false;
+                for &(ch, is_text) in &shown[..end] {
+                    if ch != ' ' && dim == is_text {
+                        line.push_str(if is_text { ANSI_RESET } else { ANSI_DIM });
+                        dim = !is_text;
+                    }
+                    line.push(ch);
+                }
+                if dim {
+                    line.push_str(ANSI_RESET);
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+/// `s` without the escapes [`AnsiGrid`] adds, e.g. to measure its width.
+pub(crate) fn strip_ansi(s: &str) -> String {
+    s.replace(ANSI_DIM, "").replace(ANSI_RESET, "")
+}
+
+impl Canvas for AnsiGrid {
+    fn width(&self) -> usize {
+        self.grid.width
+    }
+
+    fn height(&self) -> usize {
+        self.grid.height
+    }
+
+    fn get(&self, row: usize, col: usize) -> Option<char> {
+        self.grid.get(row, col)
+    }
+
+    fn set(&mut self, row: usize, col: usize, ch: char) {
+        self.grid.set(row, col, ch);
+    }
+
+    fn write_str(&mut self, row: usize, col: usize, s: &str) {
+        self.grid.write_str(row, col, s);
+    }
+}
+
+/// Weight of a line leaving a cell on one side.
+const NONE: u8 = 0;
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+/// Line glyphs by the weight of their left, right, up and down arms. Where
+/// several glyphs have the same arms, the first is the one drawn.
+const LINE_GLYPHS: &[(char, [u8; 4])] = &[
+    ('─', [LIGHT, LIGHT, NONE, NONE]),
+    ('│', [NONE, NONE, LIGHT, LIGHT]),
+    ('┌', [NONE, LIGHT, NONE, LIGHT]),
+    ('┐', [LIGHT, NONE, NONE, LIGHT]),
+    ('└', [NONE, LIGHT, LIGHT, NONE]),
+    ('┘', [LIGHT, NONE, LIGHT, NONE]),
+    ('├', [NONE, LIGHT, LIGHT, LIGHT]),
+    ('┤', [LIGHT, NONE, LIGHT, LIGHT]),
+    ('┬', [LIGHT, LIGHT, NONE, LIGHT]),
+    ('┴', [LIGHT, LIGHT, LIGHT, NONE]),
+    ('┼', [LIGHT, LIGHT, LIGHT, LIGHT]),
+    ('╌', [LIGHT, LIGHT, NONE, NONE]),
+    ('┊', [NONE, NONE, LIGHT, LIGHT]),
+    ('╭', [NONE, LIGHT, NONE, LIGHT]),
+    ('╮', [LIGHT, NONE, NONE, LIGHT]),
+    ('╰', [NONE, LIGHT, LIGHT, NONE]),
+    ('╯', [LIGHT, NONE, LIGHT, NONE]),
+    ('━', [HEAVY, HEAVY, NONE, NONE]),
+    ('┃', [NONE, NONE, HEAVY, HEAVY]),
+    ('┏', [NONE, HEAVY, NONE, HEAVY]),
+    ('┓', [HEAVY, NONE, NONE, HEAVY]),
+    ('┗', [NONE, HEAVY, HEAVY, NONE]),
+    ('┛', [HEAVY, NONE, HEAVY, NONE]),
+    ('┣', [NONE, HEAVY, HEAVY, HEAVY]),
+    ('┫', [HEAVY, NONE, HEAVY, HEAVY]),
+    ('┳', [HEAVY, HEAVY, NONE, HEAVY]),
+    ('┻', [HEAVY, HEAVY, HEAVY, NONE]),
+    ('╋', [HEAVY, HEAVY, HEAVY, HEAVY]),
+    ('┍', [NONE, HEAVY, NONE, LIGHT]),
+    ('┎', [NONE, LIGHT, NONE, HEAVY]),
+    ('┑', [HEAVY, NONE, NONE, LIGHT]),
+    ('┒', [LIGHT, NONE, NONE, HEAVY]),
+    ('┕', [NONE, HEAVY, LIGHT, NONE]),
+    ('┖', [NONE, LIGHT, HEAVY, NONE]),
+    ('┙', [HEAVY, NONE, LIGHT, NONE]),
+    ('┚', [LIGHT, NONE, HEAVY, NONE]),
+    ('┝', [NONE, HEAVY, LIGHT, LIGHT]),
+    ('┠', [NONE, LIGHT, HEAVY, HEAVY]),
+    ('┥', [HEAVY, NONE, LIGHT, LIGHT]),
+    ('┨', [LIGHT, NONE, HEAVY, HEAVY]),
+    ('┯', [HEAVY, HEAVY, NONE, LIGHT]),
+    ('┰', [LIGHT, LIGHT, NONE, HEAVY]),
+    ('┷', [HEAVY, HEAVY, LIGHT, NONE]),
+    ('┸', [LIGHT, LIGHT, HEAVY, NONE]),
+    ('┿', [HEAVY, HEAVY, LIGHT, LIGHT]),
+    ('╂', [LIGHT, LIGHT, HEAVY, HEAVY]),
+    ('═', [DOUBLE, DOUBLE, NONE, NONE]),
+    ('║', [NONE, NONE, DOUBLE, DOUBLE]),
+    ('╔', [NONE, DOUBLE, NONE, DOUBLE]),
+    ('╗', [DOUBLE, NONE, NONE, DOUBLE]),
+    ('╚', [NONE, DOUBLE, DOUBLE, NONE]),
+    ('╝', [DOUBLE, NONE, DOUBLE, NONE]),
+    ('╠', [NONE, DOUBLE, DOUBLE, DOUBLE]),
+    ('╣', [DOUBLE, NONE, DOUBLE, DOUBLE]),
+    ('╦', [DOUBLE, DOUBLE, NONE, DOUBLE]),
+    ('╩', [DOUBLE, DOUBLE, DOUBLE, NONE]),
+    ('╬', [DOUBLE, DOUBLE, DOUBLE, DOUBLE]),
+    ('╒', [NONE, DOUBLE, NONE, LIGHT]),
+    ('╓', [NONE, LIGHT, NONE, DOUBLE]),
+    ('╕', [DOUBLE, NONE, NONE, LIGHT]),
+    ('╖', [LIGHT, NONE, NONE, DOUBLE]),
+    ('╘', [NONE, DOUBLE, LIGHT, NONE]),
+    ('╙', [NONE, LIGHT, DOUBLE, NONE]),
+    ('╛', [DOUBLE, NONE, LIGHT, NONE]),
+    ('╜', [LIGHT, NONE, DOUBLE, NONE]),
+    ('╞', [NONE, DOUBLE, LIGHT, LIGHT]),
+    ('╟', [NONE, LIGHT, DOUBLE, DOUBLE]),
+    ('╡', [DOUBLE, NONE, LIGHT, LIGHT]),
+    ('╢', [LIGHT, NONE, DOUBLE, DOUBLE]),
+    ('╤', [DOUBLE, DOUBLE, NONE, LIGHT]),
+    ('╥', [LIGHT, LIGHT, NONE, DOUBLE]),
+    ('╧', [DOUBLE, DOUBLE, LIGHT, NONE]),
+    ('╨', [LIGHT, LIGHT, DOUBLE, NONE]),
+    ('╪', [DOUBLE, DOUBLE, LIGHT, LIGHT]),
+    ('╫', [LIGHT, LIGHT, DOUBLE, DOUBLE]),
+];
+
+fn box_connections(ch: char) -> Option<[u8; 4]> {
+    LINE_GLYPHS.iter().find(|(glyph, _)| *glyph == ch).map(|&(_, arms)| arms)
+}
+
+fn connections_to_char(arms: [u8; 4]) -> Option<char> {
+    LINE_GLYPHS.iter().find(|(_, a)| *a == arms).map(|&(glyph, _)| glyph)
+}
+
+/// Combine two box-drawing characters into the junction joining all their
+/// arms, each as heavy as the heavier of the two. Box drawing has no glyph
+/// for some mixes, such as a light line leaving a double corner; those are
+/// drawn all in the heaviest weight. Anything that isn't a known line
+/// character is overwritten.
+pub fn merge_box_drawing(existing: char, new_char: char) -> char {
+    let Some(ec) = box_connections(existing) else {
+        return new_char;
+    };
+    let nc = box_connections(new_char).unwrap_or_default();
+    let arms: [u8; 4] = std::array::from_fn(|i| ec[i].max(nc[i]));
+    let heaviest = arms.iter().copied().max().unwrap_or(NONE);
+    connections_to_char(arms)
+        .or_else(|| connections_to_char(arms.map(|w| if w == NONE { NONE } else { heaviest })))
+        .unwrap_or(new_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn grid_basic_operations() {
+        let mut grid = Grid::new(10, 3);
+        grid.write_str(1, 2, "hello");
+        let output = grid.render();
+        assert!(output.contains("hello"));
+    }
+
+    #[test]
+    fn grid_set_character() {
+        let mut grid = Grid::new(5, 2);
+        grid.set(0, 2, 'X');
+        let output = grid.render();
+        assert!(output.contains("X"));
+    }
+
+    #[test]
+    fn grid_write_wide_chars_correct_offset() {
+        let mut grid = Grid::new(10, 1);
+        grid.write_str(0, 0, "テス");
+        grid.set(0, 4, 'C');
+        let output = grid.render();
+        assert_eq!(output, "テスC");
+    }
+
+    #[test]
+    fn grid_set_overwrites_wide_char_continuation() {
+        let mut grid = Grid::new(10, 1);
+        grid.write_str(0, 0, "テスト");
+        // Overwrite continuation marker of ス (at col 3) with │
+        grid.set(0, 3, '│');
+        let output = grid.render();
+        // ス's base at col 2 should be cleared to space
+        assert_eq!(output, "テ │ト");
+    }
+
+    #[test]
+    fn grid_set_over_wide_char_base_keeps_row_width() {
+        let mut grid = Grid::new(6, 1);
+        grid.write_str(0, 0, "テスト");
+        grid.set(0, 2, '│');
+        assert_eq!(grid.render(), "テ│ ト");
+    }
+
+    #[test]
+    fn grid_leaves_out_wide_char_past_right_edge() {
+        let mut grid = Grid::new(5, 1);
+        grid.write_str(0, 0, "aテスト");
+        assert_eq!(grid.render(), "aテス");
+        grid.write_str(0, 0, "abテス");
+        assert_eq!(grid.render(), "abテ");
+    }
+
+    #[test]
+    fn grid_trims_trailing_spaces() {
+        let mut grid = Grid::new(10, 2);
+        grid.write_str(0, 0, "hi");
+        let output = grid.render();
+        let first_line = output.lines().next().unwrap();
+        assert_eq!(first_line, "hi");
+    }
+
+    #[test]
+    fn grid_rect_and_merge() {
+        let mut grid = Grid::new(5, 3);
+        grid.rect(0, 0, 5, 3, &Theme::default());
+        grid.set_merge(0, 2, '│');
+        grid.set_merge(1, 4, '─');
+        assert_eq!(grid.render(), "┌─┼─┐\n│   ┼\n└───┘");
+    }
+
+    #[test]
+    fn canvas_draws_through_get_and_set() {
+        /// Cells kept in a map, standing in for a non-text output format.
+        struct Sparse(std::collections::BTreeMap<(usize, usize), char>);
+
+        impl Canvas for Sparse {
+            fn width(&self) -> usize {
+                5
+            }
+
+            fn height(&self) -> usize {
+                3
+            }
+
+            fn get(&self, row: usize, col: usize) -> Option<char> {
+                (row < 3 && col < 5).then(|| self.0.get(&(row, col)).copied().unwrap_or(' '))
+            }
+
+            fn set(&mut self, row: usize, col: usize, ch: char) {
+                if row < 3 && col < 5 {
+                    self.0.insert((row, col), ch);
+                }
+            }
+        }
+
+        let mut sparse = Sparse(Default::default());
+        sparse.rect(0, 0, 5, 3, &Theme::default());
+        sparse.set_merge(0, 2, '│');
+        sparse.write_str(1, 1, "テx");
+        assert_eq!(sparse.0[&(0, 2)], '┼');
+        assert_eq!(sparse.0[&(1, 1)], 'テ');
+        assert_eq!(sparse.0[&(1, 2)], '\0');
+        assert_eq!(sparse.0[&(1, 3)], 'x');
+    }
+
+    #[test]
+    fn ansi_grid_dims_everything_but_text() {
+        let mut grid = AnsiGrid::new(9, 3);
+        grid.rect(0, 0, 9, 3, &Theme::default());
+        grid.write_str(1, 2, "a テ");
+        grid.set(1, 7, '>');
+        assert_eq!(
+            grid.lines(),
+            [
+                "\x1b[2m┌───────┐\x1b[0m",
+                "\x1b[2m│ \x1b[0ma テ \x1b[2m>│\x1b[0m",
+                "\x1b[2m└───────┘\x1b[0m",
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_unknown_char_is_overwritten() {
+        assert_eq!(merge_box_drawing('x', '─'), '─');
+        assert_eq!(merge_box_drawing('─', '│'), '┼');
+    }
+
+    #[test]
+    fn merge_keeps_heavy_and_double_arms() {
+        assert_eq!(merge_box_drawing('┃', '─'), '╂');
+        assert_eq!(merge_box_drawing('━', '│'), '┿');
+        assert_eq!(merge_box_drawing('║', '─'), '╫');
+        assert_eq!(merge_box_drawing('╗', '│'), '╣');
+        assert_eq!(merge_box_drawing('╭', '│'), '├');
+    }
+}
@@ -0,0 +1,77 @@
+const PRE_STYLE: &str = "font-family: ui-monospace, Menlo, Consolas, monospace; line-height: 1.2";
+const LINE_COLOR: &str = "#6a737d";
+
+/// Wrap rendered output in a `<pre>` block for embedding in HTML documents.
+///
+/// With `color`, runs of box-drawing and arrow glyphs are wrapped in a
+/// `<span>` so the drawing is dimmed relative to the labels. ASCII arrow
+/// heads count as drawing when they touch a line.
+pub fn to_html(text: &str, color: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = format!("<pre class=\"ma-diagram\" style=\"{PRE_STYLE}\">");
+    let mut in_span = false;
+    for (i, &ch) in chars.iter().enumerate() {
+        let touches_line = |c: Option<&char>| c.is_some_and(|&c| is_drawing_glyph(c));
+        let prev = i.checked_sub(1).and_then(|j| chars.get(j));
+        let arrow_head =
+            matches!(ch, '<' | '>') && (touches_line(prev) || touches_line(chars.get(i + 1)));
+        let drawing = color && (is_drawing_glyph(ch) || arrow_head);
+        if drawing && !in_span {
+            out.push_str(&format!("<span style=\"color: {LINE_COLOR}\">"));
+            in_span = true;
+        } else if !drawing && in_span && ch != ' ' {
+            out.push_str("</span>");
+            in_span = false;
+        }
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    if in_span {
+        out.push_str("</span>");
+    }
+    out.push_str("</pre>\n");
+    out
+}
+
+fn is_drawing_glyph(ch: char) -> bool {
+    matches!(ch, '\u{2500}'..='\u{257F}' | '\u{25A0}'..='\u{25FF}' | '✕')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn to_html_escapes_and_wraps() {
+        let html = to_html("│<a & b>│", false);
+        assert_eq!(
+            html,
+            format!("<pre class=\"ma-diagram\" style=\"{PRE_STYLE}\">│&lt;a &amp; b&gt;│</pre>\n")
+        );
+    }
+
+    #[test]
+    fn to_html_color_includes_arrow_heads() {
+        let html = to_html("a <b> ──>", true);
+        assert!(html.contains("a &lt;b&gt; <span"), "{html}");
+        assert!(html.ends_with("──&gt;</span></pre>\n"), "{html}");
+    }
+
+    #[test]
+    fn to_html_color_spans_drawing_runs() {
+        let html = to_html("┌─┐\n│A│", true);
+        let span = format!("<span style=\"color: {LINE_COLOR}\">");
+        assert_eq!(
+            html,
+            format!(
+                "<pre class=\"ma-diagram\" style=\"{PRE_STYLE}\">{span}┌─┐</span>\n{span}│</span>A{span}│</span></pre>\n"
+            )
+        );
+    }
+}
@@ -1,13 +1,23 @@
+use std::collections::HashMap;
+
 use crate::ast::*;
-use crate::display_width::{display_width, line_count, multiline_width};
+use crate::display_width::{
+    display_width, line_count, multiline_width, split_br, truncate_to_display_width,
+    wrap_to_display_width,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Layout {
     pub participants: Vec<ParticipantLayout>,
-    pub rows: Vec<Row>,
+    pub rows: Vec<LayoutRow>,
     pub total_width: usize,
-    pub activations: Vec<Vec<bool>>,
     pub destroyed: Vec<bool>,
+    /// Width reserved before message text for the `[n]` autonumber gutter
+    /// (0 when autonumber is off).
+    pub number_width: usize,
+    pub options: SequenceOptions,
+    /// Drawn centered above the participant boxes, cut to `total_width`.
+    pub title: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -19,6 +29,19 @@ pub struct ParticipantLayout {
     pub box_height: usize,
 }
 
+/// A row together with what it takes to draw it on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutRow {
+    pub row: Row,
+    /// Which lifelines are active (drawn heavy) alongside the row.
+    pub active: Vec<bool>,
+    /// Output lines the row occupies.
+    pub height: usize,
+    /// The 1-based source line the row was written on, when known (see
+    /// [`SourceLines`]).
+    pub source_line: Option<usize>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Row {
     Message(MessageRow),
@@ -27,6 +50,7 @@ pub enum Row {
     BlockEnd(BlockRow),
     BlockDivider(BlockRow),
     Destroy(DestroyRow),
+    Parallel(ParallelRow),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,6 +60,27 @@ pub struct BlockRow {
     pub frame_right: usize,
 }
 
+/// A `par` block drawn with its branches next to each other, between the
+/// frame's top and bottom borders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParallelRow {
+    pub frame_left: usize,
+    pub frame_right: usize,
+    pub lanes: Vec<Lane>,
+}
+
+/// One branch of a side-by-side `par` block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lane {
+    /// `par …` for the first lane, `and …` for the others.
+    pub label: String,
+    /// Column of the frame side or rule on the lane's left.
+    pub left: usize,
+    pub rows: Vec<LayoutRow>,
+    /// Active lifelines below the lane's last row.
+    pub active_after: Vec<bool>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct DestroyRow {
     pub col: usize,
@@ -54,8 +99,25 @@ pub struct MessageRow {
     pub from_col: usize,
     pub to_col: usize,
     pub text: String,
+    pub number: Option<usize>,
     pub arrow: Arrow,
     pub direction: Direction,
+    /// For a self-message that starts (`+`) or ends (`-`) an activation,
+    /// whether its lifeline is active where the loop returns. The rows above
+    /// show the lifeline as it was before the message.
+    pub return_active: Option<bool>,
+}
+
+impl MessageRow {
+    /// Lines drawn above the arrow: none for a message with neither text
+    /// nor a number (`A->>B:`).
+    pub fn text_lines(&self) -> usize {
+        if self.text.is_empty() && self.number.is_none() {
+            0
+        } else {
+            line_count(&self.text)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -67,78 +129,305 @@ pub enum Direction {
 const MIN_GAP: usize = 10;
 const ARROW_DECORATION_WIDTH: usize = 2;
 const SELF_LOOP_ARM: usize = 4;
+const BOX_PADDING: usize = 1;
+/// Narrowest text column a note is wrapped to in place before it is moved
+/// left instead.
+const MIN_WRAPPED_NOTE_WIDTH: usize = 12;
 
-pub fn compute(diagram: &Diagram) -> Result<Layout, String> {
-    let (participant_order, display_names) = collect_participants(diagram);
+/// Spacing knobs for the sequence layout. The defaults reproduce the
+/// standard output; smaller values give denser diagrams.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceOptions {
+    /// Minimum distance between neighbouring lifelines.
+    pub min_gap: usize,
+    /// Horizontal length of a self-message loop arm.
+    pub self_loop_arm: usize,
+    /// Spaces between a participant box border and its name.
+    pub box_padding: usize,
+    /// Left-to-right order of the participant columns.
+    pub order: ParticipantOrder,
+    /// Draw runs of at least this many identical consecutive messages (same
+    /// endpoints, arrow and text) as one row annotated `×N`. Runs shorter
+    /// than two are never collapsed.
+    pub collapse_repeats: Option<usize>,
+    /// Repeat the participant boxes below the diagram, like Mermaid's
+    /// `mirrorActors`. Without them lifelines end after the last row.
+    pub mirror_participants: bool,
+    /// Draw the branches of a `par` block side by side when each keeps to
+    /// its own range of participant columns. Branches that overlap, are
+    /// empty or contain nested blocks stay stacked.
+    pub par_side_by_side: bool,
+    /// When paginating, start a new page before a block frame that would
+    /// otherwise be split across two, if the whole frame fits on one page.
+    pub keep_blocks_together: bool,
+    /// Where message text sits above its arrow.
+    pub message_align: MessageAlign,
+}
 
-    if participant_order.is_empty() {
-        return Err("no participants found".to_string());
+/// Where message text is drawn above its arrow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageAlign {
+    /// From the left end of the arrow, after any autonumber: the most
+    /// compact.
+    #[default]
+    Left,
+    /// Centered over the arrow, as Mermaid draws it. Text that would then
+    /// cross another lifeline is centered between the two lifelines nearest
+    /// the middle instead, or placed as with `Left` if it fits nowhere.
+    Center,
+}
+
+impl MessageAlign {
+    pub const NAMES: &[&str] = &["left", "center"];
+
+    pub fn named(name: &str) -> Option<MessageAlign> {
+        match name {
+            "left" => Some(MessageAlign::Left),
+            "center" => Some(MessageAlign::Center),
+            _ => None,
+        }
     }
+}
 
-    let gaps = compute_gaps(diagram, &participant_order, &display_names);
-    let participants = compute_positions(&participant_order, &display_names, &gaps);
-    let rows = compute_rows(diagram, &participant_order, &participants);
-    let activations = compute_activations(diagram, &participant_order, rows.len());
-    let destroyed = compute_destroyed(&rows, participants.len());
+/// How participant columns are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParticipantOrder {
+    /// Source order: where each participant is declared, or first mentioned
+    /// if it never is.
+    #[default]
+    Declared,
+    /// Order of first appearance in a message, nested blocks included.
+    /// Participants that never send or receive one come last.
+    FirstUse,
+    /// Alphabetical by displayed name, so columns don't move when messages
+    /// are added or reordered.
+    Alpha,
+}
 
-    let mut total_width = participants
-        .last()
-        .map(|p| p.box_right + 1)
-        .unwrap_or(0);
+impl ParticipantOrder {
+    pub const NAMES: &[&str] = &["declared", "first-use", "alpha"];
 
-    for row in &rows {
-        match row {
-            Row::Message(m) if m.from_col == m.to_col => {
-                let right = m.from_col + 2 + multiline_width(&m.text) + 1;
-                total_width = total_width.max(right);
-                let arm_right = m.from_col + SELF_LOOP_ARM + 1;
-                total_width = total_width.max(arm_right);
-            }
-            Row::Note(n) => {
-                total_width = total_width.max(n.box_right + 1);
+    pub fn named(name: &str) -> Option<ParticipantOrder> {
+        match name {
+            "declared" => Some(ParticipantOrder::Declared),
+            "first-use" => Some(ParticipantOrder::FirstUse),
+            "alpha" => Some(ParticipantOrder::Alpha),
+            _ => None,
+        }
+    }
+}
+
+impl Default for SequenceOptions {
+    fn default() -> Self {
+        Self {
+            min_gap: MIN_GAP,
+            self_loop_arm: SELF_LOOP_ARM,
+            box_padding: BOX_PADDING,
+            order: ParticipantOrder::Declared,
+            collapse_repeats: None,
+            mirror_participants: true,
+            par_side_by_side: false,
+            keep_blocks_together: false,
+            message_align: MessageAlign::Left,
+        }
+    }
+}
+
+impl Layout {
+    /// Height of the tallest participant box.
+    pub fn box_height(&self) -> usize {
+        self.participants
+            .iter()
+            .map(|p| p.box_height)
+            .max()
+            .unwrap_or(3)
+    }
+
+    /// Lines above the participant boxes: the title and a blank line under
+    /// it, if there is one.
+    pub fn title_height(&self) -> usize {
+        self.title.as_ref().map_or(0, |title| line_count(title) + 1)
+    }
+
+    /// Lines above the first row: the title and the participant boxes.
+    pub fn header_height(&self) -> usize {
+        self.title_height() + self.box_height()
+    }
+
+    /// The output lines of each row with the source line it was written on,
+    /// for mapping a position in the rendering back to the source. Rows
+    /// without a known source line are left out, and a side-by-side `par`
+    /// block maps to its `par` line.
+    pub fn row_source_lines(&self) -> Vec<(std::ops::Range<usize>, usize)> {
+        let mut y = self.header_height();
+        let mut lines = Vec::new();
+        for row in &self.rows {
+            if let Some(line) = row.source_line {
+                lines.push((y..y + row.height, line));
             }
-            Row::BlockStart(b) | Row::BlockEnd(b) | Row::BlockDivider(b) => {
-                total_width = total_width.max(b.frame_right + 1);
+            y += row.height;
+        }
+        lines
+    }
+
+    /// Rendered height: title, top boxes, all rows, and the bottom boxes
+    /// unless `mirror_participants` is off.
+    pub fn total_height(&self) -> usize {
+        let body: usize = self.rows.iter().map(|r| r.height).sum();
+        let bottom = if self.options.mirror_participants { self.box_height() } else { 0 };
+        self.header_height() + body + bottom
+    }
+}
+
+/// Number of output lines a row occupies.
+fn row_height(row: &Row) -> usize {
+    match row {
+        Row::Message(m) => 2 + m.text_lines(),
+        Row::Note(n) => 2 + line_count(&n.text),
+        Row::BlockStart(b) | Row::BlockDivider(b) => line_count(&b.label),
+        Row::BlockEnd(_) | Row::Destroy(_) => 1,
+        Row::Parallel(p) => {
+            let lanes = p.lanes.iter().map(|l| l.rows.iter().map(|r| r.height).sum::<usize>());
+            2 + lanes.max().unwrap_or(0)
+        }
+    }
+}
+
+impl Row {
+    /// Participants this row destroys, including inside side-by-side lanes.
+    pub fn destroyed(&self) -> Vec<usize> {
+        match self {
+            Row::Destroy(d) => vec![d.participant_idx],
+            Row::Parallel(p) => {
+                p.lanes.iter().flat_map(|l| &l.rows).flat_map(|r| r.row.destroyed()).collect()
             }
-            _ => {}
+            _ => Vec::new(),
         }
     }
+}
 
-    Ok(Layout {
-        participants,
-        rows,
-        total_width,
-        activations,
-        destroyed,
-    })
+impl SequenceOptions {
+    fn box_width(&self, name: &str) -> usize {
+        multiline_width(name) + 2 + 2 * self.box_padding
+    }
+
+    fn half_box(&self, name: &str) -> usize {
+        multiline_width(name) / 2 + 1 + self.box_padding
+    }
+}
+
+pub fn compute(diagram: &Diagram) -> Result<Layout, String> {
+    compute_with(diagram, &SequenceOptions::default(), None)
 }
 
 pub fn compute_with_max_width(diagram: &Diagram, max_width: usize) -> Result<Layout, String> {
-    let (order, display_names) = collect_participants(diagram);
+    compute_with(diagram, &SequenceOptions::default(), Some(max_width))
+}
+
+pub fn compute_with(
+    diagram: &Diagram,
+    options: &SequenceOptions,
+    max_width: Option<usize>,
+) -> Result<Layout, String> {
+    compute_fitted(diagram, options, max_width, false)
+}
+
+/// Like [`compute_with`] with a `max_width`, but participant names and the
+/// title that don't fit are wrapped onto more lines instead of being cut
+/// short with `…`.
+pub fn compute_wrapped(
+    diagram: &Diagram,
+    options: &SequenceOptions,
+    max_width: usize,
+) -> Result<Layout, String> {
+    compute_fitted(diagram, options, Some(max_width), true)
+}
+
+fn compute_fitted(
+    diagram: &Diagram,
+    options: &SequenceOptions,
+    max_width: Option<usize>,
+    wrap: bool,
+) -> Result<Layout, String> {
+    // The gutter is sized for the uncollapsed count, which the last
+    // renumbered message can reach
+    let number_width = compute_number_width(diagram);
+    let Some(min_run) = options.collapse_repeats else {
+        return compute_diagram(diagram, number_width, options, max_width, wrap);
+    };
+
+    let mut repeats = Vec::new();
+    let mut lines = LineCursor::new(&diagram.source_lines);
+    let statements =
+        collapse_repeats(&diagram.statements, min_run.max(2), &mut repeats, &mut lines);
+    let collapsed = Diagram {
+        title: diagram.title.clone(),
+        statements,
+        source_lines: SourceLines(lines.kept),
+    };
+    let mut layout = compute_diagram(&collapsed, number_width, options, max_width, wrap)?;
+    let messages = layout.rows.iter_mut().filter_map(|r| match &mut r.row {
+        Row::Message(m) => Some(m),
+        _ => None,
+    });
+    let mut next = 1;
+    for (message, count) in messages.zip(repeats) {
+        if message.number.is_some() {
+            message.number = Some(next);
+            next += count;
+        }
+    }
+    Ok(layout)
+}
+
+fn compute_diagram(
+    diagram: &Diagram,
+    number_width: usize,
+    options: &SequenceOptions,
+    max_width: Option<usize>,
+    wrap: bool,
+) -> Result<Layout, String> {
+    let (order, display_names) = collect_participants(diagram, options.order);
 
     if order.is_empty() {
         return Err("no participants found".to_string());
     }
+    let index = index_participants(&order);
+    check_note_participants(&diagram.statements, &index, &mut diagram.source_lines.0.iter())?;
+
+    let Some(max_width) = max_width else {
+        let gaps = compute_gaps(diagram, &order, &index, &display_names, number_width, options);
+        let participants = compute_positions(&order, &display_names, &gaps, options);
+        return finish_layout(diagram, &index, participants, number_width, options, None, wrap);
+    };
 
-    let mut names = display_names;
+    let mut names = display_names.clone();
 
     loop {
         // Try layout with gap shrinking
-        let gaps = compute_gaps(diagram, &order, &names);
-        let min_gaps = compute_min_box_gaps(&order, &names);
+        let gaps = compute_gaps(diagram, &order, &index, &names, number_width, options);
+        let min_gaps = compute_min_box_gaps(&order, &names, options);
         let full_width = {
-            let p = compute_positions(&order, &names, &gaps);
+            let p = compute_positions(&order, &names, &gaps, options);
             p.last().map(|pp| pp.box_right + 1).unwrap_or(0)
         };
         let shrunk = shrink_gaps_to_fit(&gaps, &min_gaps, full_width, max_width);
-        let participants = compute_positions(&order, &names, &shrunk);
+        let participants = compute_positions(&order, &names, &shrunk, options);
         let base_width = participants.last().map(|p| p.box_right + 1).unwrap_or(0);
 
         if base_width <= max_width {
-            return finish_layout(diagram, &order, participants, max_width);
+            return finish_layout(
+                diagram,
+                &index,
+                participants,
+                number_width,
+                options,
+                Some(max_width),
+                wrap,
+            );
         }
 
-        // Find the longest name and truncate it by 1 char
+        // Find the longest name and narrow it by 1 column
         let (longest_id, longest_width) = order
             .iter()
             .map(|id| (id.clone(), multiline_width(names.get(id).unwrap())))
@@ -152,18 +441,42 @@ pub fn compute_with_max_width(diagram: &Diagram, max_width: usize) -> Result<Lay
         }
 
         let name = names.get(&longest_id).unwrap().clone();
-        names.insert(longest_id, truncate_to_display_width(&name, longest_width - 1));
+        // Rewrapped from the full name, so earlier breaks don't pile up
+        let narrower = if wrap {
+            wrap_to_display_width(&display_names[&longest_id], longest_width - 1)
+        } else {
+            truncate_to_display_width(&name, longest_width - 1)
+        };
+        names.insert(longest_id, narrower);
     }
 }
 
 fn finish_layout(
     diagram: &Diagram,
-    participant_order: &[String],
+    index: &HashMap<String, usize>,
     participants: Vec<ParticipantLayout>,
-    max_width: usize,
+    number_width: usize,
+    options: &SequenceOptions,
+    max_width: Option<usize>,
+    wrap: bool,
 ) -> Result<Layout, String> {
-    let rows = compute_rows(diagram, participant_order, &participants);
-    let activations = compute_activations(diagram, participant_order, rows.len());
+    let mut margin = left_margin(diagram, index, &participants, number_width, options);
+    if let Some(max_width) = max_width {
+        let base_width = participants.last().map(|p| p.box_right + 1).unwrap_or(0);
+        margin = margin.min(max_width.saturating_sub(base_width));
+    }
+    let participants = shift_participants(&participants, margin);
+    let mut rows = compute_rows(diagram, index, &participants);
+    if let Some(max_width) = max_width {
+        wrap_block_labels(&mut rows, &participants, max_width);
+        fit_notes(&mut rows, max_width);
+        fit_self_messages(&mut rows, max_width, number_width)?;
+    }
+    fit_frames_to_contents(&mut rows, number_width, options);
+    if options.par_side_by_side {
+        let right_edge = |row: &Row| row_right(row, number_width, options);
+        rows = arrange_parallel_blocks(rows, &participants, right_edge);
+    }
     let destroyed = compute_destroyed(&rows, participants.len());
 
     let mut total_width = participants
@@ -172,46 +485,575 @@ fn finish_layout(
         .unwrap_or(0);
 
     for row in &rows {
-        match row {
-            Row::Message(m) if m.from_col == m.to_col => {
-                let right = m.from_col + 2 + multiline_width(&m.text) + 1;
-                total_width = total_width.max(right);
-                let arm_right = m.from_col + SELF_LOOP_ARM + 1;
-                total_width = total_width.max(arm_right);
-            }
-            Row::Note(n) => {
-                total_width = total_width.max(n.box_right + 1);
-            }
-            Row::BlockStart(b) | Row::BlockEnd(b) | Row::BlockDivider(b) => {
-                total_width = total_width.max(b.frame_right + 1);
-            }
-            _ => {}
-        }
+        total_width = total_width.max(row_right(&row.row, number_width, options));
+    }
+    let title = diagram.title.as_ref().filter(|title| !title.is_empty());
+    if let Some(title) = title {
+        total_width = total_width.max(multiline_width(title));
     }
 
     // Cap at max_width — notes/blocks beyond will be clipped by the renderer
-    total_width = total_width.min(max_width);
+    if let Some(max_width) = max_width {
+        total_width = total_width.min(max_width);
+    }
 
     Ok(Layout {
         participants,
         rows,
         total_width,
-        activations,
         destroyed,
+        number_width,
+        options: options.clone(),
+        title: title.map(|title| {
+            if wrap && multiline_width(title) > total_width {
+                wrap_to_display_width(title, total_width)
+            } else {
+                fit_title(title, total_width)
+            }
+        }),
     })
 }
 
+/// Columns to leave left of the participant boxes so that notes left of the
+/// first lifeline, and the frames around them, start at column 0 or later.
+fn left_margin(
+    diagram: &Diagram,
+    index: &HashMap<String, usize>,
+    participants: &[ParticipantLayout],
+    number_width: usize,
+    options: &SequenceOptions,
+) -> usize {
+    let rows = compute_rows(diagram, index, participants);
+    let widest_note = rows.iter().filter_map(|r| match &r.row {
+        Row::Note(n) => Some(multiline_width(&n.text) + 4),
+        _ => None,
+    });
+    // Room for any note and its frame, measured back once laid out in it
+    let Some(room) = widest_note.max().map(|width| width + 2) else { return 0 };
+    let mut rows = compute_rows(diagram, index, &shift_participants(participants, room));
+    fit_frames_to_contents(&mut rows, number_width, options);
+    let left = rows.iter().filter_map(|r| row_left(&r.row)).min().unwrap_or(room);
+    room - left.min(room)
+}
+
+fn shift_participants(participants: &[ParticipantLayout], by: usize) -> Vec<ParticipantLayout> {
+    participants
+        .iter()
+        .map(|p| ParticipantLayout {
+            center_col: p.center_col + by,
+            box_left: p.box_left + by,
+            box_right: p.box_right + by,
+            ..p.clone()
+        })
+        .collect()
+}
+
+/// `title` with each line longer than `width` cut to fit.
+fn fit_title(title: &str, width: usize) -> String {
+    split_br(title)
+        .into_iter()
+        .map(|line| {
+            if display_width(line) > width {
+                truncate_to_display_width(line, width)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("<br/>")
+}
+
+/// Narrow frames whose labels push them past `max_width` by wrapping the
+/// labels onto extra lines, rather than letting the renderer clip them.
+/// Every row of a block shares its bounds, so a frame is resized as a whole.
+fn wrap_block_labels(
+    rows: &mut [LayoutRow],
+    participants: &[ParticipantLayout],
+    max_width: usize,
+) {
+    let (_, base_right) = compute_frame_bounds(participants);
+    let mut open: Vec<Vec<usize>> = Vec::new();
+    for i in 0..rows.len() {
+        match &rows[i].row {
+            Row::BlockStart(_) => open.push(vec![i]),
+            Row::BlockDivider(_) => {
+                if let Some(block) = open.last_mut() {
+                    block.push(i);
+                }
+            }
+            Row::BlockEnd(_) => {
+                let Some(mut block) = open.pop() else { continue };
+                block.push(i);
+                wrap_block(rows, &block, base_right, max_width);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn wrap_block(rows: &mut [LayoutRow], block: &[usize], base_right: usize, max_width: usize) {
+    let Row::BlockStart(start) = &rows[block[0]].row else { return };
+    if start.frame_right < max_width {
+        return;
+    }
+    let frame_left = start.frame_left;
+    // Never narrower than the lifelines the frame spans
+    let limit = max_width.saturating_sub(1).max(base_right).saturating_sub(frame_left + 3).max(1);
+    let mut label_width = 0;
+    for &i in block {
+        if let Row::BlockStart(b) | Row::BlockDivider(b) = &mut rows[i].row {
+            b.label = wrap_to_display_width(&b.label, limit);
+            label_width = label_width.max(multiline_width(&b.label));
+            // A line of its own below the frame's top for every extra label line
+            rows[i].height = row_height(&rows[i].row);
+        }
+    }
+    let frame_right = base_right.max(frame_left + 2 + label_width + 1);
+    for &i in block {
+        if let Row::BlockStart(b) | Row::BlockDivider(b) | Row::BlockEnd(b) = &mut rows[i].row {
+            b.frame_right = frame_right;
+        }
+    }
+}
+
+/// Widen frames to take in the rows inside them, such as a self-message on
+/// the last lifeline whose loop and text run past the frame or a note left of
+/// the first one, so a frame's side never cuts through them. A nested frame
+/// that grows widens the frames around it to the same column.
+fn fit_frames_to_contents(rows: &mut [LayoutRow], number_width: usize, options: &SequenceOptions) {
+    // Rows of each open block, with the leftmost and rightmost columns its
+    // contents need
+    let mut open: Vec<(Vec<usize>, usize, usize)> = Vec::new();
+    for i in 0..rows.len() {
+        match &rows[i].row {
+            Row::BlockStart(_) => open.push((vec![i], usize::MAX, 0)),
+            Row::BlockDivider(_) => {
+                if let Some((block, _, _)) = open.last_mut() {
+                    block.push(i);
+                }
+            }
+            Row::BlockEnd(end) => {
+                let Some((mut block, left, right)) = open.pop() else { continue };
+                block.push(i);
+                let frame_left = end.frame_left.min(left);
+                let frame_right = end.frame_right.max(right);
+                for &j in &block {
+                    if let Row::BlockStart(b) | Row::BlockDivider(b) | Row::BlockEnd(b) =
+                        &mut rows[j].row
+                    {
+                        b.frame_left = frame_left;
+                        b.frame_right = frame_right;
+                    }
+                }
+                if let Some((_, outer_left, outer_right)) = open.last_mut() {
+                    *outer_left = (*outer_left).min(frame_left);
+                    *outer_right = (*outer_right).max(frame_right);
+                }
+            }
+            row => {
+                // Leave a column between a note's border and the frame's side
+                let gap = usize::from(matches!(row, Row::Note(_)));
+                if let Some((_, left, right)) = open.last_mut() {
+                    if let Some(row_left) = row_left(row) {
+                        *left = (*left).min(row_left.saturating_sub(gap + 1));
+                    }
+                    *right = (*right).max(row_right(row, number_width, options) + gap);
+                }
+            }
+        }
+    }
+}
+
+/// Keep notes that would run past `max_width` inside it: wrap the text to
+/// the room right of the note's left edge or, when that is too narrow to
+/// read, move the note left until it fits and wrap what still doesn't. A
+/// note left of the first lifeline that the left margin can't hold is
+/// wrapped to the columns there are, if they are enough to read.
+fn fit_notes(rows: &mut [LayoutRow], max_width: usize) {
+    for row in rows {
+        let Row::Note(note) = &mut row.row else { continue };
+        let room = (note.box_right + 1).saturating_sub(4);
+        if room < multiline_width(&note.text) && room >= MIN_WRAPPED_NOTE_WIDTH {
+            note.text = wrap_to_display_width(&note.text, room);
+            note.box_left = (note.box_right + 1).saturating_sub(multiline_width(&note.text) + 4);
+            row.height = row_height(&row.row);
+            continue;
+        }
+        if note.box_right < max_width {
+            continue;
+        }
+        let room = max_width.saturating_sub(note.box_left + 4);
+        if room >= MIN_WRAPPED_NOTE_WIDTH {
+            note.text = wrap_to_display_width(&note.text, room);
+            note.box_right = note.box_left + multiline_width(&note.text) + 3;
+        } else {
+            note.text = wrap_to_display_width(&note.text, max_width.saturating_sub(4).max(1));
+            note.box_right = max_width - 1;
+            note.box_left = max_width.saturating_sub(multiline_width(&note.text) + 4);
+        }
+        row.height = row_height(&row.row);
+    }
+}
+
+/// Wrap self-message text that would run past `max_width`, or through the
+/// side of the frame around it. Text is wrapped to the room beside the loop
+/// when that is wide enough to read or to hold its longest word, and
+/// otherwise fails with the width it needs.
+fn fit_self_messages(
+    rows: &mut [LayoutRow],
+    max_width: usize,
+    number_width: usize,
+) -> Result<(), String> {
+    let mut depth = 0usize;
+    for row in rows {
+        match &mut row.row {
+            Row::BlockStart(_) => depth += 1,
+            Row::BlockEnd(_) => depth = depth.saturating_sub(1),
+            Row::Message(m) if m.from_col == m.to_col => {
+                // A frame's side takes the column after the text's trailing space
+                let reserved = if depth > 0 { 2 } else { 0 };
+                let left = m.from_col + 2 + number_width + reserved;
+                let room = max_width.saturating_sub(left);
+                if multiline_width(&m.text) <= room {
+                    continue;
+                }
+                let longest_word = split_br(&m.text)
+                    .into_iter()
+                    .flat_map(|line| line.split(' '))
+                    .map(display_width)
+                    .max()
+                    .unwrap_or(0);
+                if room < MIN_WRAPPED_NOTE_WIDTH.min(longest_word) {
+                    let needed = left + MIN_WRAPPED_NOTE_WIDTH.min(longest_word);
+                    return Err(format!(
+                        "diagram requires at least {needed} columns, but max_width is {max_width}"
+                    ));
+                }
+                m.text = wrap_to_display_width(&m.text, room);
+                row.height = row_height(&row.row);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// One past the rightmost column a row draws in, or 0 for rows that stay
+/// between the lifelines.
+fn row_right(row: &Row, number_width: usize, options: &SequenceOptions) -> usize {
+    match row {
+        Row::Message(m) if m.from_col == m.to_col => {
+            let text_right = m.from_col + 2 + number_width + multiline_width(&m.text) + 1;
+            text_right.max(m.from_col + options.self_loop_arm + 1)
+        }
+        Row::Note(n) => n.box_right + 1,
+        Row::BlockStart(b) | Row::BlockEnd(b) | Row::BlockDivider(b) => b.frame_right + 1,
+        Row::Parallel(p) => p
+            .lanes
+            .iter()
+            .flat_map(|l| &l.rows)
+            .map(|r| row_right(&r.row, number_width, options))
+            .fold(p.frame_right + 1, usize::max),
+        _ => 0,
+    }
+}
+
+/// The leftmost column a row draws in, for rows that reach left of the
+/// lifelines.
+fn row_left(row: &Row) -> Option<usize> {
+    match row {
+        Row::Note(n) => Some(n.box_left),
+        Row::BlockStart(b) | Row::BlockEnd(b) | Row::BlockDivider(b) => Some(b.frame_left),
+        _ => None,
+    }
+}
+
+/// Replace each `par` block whose branches fit side by side with a single
+/// [`Row::Parallel`].
+fn arrange_parallel_blocks(
+    rows: Vec<LayoutRow>,
+    participants: &[ParticipantLayout],
+    right_edge: impl Fn(&Row) -> usize,
+) -> Vec<LayoutRow> {
+    let mut arranged = Vec::with_capacity(rows.len());
+    let mut i = 0;
+    while i < rows.len() {
+        let block = match &rows[i].row {
+            Row::BlockStart(b) if b.label.split(' ').next() == Some("par") => {
+                parallel_block(&rows, i, participants, &right_edge)
+            }
+            _ => None,
+        };
+        match block {
+            Some((row, end)) => {
+                arranged.push(row);
+                i = end + 1;
+            }
+            None => {
+                arranged.push(rows[i].clone());
+                i += 1;
+            }
+        }
+    }
+    arranged
+}
+
+/// The side-by-side form of the `par` block starting at `rows[start]`, and
+/// the index of its end row, if its branches fit next to each other.
+fn parallel_block(
+    rows: &[LayoutRow],
+    start: usize,
+    participants: &[ParticipantLayout],
+    right_edge: &impl Fn(&Row) -> usize,
+) -> Option<(LayoutRow, usize)> {
+    let Row::BlockStart(frame) = &rows[start].row else { return None };
+    // (label, first row, end row) per branch
+    let mut branches = vec![(frame.label.clone(), start + 1, start + 1)];
+    let mut end = start + 1;
+    loop {
+        match &rows.get(end)?.row {
+            Row::BlockStart(_) | Row::Parallel(_) => return None,
+            Row::BlockDivider(b) => branches.push((b.label.clone(), end + 1, end + 1)),
+            Row::BlockEnd(_) => break,
+            _ => {}
+        }
+        if let Some(branch) = branches.last_mut() {
+            branch.2 = end + 1;
+        }
+        end += 1;
+    }
+    if branches.len() < 2 {
+        return None;
+    }
+
+    let centers: Vec<usize> = participants.iter().map(|p| p.center_col).collect();
+    let mut lanes: Vec<Lane> = Vec::new();
+    let mut previous_right = frame.frame_left;
+    for (label, first, last) in branches {
+        let branch = &rows[first..last];
+        let (left, right) = branch
+            .iter()
+            .map(|r| row_extent(&r.row, right_edge))
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))?;
+        if line_count(&label) > 1 {
+            return None;
+        }
+        let lane_left = if lanes.is_empty() {
+            frame.frame_left
+        } else {
+            // A free column between the branches, not on a lifeline
+            let mid = (previous_right + left) / 2;
+            (previous_right + 1..left)
+                .filter(|col| !centers.contains(col))
+                .min_by_key(|col| col.abs_diff(mid))?
+        };
+        if let Some(previous) = lanes.last()
+            && previous.left + 3 + display_width(&previous.label) > lane_left
+        {
+            return None;
+        }
+        lanes.push(Lane {
+            label,
+            left: lane_left,
+            rows: branch.to_vec(),
+            active_after: rows[last].active.clone(),
+        });
+        previous_right = right;
+    }
+    let last = lanes.last()?;
+    if last.left + 3 + display_width(&last.label) > frame.frame_right {
+        return None;
+    }
+
+    let row = Row::Parallel(ParallelRow {
+        frame_left: frame.frame_left,
+        frame_right: frame.frame_right,
+        lanes,
+    });
+    let height = row_height(&row);
+    let source_line = rows[start].source_line;
+    Some((LayoutRow { row, active: rows[start].active.clone(), height, source_line }, end))
+}
+
+/// The first and last columns a row inside a `par` branch draws in.
+fn row_extent(row: &Row, right_edge: &impl Fn(&Row) -> usize) -> (usize, usize) {
+    match row {
+        Row::Message(m) if m.from_col == m.to_col => (m.from_col, right_edge(row) - 1),
+        Row::Message(m) => (m.from_col.min(m.to_col), m.from_col.max(m.to_col)),
+        Row::Note(n) => (n.box_left, n.box_right),
+        Row::Destroy(d) => (d.col, d.col),
+        Row::BlockStart(b) | Row::BlockEnd(b) | Row::BlockDivider(b) => {
+            (b.frame_left, b.frame_right)
+        }
+        Row::Parallel(p) => (p.frame_left, p.frame_right),
+    }
+}
+
+/// Replace each run of at least `min_run` identical messages with one
+/// message whose text ends in `×N`. `repeats` receives how many source
+/// messages each remaining message stands for, in row order.
+fn collapse_repeats(
+    statements: &[Statement],
+    min_run: usize,
+    repeats: &mut Vec<usize>,
+    lines: &mut LineCursor,
+) -> Vec<Statement> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < statements.len() {
+        let stmt = &statements[i];
+        i += 1;
+        lines.keep();
+        let collapsed = match stmt {
+            // Activation changes apply once per message, so runs carrying
+            // them are drawn in full
+            Statement::Message(m) if !m.activate_target && !m.deactivate_source => {
+                let run = 1 + statements[i..]
+                    .iter()
+                    .take_while(|s| matches!(s, Statement::Message(other) if other == m))
+                    .count();
+                if run >= min_run {
+                    i += run - 1;
+                    lines.skip(run - 1);
+                    let text = if m.text.is_empty() {
+                        format!("×{run}")
+                    } else {
+                        format!("{} ×{run}", m.text)
+                    };
+                    repeats.push(run);
+                    Statement::Message(Message { text, ..m.clone() })
+                } else {
+                    repeats.push(1);
+                    stmt.clone()
+                }
+            }
+            Statement::Message(_) => {
+                repeats.push(1);
+                stmt.clone()
+            }
+            Statement::Loop(lb) => Statement::Loop(collapse_block(lb, min_run, repeats, lines)),
+            Statement::Opt(lb) => Statement::Opt(collapse_block(lb, min_run, repeats, lines)),
+            Statement::Break(lb) => Statement::Break(collapse_block(lb, min_run, repeats, lines)),
+            Statement::Rect(lb) => Statement::Rect(collapse_block(lb, min_run, repeats, lines)),
+            Statement::Alt(ab) => Statement::Alt(collapse_divided(ab, min_run, repeats, lines)),
+            Statement::Par(ab) => Statement::Par(collapse_divided(ab, min_run, repeats, lines)),
+            Statement::Critical(ab) => {
+                Statement::Critical(collapse_divided(ab, min_run, repeats, lines))
+            }
+            _ => stmt.clone(),
+        };
+        out.push(collapsed);
+    }
+    out
+}
+
+fn collapse_block(
+    block: &LoopBlock,
+    min_run: usize,
+    repeats: &mut Vec<usize>,
+    lines: &mut LineCursor,
+) -> LoopBlock {
+    let body = collapse_repeats(&block.body, min_run, repeats, lines);
+    lines.keep();
+    LoopBlock { label: block.label.clone(), body }
+}
+
+fn collapse_divided(
+    block: &AltBlock,
+    min_run: usize,
+    repeats: &mut Vec<usize>,
+    lines: &mut LineCursor,
+) -> AltBlock {
+    let body = collapse_repeats(&block.body, min_run, repeats, lines);
+    let else_branches = block
+        .else_branches
+        .iter()
+        .map(|branch| {
+            lines.keep();
+            ElseBranch {
+                label: branch.label.clone(),
+                body: collapse_repeats(&branch.body, min_run, repeats, lines),
+            }
+        })
+        .collect();
+    lines.keep();
+    AltBlock { label: block.label.clone(), body, else_branches }
+}
+
+/// Fails on the first note placed against a participant that no message or
+/// declaration introduces, naming it and the note's line. `lines` walks the
+/// diagram's [`SourceLines`] alongside `statements`.
+fn check_note_participants(
+    statements: &[Statement],
+    index: &HashMap<String, usize>,
+    lines: &mut std::slice::Iter<'_, usize>,
+) -> Result<(), String> {
+    for stmt in statements {
+        let line = lines.next();
+        match stmt {
+            Statement::Note(n) => {
+                let ids = match &n.placement {
+                    NotePlacement::RightOf(id)
+                    | NotePlacement::LeftOf(id)
+                    | NotePlacement::Over(id) => vec![id],
+                    NotePlacement::OverTwo(a, b) => vec![a, b],
+                };
+                if let Some(id) = ids.into_iter().find(|id| !index.contains_key(*id)) {
+                    let at = line.map(|line| format!(" at line {line}")).unwrap_or_default();
+                    return Err(format!("unknown participant '{id}' referenced in Note{at}"));
+                }
+            }
+            Statement::Loop(lb) | Statement::Opt(lb) | Statement::Break(lb) | Statement::Rect(lb) => {
+                check_note_participants(&lb.body, index, lines)?;
+                lines.next();
+            }
+            Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
+                check_note_participants(&ab.body, index, lines)?;
+                for branch in &ab.else_branches {
+                    lines.next();
+                    check_note_participants(&branch.body, index, lines)?;
+                }
+                lines.next();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Walks a diagram's [`SourceLines`] alongside its statements, keeping the
+/// lines of those that stay when repeats are collapsed.
+struct LineCursor<'a> {
+    lines: std::slice::Iter<'a, usize>,
+    kept: Vec<usize>,
+}
+
+impl<'a> LineCursor<'a> {
+    fn new(lines: &'a SourceLines) -> Self {
+        Self { lines: lines.0.iter(), kept: Vec::new() }
+    }
+
+    fn keep(&mut self) {
+        self.kept.extend(self.lines.next());
+    }
+
+    fn skip(&mut self, count: usize) {
+        for _ in 0..count {
+            self.lines.next();
+        }
+    }
+}
+
 fn compute_min_box_gaps(
     order: &[String],
-    display_names: &std::collections::HashMap<String, String>,
+    display_names: &HashMap<String, String>,
+    options: &SequenceOptions,
 ) -> Vec<usize> {
     (0..order.len().saturating_sub(1))
         .map(|i| {
             let left = display_names.get(&order[i]).unwrap();
             let right = display_names.get(&order[i + 1]).unwrap();
-            let left_half = multiline_width(left) / 2 + 2;
-            let right_half = multiline_width(right) / 2 + 2;
-            left_half + right_half + 2
+            options.half_box(left) + options.half_box(right) + 2
         })
         .collect()
 }
@@ -254,132 +1096,189 @@ fn shrink_gaps_to_fit(
     result
 }
 
-fn truncate_to_display_width(name: &str, target_width: usize) -> String {
-    if target_width <= 1 {
-        return "…".to_string();
-    }
-    let mut result = String::new();
-    let mut w = 0;
-    for ch in name.chars() {
-        if ch == '…' {
-            continue;
+fn collect_participants(
+    diagram: &Diagram,
+    ordering: ParticipantOrder,
+) -> (Vec<String>, HashMap<String, String>) {
+    let mut order: Vec<String> = Vec::new();
+    let mut display_names: HashMap<String, String> =
+        HashMap::new();
+    collect_participants_inner(&diagram.statements, &mut order, &mut display_names);
+
+    match ordering {
+        ParticipantOrder::Declared => {}
+        ParticipantOrder::FirstUse => {
+            let mut used = Vec::new();
+            collect_message_endpoints(&diagram.statements, &mut used);
+            order.sort_by_key(|id| used.iter().position(|u| u == id).unwrap_or(usize::MAX));
         }
-        let ch_w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(1);
-        if w + ch_w >= target_width {
-            break;
+        ParticipantOrder::Alpha => {
+            order.sort_by(|a, b| display_names[a].cmp(&display_names[b]).then_with(|| a.cmp(b)));
         }
-        result.push(ch);
-        w += ch_w;
     }
-    result.push('…');
-    result
-}
 
-fn collect_participants(
-    diagram: &Diagram,
-) -> (Vec<String>, std::collections::HashMap<String, String>) {
-    let mut order: Vec<String> = Vec::new();
-    let mut display_names: std::collections::HashMap<String, String> =
-        std::collections::HashMap::new();
+    (order, display_names)
+}
 
-    for stmt in &diagram.statements {
+/// Message endpoints in order of first appearance, descending into blocks.
+fn collect_message_endpoints(statements: &[Statement], used: &mut Vec<String>) {
+    for stmt in statements {
         match stmt {
-            Statement::ParticipantDecl(p) | Statement::Create(p) => {
-                if !order.contains(&p.id) {
-                    order.push(p.id.clone());
-                    let name = p.alias.clone().unwrap_or_else(|| p.id.clone());
-                    display_names.insert(p.id.clone(), name);
-                }
-            }
             Statement::Message(m) => {
                 for id in [&m.from, &m.to] {
-                    if !order.contains(id) {
-                        order.push(id.clone());
-                        display_names.insert(id.clone(), id.clone());
+                    if !used.contains(id) {
+                        used.push(id.clone());
                     }
                 }
             }
-            Statement::Note(_) | Statement::Activate(_) | Statement::Deactivate(_) | Statement::Destroy(_) | Statement::AutoNumber => {}
             Statement::Loop(lb) | Statement::Opt(lb) | Statement::Break(lb) | Statement::Rect(lb) => {
-                collect_participants_inner(&lb.body, &mut order, &mut display_names);
+                collect_message_endpoints(&lb.body, used);
             }
             Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
-                collect_participants_inner(&ab.body, &mut order, &mut display_names);
+                collect_message_endpoints(&ab.body, used);
                 for branch in &ab.else_branches {
-                    collect_participants_inner(&branch.body, &mut order, &mut display_names);
+                    collect_message_endpoints(&branch.body, used);
                 }
             }
+            _ => {}
         }
     }
-
-    (order, display_names)
 }
 
+/// Participants in order of declaration or first mention, at any block depth.
 fn collect_participants_inner(
     statements: &[Statement],
     order: &mut Vec<String>,
-    display_names: &mut std::collections::HashMap<String, String>,
+    display_names: &mut HashMap<String, String>,
 ) {
     for stmt in statements {
-        if let Statement::Message(m) = stmt {
-            for id in [&m.from, &m.to] {
-                if !order.contains(id) {
-                    order.push(id.clone());
-                    display_names.insert(id.clone(), id.clone());
+        match stmt {
+            Statement::ParticipantDecl(p) | Statement::Create(p) => {
+                if !display_names.contains_key(&p.id) {
+                    order.push(p.id.clone());
+                }
+                // An alias declared after the id's first use still names it
+                match &p.alias {
+                    Some(alias) => {
+                        display_names.insert(p.id.clone(), alias.clone());
+                    }
+                    None => {
+                        display_names.entry(p.id.clone()).or_insert_with(|| p.id.clone());
+                    }
+                }
+            }
+            Statement::Message(m) => {
+                for id in [&m.from, &m.to] {
+                    if !display_names.contains_key(id) {
+                        order.push(id.clone());
+                        display_names.insert(id.clone(), id.clone());
+                    }
+                }
+            }
+            Statement::Loop(lb)
+            | Statement::Opt(lb)
+            | Statement::Break(lb)
+            | Statement::Rect(lb) => {
+                collect_participants_inner(&lb.body, order, display_names);
+            }
+            Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
+                collect_participants_inner(&ab.body, order, display_names);
+                for branch in &ab.else_branches {
+                    collect_participants_inner(&branch.body, order, display_names);
                 }
             }
+            _ => {}
         }
     }
 }
 
+/// Map each participant id to its column index, so per-statement lookups
+/// don't scan the participant list.
+fn index_participants(order: &[String]) -> HashMap<String, usize> {
+    order.iter().enumerate().map(|(i, id)| (id.clone(), i)).collect()
+}
+
 fn compute_gaps(
     diagram: &Diagram,
     order: &[String],
-    display_names: &std::collections::HashMap<String, String>,
+    index: &HashMap<String, usize>,
+    display_names: &HashMap<String, String>,
+    number_width: usize,
+    options: &SequenceOptions,
 ) -> Vec<usize> {
     if order.len() <= 1 {
         return vec![];
     }
 
-    let mut gaps = vec![MIN_GAP; order.len() - 1];
+    let mut gaps = vec![options.min_gap; order.len() - 1];
 
-    compute_gaps_inner(&diagram.statements, order, &mut gaps);
+    compute_gaps_inner(&diagram.statements, index, &mut gaps, number_width, options);
 
     for (i, gap_idx) in (0..order.len().saturating_sub(1)).enumerate() {
         let left_name = display_names.get(&order[i]).unwrap();
         let right_name = display_names.get(&order[i + 1]).unwrap();
-        let left_half = multiline_width(left_name) / 2 + 2;
-        let right_half = multiline_width(right_name) / 2 + 2;
-        let min_for_boxes = left_half + right_half + 2;
+        let min_for_boxes = options.half_box(left_name) + options.half_box(right_name) + 2;
         gaps[gap_idx] = gaps[gap_idx].max(min_for_boxes);
     }
 
     gaps
 }
 
-fn compute_gaps_inner(statements: &[Statement], order: &[String], gaps: &mut [usize]) {
+fn compute_gaps_inner(
+    statements: &[Statement],
+    index: &HashMap<String, usize>,
+    gaps: &mut [usize],
+    number_width: usize,
+    options: &SequenceOptions,
+) {
     for stmt in statements {
         match stmt {
             Statement::Message(m) => {
-                let from_idx = order.iter().position(|id| *id == m.from);
-                let to_idx = order.iter().position(|id| *id == m.to);
+                let from_idx = index.get(&m.from).copied();
+                let to_idx = index.get(&m.to).copied();
 
                 if let (Some(fi), Some(ti)) = (from_idx, to_idx) {
                     if fi == ti {
                         // Self-message: need space to the right for text + loop arm
-                        let required =
-                            (multiline_width(&m.text) + 3).max(SELF_LOOP_ARM + 2);
+                        let required = (number_width + multiline_width(&m.text) + 3)
+                            .max(options.self_loop_arm + 2);
                         if fi < gaps.len() {
                             gaps[fi] = gaps[fi].max(required);
                         }
                     } else {
                         let (left, right) = if fi < ti { (fi, ti) } else { (ti, fi) };
                         let span_count = right - left;
-                        let required = multiline_width(&m.text) + ARROW_DECORATION_WIDTH + 2;
+                        let required = number_width
+                            + multiline_width(&m.text)
+                            + ARROW_DECORATION_WIDTH
+                            + 2;
                         let per_gap = required.div_ceil(span_count);
                         for gap in &mut gaps[left..right] {
                             *gap = (*gap).max(per_gap);
                         }
+                        if span_count > 1 {
+                            // The text steps around the lifelines it crosses, so
+                            // whatever runs on widens the gap at the far end
+                            for line in split_br(&m.text) {
+                                let cols: Vec<usize> = std::iter::once(0)
+                                    .chain(gaps[left..right].iter().scan(0, |col, gap| {
+                                        *col += gap;
+                                        Some(*col)
+                                    }))
+                                    .collect();
+                                let pieces = message_text_pieces(
+                                    line,
+                                    &cols,
+                                    number_width,
+                                    MessageAlign::Left,
+                                );
+                                if let Some(&(col, piece)) = pieces.last() {
+                                    let needed = (col + display_width(piece) + 2)
+                                        .saturating_sub(cols[span_count - 1]);
+                                    gaps[right - 1] = gaps[right - 1].max(needed);
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -387,15 +1286,15 @@ fn compute_gaps_inner(statements: &[Statement], order: &[String], gaps: &mut [us
                 let note_box_width = multiline_width(&n.text) + 4;
                 match &n.placement {
                     NotePlacement::RightOf(id) => {
-                        if let Some(idx) = order.iter().position(|p| p == id)
-                            && idx + 1 < order.len()
+                        if let Some(&idx) = index.get(id)
+                            && idx < gaps.len()
                         {
                             let required = note_box_width + 4;
                             gaps[idx] = gaps[idx].max(required);
                         }
                     }
                     NotePlacement::LeftOf(id) => {
-                        if let Some(idx) = order.iter().position(|p| p == id)
+                        if let Some(&idx) = index.get(id)
                             && idx > 0
                         {
                             let required = note_box_width + 4;
@@ -403,19 +1302,19 @@ fn compute_gaps_inner(statements: &[Statement], order: &[String], gaps: &mut [us
                         }
                     }
                     NotePlacement::Over(id) => {
-                        if let Some(idx) = order.iter().position(|p| p == id) {
+                        if let Some(&idx) = index.get(id) {
                             let half = note_box_width / 2 + 1;
                             if idx > 0 {
                                 gaps[idx - 1] = gaps[idx - 1].max(half);
                             }
-                            if idx + 1 < order.len() {
+                            if idx < gaps.len() {
                                 gaps[idx] = gaps[idx].max(half);
                             }
                         }
                     }
                     NotePlacement::OverTwo(a, b) => {
-                        let a_idx = order.iter().position(|p| p == a);
-                        let b_idx = order.iter().position(|p| p == b);
+                        let a_idx = index.get(a).copied();
+                        let b_idx = index.get(b).copied();
                         if let (Some(ai), Some(bi)) = (a_idx, b_idx) {
                             let (left, right) = if ai < bi { (ai, bi) } else { (bi, ai) };
                             let span_count = right - left;
@@ -431,12 +1330,12 @@ fn compute_gaps_inner(statements: &[Statement], order: &[String], gaps: &mut [us
                 }
             }
             Statement::Loop(lb) | Statement::Opt(lb) | Statement::Break(lb) | Statement::Rect(lb) => {
-                compute_gaps_inner(&lb.body, order, gaps);
+                compute_gaps_inner(&lb.body, index, gaps, number_width, options);
             }
             Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
-                compute_gaps_inner(&ab.body, order, gaps);
+                compute_gaps_inner(&ab.body, index, gaps, number_width, options);
                 for branch in &ab.else_branches {
-                    compute_gaps_inner(&branch.body, order, gaps);
+                    compute_gaps_inner(&branch.body, index, gaps, number_width, options);
                 }
             }
             _ => {}
@@ -444,15 +1343,81 @@ fn compute_gaps_inner(statements: &[Statement], order: &[String], gaps: &mut [us
     }
 }
 
+/// Where the pieces of one line of message text go, as `(column, text)`,
+/// for a message between the first and last of the lifeline columns
+/// `cols`. Unless it is centered in one piece, the text fills the lanes
+/// between lifelines in turn, breaking at spaces, so no piece is drawn over
+/// a lifeline it crosses. The last lane takes whatever is left.
+pub(crate) fn message_text_pieces<'a>(
+    line: &'a str,
+    cols: &[usize],
+    number_width: usize,
+    align: MessageAlign,
+) -> Vec<(usize, &'a str)> {
+    let mut rest = line.trim();
+    if align == MessageAlign::Center
+        && let [first, crossed @ .., last] = cols
+    {
+        // Over the whole arrow if that's clear of the lifelines it crosses,
+        // or else over the lane nearest its middle that the text fits in
+        let width = display_width(rest);
+        let start = |i: usize| cols[i] + 2 + if i == 0 { number_width } else { 0 };
+        let centered = |from: usize, to: usize| {
+            let room = (to - 1).saturating_sub(from);
+            (width <= room).then(|| from + (room - width) / 2)
+        };
+        if let Some(col) = centered(start(0), *last)
+            && !crossed.iter().any(|c| (col - 1..=col + width).contains(c))
+        {
+            return vec![(col, rest)];
+        }
+        let middle = first + last;
+        let mut lanes: Vec<usize> = (0..cols.len() - 1).collect();
+        lanes.sort_by_key(|&i| (cols[i] + cols[i + 1]).abs_diff(middle));
+        if let Some(col) = lanes.iter().find_map(|&i| centered(start(i), cols[i + 1])) {
+            return vec![(col, rest)];
+        }
+    }
+
+    let mut pieces = Vec::new();
+    for (lane, pair) in cols.windows(2).enumerate() {
+        if rest.is_empty() {
+            break;
+        }
+        let start = pair[0] + 2 + if lane == 0 { number_width } else { 0 };
+        let piece = if lane + 2 == cols.len() {
+            rest
+        } else {
+            let room = (pair[1] - 1).saturating_sub(start);
+            let fits = |text: &str| display_width(text) <= room;
+            if fits(rest) {
+                rest
+            } else {
+                rest.match_indices(' ')
+                    .map(|(i, _)| rest[..i].trim_end())
+                    .take_while(|text| fits(text))
+                    .last()
+                    .unwrap_or("")
+            }
+        };
+        if !piece.is_empty() {
+            pieces.push((start, piece));
+        }
+        rest = rest[piece.len()..].trim_start();
+    }
+    pieces
+}
+
 fn compute_positions(
     order: &[String],
-    display_names: &std::collections::HashMap<String, String>,
+    display_names: &HashMap<String, String>,
     gaps: &[usize],
+    options: &SequenceOptions,
 ) -> Vec<ParticipantLayout> {
     let mut participants = Vec::new();
 
     let first_name = display_names.get(&order[0]).unwrap();
-    let first_box_width = multiline_width(first_name) + 4;
+    let first_box_width = options.box_width(first_name);
     let first_center = first_box_width / 2;
 
     participants.push(ParticipantLayout {
@@ -467,7 +1432,7 @@ fn compute_positions(
         let prev_center = participants[i].center_col;
         let center = prev_center + gap;
         let name = display_names.get(&order[i + 1]).unwrap();
-        let box_width = multiline_width(name) + 4;
+        let box_width = options.box_width(name);
 
         participants.push(ParticipantLayout {
             name: name.clone(),
@@ -481,190 +1446,271 @@ fn compute_positions(
     participants
 }
 
+/// Width of the `[n] ` autonumber gutter, sized for the largest number so
+/// message text starts at the same offset on every row.
+fn compute_number_width(diagram: &Diagram) -> usize {
+    let autonumber = diagram.statements.iter().any(|s| matches!(s, Statement::AutoNumber));
+    if !autonumber {
+        return 0;
+    }
+    let count = count_messages(&diagram.statements);
+    display_width(&format!("[{}]", count.max(1))) + 1
+}
+
+fn count_messages(statements: &[Statement]) -> usize {
+    statements
+        .iter()
+        .map(|stmt| match stmt {
+            Statement::Message(_) => 1,
+            Statement::Loop(lb) | Statement::Opt(lb) | Statement::Break(lb) | Statement::Rect(lb) => {
+                count_messages(&lb.body)
+            }
+            Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
+                count_messages(&ab.body)
+                    + ab.else_branches
+                        .iter()
+                        .map(|b| count_messages(&b.body))
+                        .sum::<usize>()
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Rows in drawing order, each with the lifelines active alongside it.
 fn compute_rows(
     diagram: &Diagram,
-    order: &[String],
+    index: &HashMap<String, usize>,
     participants: &[ParticipantLayout],
-) -> Vec<Row> {
-    let mut rows = Vec::new();
+) -> Vec<LayoutRow> {
     let autonumber = diagram.statements.iter().any(|s| matches!(s, Statement::AutoNumber));
-    let mut msg_counter = if autonumber { Some(1usize) } else { None };
-    flatten_statements(&diagram.statements, order, participants, &mut rows, &mut msg_counter);
-    rows
+    let mut builder = RowBuilder {
+        index,
+        participants,
+        lines: diagram.source_lines.0.iter(),
+        rows: Vec::new(),
+        depths: vec![0; index.len()],
+        msg_counter: if autonumber { Some(1) } else { None },
+    };
+    builder.flatten_statements(&diagram.statements);
+    builder.rows
 }
 
-fn flatten_statements(
-    statements: &[Statement],
-    order: &[String],
-    participants: &[ParticipantLayout],
-    rows: &mut Vec<Row>,
-    msg_counter: &mut Option<usize>,
-) {
-    for stmt in statements {
-        match stmt {
-            Statement::Message(m) => {
-                let from_idx = order.iter().position(|id| *id == m.from).unwrap();
-                let to_idx = order.iter().position(|id| *id == m.to).unwrap();
-                let from_col = participants[from_idx].center_col;
-                let to_col = participants[to_idx].center_col;
+struct RowBuilder<'a> {
+    index: &'a HashMap<String, usize>,
+    participants: &'a [ParticipantLayout],
+    /// Source lines of the statements, dividers and `end`s still to come.
+    lines: std::slice::Iter<'a, usize>,
+    rows: Vec<LayoutRow>,
+    /// Activation depth per participant.
+    depths: Vec<usize>,
+    msg_counter: Option<usize>,
+}
 
-                let direction = if from_idx <= to_idx {
-                    Direction::LeftToRight
-                } else {
-                    Direction::RightToLeft
-                };
+impl RowBuilder<'_> {
+    /// Add `row`, written on `source_line`, with the lifelines active at
+    /// this point.
+    fn push(&mut self, row: Row, source_line: Option<usize>) {
+        let active = self.depths.iter().map(|&d| d > 0).collect();
+        let height = row_height(&row);
+        self.rows.push(LayoutRow { row, active, height, source_line });
+    }
 
-                let text = if let Some(n) = msg_counter.as_mut() {
-                    let numbered = format!("{n}. {}", m.text);
-                    *n += 1;
-                    numbered
-                } else {
-                    m.text.clone()
-                };
+    /// The source line of the next statement, divider or `end`.
+    fn next_line(&mut self) -> Option<usize> {
+        self.lines.next().copied()
+    }
 
-                rows.push(Row::Message(MessageRow {
-                    from_col,
-                    to_col,
-                    text,
-                    arrow: m.arrow,
-                    direction,
-                }));
-            }
-            Statement::Note(n) => {
-                let note_box_width = multiline_width(&n.text) + 4;
-                let (box_left, box_right) = match &n.placement {
-                    NotePlacement::RightOf(id) => {
-                        let idx = order.iter().position(|p| p == id).unwrap();
-                        let left = participants[idx].center_col + 2;
-                        (left, left + note_box_width - 1)
+    fn activate(&mut self, id: &str) {
+        if let Some(&idx) = self.index.get(id) {
+            self.depths[idx] += 1;
+        }
+    }
+
+    fn deactivate(&mut self, id: &str) {
+        if let Some(&idx) = self.index.get(id) {
+            self.depths[idx] = self.depths[idx].saturating_sub(1);
+        }
+    }
+
+    fn flatten_statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            let line = self.next_line();
+            match stmt {
+                Statement::Message(m) => {
+                    // A self-message leaves its lifeline as it was and
+                    // returns to it activated or deactivated
+                    let is_self = m.from == m.to;
+                    if m.activate_target && !is_self {
+                        self.activate(&m.to);
                     }
-                    NotePlacement::LeftOf(id) => {
-                        let idx = order.iter().position(|p| p == id).unwrap();
-                        let right = participants[idx].center_col.saturating_sub(2);
-                        (right.saturating_sub(note_box_width - 1), right)
+                    let Some(row) = self.message_row(m) else { continue };
+                    self.push(row, line);
+                    if m.activate_target && is_self {
+                        self.activate(&m.to);
                     }
-                    NotePlacement::Over(id) => {
-                        let idx = order.iter().position(|p| p == id).unwrap();
-                        let center = participants[idx].center_col;
-                        let half = note_box_width / 2;
-                        let left = center.saturating_sub(half);
-                        (left, left + note_box_width - 1)
+                    if m.deactivate_source {
+                        self.deactivate(&m.from);
                     }
-                    NotePlacement::OverTwo(a, b) => {
-                        let a_idx = order.iter().position(|p| p == a).unwrap();
-                        let b_idx = order.iter().position(|p| p == b).unwrap();
-                        let (left_idx, right_idx) = if a_idx < b_idx {
-                            (a_idx, b_idx)
-                        } else {
-                            (b_idx, a_idx)
-                        };
-                        let left = participants[left_idx].center_col.saturating_sub(1);
-                        let right = participants[right_idx].center_col + 1;
-                        let min_right = left + note_box_width - 1;
-                        (left, right.max(min_right))
+                    if is_self && (m.activate_target || m.deactivate_source) {
+                        let active = self.index.get(&m.to).is_some_and(|&i| self.depths[i] > 0);
+                        if let Some(Row::Message(row)) = self.rows.last_mut().map(|r| &mut r.row) {
+                            row.return_active = Some(active);
+                        }
                     }
-                };
-                rows.push(Row::Note(NoteRow {
-                    box_left,
-                    box_right,
-                    text: n.text.clone(),
-                }));
-            }
-            Statement::Loop(lb) => {
-                push_simple_block("loop", lb, participants, order, rows, msg_counter);
-            }
-            Statement::Opt(lb) => {
-                push_simple_block("opt", lb, participants, order, rows, msg_counter);
-            }
-            Statement::Break(lb) => {
-                push_simple_block("break", lb, participants, order, rows, msg_counter);
-            }
-            Statement::Alt(ab) => {
-                push_divided_block("alt", "else", ab, participants, order, rows, msg_counter);
+                }
+                Statement::Note(n) => {
+                    let Some(row) = self.note_row(n) else { continue };
+                    self.push(row, line);
+                }
+                Statement::Activate(id) => self.activate(id),
+                Statement::Deactivate(id) => self.deactivate(id),
+                Statement::Loop(lb) => self.push_simple_block("loop", lb, line),
+                Statement::Opt(lb) => self.push_simple_block("opt", lb, line),
+                Statement::Break(lb) => self.push_simple_block("break", lb, line),
+                Statement::Rect(lb) => self.push_simple_block("rect", lb, line),
+                Statement::Alt(ab) => self.push_divided_block("alt", "else", ab, line),
+                Statement::Par(ab) => self.push_divided_block("par", "and", ab, line),
+                Statement::Critical(ab) => {
+                    self.push_divided_block("critical", "option", ab, line)
+                }
+                Statement::Destroy(id) => {
+                    if let Some(&idx) = self.index.get(id) {
+                        let col = self.participants[idx].center_col;
+                        let row = Row::Destroy(DestroyRow { col, participant_idx: idx });
+                        self.push(row, line);
+                    }
+                }
+                Statement::ParticipantDecl(_) | Statement::Create(_) | Statement::AutoNumber => {}
             }
-            Statement::Par(ab) => {
-                push_divided_block("par", "and", ab, participants, order, rows, msg_counter);
+        }
+    }
+
+    /// The row for `m`, or `None` when it names a participant the diagram
+    /// doesn't have.
+    fn message_row(&mut self, m: &Message) -> Option<Row> {
+        let from_idx = *self.index.get(&m.from)?;
+        let to_idx = *self.index.get(&m.to)?;
+        let from_col = self.participants[from_idx].center_col;
+        let to_col = self.participants[to_idx].center_col;
+
+        let direction = if from_idx <= to_idx {
+            Direction::LeftToRight
+        } else {
+            Direction::RightToLeft
+        };
+
+        let number = self.msg_counter.as_mut().map(|n| {
+            let current = *n;
+            *n += 1;
+            current
+        });
+
+        Some(Row::Message(MessageRow {
+            from_col,
+            to_col,
+            text: m.text.clone(),
+            number,
+            arrow: m.arrow,
+            direction,
+            return_active: None,
+        }))
+    }
+
+    /// The row for `n`, or `None` when it is placed against a participant
+    /// the diagram doesn't have.
+    fn note_row(&self, n: &Note) -> Option<Row> {
+        let index = self.index;
+        let participants = self.participants;
+        let note_box_width = multiline_width(&n.text) + 4;
+        let (box_left, box_right) = match &n.placement {
+            NotePlacement::RightOf(id) => {
+                let idx = *index.get(id)?;
+                let left = participants[idx].center_col + 2;
+                (left, left + note_box_width - 1)
             }
-            Statement::Critical(ab) => {
-                push_divided_block("critical", "option", ab, participants, order, rows, msg_counter);
+            NotePlacement::LeftOf(id) => {
+                let idx = *index.get(id)?;
+                let right = participants[idx].center_col.saturating_sub(2);
+                (right.saturating_sub(note_box_width - 1), right)
             }
-            Statement::Rect(lb) => {
-                push_simple_block("rect", lb, participants, order, rows, msg_counter);
+            NotePlacement::Over(id) => {
+                let idx = *index.get(id)?;
+                let center = participants[idx].center_col;
+                let half = note_box_width / 2;
+                let left = center.saturating_sub(half);
+                (left, left + note_box_width - 1)
             }
-            Statement::Destroy(id) => {
-                if let Some(idx) = order.iter().position(|p| p == id) {
-                    let col = participants[idx].center_col;
-                    rows.push(Row::Destroy(DestroyRow {
-                        col,
-                        participant_idx: idx,
-                    }));
-                }
+            NotePlacement::OverTwo(a, b) => {
+                let a_idx = *index.get(a)?;
+                let b_idx = *index.get(b)?;
+                let (left_idx, right_idx) = if a_idx < b_idx {
+                    (a_idx, b_idx)
+                } else {
+                    (b_idx, a_idx)
+                };
+                let left = participants[left_idx].center_col.saturating_sub(1);
+                let right = participants[right_idx].center_col + 1;
+                let min_right = left + note_box_width - 1;
+                (left, right.max(min_right))
             }
-            _ => {}
+        };
+        Some(Row::Note(NoteRow {
+            box_left,
+            box_right,
+            text: n.text.clone(),
+        }))
+    }
+
+    fn push_simple_block(&mut self, keyword: &str, block: &LoopBlock, line: Option<usize>) {
+        let (frame_left, frame_right) = compute_frame_bounds(self.participants);
+        let label = block_title(keyword, &block.label);
+        let frame_right = frame_right.max(frame_left + 2 + multiline_width(&label) + 1);
+        let start = BlockRow { label, frame_left, frame_right };
+        self.push(Row::BlockStart(start), line);
+        self.flatten_statements(&block.body);
+        let end = BlockRow { label: String::new(), frame_left, frame_right };
+        let end_line = self.next_line();
+        self.push(Row::BlockEnd(end), end_line);
+    }
+
+    fn push_divided_block(
+        &mut self,
+        keyword: &str,
+        divider: &str,
+        block: &AltBlock,
+        line: Option<usize>,
+    ) {
+        let (frame_left, frame_right) = compute_frame_bounds(self.participants);
+        let start_label = block_title(keyword, &block.label);
+        let mut max_label_width = multiline_width(&start_label);
+        for branch in &block.else_branches {
+            let div_label = block_title(divider, &branch.label);
+            max_label_width = max_label_width.max(multiline_width(&div_label));
+        }
+        let frame_right = frame_right.max(frame_left + 2 + max_label_width + 1);
+        let start = BlockRow { label: start_label, frame_left, frame_right };
+        self.push(Row::BlockStart(start), line);
+        self.flatten_statements(&block.body);
+        for branch in &block.else_branches {
+            let label = block_title(divider, &branch.label);
+            let divider_line = self.next_line();
+            self.push(Row::BlockDivider(BlockRow { label, frame_left, frame_right }), divider_line);
+            self.flatten_statements(&branch.body);
         }
+        let end = BlockRow { label: String::new(), frame_left, frame_right };
+        let end_line = self.next_line();
+        self.push(Row::BlockEnd(end), end_line);
     }
 }
 
-fn push_simple_block(
-    keyword: &str,
-    block: &LoopBlock,
-    participants: &[ParticipantLayout],
-    order: &[String],
-    rows: &mut Vec<Row>,
-    msg_counter: &mut Option<usize>,
-) {
-    let (frame_left, frame_right) = compute_frame_bounds(participants);
-    let label = format!("{keyword} {}", block.label);
-    let frame_right = frame_right.max(frame_left + 2 + display_width(&label) + 1);
-    rows.push(Row::BlockStart(BlockRow {
-        label,
-        frame_left,
-        frame_right,
-    }));
-    flatten_statements(&block.body, order, participants, rows, msg_counter);
-    rows.push(Row::BlockEnd(BlockRow {
-        label: String::new(),
-        frame_left,
-        frame_right,
-    }));
-}
-
-fn push_divided_block(
-    keyword: &str,
-    divider: &str,
-    block: &AltBlock,
-    participants: &[ParticipantLayout],
-    order: &[String],
-    rows: &mut Vec<Row>,
-    msg_counter: &mut Option<usize>,
-) {
-    let (frame_left, frame_right) = compute_frame_bounds(participants);
-    let start_label = format!("{keyword} {}", block.label);
-    let mut max_label_width = display_width(&start_label);
-    for branch in &block.else_branches {
-        let div_label = format!("{divider} {}", branch.label);
-        max_label_width = max_label_width.max(display_width(&div_label));
-    }
-    let frame_right = frame_right.max(frame_left + 2 + max_label_width + 1);
-    rows.push(Row::BlockStart(BlockRow {
-        label: start_label,
-        frame_left,
-        frame_right,
-    }));
-    flatten_statements(&block.body, order, participants, rows, msg_counter);
-    for branch in &block.else_branches {
-        rows.push(Row::BlockDivider(BlockRow {
-            label: format!("{divider} {}", branch.label),
-            frame_left,
-            frame_right,
-        }));
-        flatten_statements(&branch.body, order, participants, rows, msg_counter);
-    }
-    rows.push(Row::BlockEnd(BlockRow {
-        label: String::new(),
-        frame_left,
-        frame_right,
-    }));
+/// A frame's title: the block keyword and its label, if it has one.
+fn block_title(keyword: &str, label: &str) -> String {
+    if label.is_empty() {
+        keyword.to_string()
+    } else {
+        format!("{keyword} {label}")
+    }
 }
 
 fn compute_frame_bounds(participants: &[ParticipantLayout]) -> (usize, usize) {
@@ -673,92 +1719,11 @@ fn compute_frame_bounds(participants: &[ParticipantLayout]) -> (usize, usize) {
     (frame_left, frame_right)
 }
 
-fn compute_activations(
-    diagram: &Diagram,
-    order: &[String],
-    row_count: usize,
-) -> Vec<Vec<bool>> {
-    let participant_count = order.len();
-    let mut depths: Vec<i32> = vec![0; participant_count];
-    let mut activations = Vec::with_capacity(row_count);
-
-    compute_activations_inner(&diagram.statements, order, &mut depths, &mut activations);
-
-    debug_assert_eq!(activations.len(), row_count);
-    activations
-}
-
-fn compute_activations_inner(
-    statements: &[Statement],
-    order: &[String],
-    depths: &mut Vec<i32>,
-    activations: &mut Vec<Vec<bool>>,
-) {
-    for stmt in statements {
-        match stmt {
-            Statement::Activate(id) => {
-                if let Some(idx) = order.iter().position(|p| p == id) {
-                    depths[idx] += 1;
-                }
-            }
-            Statement::Deactivate(id) => {
-                if let Some(idx) = order.iter().position(|p| p == id) {
-                    depths[idx] = (depths[idx] - 1).max(0);
-                }
-            }
-            Statement::Message(m) => {
-                if m.activate_target
-                    && let Some(idx) = order.iter().position(|p| p == &m.to)
-                {
-                    depths[idx] += 1;
-                }
-
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active);
-
-                if m.deactivate_source
-                    && let Some(idx) = order.iter().position(|p| p == &m.from)
-                {
-                    depths[idx] = (depths[idx] - 1).max(0);
-                }
-            }
-            Statement::Note(_) => {
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active);
-            }
-            Statement::Loop(lb) | Statement::Opt(lb) | Statement::Break(lb) | Statement::Rect(lb) => {
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active.clone());
-                compute_activations_inner(&lb.body, order, depths, activations);
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active);
-            }
-            Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active);
-                compute_activations_inner(&ab.body, order, depths, activations);
-                for branch in &ab.else_branches {
-                    let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                    activations.push(row_active);
-                    compute_activations_inner(&branch.body, order, depths, activations);
-                }
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active);
-            }
-            Statement::Destroy(_) => {
-                let row_active: Vec<bool> = depths.iter().map(|&d| d > 0).collect();
-                activations.push(row_active);
-            }
-            Statement::ParticipantDecl(_) | Statement::Create(_) | Statement::AutoNumber => {}
-        }
-    }
-}
-
-fn compute_destroyed(rows: &[Row], participant_count: usize) -> Vec<bool> {
+fn compute_destroyed(rows: &[LayoutRow], participant_count: usize) -> Vec<bool> {
     let mut destroyed = vec![false; participant_count];
     for row in rows {
-        if let Row::Destroy(d) = row {
-            destroyed[d.participant_idx] = true;
+        for idx in row.row.destroyed() {
+            destroyed[idx] = true;
         }
     }
     destroyed
@@ -797,6 +1762,41 @@ sequenceDiagram
         assert_eq!(layout.participants[1].name, "Bob");
     }
 
+    #[test]
+    fn layout_late_alias_names_an_implicit_participant() {
+        let input = "\
+sequenceDiagram
+    A->>B: Hello
+    participant B as Bob
+    participant A
+";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+
+        let names: Vec<&str> = layout.participants.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["A", "Bob"]);
+    }
+
+    #[test]
+    fn layout_participants_from_nested_blocks() {
+        let input = "\
+sequenceDiagram
+    participant A as Alice
+    alt first
+        loop retry
+            A->>Bob: Hello
+        end
+    else
+        participant C as Carol
+    end
+";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+
+        let names: Vec<&str> = layout.participants.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Alice", "Bob", "Carol"]);
+    }
+
     #[test]
     fn layout_gap_accommodates_message_text() {
         let diagram =
@@ -810,13 +1810,40 @@ sequenceDiagram
         );
     }
 
+    #[test]
+    fn message_text_steps_around_crossed_lifelines() {
+        let cols = [0, 10, 20, 30];
+        assert_eq!(
+            message_text_pieces("ask the far side for a refund", &cols, 0, MessageAlign::Left),
+            [(2, "ask the"), (12, "far"), (22, "side for a refund")]
+        );
+        // A word too wide for a lane moves on whole; the last lane takes the rest
+        assert_eq!(
+            message_text_pieces("go supercalifragilistic", &cols, 4, MessageAlign::Left),
+            [(6, "go"), (22, "supercalifragilistic")]
+        );
+        assert_eq!(message_text_pieces("hi", &cols, 0, MessageAlign::Left), [(2, "hi")]);
+
+        let diagram = parse_diagram(
+            "sequenceDiagram\n    participant A\n    participant B\n    participant C\n    \
+             A->>C: a label too long for the gap next to A\n",
+        )
+        .unwrap();
+        let layout = compute(&diagram).unwrap();
+        let cols: Vec<usize> = layout.participants.iter().map(|p| p.center_col).collect();
+        let line = "a label too long for the gap next to A";
+        let pieces = message_text_pieces(line, &cols, 0, MessageAlign::Left);
+        let &(col, last) = pieces.last().unwrap();
+        assert!(col + last.len() + 2 <= cols[2], "{pieces:?} in {cols:?}");
+    }
+
     #[test]
     fn layout_message_direction_left_to_right() {
         let diagram = parse_diagram("sequenceDiagram\n    Alice->>Bob: Hi\n").unwrap();
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 1);
-        match &layout.rows[0] {
+        match &layout.rows[0].row {
             Row::Message(m) => {
                 assert_eq!(m.direction, Direction::LeftToRight);
                 assert_eq!(m.text, "Hi");
@@ -831,7 +1858,7 @@ sequenceDiagram
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 2);
-        match &layout.rows[1] {
+        match &layout.rows[1].row {
             Row::Message(m) => {
                 assert_eq!(m.direction, Direction::RightToLeft);
                 assert_eq!(m.text, "Hello");
@@ -867,7 +1894,7 @@ sequenceDiagram
         assert!(layout.participants[1].center_col < layout.participants[2].center_col);
         assert_eq!(layout.rows.len(), 3);
 
-        match &layout.rows[2] {
+        match &layout.rows[2].row {
             Row::Message(m) => {
                 assert_eq!(m.direction, Direction::RightToLeft);
             }
@@ -889,10 +1916,32 @@ sequenceDiagram
 
         // Row 0: Alice->>+Bob → Bob active after this message
         // Row 1: Bob-->>-Alice → Bob deactivated after this message
-        assert!(!layout.activations[0][0], "Alice not active at row 0");
-        assert!(layout.activations[0][1], "Bob active at row 0");
-        assert!(!layout.activations[1][0], "Alice not active at row 1");
-        assert!(layout.activations[1][1], "Bob still active at row 1 (deactivated after)");
+        assert!(!layout.rows[0].active[0], "Alice not active at row 0");
+        assert!(layout.rows[0].active[1], "Bob active at row 0");
+        assert!(!layout.rows[1].active[0], "Alice not active at row 1");
+        assert!(layout.rows[1].active[1], "Bob still active at row 1 (deactivated after)");
+    }
+
+    #[test]
+    fn layout_self_message_activation_changes_at_return() {
+        let input = "\
+sequenceDiagram
+    A->>+A: work
+    A-->>-A: done
+    A->>A: idle
+";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+
+        let returns: Vec<(bool, Option<bool>)> = layout
+            .rows
+            .iter()
+            .map(|r| match &r.row {
+                Row::Message(m) => (r.active[0], m.return_active),
+                other => panic!("expected Message, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(returns, [(false, Some(true)), (true, Some(false)), (false, None)]);
     }
 
     #[test]
@@ -909,8 +1958,8 @@ sequenceDiagram
 
         // Only Message rows are in layout.rows, Activate/Deactivate are not rows
         assert_eq!(layout.rows.len(), 2);
-        assert!(layout.activations[0][0], "Alice active at row 0");
-        assert!(!layout.activations[1][0], "Alice not active at row 1");
+        assert!(layout.rows[0].active[0], "Alice active at row 0");
+        assert!(!layout.rows[1].active[0], "Alice not active at row 1");
     }
 
     #[test]
@@ -919,9 +1968,9 @@ sequenceDiagram
         let diagram = parse_diagram(input).unwrap();
         let layout = compute(&diagram).unwrap();
 
-        assert_eq!(layout.activations.len(), 1);
-        assert!(!layout.activations[0][0]);
-        assert!(!layout.activations[0][1]);
+        assert_eq!(layout.rows.len(), 1);
+        assert!(!layout.rows[0].active[0]);
+        assert!(!layout.rows[0].active[1]);
     }
 
     // --- notes ---
@@ -940,19 +1989,19 @@ sequenceDiagram
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 3, "BlockStart + Message + BlockEnd");
-        match &layout.rows[0] {
+        match &layout.rows[0].row {
             Row::BlockStart(b) => {
                 assert_eq!(b.label, "loop Check");
             }
             other => panic!("expected BlockStart, got {other:?}"),
         }
-        match &layout.rows[1] {
+        match &layout.rows[1].row {
             Row::Message(m) => {
                 assert_eq!(m.text, "Ping");
             }
             other => panic!("expected Message, got {other:?}"),
         }
-        match &layout.rows[2] {
+        match &layout.rows[2].row {
             Row::BlockEnd(b) => {
                 assert!(b.frame_left < layout.participants[0].center_col);
                 assert!(b.frame_right > layout.participants[1].center_col);
@@ -961,6 +2010,56 @@ sequenceDiagram
         }
     }
 
+    #[test]
+    fn layout_unlabeled_blocks_show_just_the_keyword() {
+        let input = "sequenceDiagram\n    alt\n        A->>B: x\n    else\n        A->>B: y\n    end\n";
+        let layout = compute(&parse_diagram(input).unwrap()).unwrap();
+        let labels: Vec<&str> = layout
+            .rows
+            .iter()
+            .filter_map(|r| match &r.row {
+                Row::BlockStart(b) | Row::BlockDivider(b) => Some(b.label.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(labels, ["alt", "else"]);
+    }
+
+    #[test]
+    fn layout_rows_know_their_source_lines() {
+        let input = "\
+sequenceDiagram
+    participant A
+    %% a comment
+
+    A->>B: one
+    alt ok
+        A->>B: two
+        A->>B: two
+        A->>B: two
+    else
+        Note over B: three
+    end
+";
+        let diagram = parse_diagram(input).unwrap();
+        let lines = |layout: &Layout| -> Vec<Option<usize>> {
+            layout.rows.iter().map(|r| r.source_line).collect()
+        };
+        let layout = compute(&diagram).unwrap();
+        let all = [5, 6, 7, 8, 9, 10, 11, 12].map(Some);
+        assert_eq!(lines(&layout), all);
+        let rows = layout.row_source_lines();
+        assert_eq!(rows[0], (3..6, 5));
+        assert_eq!(rows[1], (6..7, 6));
+
+        let options = SequenceOptions { collapse_repeats: Some(2), ..SequenceOptions::default() };
+        let collapsed = compute_with(&diagram, &options, None).unwrap();
+        assert_eq!(lines(&collapsed), [5, 6, 7, 10, 11, 12].map(Some));
+
+        let built = Diagram { source_lines: SourceLines::default(), ..diagram };
+        assert!(compute(&built).unwrap().row_source_lines().is_empty());
+    }
+
     #[test]
     fn layout_loop_with_surrounding_messages() {
         let input = "\
@@ -989,7 +2088,7 @@ sequenceDiagram
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 3);
-        match &layout.rows[1] {
+        match &layout.rows[1].row {
             Row::Note(n) => {
                 assert_eq!(n.text, "Got it!");
                 assert!(
@@ -1001,6 +2100,27 @@ sequenceDiagram
         }
     }
 
+    #[test]
+    fn layout_note_on_unknown_participant_is_an_error() {
+        let input = "\
+sequenceDiagram
+    Alice->>Bob: Hello
+    alt ok
+        Bob-->>Alice: Hi!
+    else
+        Note over Alice,Carol: who?
+    end
+";
+        let diagram = parse_diagram(input).unwrap();
+        assert_eq!(
+            compute(&diagram).unwrap_err(),
+            "unknown participant 'Carol' referenced in Note at line 6"
+        );
+
+        let built = Diagram { source_lines: SourceLines::default(), ..diagram };
+        assert_eq!(compute(&built).unwrap_err(), "unknown participant 'Carol' referenced in Note");
+    }
+
     #[test]
     fn layout_note_left_of_generates_row() {
         let input = "\
@@ -1012,7 +2132,7 @@ sequenceDiagram
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 2);
-        match &layout.rows[1] {
+        match &layout.rows[1].row {
             Row::Note(n) => {
                 assert_eq!(n.text, "Left note");
                 assert!(
@@ -1035,7 +2155,7 @@ sequenceDiagram
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 2);
-        match &layout.rows[1] {
+        match &layout.rows[1].row {
             Row::Note(n) => {
                 assert_eq!(n.text, "Thinking");
                 let alice_center = layout.participants[0].center_col;
@@ -1057,7 +2177,7 @@ sequenceDiagram
         let layout = compute(&diagram).unwrap();
 
         assert_eq!(layout.rows.len(), 2);
-        match &layout.rows[1] {
+        match &layout.rows[1].row {
             Row::Note(n) => {
                 assert_eq!(n.text, "Shared note");
                 let alice_center = layout.participants[0].center_col;
@@ -1109,6 +2229,18 @@ sequenceDiagram
         );
     }
 
+    #[test]
+    fn layout_wrapped_puts_long_names_on_more_lines() {
+        let input = "sequenceDiagram\n    title Greetings between two parties\n    \
+                     participant V as Very Long Participant\n    V->>AnotherLongName: Hi\n";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute_wrapped(&diagram, &SequenceOptions::default(), 25).unwrap();
+        assert!(layout.total_width <= 25, "width {}", layout.total_width);
+        let names: Vec<&str> = layout.participants.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Very<br/>Long<br/>Particip<br/>ant", "Another<br/>LongNam<br/>e"]);
+        assert_eq!(layout.title.as_deref(), Some("Greetings between two<br/>parties"));
+    }
+
     #[test]
     fn layout_max_width_impossible_returns_error() {
         let diagram = parse_diagram("sequenceDiagram\n    A->>B: Hi\n").unwrap();
@@ -1145,6 +2277,278 @@ sequenceDiagram
         );
     }
 
+    #[test]
+    fn layout_autonumber_keeps_text_and_sets_number() {
+        let input = "sequenceDiagram\n    autonumber\n    A->>B: Hi\n    B->>A: Yo\n";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+
+        assert_eq!(layout.number_width, "[2] ".len());
+        match &layout.rows[1].row {
+            Row::Message(m) => {
+                assert_eq!(m.text, "Yo");
+                assert_eq!(m.number, Some(2));
+            }
+            other => panic!("expected Message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn layout_options_control_gap_arm_and_padding() {
+        let diagram = parse_diagram("sequenceDiagram\n    A->>B: x\n    A->>A: y\n").unwrap();
+        let options = SequenceOptions {
+            min_gap: 6,
+            self_loop_arm: 2,
+            box_padding: 0,
+            ..SequenceOptions::default()
+        };
+        let layout = compute_with(&diagram, &options, None).unwrap();
+
+        let a = &layout.participants[0];
+        assert_eq!(a.box_right - a.box_left + 1, "A".len() + 2);
+        assert_eq!(layout.participants[1].center_col - a.center_col, 6);
+        assert_eq!(layout.options.self_loop_arm, 2);
+    }
+
+    #[test]
+    fn layout_participant_order_option() {
+        let input = "\
+sequenceDiagram
+    participant Zed
+    participant M as Mid
+    loop again
+        Bob->>Al: x
+    end
+    Al->>Zed: y
+";
+        let diagram = parse_diagram(input).unwrap();
+        let names = |order| {
+            let options = SequenceOptions { order, ..SequenceOptions::default() };
+            let layout = compute_with(&diagram, &options, None).unwrap();
+            layout.participants.into_iter().map(|p| p.name).collect::<Vec<_>>()
+        };
+        assert_eq!(names(ParticipantOrder::Declared), ["Zed", "Mid", "Bob", "Al"]);
+        assert_eq!(names(ParticipantOrder::FirstUse), ["Bob", "Al", "Zed", "Mid"]);
+        assert_eq!(names(ParticipantOrder::Alpha), ["Al", "Bob", "Mid", "Zed"]);
+    }
+
+    #[test]
+    fn layout_activations_follow_rows_through_nested_blocks() {
+        let input = "\
+sequenceDiagram
+    par p
+        A->>+B: open
+        alt ok
+            critical c
+                B->>A: one
+            option o
+                B-->>-A: close
+            end
+        else no
+            A->>B: two
+        end
+    and q
+        A->>B: three
+    end
+";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute(&diagram).unwrap();
+        let active_b: Vec<(&str, bool)> = layout
+            .rows
+            .iter()
+            .map(|laid| {
+                let name = match &laid.row {
+                    Row::Message(m) => m.text.as_str(),
+                    Row::BlockStart(b) | Row::BlockDivider(b) => b.label.as_str(),
+                    _ => "end",
+                };
+                (name, laid.active[1])
+            })
+            .collect();
+        assert_eq!(
+            active_b,
+            [
+                ("par p", false),
+                ("open", true),
+                ("alt ok", true),
+                ("critical c", true),
+                ("one", true),
+                ("option o", true),
+                ("close", true),
+                ("end", false),
+                ("else no", false),
+                ("two", false),
+                ("end", false),
+                ("and q", false),
+                ("three", false),
+                ("end", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_par_side_by_side_needs_separate_columns() {
+        let options = SequenceOptions { par_side_by_side: true, ..SequenceOptions::default() };
+        let layout = |input: &str| {
+            let diagram = parse_diagram(input).unwrap();
+            compute_with(&diagram, &options, None).unwrap()
+        };
+
+        let split = layout("sequenceDiagram\n    par a\n    A->>+B: x\n    and b\n    C->>D: y\n    end\n    A->>D: z\n");
+        assert_eq!(split.rows.len(), 2);
+        let Row::Parallel(par) = &split.rows[0].row else { panic!("{:?}", split.rows[0]) };
+        let labels: Vec<&str> = par.lanes.iter().map(|l| l.label.as_str()).collect();
+        assert_eq!(labels, ["par a", "and b"]);
+        assert_eq!(par.lanes[1].rows.len(), 1);
+        assert!(par.lanes[0].left < par.lanes[1].left);
+        assert!(par.lanes[1].left < split.participants[2].center_col);
+        assert_eq!(par.lanes[0].rows[0].active, [false, true, false, false]);
+        assert_eq!(par.lanes[0].active_after, [false, true, false, false]);
+        assert_eq!(split.rows[0].height, 5);
+
+        // Branches sharing B, or holding a nested block, stay stacked
+        let shared = layout("sequenceDiagram\n    par a\n    A->>B: x\n    and b\n    B->>C: y\n    end\n");
+        assert!(matches!(shared.rows[0].row, Row::BlockStart(_)));
+        let nested = layout("sequenceDiagram\n    participant C\n    participant D\n    par a\n    A->>B: x\n    and b\n    loop l\n    C->>D: y\n    end\n    end\n");
+        assert!(matches!(nested.rows[0].row, Row::BlockStart(_)));
+    }
+
+    #[test]
+    fn layout_fits_right_margin_notes_under_max_width() {
+        let input = "\
+sequenceDiagram
+    Alice->>Bob: a fairly long message
+    Note right of Alice: wrapped where it stands
+    Note right of Bob: moved left to fit
+";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute_with_max_width(&diagram, 30).unwrap();
+        let notes: Vec<_> = layout
+            .rows
+            .iter()
+            .filter_map(|laid| match &laid.row {
+                Row::Note(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(notes[0].box_left, layout.participants[0].center_col + 2);
+        assert_eq!(notes[0].text, "wrapped where it<br/>stands");
+        assert_eq!(notes[1].text, "moved left to fit");
+        assert_eq!(notes[1].box_right, 29);
+        assert!(notes[1].box_left < layout.participants[1].center_col);
+        assert_eq!(layout.total_width, 30);
+    }
+
+    #[test]
+    fn layout_fits_self_messages_and_their_frames_under_max_width() {
+        let input = "\
+sequenceDiagram
+    A->>B: hi
+    loop retry
+        B->>B: a long self message text here
+    end
+    B->>B: a long self message text here
+";
+        let diagram = parse_diagram(input).unwrap();
+        let layout = compute_with_max_width(&diagram, 30).unwrap();
+        let texts: Vec<&str> = layout
+            .rows
+            .iter()
+            .filter_map(|laid| match &laid.row {
+                Row::Message(m) if m.from_col == m.to_col => Some(m.text.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let wrapped = "a long self<br/>message text<br/>here";
+        assert_eq!(texts, [wrapped, wrapped]);
+        let Row::BlockStart(frame) = &layout.rows[1].row else { panic!("expected the loop") };
+        assert_eq!(frame.frame_right, 27);
+        assert_eq!(layout.total_width, 28);
+    }
+
+    #[test]
+    fn layout_wraps_self_messages_narrower_or_fails_with_the_width_needed() {
+        let input = "\
+sequenceDiagram
+    A->>B: hi
+    loop L
+        B->>B: self with a long text here
+    end
+";
+        let diagram = parse_diagram(input).unwrap();
+        for (max_width, wrapped) in [
+            (26, "self with<br/>a long<br/>text here"),
+            (20, "self<br/>with<br/>a<br/>long<br/>text<br/>here"),
+        ] {
+            let layout = compute_with_max_width(&diagram, max_width).unwrap();
+            let Row::Message(m) = &layout.rows[2].row else { panic!("expected the message") };
+            assert_eq!(m.text, wrapped);
+            let Row::BlockStart(frame) = &layout.rows[1].row else { panic!("expected the loop") };
+            assert!(frame.frame_right < max_width);
+        }
+        let error = compute_with_max_width(&diagram, 18).unwrap_err();
+        assert_eq!(error, "diagram requires at least 20 columns, but max_width is 18");
+    }
+
+    #[test]
+    fn layout_reserves_a_line_per_wrapped_frame_label_line() {
+        let input = "sequenceDiagram\n    loop retry the request until the server answers\n    \
+                     A->>B: x\n    end\n";
+        let diagram = parse_diagram(input).unwrap();
+        let wide = compute(&diagram).unwrap();
+        let narrow = compute_with_max_width(&diagram, 24).unwrap();
+
+        let Row::BlockStart(frame) = &narrow.rows[0].row else { panic!("expected the loop") };
+        assert_eq!(line_count(&frame.label), 3);
+        let heights = |layout: &Layout| layout.rows.iter().map(|r| r.height).collect::<Vec<_>>();
+        assert_eq!(heights(&wide), [1, 3, 1]);
+        assert_eq!(heights(&narrow), [3, 3, 1]);
+        assert_eq!(narrow.total_height(), wide.total_height() + 2);
+        assert_eq!(narrow.row_source_lines()[1], (6..9, 3));
+    }
+
+    #[test]
+    fn layout_collapses_repeated_messages() {
+        let input = "\
+sequenceDiagram
+    autonumber
+    A->>B: poll
+    A->>B: poll
+    A->>B: poll
+    loop retry
+        B-->>A: wait
+        B-->>A: wait
+    end
+    A->>+B: poll
+    A->>+B: poll
+    A->>B: done
+";
+        let diagram = parse_diagram(input).unwrap();
+        let options = SequenceOptions { collapse_repeats: Some(3), ..SequenceOptions::default() };
+        let layout = compute_with(&diagram, &options, None).unwrap();
+        let messages: Vec<_> = layout
+            .rows
+            .iter()
+            .filter_map(|laid| match &laid.row {
+                Row::Message(m) => Some((m.number.unwrap(), m.text.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [(1, "poll ×3"), (4, "wait"), (5, "wait"), (6, "poll"), (7, "poll"), (8, "done")]
+        );
+        // Sized for the eight source messages, not the six rows
+        assert_eq!(layout.number_width, "[8]".len() + 1);
+
+        let options = SequenceOptions { collapse_repeats: Some(2), ..options };
+        let layout = compute_with(&diagram, &options, None).unwrap();
+        let Row::Message(wait) = &layout.rows[2].row else { panic!("{:?}", layout.rows[2]) };
+        assert_eq!((wait.text.as_str(), wait.number), ("wait ×2", Some(4)));
+    }
+
     #[test]
     fn layout_gap_accommodates_message_inside_loop() {
         let input = "\
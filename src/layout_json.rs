@@ -0,0 +1,510 @@
+//! Computed layouts as JSON, for renderers that reuse ma's layout engine.
+//!
+//! Coordinates are zero-based character cells: `x` counts columns from the
+//! left and `y` lines from the top of the rendered output. Boxes are given
+//! by their top-left corner and size, borders included.
+
+use crate::ast::{ArrowHead, LineStyle};
+use crate::er_ast::Cardinality;
+use crate::er_layout::ErLayout;
+use crate::graph_ast::{Direction, EdgeType, NodeShape};
+use crate::graph_layout::{GraphLayout, NodeLayout};
+use crate::layout::{Layout, LayoutRow, Row};
+use crate::Computed;
+
+/// A JSON value, kept in insertion order.
+enum Value {
+    Null,
+    Number(usize),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+    /// An object whose keys come from the diagram rather than the format.
+    Map(Vec<(String, Value)>),
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+fn point(x: usize, y: usize) -> Value {
+    Value::Array(vec![x.into(), y.into()])
+}
+
+impl Value {
+    /// Pretty-print with two-space indentation. Arrays of plain values and
+    /// of points stay on one line.
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = "  ".repeat(indent + 1);
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Number(n) => out.push_str(&n.to_string()),
+            Value::String(s) => write_string(out, s),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) if items.iter().all(Value::is_inline) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write(out, indent);
+                }
+                out.push(']');
+            }
+            Value::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&pad);
+                    item.write(out, indent + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Value::Object(fields) => write_fields(out, indent, fields.iter().map(|(k, v)| (*k, v))),
+            Value::Map(fields) => {
+                write_fields(out, indent, fields.iter().map(|(k, v)| (k.as_str(), v)));
+            }
+        }
+    }
+
+    fn is_inline(&self) -> bool {
+        match self {
+            Value::Array(items) => items.iter().all(Value::is_scalar),
+            value => value.is_scalar(),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Value::Array(_) | Value::Object(_) | Value::Map(_))
+    }
+}
+
+fn write_fields<'v>(
+    out: &mut String,
+    indent: usize,
+    fields: impl ExactSizeIterator<Item = (&'v str, &'v Value)>,
+) {
+    let count = fields.len();
+    if count == 0 {
+        out.push_str("{}");
+        return;
+    }
+    let pad = "  ".repeat(indent + 1);
+    out.push_str("{\n");
+    for (i, (key, value)) in fields.enumerate() {
+        out.push_str(&pad);
+        write_string(out, key);
+        out.push_str(": ");
+        value.write(out, indent + 1);
+        out.push_str(if i + 1 < count { ",\n" } else { "\n" });
+    }
+    out.push_str(&"  ".repeat(indent));
+    out.push('}');
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// The layout as a pretty-printed JSON object. Custom layouts, which ma
+/// can't look inside, have none.
+pub(crate) fn to_json(computed: &Computed) -> Result<String, String> {
+    let value = match computed {
+        Computed::Sequence(layout) => sequence(layout),
+        Computed::Graph(layout) => graph(layout),
+        Computed::Er(layout) => er(layout),
+        Computed::Custom(_) => return Err("no layout JSON for custom diagram types".to_string()),
+    };
+    let mut out = String::new();
+    value.write(&mut out, 0);
+    Ok(out)
+}
+
+fn sequence(layout: &Layout) -> Value {
+    let participants = layout
+        .participants
+        .iter()
+        .map(|p| {
+            Value::Object(vec![
+                ("name", p.name.as_str().into()),
+                ("x", p.box_left.into()),
+                ("width", (p.box_right - p.box_left + 1).into()),
+                ("height", p.box_height.into()),
+                ("lifeline_x", p.center_col.into()),
+            ])
+        })
+        .collect();
+
+    Value::Object(vec![
+        ("kind", "sequence".into()),
+        ("width", layout.total_width.into()),
+        ("height", layout.total_height().into()),
+        ("title", layout.title.as_deref().into()),
+        ("participants", Value::Array(participants)),
+        ("rows", Value::Array(sequence_rows(&layout.rows, layout.header_height()))),
+    ])
+}
+
+/// `rows` stacked from line `y` down.
+fn sequence_rows(rows: &[LayoutRow], mut y: usize) -> Vec<Value> {
+    let mut values = Vec::new();
+    for laid in rows {
+        let (row, height) = (&laid.row, laid.height);
+        let mut fields =
+            vec![("kind", row_kind(row).into()), ("y", y.into()), ("height", height.into())];
+        match row {
+            Row::Message(m) => fields.extend([
+                ("from_x", m.from_col.into()),
+                ("to_x", m.to_col.into()),
+                ("text", m.text.as_str().into()),
+                ("number", m.number.into()),
+                ("line", line_style(m.arrow.line_style).into()),
+                ("head", arrow_head(m.arrow.head).into()),
+            ]),
+            Row::Note(n) => fields.extend([
+                ("x", n.box_left.into()),
+                ("width", (n.box_right - n.box_left + 1).into()),
+                ("text", n.text.as_str().into()),
+            ]),
+            Row::BlockStart(b) | Row::BlockEnd(b) | Row::BlockDivider(b) => fields.extend([
+                ("x", b.frame_left.into()),
+                ("width", (b.frame_right - b.frame_left + 1).into()),
+                ("label", b.label.as_str().into()),
+            ]),
+            Row::Destroy(d) => fields.extend([
+                ("x", d.col.into()),
+                ("participant", d.participant_idx.into()),
+            ]),
+            Row::Parallel(p) => {
+                let lanes = p.lanes.iter().map(|lane| {
+                    Value::Object(vec![
+                        ("label", lane.label.as_str().into()),
+                        ("x", lane.left.into()),
+                        ("rows", Value::Array(sequence_rows(&lane.rows, y + 1))),
+                    ])
+                });
+                fields.extend([
+                    ("x", p.frame_left.into()),
+                    ("width", (p.frame_right - p.frame_left + 1).into()),
+                    ("lanes", Value::Array(lanes.collect())),
+                ]);
+            }
+        }
+        values.push(Value::Object(fields));
+        y += height;
+    }
+    values
+}
+
+fn row_kind(row: &Row) -> &'static str {
+    match row {
+        Row::Message(_) => "message",
+        Row::Note(_) => "note",
+        Row::BlockStart(_) => "block_start",
+        Row::BlockEnd(_) => "block_end",
+        Row::BlockDivider(_) => "block_divider",
+        Row::Destroy(_) => "destroy",
+        Row::Parallel(_) => "parallel",
+    }
+}
+
+fn line_style(style: LineStyle) -> &'static str {
+    match style {
+        LineStyle::Solid => "solid",
+        LineStyle::Dotted => "dotted",
+    }
+}
+
+fn arrow_head(head: ArrowHead) -> &'static str {
+    match head {
+        ArrowHead::None => "none",
+        ArrowHead::Arrowhead => "arrow",
+        ArrowHead::Cross => "cross",
+        ArrowHead::Open => "open",
+    }
+}
+
+fn graph(layout: &GraphLayout) -> Value {
+    let nodes = layout
+        .nodes
+        .iter()
+        .map(|n| {
+            Value::Object(vec![
+                ("id", n.id.as_str().into()),
+                ("label", n.label.as_str().into()),
+                ("shape", node_shape(n.shape).into()),
+                ("x", n.x.into()),
+                ("y", n.y.into()),
+                ("width", n.width.into()),
+                ("height", n.height.into()),
+                (
+                    "metadata",
+                    Value::Map(
+                        n.metadata.iter().map(|(k, v)| (k.clone(), v.as_str().into())).collect(),
+                    ),
+                ),
+            ])
+        })
+        .collect();
+    let subgraphs = layout
+        .subgraphs
+        .iter()
+        .map(|s| {
+            Value::Object(vec![
+                ("label", s.label.as_str().into()),
+                ("x", s.x.into()),
+                ("y", s.y.into()),
+                ("width", s.width.into()),
+                ("height", s.height.into()),
+            ])
+        })
+        .collect();
+    let find = |id: &str| layout.nodes.iter().find(|n| n.id == id);
+    let edges = layout
+        .edges
+        .iter()
+        .map(|e| {
+            let points = match (find(&e.from_id), find(&e.to_id)) {
+                (Some(from), Some(to)) => graph_edge_points(&layout.direction, from, to),
+                _ => Value::Null,
+            };
+            Value::Object(vec![
+                ("from", e.from_id.as_str().into()),
+                ("to", e.to_id.as_str().into()),
+                ("type", edge_type(e.edge_type).into()),
+                ("label", e.label.as_deref().into()),
+                ("points", points),
+            ])
+        })
+        .collect();
+
+    Value::Object(vec![
+        ("kind", "graph".into()),
+        (
+            "direction",
+            match layout.direction {
+                Direction::TopDown => "TD",
+                Direction::LeftRight => "LR",
+            }
+            .into(),
+        ),
+        ("width", layout.width.into()),
+        ("height", layout.height.into()),
+        ("nodes", Value::Array(nodes)),
+        ("subgraphs", Value::Array(subgraphs)),
+        ("edges", Value::Array(edges)),
+    ])
+}
+
+/// Where an edge leaves and enters its nodes' borders: bottom to top centre
+/// in TD graphs, right to left middle in LR ones. `null` for self-loops and
+/// LR edges back into an earlier rank, which aren't drawn as a straight run.
+fn graph_edge_points(direction: &Direction, from: &NodeLayout, to: &NodeLayout) -> Value {
+    if from.id == to.id {
+        return Value::Null;
+    }
+    match direction {
+        Direction::TopDown => Value::Array(vec![
+            point(from.center_x, from.y + from.height - 1),
+            point(to.center_x, to.y),
+        ]),
+        Direction::LeftRight if to.x > from.x + from.width => Value::Array(vec![
+            point(from.x + from.width - 1, from.center_y),
+            point(to.x, to.center_y),
+        ]),
+        Direction::LeftRight => Value::Null,
+    }
+}
+
+fn node_shape(shape: NodeShape) -> &'static str {
+    match shape {
+        NodeShape::Box => "box",
+        NodeShape::Round => "round",
+        NodeShape::Diamond => "diamond",
+        NodeShape::Circle => "circle",
+    }
+}
+
+fn edge_type(edge_type: EdgeType) -> &'static str {
+    match edge_type {
+        EdgeType::Arrow => "arrow",
+        EdgeType::OpenLink => "open",
+        EdgeType::DottedArrow => "dotted_arrow",
+        EdgeType::DottedLink => "dotted",
+        EdgeType::ThickArrow => "thick_arrow",
+        EdgeType::ThickLink => "thick",
+    }
+}
+
+fn er(layout: &ErLayout) -> Value {
+    let entities = layout
+        .nodes
+        .iter()
+        .map(|n| {
+            let attributes = n
+                .attributes
+                .iter()
+                .map(|a| {
+                    Value::Object(vec![
+                        ("type", a.attr_type.as_str().into()),
+                        ("name", a.name.as_str().into()),
+                        ("key", a.key.as_deref().into()),
+                    ])
+                })
+                .collect();
+            // Attribute text starts inside the border and its padding space
+            let column = n.x + 2;
+            let columns = Value::Object(vec![
+                ("type", column.into()),
+                ("name", (column + n.columns.name_offset()).into()),
+                ("key", (n.columns.key_width > 0).then(|| column + n.columns.key_offset()).into()),
+            ]);
+            Value::Object(vec![
+                ("name", n.name.as_str().into()),
+                ("x", n.x.into()),
+                ("y", n.y.into()),
+                ("width", n.width.into()),
+                ("height", n.height.into()),
+                ("columns", columns),
+                ("attributes", Value::Array(attributes)),
+            ])
+        })
+        .collect();
+    let relationships = layout
+        .edges
+        .iter()
+        .map(|e| {
+            // From the left entity's border to the right one's; entities in
+            // the same column aren't connected
+            let points = if e.points.is_empty() {
+                Value::Null
+            } else {
+                Value::Array(e.points.iter().map(|&(x, y)| point(x, y)).collect())
+            };
+            Value::Object(vec![
+                ("from", e.from.as_str().into()),
+                ("to", e.to.as_str().into()),
+                ("from_cardinality", cardinality(e.left_card).into()),
+                ("to_cardinality", cardinality(e.right_card).into()),
+                ("label", e.label.as_str().into()),
+                ("points", points),
+            ])
+        })
+        .collect();
+
+    Value::Object(vec![
+        ("kind", "er".into()),
+        ("width", layout.width.into()),
+        ("height", layout.height.into()),
+        ("entities", Value::Array(entities)),
+        ("relationships", Value::Array(relationships)),
+    ])
+}
+
+fn cardinality(card: Cardinality) -> &'static str {
+    match card {
+        Cardinality::ExactlyOne => "exactly_one",
+        Cardinality::ZeroOrOne => "zero_or_one",
+        Cardinality::OneOrMany => "one_or_many",
+        Cardinality::ZeroOrMany => "zero_or_many",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RenderOptions, layout_json, parse};
+    use pretty_assertions::assert_eq;
+
+    fn json(input: &str) -> String {
+        layout_json(&parse(input).unwrap(), &RenderOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn graph_nodes_and_edge_points() {
+        assert_eq!(
+            json("graph LR\n    A --> B\n    click B \"https://x.io\" \"Tip\"\n"),
+            r#"{
+  "kind": "graph",
+  "direction": "LR",
+  "width": 15,
+  "height": 3,
+  "nodes": [
+    {
+      "id": "A",
+      "label": "A",
+      "shape": "box",
+      "x": 0,
+      "y": 0,
+      "width": 5,
+      "height": 3,
+      "metadata": {}
+    },
+    {
+      "id": "B",
+      "label": "B",
+      "shape": "box",
+      "x": 10,
+      "y": 0,
+      "width": 5,
+      "height": 3,
+      "metadata": {
+        "link": "https://x.io",
+        "tooltip": "Tip"
+      }
+    }
+  ],
+  "subgraphs": [],
+  "edges": [
+    {
+      "from": "A",
+      "to": "B",
+      "type": "arrow",
+      "label": null,
+      "points": [[4, 1], [10, 1]]
+    }
+  ]
+}"#
+        );
+    }
+
+    #[test]
+    fn sequence_rows_are_stacked_below_the_boxes() {
+        let out = json("sequenceDiagram\n    A->>B: \"hi\"\n    Note over A: n\n");
+        assert!(out.contains("\"kind\": \"sequence\""), "{out}");
+        assert!(out.contains("\"kind\": \"message\",\n      \"y\": 3,\n      \"height\": 3"), "{out}");
+        assert!(out.contains("\"kind\": \"note\",\n      \"y\": 6,"), "{out}");
+        assert!(out.contains(r#""text": "\"hi\"""#), "{out}");
+    }
+
+    #[test]
+    fn er_relationship_points() {
+        let out = json("erDiagram\n    A ||--o{ B : has\n");
+        assert!(out.contains("\"from_cardinality\": \"exactly_one\""), "{out}");
+        assert!(out.contains("\"points\": [["), "{out}");
+    }
+}
@@ -1,47 +1,864 @@
 pub mod ast;
+pub mod bidi;
+pub mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod diagnostics;
 pub mod display_width;
+pub mod dot_parser;
 pub mod er_ast;
 pub mod er_layout;
 pub mod er_parser;
 pub mod er_renderer;
+pub mod format;
+mod frontmatter;
 pub mod graph_ast;
 pub mod graph_layout;
 pub mod graph_parser;
 pub mod graph_renderer;
+pub mod grid;
+pub mod html;
 pub mod layout;
+mod layout_json;
+pub mod markdown;
+pub mod parse_error;
 pub mod parser;
+pub mod plantuml_parser;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod registry;
 pub mod renderer;
+pub mod session;
+pub mod stats;
+pub mod style;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use diagnostics::Warning;
+pub use registry::{CustomDocument, DiagramHandler};
+pub use session::{RenderDelta, Session};
+
+/// Options controlling how diagrams are laid out and rendered.
+///
+/// Construct with `RenderOptions::default()` and set the fields you need,
+/// so new options can be added without breaking callers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderOptions {
+    /// Maximum output width in columns; `None` means unbounded.
+    pub max_width: Option<usize>,
+    /// Fit `max_width` by wrapping sequence participant names and titles
+    /// and flowchart node labels onto more lines, instead of cutting them
+    /// short with `…`.
+    pub wrap: bool,
+    /// Split long sequence diagrams into pages of at most this many lines,
+    /// repeating the participant header on each page. Other diagram types
+    /// are not paginated.
+    pub max_height: Option<usize>,
+    /// Draw boxes, lines and arrows with plain ASCII (`+ - | > < v ^`)
+    /// instead of box-drawing characters. Labels are kept as written.
+    pub ascii_only: bool,
+    /// Dim lines, boxes and arrows with ANSI escapes for a terminal, leaving
+    /// labels at full brightness.
+    pub color: bool,
+    /// How right-to-left text in labels is written.
+    pub rtl: bidi::RtlMode,
+    /// Columns taken by each emoji, for terminals that disagree with
+    /// unicode-width.
+    pub emoji_width: display_width::EmojiWidth,
+    /// Border glyphs for boxes and frames.
+    pub theme: style::Theme,
+    /// Prefix every output line with its line number.
+    pub line_numbers: bool,
+    pub sequence: layout::SequenceOptions,
+    /// Syntax of the input text.
+    pub input: InputFormat,
+    /// Skip lines that don't parse instead of failing (see [`parse_lenient`]).
+    pub lenient: bool,
+    /// Diagram type for Mermaid input without a header line, such as a
+    /// snippet pasted without `sequenceDiagram`.
+    pub diagram_type: DiagramType,
+    /// Draw a flowchart node declared with different shapes or labels with
+    /// its last one, as Mermaid does, instead of its first and a
+    /// [`Warning::RedeclaredNode`].
+    pub last_shape_wins: bool,
+}
+
+/// Source syntax accepted by [`parse_as`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Mermaid, DOT when the input starts like a Graphviz graph, or
+    /// PlantUML when it starts with `@startuml`.
+    #[default]
+    Auto,
+    Mermaid,
+    /// A subset of Graphviz DOT, rendered as a flowchart.
+    Dot,
+    /// A subset of PlantUML sequence diagrams.
+    PlantUml,
+}
+
+impl InputFormat {
+    pub const NAMES: &[&str] = &["auto", "mermaid", "dot", "plantuml"];
+
+    pub fn named(name: &str) -> Option<InputFormat> {
+        match name {
+            "auto" => Some(InputFormat::Auto),
+            "mermaid" => Some(InputFormat::Mermaid),
+            "dot" => Some(InputFormat::Dot),
+            "plantuml" => Some(InputFormat::PlantUml),
+            _ => None,
+        }
+    }
+}
+
+/// What Mermaid input without a header line is parsed as. Input that has
+/// one keeps its own type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiagramType {
+    /// Require a header line.
+    #[default]
+    Auto,
+    /// Whichever built-in type is the only one the input parses as, with a
+    /// [`Warning::AssumedHeader`].
+    Detect,
+    Sequence,
+    /// A top-down flowchart, as if headed `graph TD`.
+    Flowchart,
+    Er,
+}
+
+impl DiagramType {
+    pub const NAMES: &[&str] = &["auto", "detect", "sequence", "flowchart", "graph", "er"];
+
+    /// The types [`Detect`](Self::Detect) chooses from.
+    const DETECTABLE: [DiagramType; 3] =
+        [DiagramType::Sequence, DiagramType::Flowchart, DiagramType::Er];
+
+    pub fn named(name: &str) -> Option<DiagramType> {
+        match name {
+            "auto" => Some(DiagramType::Auto),
+            "detect" => Some(DiagramType::Detect),
+            "sequence" => Some(DiagramType::Sequence),
+            "flowchart" | "graph" => Some(DiagramType::Flowchart),
+            "er" => Some(DiagramType::Er),
+            _ => None,
+        }
+    }
+
+    /// The header line assumed for input without one.
+    fn header(self) -> Option<&'static str> {
+        match self {
+            DiagramType::Auto | DiagramType::Detect => None,
+            DiagramType::Sequence => Some("sequenceDiagram"),
+            DiagramType::Flowchart => Some("graph TD"),
+            DiagramType::Er => Some("erDiagram"),
+        }
+    }
+}
+
+/// Printed between the pages of a paginated sequence diagram.
+const PAGE_SEPARATOR: &str = "\n\n";
+
+/// A parsed diagram of any supported type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Document {
+    Sequence(ast::Diagram),
+    Graph(graph_ast::GraphDiagram),
+    Er(er_ast::ErDiagram),
+    /// A diagram type added with [`registry::register`]. Its labels are left
+    /// for its handler to sanitize.
+    Custom(CustomDocument),
+}
+
+impl Document {
+    /// A copy with `f` applied to every label, and to every id that is shown
+    /// as a name. Ids change the same way everywhere they appear, so
+    /// references between statements still match.
+    pub(crate) fn map_text(&self, f: impl Fn(&str) -> String) -> Document {
+        let text = |s: &mut String| *s = f(s);
+        let mut document = self.clone();
+        match &mut document {
+            Document::Sequence(diagram) => {
+                diagram.title.iter_mut().for_each(text);
+                map_statements(&mut diagram.statements, &text);
+            }
+            Document::Graph(diagram) => {
+                for node in &mut diagram.nodes {
+                    text(&mut node.label);
+                }
+                for edge in &mut diagram.edges {
+                    edge.label.iter_mut().for_each(text);
+                }
+                for subgraph in &mut diagram.subgraphs {
+                    text(&mut subgraph.label);
+                }
+            }
+            Document::Er(diagram) => {
+                for entity in &mut diagram.entities {
+                    text(&mut entity.name);
+                    for attribute in &mut entity.attributes {
+                        text(&mut attribute.attr_type);
+                        text(&mut attribute.name);
+                    }
+                }
+                for relationship in &mut diagram.relationships {
+                    text(&mut relationship.from);
+                    text(&mut relationship.to);
+                    text(&mut relationship.label);
+                }
+            }
+            Document::Custom(_) => {}
+        }
+        document
+    }
+}
+
+fn map_statements(statements: &mut [ast::Statement], text: &impl Fn(&mut String)) {
+    use ast::{NotePlacement, Statement};
+    for statement in statements {
+        match statement {
+            Statement::ParticipantDecl(decl) | Statement::Create(decl) => {
+                text(&mut decl.id);
+                decl.alias.iter_mut().for_each(text);
+            }
+            Statement::Message(message) => {
+                text(&mut message.from);
+                text(&mut message.to);
+                text(&mut message.text);
+            }
+            Statement::Note(note) => {
+                match &mut note.placement {
+                    NotePlacement::RightOf(id)
+                    | NotePlacement::LeftOf(id)
+                    | NotePlacement::Over(id) => text(id),
+                    NotePlacement::OverTwo(a, b) => {
+                        text(a);
+                        text(b);
+                    }
+                }
+                text(&mut note.text);
+            }
+            Statement::Activate(id) | Statement::Deactivate(id) | Statement::Destroy(id) => {
+                text(id)
+            }
+            Statement::Loop(block)
+            | Statement::Opt(block)
+            | Statement::Break(block)
+            | Statement::Rect(block) => {
+                text(&mut block.label);
+                map_statements(&mut block.body, text);
+            }
+            Statement::Alt(block) | Statement::Par(block) | Statement::Critical(block) => {
+                text(&mut block.label);
+                map_statements(&mut block.body, text);
+                for branch in &mut block.else_branches {
+                    text(&mut branch.label);
+                    map_statements(&mut branch.body, text);
+                }
+            }
+            Statement::AutoNumber => {}
+        }
+    }
+}
+
+/// Error returned by [`parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    message: String,
+}
+
+impl Error {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self {
+            message: format!("write failed: {e}"),
+        }
+    }
+}
+
+/// Parse `input` into a typed [`Document`] without laying it out.
+pub fn parse(input: &str) -> Result<Document, Error> {
+    parse_as(input, InputFormat::Auto)
+}
+
+/// Parse `input` written in the given syntax.
+pub fn parse_as(input: &str, format: InputFormat) -> Result<Document, Error> {
+    Ok(parse_document(input, format, DiagramType::Auto)?.1)
+}
+
+/// `input` without a leading byte order mark and with `\r\n` (or a lone
+/// `\r`) line endings turned into `\n`, as files saved on Windows often have
+/// them, so no parser has to allow for either.
+fn normalize_input(input: &str) -> std::borrow::Cow<'_, str> {
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    if input.contains('\r') {
+        input.replace("\r\n", "\n").replace('\r', "\n").into()
+    } else {
+        input.into()
+    }
+}
+
+/// [`parse_as`], also returning the handler that lays out and draws the
+/// document.
+fn parse_document(
+    input: &str,
+    format: InputFormat,
+    diagram_type: DiagramType,
+) -> Result<(&'static dyn DiagramHandler, Document), Error> {
+    let input = &normalize_input(input);
+    let document = match format {
+        InputFormat::Dot => Document::Graph(dot_parser::parse_dot(input)?),
+        InputFormat::PlantUml => Document::Sequence(plantuml_parser::parse_plantuml(input)?),
+        InputFormat::Auto if dot_parser::looks_like_dot(input) => {
+            Document::Graph(dot_parser::parse_dot(input)?)
+        }
+        InputFormat::Auto if plantuml_parser::looks_like_plantuml(input) => {
+            Document::Sequence(plantuml_parser::parse_plantuml(input)?)
+        }
+        InputFormat::Auto | InputFormat::Mermaid => return parse_mermaid(input, diagram_type),
+    };
+    Ok((registry::handler_of(&document), document))
+}
+
+/// Parse like [`parse_as`], skipping lines that don't parse instead of
+/// failing, so a file using syntax ma doesn't know yet still renders. Each
+/// skipped line is returned as a [`Warning::SkippedStatement`]; a block
+/// missing its `end` loses its opening line but keeps its body. Errors that
+/// can't be pinned to a line after the header, such as an unknown diagram
+/// type, still fail.
+pub fn parse_lenient(input: &str, format: InputFormat) -> Result<(Document, Vec<Warning>), Error> {
+    let (_, document, warnings) = parse_document_lenient(input, format, DiagramType::Auto)?;
+    Ok((document, warnings))
+}
+
+/// [`parse_lenient`], also returning the document's handler.
+fn parse_document_lenient(
+    input: &str,
+    format: InputFormat,
+    diagram_type: DiagramType,
+) -> Result<Parsed, Error> {
+    let input = &normalize_input(input);
+    // With an assumed header, the first line is part of the body
+    let headless = diagram_type != DiagramType::Auto
+        && registry::handler_for(&frontmatter::strip(input).1).is_none();
+    let mut lines: Vec<&str> = input.split('\n').collect();
+    let mut warnings = Vec::new();
+    loop {
+        let error = match parse_document(&lines.join("\n"), format, diagram_type) {
+            Ok((handler, document)) => return Ok((handler, document, warnings)),
+            Err(error) => error,
+        };
+        let Some(line) = parse_error::error_line(&error.message) else { return Err(error) };
+        let text = lines.get(line - 1).map_or("", |l| l.trim());
+        let is_header = !headless && lines[..line - 1].iter().all(|l| l.trim().is_empty());
+        if text.is_empty() || is_header {
+            return Err(error);
+        }
+        warnings.push(Warning::SkippedStatement { line, text: text.to_string() });
+        lines[line - 1] = "";
+    }
+}
+
+/// A parsed document with its handler and the lines skipped to parse it.
+type Parsed = (&'static dyn DiagramHandler, Document, Vec<Warning>);
+
+/// Parse with the syntax, diagram type and leniency `options` ask for.
+fn parse_with(input: &str, options: &RenderOptions) -> Result<Parsed, Error> {
+    let input = &normalize_input(input);
+    let mut warnings = Vec::new();
+    let diagram_type = match options.diagram_type {
+        DiagramType::Detect => match detect_type(input, options.input) {
+            Some(detected) => {
+                let header = detected.header().unwrap_or_default().to_string();
+                warnings.push(Warning::AssumedHeader { header });
+                detected
+            }
+            None => DiagramType::Auto,
+        },
+        diagram_type => diagram_type,
+    };
+    let (handler, mut document) = if options.lenient {
+        let (handler, document, skipped) =
+            parse_document_lenient(input, options.input, diagram_type)?;
+        warnings.extend(skipped);
+        (handler, document)
+    } else {
+        parse_document(input, options.input, diagram_type)?
+    };
+    if options.last_shape_wins
+        && let Document::Graph(diagram) = &mut document
+    {
+        diagram.apply_redeclared();
+    }
+    Ok((handler, document, warnings))
+}
+
+/// The only built-in type that Mermaid `input` without a header parses as.
+/// `None` if the input has a header, isn't Mermaid, or fits no type or
+/// several.
+fn detect_type(input: &str, format: InputFormat) -> Option<DiagramType> {
+    let mermaid = match format {
+        InputFormat::Mermaid => true,
+        InputFormat::Auto => {
+            !dot_parser::looks_like_dot(input) && !plantuml_parser::looks_like_plantuml(input)
+        }
+        InputFormat::Dot | InputFormat::PlantUml => false,
+    };
+    if !mermaid || registry::handler_for(&frontmatter::strip(input).1).is_some() {
+        return None;
+    }
+    let fitting: Vec<_> = DiagramType::DETECTABLE
+        .into_iter()
+        .filter(|&diagram_type| parse_mermaid(input, diagram_type).is_ok())
+        .collect();
+    match fitting[..] {
+        [diagram_type] => Some(diagram_type),
+        // Only bare arrows such as `A --> B` read as both, and they are far
+        // likelier a flowchart than messages without text
+        [DiagramType::Sequence, DiagramType::Flowchart] => Some(DiagramType::Flowchart),
+        _ => None,
+    }
+}
+
+fn parse_mermaid(
+    input: &str,
+    diagram_type: DiagramType,
+) -> Result<(&'static dyn DiagramHandler, Document), Error> {
+    let (title, mut input) = frontmatter::strip(input);
+    if let Some(header) = diagram_type.header()
+        && registry::handler_for(&input).is_none()
+    {
+        // On the first line, so line numbers still match the source
+        input = format!("{header} {input}").into();
+    }
+    let Some(handler) = registry::handler_for(&input) else {
+        let first_word = input.split_whitespace().next().unwrap_or("(empty)");
+        return Err(registry::unknown_diagram_type(first_word).into());
+    };
+    let mut document = handler.parse(&input)?;
+    // A `title` line in the diagram itself wins over the frontmatter
+    if let Document::Sequence(diagram) = &mut document
+        && diagram.title.is_none()
+    {
+        diagram.title = title;
+    }
+    Ok((handler, document))
+}
 
 pub fn render(input: &str) -> Result<String, String> {
     render_with_options(input, None)
 }
 
 pub fn render_with_options(input: &str, max_width: Option<usize>) -> Result<String, String> {
-    let trimmed = input.trim_start();
-    if trimmed.starts_with("graph") || trimmed.starts_with("flowchart") {
-        let diagram = graph_parser::parse_graph(input)?;
-        let computed = match max_width {
-            Some(w) => graph_layout::compute_with_max_width(&diagram, w)?,
-            None => graph_layout::compute(&diagram)?,
-        };
-        Ok(graph_renderer::render(&computed))
-    } else if trimmed.starts_with("erDiagram") {
-        let diagram = er_parser::parse_er(input)?;
-        let computed = match max_width {
-            Some(w) => er_layout::compute_with_max_width(&diagram, w)?,
-            None => er_layout::compute(&diagram)?,
-        };
-        Ok(er_renderer::render(&computed))
-    } else if trimmed.starts_with("sequenceDiagram") {
-        let diagram = parser::parse_diagram(input)?;
-        let computed = match max_width {
-            Some(w) => layout::compute_with_max_width(&diagram, w)?,
-            None => layout::compute(&diagram)?,
-        };
-        Ok(renderer::render(&computed))
+    render_with(
+        input,
+        &RenderOptions {
+            max_width,
+            ..RenderOptions::default()
+        },
+    )
+}
+
+pub fn render_with(input: &str, options: &RenderOptions) -> Result<String, String> {
+    let (handler, document, _) = parse_with(input, options).map_err(|e| e.message)?;
+    let computed = compute_layout(handler, &document, options)?;
+    Ok(draw(handler, &computed, options))
+}
+
+/// [`render_with`], plus a [`Warning`] for each place the rendering loses
+/// part of the source: participant names shortened to fit, clipped notes,
+/// edge labels with no room to be drawn, and flowchart styling lines and
+/// (with [`RenderOptions::lenient`]) unparsable lines that are skipped.
+pub fn render_with_diagnostics(
+    input: &str,
+    options: &RenderOptions,
+) -> Result<(String, Vec<Warning>), String> {
+    let input = &normalize_input(input);
+    let (handler, document, mut warnings) = parse_with(input, options).map_err(|e| e.message)?;
+    let computed = compute_layout(handler, &document, options)?;
+    warnings.extend(diagnostics::collect(input, &document, &computed, options)?);
+    Ok((draw(handler, &computed, options), warnings))
+}
+
+/// [`render_with_diagnostics`] for each diagram of a multi-diagram input.
+pub fn render_all_with_diagnostics(
+    input: &str,
+    options: &RenderOptions,
+) -> Vec<Result<(String, Vec<Warning>), Error>> {
+    split_diagrams(input)
+        .into_iter()
+        .map(|source| render_with_diagnostics(source, options).map_err(Error::from))
+        .collect()
+}
+
+/// The computed layout of `document` as JSON: box positions and sizes,
+/// sequence rows with their line ranges, and edge end points, in character
+/// cells. Lets other renderers reuse the layout engine.
+pub fn layout_json(document: &Document, options: &RenderOptions) -> Result<String, String> {
+    let handler = registry::handler_of(document);
+    layout_json::to_json(&compute_layout(handler, document, options)?)
+}
+
+/// [`layout_json`] for each diagram of a multi-diagram input.
+pub fn layout_json_all(input: &str, options: &RenderOptions) -> Vec<Result<String, Error>> {
+    split_diagrams(input)
+        .into_iter()
+        .map(|source| {
+            let (handler, document, _) = parse_with(source, options)?;
+            Ok(layout_json::to_json(&compute_layout(handler, &document, options)?)?)
+        })
+        .collect()
+}
+
+/// Parse each diagram of a multi-diagram input (see [`render_all`]).
+pub fn parse_all(input: &str) -> Vec<Result<Document, Error>> {
+    split_diagrams(input).into_iter().map(parse).collect()
+}
+
+/// Summarise a diagram's element counts, nesting and laid-out size without
+/// drawing it.
+pub fn inspect(document: &Document, options: &RenderOptions) -> Result<stats::Stats, String> {
+    let computed = compute_layout(registry::handler_of(document), document, options)?;
+    let stats = match (document, computed) {
+        (Document::Sequence(diagram), Computed::Sequence(computed)) => {
+            let (messages, max_depth) = stats::sequence_counts(&diagram.statements);
+            stats::Stats {
+                kind: "sequence",
+                elements: computed.participants.len(),
+                connections: messages,
+                max_depth,
+                width: computed.total_width,
+                height: computed.total_height(),
+            }
+        }
+        (Document::Graph(diagram), Computed::Graph(computed)) => stats::Stats {
+            kind: "graph",
+            elements: computed.nodes.len(),
+            connections: computed.edges.len(),
+            max_depth: usize::from(!diagram.subgraphs.is_empty()),
+            width: computed.width,
+            height: computed.height,
+        },
+        (_, Computed::Er(computed)) => stats::Stats {
+            kind: "er",
+            elements: computed.nodes.len(),
+            connections: computed.edges.len(),
+            max_depth: 0,
+            width: computed.width,
+            height: computed.height,
+        },
+        // ma can't count what's inside, only measure the drawing
+        (Document::Custom(custom), computed @ Computed::Custom(_)) => {
+            let output = draw(custom.handler(), &computed, options);
+            stats::Stats {
+                kind: custom.handler().keywords().first().copied().unwrap_or("custom"),
+                elements: 0,
+                connections: 0,
+                max_depth: 0,
+                width: output.lines().map(display_width::display_width).max().unwrap_or(0),
+                height: output.lines().count(),
+            }
+        }
+        _ => unreachable!("layout kind always matches the document"),
+    };
+    Ok(stats)
+}
+
+/// [`inspect`] for each diagram of a multi-diagram input, parsed with the
+/// syntax, diagram type and leniency `options` ask for, as rendering does.
+/// Comes with the warnings for lines skipped to parse it.
+pub fn inspect_all(
+    input: &str,
+    options: &RenderOptions,
+) -> Vec<Result<(stats::Stats, Vec<Warning>), Error>> {
+    split_diagrams(input)
+        .into_iter()
+        .map(|source| {
+            let (_, document, warnings) = parse_with(source, options)?;
+            Ok((inspect(&document, options)?, warnings))
+        })
+        .collect()
+}
+
+/// Render each diagram in an input holding several, separated by `---` lines
+/// or by a blank line followed by a new diagram header.
+pub fn render_all(input: &str) -> Vec<Result<String, Error>> {
+    render_all_with(input, &RenderOptions::default())
+}
+
+pub fn render_all_with(input: &str, options: &RenderOptions) -> Vec<Result<String, Error>> {
+    split_diagrams(input)
+        .into_iter()
+        .map(|source| render_with(source, options).map_err(Error::from))
+        .collect()
+}
+
+/// Split `input` into per-diagram sources. Always returns at least one entry
+/// so empty input still reports an error. A `---` line starting a diagram
+/// opens its frontmatter rather than separating it from the previous one
+/// when the next line isn't a diagram header.
+fn split_diagrams(input: &str) -> Vec<&str> {
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut has_content = false;
+    let mut prev_blank = false;
+    let mut in_frontmatter = false;
+    let mut after_frontmatter = false;
+
+    let mut lines = input.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if in_frontmatter {
+            in_frontmatter = trimmed != "---";
+            after_frontmatter = !in_frontmatter;
+        } else if trimmed == "---"
+            && !has_content
+            && lines.peek().is_some_and(|next| !next.trim().is_empty() && !is_header(next.trim()))
+        {
+            in_frontmatter = true;
+            has_content = true;
+        } else if trimmed == "---" {
+            if has_content {
+                chunks.push(&input[start..offset]);
+            }
+            start = offset + line.len();
+            has_content = false;
+        } else if trimmed.is_empty() {
+            if !has_content {
+                start = offset + line.len();
+            }
+        } else {
+            if has_content && prev_blank && is_header(trimmed) && !after_frontmatter {
+                chunks.push(&input[start..offset]);
+                start = offset;
+            }
+            has_content = true;
+            after_frontmatter = false;
+        }
+        prev_blank = trimmed.is_empty();
+        offset += line.len();
+    }
+    if has_content || chunks.is_empty() {
+        chunks.push(&input[start..]);
+    }
+    chunks
+}
+
+/// Whether `line` starts a diagram.
+fn is_header(line: &str) -> bool {
+    line.split_whitespace().next() == Some("@startuml")
+        || registry::handlers().iter().any(|h| h.sniff(line))
+}
+
+/// Regenerate Mermaid source for a parsed [`Document`]. Parsing the result
+/// yields an equal document, apart from the source lines a sequence
+/// diagram records ([`ast::SourceLines`]).
+pub fn to_mermaid(document: &Document) -> String {
+    format::format_document(document)
+}
+
+/// Reformat every Mermaid diagram in `input` as canonical source, separating
+/// diagrams with a blank line (see [`to_mermaid`]). Diagrams of a type
+/// added with [`registry::register`] are kept as written.
+///
+/// `%%` comments aren't part of the parsed document, so input containing
+/// them is rejected rather than having them silently dropped.
+pub fn format(input: &str) -> Result<String, Error> {
+    let input = &normalize_input(input);
+    if let Some(n) = input.lines().position(|line| line.trim_start().starts_with("%%")) {
+        return Err(format!("line {}: formatting would drop this %% comment", n + 1).into());
+    }
+    let formatted = split_diagrams(input)
+        .into_iter()
+        .map(|source| {
+            parse_mermaid(source, DiagramType::Auto).map(|(_, document)| match document {
+                Document::Custom(_) => format!("{}\n", source.trim_end()),
+                document => to_mermaid(&document),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(formatted.join("\n"))
+}
+
+/// Render every ```` ```mermaid ```` block in a Markdown document, returning
+/// the document with each block replaced by a plain fence holding its output.
+/// A block that fails to render is left as written.
+pub fn render_markdown(input: &str, options: &RenderOptions) -> String {
+    render_markdown_with_diagnostics(input, options).0
+}
+
+/// [`render_markdown`], plus a [`Warning::FailedBlock`] for each block left
+/// as written.
+pub fn render_markdown_with_diagnostics(
+    input: &str,
+    options: &RenderOptions,
+) -> (String, Vec<Warning>) {
+    let (output, errors) =
+        markdown::replace_mermaid_fences(input, |source| render_with(source, options));
+    let warnings = errors.into_iter().map(|(line, error)| Warning::FailedBlock { line, error });
+    (output, warnings.collect())
+}
+
+/// Lay out and render an already parsed [`Document`].
+pub fn render_document(document: &Document, options: &RenderOptions) -> Result<String, String> {
+    let handler = registry::handler_of(document);
+    Ok(draw(handler, &compute_layout(handler, document, options)?, options))
+}
+
+/// Draw a computed layout with `handler`, applying line numbers and ASCII
+/// mode.
+fn draw(handler: &dyn DiagramHandler, computed: &Computed, options: &RenderOptions) -> String {
+    let output = display_width::with_emoji_width(options.emoji_width, || {
+        style::with_ascii_only(options.ascii_only, || handler.render(computed, options))
+    });
+    if options.line_numbers {
+        let gutter = if options.ascii_only { '|' } else { '│' };
+        number_lines(&output, gutter)
     } else {
-        let first_word = trimmed.split_whitespace().next().unwrap_or("(empty)");
-        Err(format!("unknown diagram type: {first_word}"))
+        output
+    }
+}
+
+/// Prefix each line with a right-aligned 1-based line number and `gutter`.
+fn number_lines(output: &str, gutter: char) -> String {
+    let count = output.lines().count();
+    let width = count.to_string().len();
+    output
+        .lines()
+        .enumerate()
+        .map(|(i, line)| format!("{:>width$} {gutter} {line}", i + 1).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// ma's own drawing of a layout; see [`DiagramHandler::render`].
+pub(crate) fn draw_layout(computed: &Computed, options: &RenderOptions) -> String {
+    match computed {
+        Computed::Graph(computed) if options.color => {
+            graph_renderer::render_ansi(computed, &options.theme)
+        }
+        Computed::Graph(computed) => graph_renderer::render_with(computed, &options.theme),
+        Computed::Er(computed) if options.color => {
+            er_renderer::render_ansi(computed, &options.theme)
+        }
+        Computed::Er(computed) => er_renderer::render_with(computed, &options.theme),
+        Computed::Sequence(computed) => match (options.max_height, options.color) {
+            (Some(h), false) => {
+                renderer::render_pages(computed, &options.theme, h).join(PAGE_SEPARATOR)
+            }
+            (Some(h), true) => {
+                renderer::render_pages_ansi(computed, &options.theme, h).join(PAGE_SEPARATOR)
+            }
+            (None, false) => renderer::render_with(computed, &options.theme),
+            (None, true) => renderer::render_ansi(computed, &options.theme),
+        },
+        Computed::Custom(_) => String::new(),
+    }
+}
+
+/// Parse `input` and write its rendering to `out`.
+///
+/// Without line numbers, color or pagination the grid is written row by
+/// row, so the full output is never held as one string.
+pub fn render_to<W: std::io::Write>(
+    input: &str,
+    out: &mut W,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let (handler, document, _) = parse_with(input, options)?;
+    let computed = compute_layout(handler, &document, options)?;
+    if options.line_numbers || options.color || options.max_height.is_some() {
+        out.write_all(draw(handler, &computed, options).as_bytes())?;
+        return Ok(());
+    }
+    display_width::with_emoji_width(options.emoji_width, || {
+        style::with_ascii_only(options.ascii_only, || handler.render_to(&computed, options, out))
+    })?;
+    Ok(())
+}
+
+/// ma's own drawing of a layout written row by row; see
+/// [`DiagramHandler::render_to`].
+pub(crate) fn stream_layout(
+    computed: &Computed,
+    options: &RenderOptions,
+    mut out: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    match computed {
+        Computed::Graph(computed) => graph_renderer::render_to(computed, &options.theme, &mut out),
+        Computed::Er(computed) => er_renderer::render_to(computed, &options.theme, &mut out),
+        Computed::Sequence(computed) => renderer::render_to(computed, &options.theme, &mut out),
+        Computed::Custom(_) => Ok(()),
+    }
+}
+
+/// Layout of a [`Document`], before drawing.
+pub enum Computed {
+    Sequence(layout::Layout),
+    Graph(graph_layout::GraphLayout),
+    Er(er_layout::ErLayout),
+    /// Whatever a handler's own [`layout`](DiagramHandler::layout) computed
+    /// for its [`render`](DiagramHandler::render) to draw.
+    Custom(Box<dyn std::any::Any + Send + Sync>),
+}
+
+/// Lay out `document` with `handler`, after sanitizing its labels.
+fn compute_layout(
+    handler: &dyn DiagramHandler,
+    document: &Document,
+    options: &RenderOptions,
+) -> Result<Computed, String> {
+    display_width::with_emoji_width(options.emoji_width, || {
+        let document = document.map_text(|s| options.rtl.apply(&display_width::sanitize(s)));
+        handler.layout(&document, options)
+    })
+}
+
+/// ma's own layout of a document; see [`DiagramHandler::layout`].
+pub(crate) fn layout_document(
+    document: &Document,
+    options: &RenderOptions,
+) -> Result<Computed, String> {
+    let max_width = options.max_width;
+    match document {
+        Document::Sequence(diagram) => Ok(Computed::Sequence(match max_width {
+            Some(w) if options.wrap => layout::compute_wrapped(diagram, &options.sequence, w)?,
+            _ => layout::compute_with(diagram, &options.sequence, max_width)?,
+        })),
+        Document::Graph(diagram) => Ok(Computed::Graph(match max_width {
+            Some(w) if options.wrap => graph_layout::compute_wrapped(diagram, w)?,
+            Some(w) => graph_layout::compute_with_max_width(diagram, w)?,
+            None => graph_layout::compute(diagram)?,
+        })),
+        Document::Er(diagram) => Ok(Computed::Er(match max_width {
+            Some(w) => er_layout::compute_with_max_width(diagram, w)?,
+            None => er_layout::compute(diagram)?,
+        })),
+        Document::Custom(custom) => Err(format!(
+            "no layout for {} diagrams: their handler must implement `layout`",
+            custom.handler().keywords().join("/")
+        )),
     }
 }
 
@@ -65,6 +882,104 @@ mod tests {
         assert!(err.contains("unknown diagram type"), "got: {err}");
     }
 
+    #[test]
+    fn parse_returns_typed_document() {
+        match parse("sequenceDiagram\n    Alice->>Bob: Hello\n").unwrap() {
+            Document::Sequence(d) => assert_eq!(d.statements.len(), 1),
+            other => panic!("expected sequence document, got {other:?}"),
+        }
+        assert!(matches!(parse("graph TD\n    A --> B\n").unwrap(), Document::Graph(_)));
+        assert!(matches!(parse("erDiagram\n    A ||--o{ B : has\n").unwrap(), Document::Er(_)));
+    }
+
+    #[test]
+    fn dot_input_is_sniffed_or_selected() {
+        let dot = render("digraph {\n    A -> B\n}\n").unwrap();
+        assert!(dot.contains("A") && dot.contains("B"), "{dot}");
+        let options = RenderOptions { input: InputFormat::Dot, ..RenderOptions::default() };
+        assert_eq!(render_with("graph { A -- B }", &options), render("graph { A -- B }"));
+        let options = RenderOptions { input: InputFormat::Mermaid, ..RenderOptions::default() };
+        assert!(render_with("digraph { A -> B }", &options).is_err());
+    }
+
+    #[test]
+    fn plantuml_input_renders_like_mermaid() {
+        let plantuml = render("@startuml\nAlice -> Bob: hi\nBob --> Alice: ok\n@enduml\n");
+        let mermaid = render("sequenceDiagram\nAlice->>Bob: hi\nBob-->>Alice: ok\n");
+        assert_eq!(plantuml, mermaid);
+        let both = render_all("@startuml\nA -> B\n@enduml\n\n@startuml\nB -> A\n@enduml\n");
+        assert_eq!(both.len(), 2);
+    }
+
+    #[test]
+    fn diagram_type_supplies_a_missing_header() {
+        let options =
+            RenderOptions { diagram_type: DiagramType::Sequence, ..RenderOptions::default() };
+        let snippet = "    A->>B: hi\n    B-->>A: yo\n";
+        let headed = format!("sequenceDiagram\n{snippet}");
+        assert_eq!(render_with(snippet, &options), render(&headed));
+        assert!(render(snippet).unwrap_err().contains("unknown diagram type"));
+
+        let error = render_with("    A->>B: hi\n    B->>: yo\n", &options).unwrap_err();
+        assert!(error.starts_with("syntax error at line 2:5:"), "{error}");
+        let graph = "graph LR\n    A --> B\n";
+        assert_eq!(render_with(graph, &options), render(graph));
+    }
+
+    #[test]
+    fn detect_reads_an_unambiguous_snippet_with_a_warning() {
+        let options =
+            RenderOptions { diagram_type: DiagramType::Detect, ..RenderOptions::default() };
+        for (snippet, header) in [
+            ("    A->>B: hi\n", "sequenceDiagram"),
+            ("    A --> B\n", "graph TD"),
+            ("    A ||--o{ B : has\n", "erDiagram"),
+        ] {
+            let (output, warnings) = render_with_diagnostics(snippet, &options).unwrap();
+            assert_eq!(output, render(&format!("{header}\n{snippet}")).unwrap());
+            assert_eq!(warnings, vec![Warning::AssumedHeader { header: header.to_string() }]);
+        }
+
+        // Bare arrows could also be sequence messages without text
+        let bare = "    A --> B\n    B --> C\n";
+        assert_eq!(render_with(bare, &options), render(&format!("graph TD\n{bare}")));
+
+        // A lone name is both a graph node and an ER entity
+        assert!(render_with("A\n", &options).unwrap_err().contains("unknown diagram type"));
+        let headed = "graph LR\n    A --> B\n";
+        assert_eq!(render_with_diagnostics(headed, &options).unwrap().1, vec![]);
+    }
+
+    #[test]
+    fn parse_unknown_type_returns_error() {
+        let err = parse("pie\n").unwrap_err();
+        // Types registered by other tests may follow the built-in ones
+        assert!(
+            err.message().starts_with(
+                "unknown diagram type: pie; supported types: sequenceDiagram, graph, flowchart, erDiagram"
+            ),
+            "{err}"
+        );
+        let err = parse("sequenceDiagramm\n    A->>B: hi\n").unwrap_err();
+        assert!(
+            err.message()
+                .starts_with("unknown diagram type: sequenceDiagramm (did you mean sequenceDiagram?);"),
+            "{err}"
+        );
+        let err = parse("erdiagram\n").unwrap_err();
+        assert!(err.message().contains("(did you mean erDiagram?)"), "{err}");
+    }
+
+    #[test]
+    fn render_document_after_transform() {
+        let Document::Graph(mut diagram) = parse("graph LR\n    A --> B\n").unwrap() else {
+            panic!("expected graph");
+        };
+        diagram.nodes[0].label = "Start".to_string();
+        let output = render_document(&Document::Graph(diagram), &RenderOptions::default()).unwrap();
+        assert!(output.contains("│ Start │"), "{output}");
+    }
+
     #[test]
     fn render_sequence_diagram_works() {
         let output = render("sequenceDiagram\n    Alice->>Bob: Hello\n").unwrap();
@@ -77,9 +992,372 @@ mod tests {
         assert!(output.contains("A"));
     }
 
+    #[test]
+    fn render_with_sequence_options_changes_spacing() {
+        let input = "sequenceDiagram\n    A->>B: Hi\n";
+        let default = render(input).unwrap();
+        let mut options = RenderOptions::default();
+        options.sequence.min_gap = 20;
+        options.sequence.box_padding = 2;
+        let airy = render_with(input, &options).unwrap();
+        assert!(airy.contains("│  A  │"), "padding applied:\n{airy}");
+        let width = |s: &str| s.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+        assert!(width(&airy) > width(&default), "wider gap:\n{airy}");
+    }
+
+    #[test]
+    fn render_with_max_width_matches_shim() {
+        let input = "sequenceDiagram\n    Alice->>Bob: A fairly long message here\n";
+        let options = RenderOptions {
+            max_width: Some(30),
+            ..RenderOptions::default()
+        };
+        let output = render_with(input, &options).unwrap();
+        assert_eq!(output, render_with_options(input, Some(30)).unwrap());
+        assert!(output.lines().all(|l| display_width::display_width(l) <= 30));
+    }
+
+    #[test]
+    fn render_ascii_only_has_no_box_drawing() {
+        let options = RenderOptions {
+            ascii_only: true,
+            ..RenderOptions::default()
+        };
+        for input in [
+            "sequenceDiagram\n    Alice->>Bob: Hello\n    Bob-->>Alice: Hi\n",
+            "graph TD\n    A{Go?} -->|Yes| B(Done)\n    A --> A\n",
+            "erDiagram\n    A ||--o{ B : has\n",
+        ] {
+            let output = render_with(input, &options).unwrap();
+            assert!(output.is_ascii(), "non-ASCII output:\n{output}");
+        }
+
+        // Marks added by ma itself: collapsed rows and names cut to fit
+        let input = "sequenceDiagram\n    Alexandria->>Bartholomew: poll\n    \
+                     Alexandria->>Bartholomew: poll\n";
+        let sequence = layout::SequenceOptions {
+            collapse_repeats: Some(2),
+            ..layout::SequenceOptions::default()
+        };
+        let options = RenderOptions { max_width: Some(20), sequence, ..options };
+        let output = render_with(input, &options).unwrap();
+        assert!(output.is_ascii(), "non-ASCII output:\n{output}");
+        assert!(output.contains("poll x2") && output.contains("Alex."), "{output}");
+    }
+
+    #[test]
+    fn render_ascii_only_keeps_box_drawing_in_labels() {
+        let options = RenderOptions {
+            ascii_only: true,
+            ..RenderOptions::default()
+        };
+        let output = render_with("graph LR\n    A[a─b] -->|└┘| B\n", &options).unwrap();
+        assert_eq!(output, "+-----+ └┘  +---+\n| a─b |---->| B |\n+-----+     +---+");
+
+        let input = "sequenceDiagram\n    Alice->>Bob: ──►\n";
+        let output = render_with(input, &options).unwrap();
+        assert!(output.contains("| ──►") && output.contains("|-------->|"), "{output}");
+
+        let color = RenderOptions { color: true, ..options };
+        let output = grid::strip_ansi(&render_with(input, &color).unwrap());
+        assert!(output.contains("| ──►") && output.contains("|-------->|"), "{output}");
+    }
+
+    #[test]
+    fn render_with_theme_changes_borders() {
+        let input = "sequenceDiagram\n    A->>B: Hi\n";
+        let options = RenderOptions {
+            theme: style::Theme::DOUBLE,
+            ..RenderOptions::default()
+        };
+        let output = render_with(input, &options).unwrap();
+        assert!(output.starts_with("╔═══╗"), "{output}");
+        assert!(output.contains("╚═╤═╝"), "{output}");
+        assert!(output.contains("│ Hi"), "lifelines stay light:\n{output}");
+    }
+
+    #[test]
+    fn render_markdown_replaces_mermaid_blocks() {
+        let input = "# Doc\n\n```mermaid\ngraph LR\n    A --> B\n```\n\nAfter.\n";
+        let output = render_markdown(input, &RenderOptions::default());
+        let diagram = render("graph LR\n    A --> B\n").unwrap();
+        assert_eq!(output, format!("# Doc\n\n```\n{diagram}\n```\n\nAfter.\n"));
+    }
+
+    #[test]
+    fn render_markdown_keeps_a_failing_block_and_renders_the_rest() {
+        let bad = "```mermaid\ngraph LR\n    A -->\n```\n";
+        let input = format!("{bad}\n```mermaid\ngraph LR\n    A --> B\n```\n");
+        let (output, warnings) =
+            render_markdown_with_diagnostics(&input, &RenderOptions::default());
+        let diagram = render("graph LR\n    A --> B\n").unwrap();
+        assert_eq!(output, format!("{bad}\n```\n{diagram}\n```\n"));
+        assert!(matches!(warnings.as_slice(), [Warning::FailedBlock { line: 1, .. }]));
+    }
+
+    #[test]
+    fn split_diagrams_on_separator_and_headers() {
+        let input = "graph LR\n    A --> B\n---\nerDiagram\n    A ||--o{ B : has\n\n\nsequenceDiagram\n    A->>B: Hi\n\n    B->>A: Yo\n";
+        assert_eq!(
+            split_diagrams(input),
+            vec![
+                "graph LR\n    A --> B\n",
+                "erDiagram\n    A ||--o{ B : has\n\n\n",
+                "sequenceDiagram\n    A->>B: Hi\n\n    B->>A: Yo\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_diagrams_keeps_frontmatter_with_its_diagram() {
+        let input = "---\ntitle: One\n---\nsequenceDiagram\n    A->>B: Hi\n---\n---\ntitle: Two\n---\n\ngraph LR\n";
+        assert_eq!(
+            split_diagrams(input),
+            vec![
+                "---\ntitle: One\n---\nsequenceDiagram\n    A->>B: Hi\n",
+                "---\ntitle: Two\n---\n\ngraph LR\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn frontmatter_title_is_used_unless_the_diagram_has_one() {
+        let title = |input| match parse(input).unwrap() {
+            Document::Sequence(diagram) => diagram.title,
+            _ => unreachable!(),
+        };
+        let frontmatter = "---\ntitle: Outer\n---\nsequenceDiagram\n    A->>B: hi\n";
+        assert_eq!(title(frontmatter).as_deref(), Some("Outer"));
+        let both = "---\ntitle: Outer\n---\nsequenceDiagram\n    title Inner\n    A->>B: hi\n";
+        assert_eq!(title(both).as_deref(), Some("Inner"));
+        assert!(render("---\ntitle: Flow\n---\ngraph LR\n    A --> B\n").is_ok());
+    }
+
+    #[test]
+    fn render_all_reports_each_diagram() {
+        let input = "graph LR\n    A --> B\n\n?? oops\n---\n\nsequenceDiagram\n    A->>B: Hi\n";
+        let results = render_all(input);
+        assert_eq!(results.len(), 2);
+        let err = results[0].as_ref().unwrap_err();
+        assert_eq!(
+            err.message().lines().next().unwrap(),
+            "syntax error in graph diagram at line 4:1: unexpected `?? oops`"
+        );
+        let expected = render("sequenceDiagram\n    A->>B: Hi\n").unwrap();
+        assert_eq!(results[1].as_ref().unwrap(), &expected);
+    }
+
+    #[test]
+    fn bom_and_crlf_input_reads_like_lf() {
+        let inputs = [
+            "---\ntitle: Flow\n---\nsequenceDiagram\n    loop again\n        A->>B: Hi\n    end\n",
+            "graph LR\n    A -->|go| B\n",
+            "erDiagram\n    A ||--o{ B : has\n    B {\n        int id PK\n    }\n",
+            "digraph {\n    a -> b\n}\n",
+            "@startuml\nAlice -> Bob: hi\n@enduml\n",
+        ];
+        for input in inputs {
+            let windows = format!("\u{FEFF}{}", input.replace('\n', "\r\n"));
+            assert_eq!(parse(&windows), parse(input), "{input}");
+            assert_eq!(render(&windows), render(input), "{input}");
+        }
+        let both = format!("\u{FEFF}{}\n{}", inputs[0], inputs[1]).replace('\n', "\r\n");
+        let results = render_all(&both);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(Result::is_ok));
+        assert!(format("\u{FEFF}graph LR\r\n    A --> B\r\n").is_ok());
+    }
+
+    #[test]
+    fn render_all_empty_input_is_one_error() {
+        let results = render_all("");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+    }
+
+    #[test]
+    fn inspect_matches_rendered_size() {
+        let input = "sequenceDiagram\n    A->>B: Hi\n    loop again\n        B->>A: Yo\n    end\n";
+        let stats = inspect(&parse(input).unwrap(), &RenderOptions::default()).unwrap();
+        let output = render(input).unwrap();
+        assert_eq!(stats.kind, "sequence");
+        assert_eq!((stats.elements, stats.connections, stats.max_depth), (2, 2, 1));
+        assert_eq!(stats.height, output.lines().count());
+        let widest = output.lines().map(display_width::display_width).max().unwrap();
+        assert!(stats.width >= widest, "{stats:?}\n{output}");
+    }
+
+    #[test]
+    fn inspect_all_reads_the_input_as_rendering_does() {
+        let options = RenderOptions {
+            diagram_type: DiagramType::Sequence,
+            lenient: true,
+            ..RenderOptions::default()
+        };
+        let results = inspect_all("A->>B: Hi\n???\n---\nB->>C: Yo\n", &options);
+        let (stats, warnings) = results[0].as_ref().unwrap();
+        assert_eq!((stats.kind, stats.elements, stats.connections), ("sequence", 2, 1));
+        assert_eq!(warnings.len(), 1);
+        assert!(results[1].is_ok());
+        assert!(inspect_all("A->>B: Hi\n", &RenderOptions::default())[0].is_err());
+    }
+
+    #[test]
+    fn render_with_line_numbers_adds_gutter() {
+        let options = RenderOptions {
+            line_numbers: true,
+            ..RenderOptions::default()
+        };
+        let output = render_with("graph LR\n    A --> B\n", &options).unwrap();
+        assert_eq!(
+            output,
+            "1 │ ┌───┐     ┌───┐\n2 │ │ A │────>│ B │\n3 │ └───┘     └───┘"
+        );
+    }
+
+    #[test]
+    fn number_lines_right_aligns_and_trims() {
+        let text = (1..=10).map(|i| if i == 2 { String::new() } else { format!("l{i}") });
+        let numbered = number_lines(&text.collect::<Vec<_>>().join("\n"), '│');
+        let lines: Vec<&str> = numbered.lines().collect();
+        assert_eq!(lines[0], " 1 │ l1");
+        assert_eq!(lines[1], " 2 │");
+        assert_eq!(lines[9], "10 │ l10");
+    }
+
+    #[test]
+    fn render_to_matches_render_with() {
+        let ascii = RenderOptions {
+            ascii_only: true,
+            ..RenderOptions::default()
+        };
+        let color = RenderOptions { color: true, ..RenderOptions::default() };
+        for options in [RenderOptions::default(), ascii, color] {
+            for input in [
+                "sequenceDiagram\n    Alice->>Bob: Hello\n",
+                "graph TD\n    A --> B\n",
+                "erDiagram\n    A ||--o{ B : has\n",
+            ] {
+                let mut out = Vec::new();
+                render_to(input, &mut out, &options).unwrap();
+                assert_eq!(String::from_utf8(out).unwrap(), render_with(input, &options).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn color_dims_the_drawing_of_every_diagram_type() {
+        for max_height in [None, Some(6)] {
+            for input in [
+                "sequenceDiagram\n    Alice->>Bob: Hello\n    Bob->>Alice: Hi\n",
+                "graph TD\n    A --> B\n",
+                "erDiagram\n    A ||--o{ B : has\n",
+            ] {
+                let plain = RenderOptions { max_height, ..RenderOptions::default() };
+                let color = RenderOptions { color: true, ..plain.clone() };
+                let output = render_with(input, &color).unwrap();
+                assert_eq!(grid::strip_ansi(&output), render_with(input, &plain).unwrap());
+                assert!(output.contains("\x1b[2m┌"), "{output}");
+            }
+        }
+        let color = RenderOptions { color: true, ..RenderOptions::default() };
+        let output = render_with("graph LR\n    A --> B\n", &color).unwrap();
+        let row = "\x1b[2m│ \x1b[0mA \x1b[2m│────>│ \x1b[0mB \x1b[2m│\x1b[0m";
+        assert_eq!(output.lines().nth(1).unwrap(), row);
+    }
+
     #[test]
     fn render_er_diagram_works() {
         let output = render("erDiagram\n    A ||--o{ B : has\n").unwrap();
         assert!(output.contains("A"));
     }
+
+    /// `document`, a sequence diagram, placed on `lines` of its source.
+    fn with_source_lines(document: Document, lines: Vec<usize>) -> Document {
+        let Document::Sequence(diagram) = document else { panic!("expected a sequence diagram") };
+        let source_lines = ast::SourceLines(lines);
+        Document::Sequence(ast::Diagram { source_lines, ..diagram })
+    }
+
+    #[test]
+    fn parse_lenient_skips_unparsable_lines() {
+        let input = "sequenceDiagram\n    accTitle: Checkout\n    A->>B: hi\n    repeat\n    B->>A: yo\n    end\n";
+        assert!(parse(input).is_err());
+
+        let (document, warnings) = parse_lenient(input, InputFormat::Auto).unwrap();
+        let kept = "sequenceDiagram\n    A->>B: hi\n    B->>A: yo\n";
+        assert_eq!(document, with_source_lines(parse(kept).unwrap(), vec![3, 5]));
+        assert_eq!(
+            warnings,
+            [
+                Warning::SkippedStatement { line: 2, text: "accTitle: Checkout".to_string() },
+                Warning::SkippedStatement { line: 4, text: "repeat".to_string() },
+                Warning::SkippedStatement { line: 6, text: "end".to_string() },
+            ]
+        );
+
+        let options = RenderOptions { lenient: true, ..RenderOptions::default() };
+        assert_eq!(render_with(input, &options), render_document(&document, &options));
+    }
+
+    #[test]
+    fn parse_lenient_keeps_header_errors_and_unwraps_unclosed_blocks() {
+        let unknown = "classDiagram\n    Foo\n";
+        assert_eq!(
+            parse_lenient(unknown, InputFormat::Auto).unwrap_err().message(),
+            parse(unknown).unwrap_err().message()
+        );
+
+        let unclosed = "sequenceDiagram\n    loop forever\n    A->>B: hi\n";
+        let (document, warnings) = parse_lenient(unclosed, InputFormat::Auto).unwrap();
+        let kept = "sequenceDiagram\n    A->>B: hi\n";
+        assert_eq!(document, with_source_lines(parse(kept).unwrap(), vec![3]));
+        assert_eq!(
+            warnings,
+            [Warning::SkippedStatement { line: 2, text: "loop forever".to_string() }]
+        );
+    }
+
+    #[test]
+    fn rtl_reverse_keeps_references_matching() {
+        let options = RenderOptions { rtl: bidi::RtlMode::Reverse, ..RenderOptions::default() };
+        assert_eq!(
+            render_with("sequenceDiagram\n    שרת->>לקוח: אישור 200\n    activate לקוח\n", &options),
+            render("sequenceDiagram\n    תרש->>חוקל: 200 רושיא\n    activate חוקל\n")
+        );
+    }
+
+    #[test]
+    fn rtl_reverse_arabic_keeps_boxes_and_lifelines_aligned() {
+        // Reversed, every `ال` becomes the lam-alef pair `لا`
+        let options = RenderOptions { rtl: bidi::RtlMode::Reverse, ..RenderOptions::default() };
+        let output = render_with("graph TD\n    A[الكتاب الجديد]\n", &options).unwrap();
+        let expected = "┌───────────────┐\n│ ديدجلا باتكلا │\n└───────────────┘";
+        assert_eq!(output, expected);
+
+        let output = render_with("sequenceDiagram\n    A->>B: الكتاب الجديد\n", &options).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[3], "  │ ديدجلا باتكلا  │");
+        assert_eq!(display_width::display_width(lines[3]), display_width::display_width(lines[4]));
+    }
+
+    #[test]
+    fn invisible_characters_do_not_shift_columns() {
+        assert_eq!(
+            render("graph LR\n    A[\u{2764}\u{FE0F} ok] -->|\u{200B}go| B[a\tb]\n"),
+            render("graph LR\n    A[\u{2764} ok] -->|go| B[a b]\n")
+        );
+    }
+
+    #[test]
+    fn emoji_width_keeps_boxes_square() {
+        for emoji_width in [display_width::EmojiWidth::Narrow, display_width::EmojiWidth::Wide] {
+            let options = RenderOptions { emoji_width, ..RenderOptions::default() };
+            let output = render_with("graph TD\n    A[❤ 🎉 party]\n", &options).unwrap();
+            let widths: Vec<usize> = display_width::with_emoji_width(emoji_width, || {
+                output.lines().map(display_width::display_width).collect()
+            });
+            assert_eq!(widths, [widths[0]; 3], "{emoji_width:?}\n{output}");
+        }
+    }
 }
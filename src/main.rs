@@ -1,41 +1,637 @@
-use std::io::Read;
+//! The `ma` command-line tool (the default `cli` feature). It only uses the
+//! public library API, so the library builds without any of its dependencies.
 
-use clap::Parser;
+use std::io::{IsTerminal, Read, Write};
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
 
 #[derive(Parser)]
-#[command(name = "ma", about = "Render Mermaid diagrams as ASCII art (sequence, flowchart, ER)")]
+#[command(
+    name = "ma",
+    about = "Render Mermaid diagrams as ASCII art (sequence, flowchart, ER)",
+    args_conflicts_with_subcommands = true
+)]
 struct Cli {
-    /// Input file (reads from stdin if not provided)
-    file: Option<std::path::PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Maximum output width in columns
+    /// Input files or glob patterns (reads from stdin if none are given)
+    files: Vec<String>,
+
+    /// Maximum output width in columns (defaults to the terminal width)
     #[arg(long, short = 'w')]
     width: Option<usize>,
+
+    /// Wrap names and labels that don't fit the width onto more lines instead of cutting them short
+    #[arg(long)]
+    wrap: bool,
+
+    /// Prefix every output line with its line number
+    #[arg(long, short = 'n')]
+    line_numbers: bool,
+
+    /// Split long sequence diagrams into pages of at most N lines
+    #[arg(long, value_name = "N")]
+    max_height: Option<usize>,
+
+    /// Write the rendering to FILE instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Overwrite an existing output file
+    #[arg(long, requires = "output")]
+    force: bool,
+
+    /// Create the output file's missing parent directories
+    #[arg(long, requires = "output")]
+    create_dirs: bool,
+
+    /// Don't fit the output to the terminal width
+    #[arg(long, conflicts_with = "width")]
+    no_fit: bool,
+
+    /// Use plain ASCII instead of Unicode box-drawing characters
+    #[arg(long)]
+    ascii: bool,
+
+    /// How to write right-to-left (Hebrew, Arabic) text: as typed, or reversed for terminals without bidi support
+    #[arg(long, default_value = "keep", value_parser = clap::builder::PossibleValuesParser::new(ma::bidi::RtlMode::NAMES))]
+    rtl: String,
+
+    /// Columns each emoji takes, if your terminal disagrees with the default measurement
+    #[arg(long, default_value = "auto", value_parser = clap::builder::PossibleValuesParser::new(ma::display_width::EmojiWidth::NAMES))]
+    emoji_width: String,
+
+    /// Border style for boxes and frames
+    #[arg(long, default_value = "light", value_parser = clap::builder::PossibleValuesParser::new(ma::style::Theme::NAMES))]
+    style: String,
+
+    /// Left-to-right order of sequence diagram participants
+    #[arg(long, default_value = "declared", value_parser = clap::builder::PossibleValuesParser::new(ma::layout::ParticipantOrder::NAMES))]
+    order: String,
+
+    /// Draw runs of at least N identical consecutive sequence messages as one row marked `×N`
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(2..))]
+    collapse_repeats: Option<u16>,
+
+    /// Don't repeat the participant boxes below sequence diagrams (Mermaid's `mirrorActors: false`)
+    #[arg(long)]
+    no_mirror: bool,
+
+    /// Draw the branches of a sequence `par` block side by side when they use separate participants
+    #[arg(long)]
+    par_side_by_side: bool,
+
+    /// Where sequence message text sits above its arrow: from the left end, or centered as in Mermaid
+    #[arg(long, value_name = "ALIGN", default_value = "left", value_parser = clap::builder::PossibleValuesParser::new(ma::layout::MessageAlign::NAMES))]
+    message_align: String,
+
+    /// With --max-height, move a sequence block that would be split across pages onto the next one
+    #[arg(long, requires = "max_height")]
+    keep_blocks_together: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Don't print warnings on stderr
+    #[arg(long, short = 'q')]
+    quiet: bool,
+
+    /// Treat the input as Markdown and render each ```mermaid block in place
+    #[arg(long, conflicts_with = "format")]
+    markdown: bool,
+
+    /// Output format
+    #[arg(long, default_value = "text", value_parser = ["text", "html"])]
+    format: String,
+
+    /// What to emit: the rendering, or the computed layout as JSON coordinates
+    #[arg(long, default_value = "render", value_parser = ["render", "layout-json"], conflicts_with_all = ["markdown", "format", "color", "line_numbers"])]
+    emit: String,
+
+    /// Dim box-drawing glyphs: with ANSI escapes in text output, with color spans in HTML
+    #[arg(long, conflicts_with = "markdown")]
+    color: bool,
+
+    /// Re-render whenever FILE changes, redrawing the screen
+    #[arg(long, requires = "files", conflicts_with = "output")]
+    watch: bool,
+
+    /// Text printed between diagrams when the input holds several
+    #[arg(long, default_value = "\n\n")]
+    separator: String,
+}
+
+/// How to read the input, shared by rendering and `inspect` so both parse it
+/// the same way.
+#[derive(Args)]
+struct InputArgs {
+    /// Skip lines that don't parse, with a warning, instead of failing
+    #[arg(long)]
+    lenient: bool,
+
+    /// Draw a flowchart node declared with different shapes or labels with its last one, as Mermaid does
+    #[arg(long)]
+    last_shape_wins: bool,
+
+    /// Input syntax; `auto` reads DOT when the input starts like a Graphviz graph
+    #[arg(long, default_value = "auto", value_parser = clap::builder::PossibleValuesParser::new(ma::InputFormat::NAMES))]
+    from: String,
+
+    /// Diagram type for Mermaid input without a header line, such as a snippet pasted without `sequenceDiagram`
+    #[arg(long = "type", value_name = "TYPE", default_value = "auto", value_parser = clap::builder::PossibleValuesParser::new(ma::DiagramType::NAMES))]
+    diagram_type: String,
+}
+
+impl InputArgs {
+    /// Default render options, reading the input as these flags ask.
+    fn options(&self) -> ma::RenderOptions {
+        ma::RenderOptions {
+            input: ma::InputFormat::named(&self.from).unwrap_or_default(),
+            lenient: self.lenient,
+            last_shape_wins: self.last_shape_wins,
+            diagram_type: ma::DiagramType::named(&self.diagram_type).unwrap_or_default(),
+            ..ma::RenderOptions::default()
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print diagram kind, element counts, nesting depth and laid-out size without rendering
+    Inspect {
+        /// Input files or glob patterns (reads from stdin if none are given)
+        files: Vec<String>,
+
+        /// Maximum width to lay out for
+        #[arg(long, short = 'w')]
+        width: Option<usize>,
+
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Reformat diagram sources: indent blocks and align arrows and colons
+    Fmt {
+        /// Input files or glob patterns (reads from stdin if none are given)
+        files: Vec<String>,
+
+        /// Rewrite the files in place instead of printing them
+        #[arg(long, conflicts_with = "check")]
+        write: bool,
+
+        /// Only report files that aren't formatted, exiting with 1 if any
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    let input = match cli.file {
-        Some(path) => std::fs::read_to_string(&path).unwrap_or_else(|e| {
-            eprintln!("ERROR: failed to read {}: {e}", path.display());
-            std::process::exit(1);
-        }),
-        None => {
-            let mut buf = String::new();
-            std::io::stdin().read_to_string(&mut buf).unwrap_or_else(|e| {
+    match &cli.command {
+        Some(Command::Inspect { files, width, input }) => {
+            let options = ma::RenderOptions { max_width: *width, ..input.options() };
+            let failed = inspect(files, &options);
+            std::process::exit(i32::from(failed));
+        }
+        Some(Command::Fmt { files, write, check }) => {
+            let failed = fmt(files, *write, *check);
+            std::process::exit(i32::from(failed));
+        }
+        None => {}
+    }
+
+    let max_width = if cli.no_fit || cli.output.is_some() {
+        cli.width
+    } else {
+        cli.width.or_else(terminal_width)
+    };
+
+    let options = ma::RenderOptions {
+        max_width,
+        wrap: cli.wrap,
+        max_height: cli.max_height,
+        line_numbers: cli.line_numbers,
+        ascii_only: cli.ascii,
+        color: cli.color && cli.format == "text",
+        rtl: ma::bidi::RtlMode::named(&cli.rtl).unwrap_or_default(),
+        emoji_width: ma::display_width::EmojiWidth::named(&cli.emoji_width).unwrap_or_default(),
+        theme: ma::style::Theme::named(&cli.style).unwrap_or_default(),
+        sequence: ma::layout::SequenceOptions {
+            order: ma::layout::ParticipantOrder::named(&cli.order).unwrap_or_default(),
+            collapse_repeats: cli.collapse_repeats.map(usize::from),
+            mirror_participants: !cli.no_mirror,
+            par_side_by_side: cli.par_side_by_side,
+            keep_blocks_together: cli.keep_blocks_together,
+            message_align: ma::layout::MessageAlign::named(&cli.message_align).unwrap_or_default(),
+            ..ma::layout::SequenceOptions::default()
+        },
+        ..cli.input.options()
+    };
+
+    let paths = expand_paths(&cli.files).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        std::process::exit(1);
+    });
+
+    if cli.watch {
+        match paths.as_slice() {
+            [path] => watch(&cli, path, &options),
+            _ => {
+                eprintln!("ERROR: --watch takes exactly one file");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (output, failed) = match paths.as_slice() {
+        [] => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input).unwrap_or_else(|e| {
                 eprintln!("ERROR: failed to read stdin: {e}");
                 std::process::exit(1);
             });
-            buf
+            render_input(&cli, &input, &options, "")
+        }
+        [path] => {
+            let input = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("ERROR: failed to read {}: {e}", path.display());
+                std::process::exit(1);
+            });
+            render_input(&cli, &input, &options, "")
         }
+        _ => render_batch(&cli, &paths, &options),
     };
 
-    match ma::render_with_options(&input, cli.width) {
-        Ok(output) => print!("{output}"),
-        Err(e) => {
-            eprintln!("ERROR: {e}");
+    match &cli.output {
+        Some(path) => {
+            if let Err(e) = write_output(path, &output, cli.force, cli.create_dirs) {
+                eprintln!("ERROR: {e}");
+                std::process::exit(1);
+            }
+        }
+        None => print!("{output}"),
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Poll `path` for changes and redraw the rendering until interrupted.
+/// Errors are shown in place of the diagram instead of exiting.
+fn watch(cli: &Cli, path: &Path, options: &ma::RenderOptions) -> ! {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_seen = None;
+    loop {
+        let current = modified(path);
+        if current != last_seen {
+            // Debounce: wait for the editor to finish writing before reading
+            std::thread::sleep(WATCH_DEBOUNCE);
+            if modified(path) != current {
+                continue;
+            }
+            last_seen = current;
+            print!("\x1b[2J\x1b[H");
+            let _ = std::io::stdout().flush();
+            match std::fs::read_to_string(path) {
+                Ok(input) => {
+                    let (output, _) = render_input(cli, &input, options, "");
+                    println!("{output}");
+                }
+                Err(e) => println!("ERROR: failed to read {}: {e}", path.display()),
+            }
+            let _ = std::io::stdout().flush();
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Print stats for every diagram in `files`, parsed as `options` ask the
+/// same way rendering does. Returns whether any failed.
+fn inspect(files: &[String], options: &ma::RenderOptions) -> bool {
+    let paths = expand_paths(files).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        std::process::exit(1);
+    });
+    let mut sources = Vec::new();
+    if paths.is_empty() {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input).unwrap_or_else(|e| {
+            eprintln!("ERROR: failed to read stdin: {e}");
             std::process::exit(1);
+        });
+        sources.push(("stdin".to_string(), input));
+    }
+    let mut failed = false;
+    for path in &paths {
+        match std::fs::read_to_string(path) {
+            Ok(input) => sources.push((path.display().to_string(), input)),
+            Err(e) => {
+                eprintln!("ERROR: failed to read {}: {e}", path.display());
+                failed = true;
+            }
+        }
+    }
+
+    let mut reports = Vec::new();
+    for (name, input) in &sources {
+        let results = ma::inspect_all(input, options);
+        let numbered = results.len() > 1;
+        for (i, result) in results.into_iter().enumerate() {
+            let label = if numbered { format!("{name} #{}", i + 1) } else { name.clone() };
+            match result {
+                Ok((stats, warnings)) => {
+                    for warning in warnings {
+                        eprintln!("WARNING: {label}: {warning}");
+                    }
+                    reports.push((label, stats));
+                }
+                Err(e) => {
+                    eprintln!("ERROR: {label}: {e}");
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    let headers = reports.len() > 1;
+    let blocks: Vec<String> = reports
+        .into_iter()
+        .map(|(label, stats)| {
+            if headers {
+                format!("==> {label} <==\n{stats}")
+            } else {
+                stats.to_string()
+            }
+        })
+        .collect();
+    if !blocks.is_empty() {
+        println!("{}", blocks.join("\n\n"));
+    }
+    failed
+}
+
+/// Format every file in `files` (or stdin), printing the result, rewriting
+/// the files with `write`, or listing unformatted ones with `check`.
+/// Returns whether anything failed or, with `check`, needs formatting.
+fn fmt(files: &[String], write: bool, check: bool) -> bool {
+    let paths = expand_paths(files).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        std::process::exit(1);
+    });
+    if paths.is_empty() {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input).unwrap_or_else(|e| {
+            eprintln!("ERROR: failed to read stdin: {e}");
+            std::process::exit(1);
+        });
+        return match ma::format(&input) {
+            Ok(formatted) if check => formatted != input,
+            Ok(formatted) => {
+                print!("{formatted}");
+                false
+            }
+            Err(e) => {
+                eprintln!("ERROR: {e}");
+                true
+            }
+        };
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))
+            .and_then(|input| {
+                let formatted =
+                    ma::format(&input).map_err(|e| format!("{}: {e}", path.display()))?;
+                Ok((input, formatted))
+            });
+        match result {
+            Ok((input, formatted)) if check => {
+                if formatted != input {
+                    println!("{}", path.display());
+                    failed = true;
+                }
+            }
+            Ok((input, formatted)) if write => {
+                if formatted != input
+                    && let Err(e) = std::fs::write(path, formatted)
+                {
+                    eprintln!("ERROR: failed to write {}: {e}", path.display());
+                    failed = true;
+                }
+            }
+            Ok((_, formatted)) if paths.len() > 1 => {
+                println!("==> {} <==\n{formatted}", path.display())
+            }
+            Ok((_, formatted)) => print!("{formatted}"),
+            Err(e) => {
+                eprintln!("ERROR: {e}");
+                failed = true;
+            }
+        }
+    }
+    failed
+}
+
+/// Expand glob patterns among the input arguments. Arguments naming an
+/// existing file are taken literally; a pattern matching nothing is an error.
+fn expand_paths(args: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for arg in args {
+        let literal = PathBuf::from(arg);
+        if literal.exists() || !arg.contains(['*', '?', '[']) {
+            paths.push(literal);
+            continue;
+        }
+        let matches = glob::glob(arg).map_err(|e| format!("invalid pattern {arg}: {e}"))?;
+        let before = paths.len();
+        paths.extend(matches.filter_map(Result::ok).filter(|p| p.is_file()));
+        if paths.len() == before {
+            return Err(format!("no files match {arg}"));
+        }
+    }
+    Ok(paths)
+}
+
+/// Render each file under a `==> path <==` header, continuing past failures.
+fn render_batch(cli: &Cli, paths: &[PathBuf], options: &ma::RenderOptions) -> (String, bool) {
+    let mut sections = Vec::new();
+    let mut failed = false;
+    for path in paths {
+        let prefix = format!("{}: ", path.display());
+        let (output, file_failed) = match std::fs::read_to_string(path) {
+            Ok(input) => render_input(cli, &input, options, &prefix),
+            Err(e) => {
+                eprintln!("ERROR: failed to read {}: {e}", path.display());
+                (String::new(), true)
+            }
+        };
+        failed |= file_failed;
+        if !output.is_empty() {
+            sections.push(format!("==> {} <==\n{output}", path.display()));
+        }
+    }
+    (sections.join("\n\n"), failed)
+}
+
+/// Render the whole input, reporting errors to stderr with `prefix`. Returns
+/// the combined output and whether any diagram failed.
+fn render_input(
+    cli: &Cli,
+    input: &str,
+    options: &ma::RenderOptions,
+    prefix: &str,
+) -> (String, bool) {
+    if cli.markdown {
+        let (output, warnings) = ma::render_markdown_with_diagnostics(input, options);
+        for warning in &warnings {
+            eprintln!("ERROR: {prefix}{warning}");
+        }
+        return (output, !warnings.is_empty());
+    }
+
+    let results = if cli.emit == "layout-json" {
+        ma::layout_json_all(input, options)
+    } else {
+        let results = ma::render_all_with_diagnostics(input, options);
+        let multiple = results.len() > 1;
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let (output, warnings) = result?;
+                for warning in warnings.into_iter().filter(|_| !cli.quiet) {
+                    if multiple {
+                        eprintln!("WARNING: {prefix}diagram {}: {warning}", i + 1);
+                    } else {
+                        eprintln!("WARNING: {prefix}{warning}");
+                    }
+                }
+                Ok(output)
+            })
+            .collect()
+    };
+    let multiple = results.len() > 1;
+    let mut outputs = Vec::new();
+    let mut failed = false;
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(output) if cli.format == "html" => outputs.push(ma::html::to_html(&output, cli.color)),
+            Ok(output) => outputs.push(output),
+            Err(e) if multiple => {
+                eprintln!("ERROR: {prefix}diagram {}: {e}", i + 1);
+                failed = true;
+            }
+            Err(e) => {
+                let hint = detect_hint(input, options).unwrap_or_default();
+                eprintln!("ERROR: {prefix}{e}{hint}");
+                failed = true;
+            }
+        }
+    }
+    (outputs.join(&cli.separator), failed)
+}
+
+/// A pointer to `--type detect` for input that failed for want of a header
+/// line but that detection reads as one type.
+fn detect_hint(input: &str, options: &ma::RenderOptions) -> Option<String> {
+    if options.diagram_type != ma::DiagramType::Auto {
+        return None;
+    }
+    let detect = ma::RenderOptions { diagram_type: ma::DiagramType::Detect, ..options.clone() };
+    let (_, warnings) = ma::render_with_diagnostics(input, &detect).ok()?;
+    warnings.into_iter().find_map(|warning| match warning {
+        ma::Warning::AssumedHeader { header } => {
+            Some(format!(" (no header line; use --type detect to read it as `{header}`)"))
+        }
+        _ => None,
+    })
+}
+
+/// Write `output` to `path` with a trailing newline. Existing files are only
+/// replaced with `force`, and missing parent directories only created with
+/// `create_dirs`.
+fn write_output(path: &Path, output: &str, force: bool, create_dirs: bool) -> Result<(), String> {
+    if path.exists() && !force {
+        return Err(format!("{} already exists (use --force to overwrite)", path.display()));
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty())
+        && !parent.exists()
+    {
+        if !create_dirs {
+            return Err(format!(
+                "directory {} does not exist (use --create-dirs to create it)",
+                parent.display()
+            ));
         }
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create {}: {e}", parent.display()))?;
+    }
+    let mut contents = output.to_string();
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    std::fs::write(path, contents).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+/// Width of stdout when it is a terminal.
+fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| w as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// A fresh directory under the system temp dir, removed up front.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ma-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn write_output_fails_on_a_missing_directory_without_create_dirs() {
+        let dir = scratch("missing-dir");
+        let path = dir.join("out.txt");
+        let error = write_output(&path, "x", false, false).unwrap_err();
+        let expected =
+            format!("directory {} does not exist (use --create-dirs to create it)", dir.display());
+        assert_eq!(error, expected);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn write_output_creates_missing_directories_with_create_dirs() {
+        let dir = scratch("create-dirs");
+        let path = dir.join("nested").join("out.txt");
+        write_output(&path, "x", false, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detect_hint_points_header_less_snippets_to_type_detect() {
+        let options = ma::RenderOptions::default();
+        let hint = detect_hint("    A->>B: hi\n", &options);
+        let expected = " (no header line; use --type detect to read it as `sequenceDiagram`)";
+        assert_eq!(hint.as_deref(), Some(expected));
+
+        // Nothing to point to for a typo, an ambiguous snippet or a chosen type
+        assert_eq!(detect_hint("pie\n", &options), None);
+        assert_eq!(detect_hint("A\n", &options), None);
+        let sequence = ma::RenderOptions { diagram_type: ma::DiagramType::Sequence, ..options };
+        assert_eq!(detect_hint("    A->>B: hi\n", &sequence), None);
     }
 }
@@ -0,0 +1,148 @@
+/// Replace every fenced ```` ```mermaid ```` block in a Markdown document with
+/// a plain fence holding `render`'s output for the block's source.
+///
+/// Both backtick and tilde fences are recognised; a block closes at the first
+/// fence of the same character that is at least as long as the opener.
+/// A block that fails to render or is never closed is kept as written, and
+/// its error is returned with the 1-based line of its opening fence, so one
+/// bad block doesn't cost the rest of the document.
+pub fn replace_mermaid_fences<F>(input: &str, mut render: F) -> (String, Vec<(usize, String)>)
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    let mut out = String::with_capacity(input.len());
+    let mut errors = Vec::new();
+    let mut lines = input.split_inclusive('\n').enumerate();
+
+    while let Some((idx, line)) = lines.next() {
+        let Some((indent, fence, info)) = opening_fence(line) else {
+            out.push_str(line);
+            continue;
+        };
+        if info != "mermaid" {
+            // Copy non-mermaid fences verbatim so their contents aren't scanned
+            out.push_str(line);
+            for (_, inner) in lines.by_ref() {
+                out.push_str(inner);
+                if is_closing_fence(inner, &fence) {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        let mut source = String::new();
+        let mut closing = None;
+        for (_, inner) in lines.by_ref() {
+            if is_closing_fence(inner, &fence) {
+                closing = Some(inner);
+                break;
+            }
+            source.push_str(inner);
+        }
+        let rendered = match closing {
+            Some(_) => render(&source),
+            None => Err("not closed".to_string()),
+        };
+        let rendered = match rendered {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                errors.push((idx + 1, e));
+                out.push_str(line);
+                out.push_str(&source);
+                out.push_str(closing.unwrap_or_default());
+                continue;
+            }
+        };
+        out.push_str(indent);
+        out.push_str(&fence);
+        out.push('\n');
+        for rendered_line in rendered.lines() {
+            out.push_str(indent);
+            out.push_str(rendered_line);
+            out.push('\n');
+        }
+        out.push_str(indent);
+        out.push_str(&fence);
+        out.push('\n');
+    }
+
+    (out, errors)
+}
+
+/// Indentation, fence marker and info string of an opening code fence.
+fn opening_fence(line: &str) -> Option<(&str, String, &str)> {
+    let body = line.trim_start_matches(' ');
+    let indent = &line[..line.len() - body.len()];
+    if indent.len() > 3 {
+        return None;
+    }
+    let marker = body.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = body.chars().take_while(|c| *c == marker).count();
+    if len < 3 {
+        return None;
+    }
+    let info = body[len..].trim();
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    Some((indent, marker.to_string().repeat(len), info))
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let body = line.trim();
+    let marker = fence.chars().next().unwrap_or('`');
+    body.len() >= fence.len() && body.chars().all(|c| c == marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn upper(src: &str) -> Result<String, String> {
+        Ok(src.trim().to_uppercase())
+    }
+
+    #[test]
+    fn replaces_mermaid_fence_only() {
+        let input = "# Title\n\n```mermaid\ngraph LR\n```\n\n```rust\nlet x = 1;\n```\n";
+        let (output, errors) = replace_mermaid_fences(input, upper);
+        assert!(errors.is_empty());
+        assert_eq!(
+            output,
+            "# Title\n\n```\nGRAPH LR\n```\n\n```rust\nlet x = 1;\n```\n"
+        );
+    }
+
+    #[test]
+    fn mermaid_inside_other_fence_is_left_alone() {
+        let input = "````md\n```mermaid\ngraph LR\n```\n````\n";
+        let (output, _) = replace_mermaid_fences(input, upper);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn tilde_fence_and_indent_are_kept() {
+        let input = "  ~~~mermaid\n  a\n  ~~~\n";
+        let (output, _) = replace_mermaid_fences(input, upper);
+        assert_eq!(output, "  ~~~\n  A\n  ~~~\n");
+    }
+
+    #[test]
+    fn failed_block_is_kept_and_the_rest_rendered() {
+        let input = "text\n```mermaid\nbad\n```\n\n```mermaid\ngood\n```\n";
+        let render = |src: &str| if src == "bad\n" { Err("boom".to_string()) } else { upper(src) };
+        let (output, errors) = replace_mermaid_fences(input, render);
+        assert_eq!(output, "text\n```mermaid\nbad\n```\n\n```\nGOOD\n```\n");
+        assert_eq!(errors, [(2, "boom".to_string())]);
+    }
+
+    #[test]
+    fn unterminated_block_is_kept_as_written() {
+        let input = "```mermaid\ngraph LR\n";
+        let (output, errors) = replace_mermaid_fences(input, upper);
+        assert_eq!(output, input);
+        assert_eq!(errors, [(1, "not closed".to_string())]);
+    }
+}
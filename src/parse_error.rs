@@ -0,0 +1,235 @@
+use crate::display_width::display_width;
+
+const CONTEXT_MAX_CHARS: usize = 40;
+
+/// 1-based line and column of `remaining` within `source`.
+///
+/// `remaining` must be a suffix of `source` (what a parser had left when it
+/// stopped). Leading indentation is skipped so the position points at the
+/// first character of the offending statement.
+pub fn position(source: &str, remaining: &str) -> (usize, usize) {
+    let offset = source.len() - remaining.len();
+    let rest = &source[offset..];
+    let indent = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    let offset = offset + indent;
+
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = source[..offset].matches('\n').count() + 1;
+    let col = source[line_start..offset].chars().count() + 1;
+    (line, col)
+}
+
+/// Format a syntax error for the point where parsing stopped, with the source
+/// line and a caret under the offending column.
+pub fn syntax_error(kind: Option<&str>, source: &str, remaining: &str) -> String {
+    let (line, col) = position(source, remaining);
+    let source_line = source.lines().nth(line - 1).unwrap_or("").trim_end_matches('\r');
+
+    let context = source_line.trim();
+    let context_display = shorten(context);
+
+    let what = match kind {
+        Some(kind) => format!("syntax error in {kind}"),
+        None => "syntax error".to_string(),
+    };
+    let message = if context.is_empty() {
+        format!("{what} at line {line}:{col}: unexpected end of input")
+    } else {
+        format!("{what} at line {line}:{col}: unexpected `{context_display}`")
+    };
+    format!("{message}\n{}", snippet(source, line, col))
+}
+
+/// The error for text left after a complete statement, such as the `junk`
+/// in `deactivate Bob junk`. `None` unless parsing stopped partway through
+/// a line.
+pub fn trailing_text(kind: Option<&str>, source: &str, remaining: &str) -> Option<String> {
+    let rest = remaining.trim_start_matches([' ', '\t']);
+    let offset = source.len() - rest.len();
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let statement = source[line_start..offset].trim();
+    let text = rest.lines().next().unwrap_or("").trim();
+    if statement.is_empty() || text.is_empty() {
+        return None;
+    }
+
+    let (line, col) = position(source, remaining);
+    let what = match kind {
+        Some(kind) => format!("syntax error in {kind}"),
+        None => "syntax error".to_string(),
+    };
+    Some(format!(
+        "{what} at line {line}:{col}: unexpected trailing text `{}` after `{}`\n{}",
+        shorten(text),
+        shorten(statement),
+        snippet(source, line, col)
+    ))
+}
+
+/// `text`, cut to [`CONTEXT_MAX_CHARS`] characters with `...` if longer.
+fn shorten(text: &str) -> String {
+    if text.chars().count() > CONTEXT_MAX_CHARS {
+        let head: String = text.chars().take(CONTEXT_MAX_CHARS).collect();
+        format!("{head}...")
+    } else {
+        text.to_string()
+    }
+}
+
+/// [`syntax_error`] saying what the parser was in the middle of, such as
+/// "parsing attributes of CUSTOMER".
+pub fn syntax_error_while(
+    kind: Option<&str>,
+    source: &str,
+    remaining: &str,
+    doing: &str,
+) -> String {
+    let error = syntax_error(kind, source, remaining);
+    let (message, snippet) = error.split_once('\n').unwrap_or((&error, ""));
+    format!("{message} while {doing}\n{snippet}")
+}
+
+/// The error for a block opened by one of `keywords` and never closed by
+/// `end`, pointing at the start of the innermost such block. `None` unless
+/// some block is unclosed and parsing stopped at the end of the input or on
+/// the line an unclosed block starts. With `ignore_case`, `Loop` and `END`
+/// count as keywords too.
+pub fn unclosed_block(
+    kind: Option<&str>,
+    keywords: &[&str],
+    ignore_case: bool,
+    source: &str,
+    remaining: &str,
+) -> Option<String> {
+    let is = |word: &str, keyword: &str| {
+        if ignore_case { word.eq_ignore_ascii_case(keyword) } else { word == keyword }
+    };
+    let mut open: Vec<(&str, &str)> = Vec::new();
+    let mut offset = 0;
+    for text in source.split_inclusive('\n') {
+        match text.split_whitespace().next() {
+            Some(word) if is(word, "end") => {
+                open.pop();
+            }
+            Some(word) if keywords.iter().any(|k| is(word, k)) => {
+                open.push((word, &source[offset..]))
+            }
+            _ => {}
+        }
+        offset += text.len();
+    }
+
+    let (stopped, _) = position(source, remaining);
+    let stopped_at_block = open.iter().any(|&(_, start)| position(source, start).0 == stopped);
+    if !remaining.trim().is_empty() && !stopped_at_block {
+        return None;
+    }
+    let &(keyword, start) = open.last()?;
+    let (line, col) = position(source, start);
+    let what = match kind {
+        Some(kind) => format!("unclosed `{keyword}` in {kind}"),
+        None => format!("unclosed `{keyword}`"),
+    };
+    Some(format!(
+        "{what} started at line {line}:{col}: no matching `end`\n{}",
+        snippet(source, line, col)
+    ))
+}
+
+/// Source line `line` with a caret under column `col`.
+fn snippet(source: &str, line: usize, col: usize) -> String {
+    let source_line = source.lines().nth(line - 1).unwrap_or("").trim_end_matches('\r');
+    let prefix: String = source_line.chars().take(col - 1).collect();
+    let gutter = " ".repeat(line.to_string().len());
+    let caret_pad = " ".repeat(display_width(&prefix));
+    format!("{gutter} |\n{line} | {source_line}\n{gutter} | {caret_pad}^")
+}
+
+/// The line number of an error message built by [`syntax_error`].
+pub fn error_line(message: &str) -> Option<usize> {
+    const MARKER: &str = " at line ";
+    let rest = &message[message.find(MARKER)? + MARKER.len()..];
+    rest[..rest.find(':')?].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_first_line() {
+        let src = "abc\ndef\n";
+        assert_eq!(position(src, src), (1, 1));
+    }
+
+    #[test]
+    fn position_skips_indentation() {
+        let src = "header\n    bad line\n";
+        let remaining = &src[7..];
+        assert_eq!(position(src, remaining), (2, 5));
+    }
+
+    #[test]
+    fn position_counts_chars_not_bytes() {
+        let src = "テスト x";
+        let remaining = &src["テスト ".len()..];
+        assert_eq!(position(src, remaining), (1, 5));
+    }
+
+    #[test]
+    fn syntax_error_has_caret_snippet() {
+        let src = "sequenceDiagram\n    ??? oops\n";
+        let err = syntax_error(None, src, &src[16..]);
+        assert_eq!(
+            err,
+            "syntax error at line 2:5: unexpected `??? oops`\n  |\n2 |     ??? oops\n  |     ^"
+        );
+    }
+
+    #[test]
+    fn syntax_error_truncates_long_non_ascii_context() {
+        let line = "あ".repeat(50);
+        let src = format!("graph TD\n{line}\n");
+        let err = syntax_error(Some("graph diagram"), &src, &src[9..]);
+        assert!(err.starts_with("syntax error in graph diagram at line 2:1:"), "{err}");
+        assert!(err.contains("..."), "{err}");
+    }
+
+    #[test]
+    fn trailing_text_names_the_statement_it_follows() {
+        let src = "sequenceDiagram\n    deactivate Bob extra junk\n";
+        let err = trailing_text(None, src, &src[34..]).unwrap();
+        assert_eq!(
+            err,
+            "syntax error at line 2:20: unexpected trailing text `extra junk` after \
+             `deactivate Bob`\n  |\n2 |     deactivate Bob extra junk\n  |                    ^"
+        );
+
+        // Stopped at the start of a line, or at its end
+        assert_eq!(trailing_text(None, src, &src[16..]), None);
+        assert_eq!(trailing_text(None, src, &src[src.len() - 1..]), None);
+    }
+
+    #[test]
+    fn unclosed_block_points_at_innermost_open_block() {
+        let src = "sequenceDiagram\n    loop a\n    alt b\n    end\n    opt c\n";
+        let err = unclosed_block(None, &["loop", "alt", "opt"], false, src, "").unwrap();
+        assert_eq!(
+            err,
+            "unclosed `opt` started at line 5:5: no matching `end`\n  |\n5 |     opt c\n  |     ^"
+        );
+        assert_eq!(error_line(&err), Some(5));
+
+        // Stopped somewhere else, or everything closed
+        let keywords = &["loop", "alt", "opt"];
+        assert_eq!(unclosed_block(None, keywords, false, src, &src[27..]), None);
+        assert_eq!(unclosed_block(None, &["alt"], false, src, ""), None);
+    }
+
+    #[test]
+    fn error_line_reads_syntax_errors() {
+        let src = "sequenceDiagram\n    ??? oops\n";
+        assert_eq!(error_line(&syntax_error(Some("kind"), src, &src[16..])), Some(2));
+        assert_eq!(error_line("no participants found"), None);
+    }
+}
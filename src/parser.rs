@@ -1,36 +1,82 @@
 use winnow::prelude::*;
-use winnow::ascii::{line_ending, space0, space1, till_line_ending};
-use winnow::combinator::{alt, opt, preceded, repeat};
+use winnow::ascii::{Caseless, line_ending, multispace0, space0, space1, till_line_ending};
+use winnow::combinator::{alt, cut_err, eof, opt, peek, preceded, repeat};
+use winnow::error::{ErrMode, StrContext};
 use winnow::token::take_while;
 
 use crate::ast::*;
-
-pub fn parse_diagram(input: &str) -> Result<Diagram, String> {
-    let mut input = input;
-    diagram(&mut input).map_err(|_| {
-        let line_num = input.lines().count().max(1);
-        let context = input.lines().next().unwrap_or("").trim();
-        let context_display = if context.len() > 40 {
-            format!("{}...", &context[..40])
-        } else {
-            context.to_string()
-        };
-        format!("syntax error at line {line_num}: unexpected `{context_display}`")
-    })
+use crate::parse_error::{syntax_error, trailing_text, unclosed_block};
+
+/// Keywords that open a block closed by `end`.
+const BLOCK_KEYWORDS: &[&str] =
+    &["loop", "alt", "opt", "break", "par", "par_over", "critical", "rect"];
+
+/// What [`line_end`] expected, marking its errors apart from others.
+const LINE_END: StrContext = StrContext::Label("end of line");
+
+pub fn parse_diagram(source: &str) -> Result<Diagram, String> {
+    let mut input = source;
+    let result = diagram(&mut input);
+    let at_line_end =
+        matches!(&result, Err(ErrMode::Cut(e)) if e.context().any(|c| *c == LINE_END));
+    match result {
+        Ok(d) if input.trim().is_empty() => Ok(Diagram { source_lines: source_lines(source), ..d }),
+        _ => Err(at_line_end
+            .then(|| trailing_text(None, source, input))
+            .flatten()
+            .or_else(|| unclosed_block(None, BLOCK_KEYWORDS, true, source, input))
+            .unwrap_or_else(|| syntax_error(None, source, input))),
+    }
 }
 
-fn diagram(input: &mut &str) -> winnow::Result<Diagram> {
-    space0.parse_next(input)?;
+fn diagram(input: &mut &str) -> winnow::ModalResult<Diagram> {
+    multispace0.parse_next(input)?;
     "sequenceDiagram".parse_next(input)?;
     opt(line_ending).parse_next(input)?;
 
-    let statements: Vec<Option<Statement>> = repeat(0.., statement).parse_next(input)?;
+    let mut title = None;
+    let statements: Vec<Option<Statement>> = repeat(
+        0..,
+        alt((
+            title_line.map(|text| {
+                title = Some(text);
+                None
+            }),
+            statement,
+        )),
+    )
+    .parse_next(input)?;
     let statements = statements.into_iter().flatten().collect();
 
-    Ok(Diagram { statements })
+    Ok(Diagram { title, statements, source_lines: SourceLines::default() })
+}
+
+/// `title Checkout` or `title: Checkout`, outside any block.
+fn title_line(input: &mut &str) -> winnow::ModalResult<String> {
+    (space0, Caseless("title"), alt(((space0, ":").void(), space1.void()))).parse_next(input)?;
+    let text = till_line_ending.parse_next(input)?;
+    opt(line_ending).parse_next(input)?;
+    Ok(text.trim().to_string())
 }
 
-fn statement(input: &mut &str) -> winnow::Result<Option<Statement>> {
+/// Where each statement, divider and `end` of `source` is. Each takes a line
+/// of its own, and every other line is blank, a comment, the header or the
+/// title.
+fn source_lines(source: &str) -> SourceLines {
+    let mut header = true;
+    let lines = source.lines().enumerate().filter_map(|(i, line)| {
+        let mut text = line.trim();
+        if header && !text.is_empty() {
+            header = false;
+            text = text.trim_start_matches("sequenceDiagram").trim();
+        }
+        let skipped = text.is_empty() || text.starts_with("%%") || title_line.parse(text).is_ok();
+        (!skipped).then_some(i + 1)
+    });
+    SourceLines(lines.collect())
+}
+
+fn statement(input: &mut &str) -> winnow::ModalResult<Option<Statement>> {
     space0.parse_next(input)?;
 
     if input.is_empty() {
@@ -61,121 +107,160 @@ fn statement(input: &mut &str) -> winnow::Result<Option<Statement>> {
     Ok(result)
 }
 
-fn comment_line(input: &mut &str) -> winnow::Result<()> {
+fn comment_line(input: &mut &str) -> winnow::ModalResult<()> {
     "%%".parse_next(input)?;
     till_line_ending.parse_next(input)?;
     opt(line_ending).parse_next(input)?;
     Ok(())
 }
 
-fn blank_line(input: &mut &str) -> winnow::Result<()> {
+fn blank_line(input: &mut &str) -> winnow::ModalResult<()> {
     line_ending.void().parse_next(input)
 }
 
-fn activate_stmt(input: &mut &str) -> winnow::Result<String> {
-    "activate".parse_next(input)?;
+/// The end of a statement that takes nothing more on its line, such as
+/// `end` or `deactivate Bob`. Leftover text is an error rather than a
+/// backtrack, so it is reported as trailing text instead of being tried as
+/// the next statement.
+fn line_end(input: &mut &str) -> winnow::ModalResult<()> {
+    space0.parse_next(input)?;
+    cut_err(alt((line_ending, eof)).void()).context(LINE_END).parse_next(input)
+}
+
+/// Whether `input` starts with the keyword `word` on its own, in any case,
+/// so that a participant such as `endpoint` isn't read as `end`.
+fn at_keyword(input: &str, word: &str) -> bool {
+    input.get(..word.len()).is_some_and(|start| start.eq_ignore_ascii_case(word))
+        && !input[word.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+}
+
+fn activate_stmt(input: &mut &str) -> winnow::ModalResult<String> {
+    Caseless("activate").parse_next(input)?;
     space1.parse_next(input)?;
-    let id = identifier.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+    let id = participant_ref.parse_next(input)?;
+    line_end.parse_next(input)?;
     Ok(id.to_string())
 }
 
-fn deactivate_stmt(input: &mut &str) -> winnow::Result<String> {
-    "deactivate".parse_next(input)?;
+fn deactivate_stmt(input: &mut &str) -> winnow::ModalResult<String> {
+    Caseless("deactivate").parse_next(input)?;
     space1.parse_next(input)?;
-    let id = identifier.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+    let id = participant_ref.parse_next(input)?;
+    line_end.parse_next(input)?;
     Ok(id.to_string())
 }
 
-fn create_stmt(input: &mut &str) -> winnow::Result<ParticipantDecl> {
-    "create".parse_next(input)?;
+fn create_stmt(input: &mut &str) -> winnow::ModalResult<ParticipantDecl> {
+    Caseless("create").parse_next(input)?;
     space1.parse_next(input)?;
     participant_decl(input)
 }
 
-fn destroy_stmt(input: &mut &str) -> winnow::Result<String> {
-    "destroy".parse_next(input)?;
+fn destroy_stmt(input: &mut &str) -> winnow::ModalResult<String> {
+    Caseless("destroy").parse_next(input)?;
     space1.parse_next(input)?;
-    let id = identifier.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+    let id = participant_ref.parse_next(input)?;
+    line_end.parse_next(input)?;
     Ok(id.to_string())
 }
 
-fn participant_decl(input: &mut &str) -> winnow::Result<ParticipantDecl> {
-    alt(("participant", "actor")).parse_next(input)?;
+fn participant_decl(input: &mut &str) -> winnow::ModalResult<ParticipantDecl> {
+    alt((Caseless("participant"), Caseless("actor"))).parse_next(input)?;
     space1.parse_next(input)?;
-    let id = identifier.parse_next(input)?;
-
-    let alias = opt(preceded((space1, "as", space1), till_line_ending)).parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+    let decl = alt((
+        // `"Display Name" as Id`, as in PlantUML
+        (
+            quoted,
+            space1,
+            Caseless("as"),
+            space1,
+            participant_ref,
+            space0,
+            peek(alt((line_ending, eof))),
+        )
+            .map(|(alias, _, _, _, id, _, _): (&str, _, _, _, &str, _, _)| ParticipantDecl {
+                id: id.to_string(),
+                alias: Some(alias.to_string()),
+            }),
+        (participant_ref, opt(preceded((space1, Caseless("as"), space1), till_line_ending))).map(
+            |(id, alias): (&str, Option<&str>)| ParticipantDecl {
+                id: id.to_string(),
+                alias: alias.map(|s| s.trim().to_string()),
+            },
+        ),
+    ))
+    .parse_next(input)?;
+    line_end.parse_next(input)?;
 
-    Ok(ParticipantDecl {
-        id: id.to_string(),
-        alias: alias.map(|s: &str| s.trim().to_string()),
-    })
+    Ok(decl)
 }
 
-fn loop_stmt(input: &mut &str) -> winnow::Result<LoopBlock> {
-    "loop".parse_next(input)?;
-    space1.parse_next(input)?;
-    let label = till_line_ending.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+fn loop_stmt(input: &mut &str) -> winnow::ModalResult<LoopBlock> {
+    Caseless("loop").parse_next(input)?;
+    let label = block_label.parse_next(input)?;
 
     let mut body = Vec::new();
     loop {
         space0.parse_next(input)?;
-        if input.starts_with("end") {
-            "end".parse_next(input)?;
-            opt(line_ending).parse_next(input)?;
+        if at_keyword(input, "end") {
+            Caseless("end").parse_next(input)?;
+            line_end.parse_next(input)?;
             break;
         }
         if input.is_empty() {
             return Err(winnow::error::ParserError::from_input(input));
         }
-        let stmt = statement.parse_next(input)?;
+        let stmt = cut_err(statement).parse_next(input)?;
         if let Some(s) = stmt {
             body.push(s);
         }
     }
 
     Ok(LoopBlock {
-        label: label.trim().to_string(),
+        label,
         body,
     })
 }
 
-fn alt_stmt(input: &mut &str) -> winnow::Result<AltBlock> {
-    "alt".parse_next(input)?;
+/// The text after a block keyword up to the end of the line, or nothing
+/// when the line ends with the keyword.
+fn block_label(input: &mut &str) -> winnow::ModalResult<String> {
+    let label = alt((preceded(space1, till_line_ending), peek(alt((line_ending, eof)))))
+        .parse_next(input)?;
+    opt(line_ending).parse_next(input)?;
+    Ok(label.trim().to_string())
+}
+
+fn alt_stmt(input: &mut &str) -> winnow::ModalResult<AltBlock> {
+    Caseless("alt").parse_next(input)?;
     block_with_divider(input, "else")
 }
 
-fn par_stmt(input: &mut &str) -> winnow::Result<AltBlock> {
-    "par".parse_next(input)?;
+/// `par`, or Mermaid's `par_over`, which reads the same here.
+fn par_stmt(input: &mut &str) -> winnow::ModalResult<AltBlock> {
+    alt((Caseless("par_over"), Caseless("par"))).parse_next(input)?;
     block_with_divider(input, "and")
 }
 
-fn critical_stmt(input: &mut &str) -> winnow::Result<AltBlock> {
-    "critical".parse_next(input)?;
+fn critical_stmt(input: &mut &str) -> winnow::ModalResult<AltBlock> {
+    Caseless("critical").parse_next(input)?;
     block_with_divider(input, "option")
 }
 
-fn block_with_divider(input: &mut &str, divider: &str) -> winnow::Result<AltBlock> {
-    space1.parse_next(input)?;
-    let label = till_line_ending.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+fn block_with_divider(input: &mut &str, divider: &str) -> winnow::ModalResult<AltBlock> {
+    let label = block_label.parse_next(input)?;
 
     let mut body = Vec::new();
     let mut else_branches = Vec::new();
 
     loop {
         space0.parse_next(input)?;
-        if input.starts_with("end") {
-            "end".parse_next(input)?;
-            opt(line_ending).parse_next(input)?;
+        if at_keyword(input, "end") {
+            Caseless("end").parse_next(input)?;
+            line_end.parse_next(input)?;
             break;
         }
-        if input.starts_with(divider) {
+        if at_keyword(input, divider) {
             input.next_slice(divider.len());
             let else_label = if input.starts_with([' ', '\t']) {
                 space1.parse_next(input)?;
@@ -183,20 +268,20 @@ fn block_with_divider(input: &mut &str, divider: &str) -> winnow::Result<AltBloc
                 opt(line_ending).parse_next(input)?;
                 l.trim().to_string()
             } else {
-                opt(line_ending).parse_next(input)?;
+                line_end.parse_next(input)?;
                 String::new()
             };
 
             let mut else_body = Vec::new();
             loop {
                 space0.parse_next(input)?;
-                if input.starts_with("end") || input.starts_with(divider) {
+                if at_keyword(input, "end") || at_keyword(input, divider) {
                     break;
                 }
                 if input.is_empty() {
                     return Err(winnow::error::ParserError::from_input(input));
                 }
-                let stmt = statement.parse_next(input)?;
+                let stmt = cut_err(statement).parse_next(input)?;
                 if let Some(s) = stmt {
                     else_body.push(s);
                 }
@@ -210,137 +295,135 @@ fn block_with_divider(input: &mut &str, divider: &str) -> winnow::Result<AltBloc
         if input.is_empty() {
             return Err(winnow::error::ParserError::from_input(input));
         }
-        let stmt = statement.parse_next(input)?;
+        let stmt = cut_err(statement).parse_next(input)?;
         if let Some(s) = stmt {
             body.push(s);
         }
     }
 
     Ok(AltBlock {
-        label: label.trim().to_string(),
+        label,
         body,
         else_branches,
     })
 }
 
-fn opt_stmt(input: &mut &str) -> winnow::Result<LoopBlock> {
-    "opt".parse_next(input)?;
-    space1.parse_next(input)?;
-    let label = till_line_ending.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+fn opt_stmt(input: &mut &str) -> winnow::ModalResult<LoopBlock> {
+    Caseless("opt").parse_next(input)?;
+    let label = block_label.parse_next(input)?;
 
     let mut body = Vec::new();
     loop {
         space0.parse_next(input)?;
-        if input.starts_with("end") {
-            "end".parse_next(input)?;
-            opt(line_ending).parse_next(input)?;
+        if at_keyword(input, "end") {
+            Caseless("end").parse_next(input)?;
+            line_end.parse_next(input)?;
             break;
         }
         if input.is_empty() {
             return Err(winnow::error::ParserError::from_input(input));
         }
-        let stmt = statement.parse_next(input)?;
+        let stmt = cut_err(statement).parse_next(input)?;
         if let Some(s) = stmt {
             body.push(s);
         }
     }
 
     Ok(LoopBlock {
-        label: label.trim().to_string(),
+        label,
         body,
     })
 }
 
-fn break_stmt(input: &mut &str) -> winnow::Result<LoopBlock> {
-    "break".parse_next(input)?;
-    space1.parse_next(input)?;
-    let label = till_line_ending.parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+fn break_stmt(input: &mut &str) -> winnow::ModalResult<LoopBlock> {
+    Caseless("break").parse_next(input)?;
+    let label = block_label.parse_next(input)?;
 
     let mut body = Vec::new();
     loop {
         space0.parse_next(input)?;
-        if input.starts_with("end") {
-            "end".parse_next(input)?;
-            opt(line_ending).parse_next(input)?;
+        if at_keyword(input, "end") {
+            Caseless("end").parse_next(input)?;
+            line_end.parse_next(input)?;
             break;
         }
         if input.is_empty() {
             return Err(winnow::error::ParserError::from_input(input));
         }
-        let stmt = statement.parse_next(input)?;
+        let stmt = cut_err(statement).parse_next(input)?;
         if let Some(s) = stmt {
             body.push(s);
         }
     }
 
     Ok(LoopBlock {
-        label: label.trim().to_string(),
+        label,
         body,
     })
 }
 
-fn rect_stmt(input: &mut &str) -> winnow::Result<LoopBlock> {
-    "rect".parse_next(input)?;
-    let label = opt(preceded(space1, till_line_ending)).parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+fn rect_stmt(input: &mut &str) -> winnow::ModalResult<LoopBlock> {
+    Caseless("rect").parse_next(input)?;
+    let label = block_label.parse_next(input)?;
 
     let mut body = Vec::new();
     loop {
         space0.parse_next(input)?;
-        if input.starts_with("end") {
-            "end".parse_next(input)?;
-            opt(line_ending).parse_next(input)?;
+        if at_keyword(input, "end") {
+            Caseless("end").parse_next(input)?;
+            line_end.parse_next(input)?;
             break;
         }
         if input.is_empty() {
             return Err(winnow::error::ParserError::from_input(input));
         }
-        let stmt = statement.parse_next(input)?;
+        let stmt = cut_err(statement).parse_next(input)?;
         if let Some(s) = stmt {
             body.push(s);
         }
     }
 
     Ok(LoopBlock {
-        label: label.map(|s| s.trim().to_string()).unwrap_or_default(),
+        label,
         body,
     })
 }
 
-fn autonumber_stmt(input: &mut &str) -> winnow::Result<()> {
-    "autonumber".parse_next(input)?;
-    opt(line_ending).parse_next(input)?;
+fn autonumber_stmt(input: &mut &str) -> winnow::ModalResult<()> {
+    Caseless("autonumber").parse_next(input)?;
+    line_end.parse_next(input)?;
     Ok(())
 }
 
-fn note_stmt(input: &mut &str) -> winnow::Result<Note> {
-    "Note".parse_next(input)?;
+fn note_stmt(input: &mut &str) -> winnow::ModalResult<Note> {
+    Caseless("note").parse_next(input)?;
     space1.parse_next(input)?;
 
     let placement = alt((
-        ("right of", space1, identifier).map(|(_, _, id): (&str, &str, &str)| {
+        (Caseless("right of"), space1, participant_ref).map(|(_, _, id): (&str, &str, &str)| {
             NotePlacement::RightOf(id.to_string())
         }),
-        ("left of", space1, identifier).map(|(_, _, id): (&str, &str, &str)| {
+        (Caseless("left of"), space1, participant_ref).map(|(_, _, id): (&str, &str, &str)| {
             NotePlacement::LeftOf(id.to_string())
         }),
-        ("over", space1, identifier, ",", space0, identifier).map(
+        (Caseless("over"), space1, participant_ref, ",", space0, participant_ref).map(
             |(_, _, a, _, _, b): (&str, &str, &str, &str, &str, &str)| {
                 NotePlacement::OverTwo(a.to_string(), b.to_string())
             },
         ),
-        ("over", space1, identifier).map(|(_, _, id): (&str, &str, &str)| {
+        (Caseless("over"), space1, participant_ref).map(|(_, _, id): (&str, &str, &str)| {
             NotePlacement::Over(id.to_string())
         }),
     ))
     .parse_next(input)?;
 
     space0.parse_next(input)?;
-    ":".parse_next(input)?;
-    space0.parse_next(input)?;
-    let text = till_line_ending.parse_next(input)?;
+    // The text, and even the colon before it, may be left out
+    let text = alt((
+        preceded(":", till_line_ending),
+        peek(alt((line_ending, eof))).value(""),
+    ))
+    .parse_next(input)?;
     opt(line_ending).parse_next(input)?;
 
     Ok(Note {
@@ -349,18 +432,39 @@ fn note_stmt(input: &mut &str) -> winnow::Result<Note> {
     })
 }
 
-fn message(input: &mut &str) -> winnow::Result<Message> {
-    let from = identifier.parse_next(input)?;
+fn message(input: &mut &str) -> winnow::ModalResult<Message> {
+    if let Some(from) = opt(quoted).parse_next(input)? {
+        return message_tail(from, input);
+    }
+    // Ids can contain `-`, so the arrow may start inside the longest name:
+    // try each `-` in turn, then the end of the name.
+    let start = *input;
+    let word = name.parse_next(input)?;
+    let splits = word.match_indices('-').map(|(i, _)| i).chain([word.len()]);
+    for split in splits {
+        *input = &start[split..];
+        if let Ok(m) = message_tail(&word[..split], input) {
+            return Ok(m);
+        }
+    }
+    Err(winnow::error::ParserError::from_input(input))
+}
+
+/// The arrow, target and optional text of a message from `from`.
+fn message_tail(from: &str, input: &mut &str) -> winnow::ModalResult<Message> {
     space0.parse_next(input)?;
     let arr = arrow.parse_next(input)?;
 
     let modifier = opt(alt(("+".value('+'), "-".value('-')))).parse_next(input)?;
     space0.parse_next(input)?;
-    let to = identifier.parse_next(input)?;
+    let to = participant_ref.parse_next(input)?;
     space0.parse_next(input)?;
-    ":".parse_next(input)?;
-    space0.parse_next(input)?;
-    let text = till_line_ending.parse_next(input)?;
+    // The text, and even the colon before it, may be left out
+    let text = alt((
+        preceded(":", till_line_ending),
+        peek(alt((line_ending, eof))).value(""),
+    ))
+    .parse_next(input)?;
     opt(line_ending).parse_next(input)?;
 
     Ok(Message {
@@ -373,7 +477,7 @@ fn message(input: &mut &str) -> winnow::Result<Message> {
     })
 }
 
-fn arrow(input: &mut &str) -> winnow::Result<Arrow> {
+fn arrow(input: &mut &str) -> winnow::ModalResult<Arrow> {
     let line_style = alt((
         "--".value(LineStyle::Dotted),
         "-".value(LineStyle::Solid),
@@ -391,10 +495,29 @@ fn arrow(input: &mut &str) -> winnow::Result<Arrow> {
     Ok(Arrow { line_style, head })
 }
 
-fn identifier<'s>(input: &mut &'s str) -> winnow::Result<&'s str> {
+fn identifier<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
     take_while(1.., |c: char| c.is_alphanumeric() || c == '_').parse_next(input)
 }
 
+/// An identifier that may continue with `-` and `.`, as in `auth-service`
+/// or `svc.api`.
+fn name<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
+    (identifier, take_while(0.., |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+        .take()
+        .parse_next(input)
+}
+
+/// A participant id, either bare or quoted to allow spaces.
+fn participant_ref<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
+    alt((quoted, name)).parse_next(input)
+}
+
+fn quoted<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
+    ("\"", take_while(1.., |c| c != '"' && c != '\n'), "\"")
+        .map(|(_, s, _)| s)
+        .parse_next(input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -423,6 +546,13 @@ mod tests {
         assert_eq!(input, " rest");
     }
 
+    #[test]
+    fn parse_name_with_hyphens_and_dots() {
+        let mut input = "svc.api-v2: x";
+        assert_eq!(name(&mut input).unwrap(), "svc.api-v2");
+        assert_eq!(input, ": x");
+    }
+
     // --- arrow ---
 
     #[test]
@@ -513,6 +643,26 @@ mod tests {
         assert_eq!(msg.text, "Hi there!");
     }
 
+    #[test]
+    fn parse_message_with_hyphenated_and_dotted_ids() {
+        let mut input = "auth-service-->>svc.api: token";
+        let msg = message(&mut input).unwrap();
+        assert_eq!(msg.from, "auth-service");
+        assert_eq!(msg.to, "svc.api");
+        assert_eq!(msg.arrow.line_style, LineStyle::Dotted);
+
+        let mut input = "auth-xray->>-db-1: q";
+        let msg = message(&mut input).unwrap();
+        assert_eq!(msg.from, "auth-xray");
+        assert_eq!(msg.to, "db-1");
+        assert!(msg.deactivate_source);
+
+        let mut input = "A-xB: lost";
+        let msg = message(&mut input).unwrap();
+        assert_eq!((msg.from.as_str(), msg.to.as_str()), ("A", "B"));
+        assert_eq!(msg.arrow.head, ArrowHead::Cross);
+    }
+
     #[test]
     fn parse_message_with_spaces_around_colon() {
         let mut input = "Alice ->> Bob : Hello World";
@@ -522,6 +672,18 @@ mod tests {
         assert_eq!(msg.text, "Hello World");
     }
 
+    #[test]
+    fn parse_message_without_text() {
+        for line in ["A->>B:", "A->>B:   ", "A ->> B", "A->>+B\n"] {
+            let mut input = line;
+            let msg = message(&mut input).unwrap();
+            assert_eq!((msg.from.as_str(), msg.to.as_str(), msg.text.as_str()), ("A", "B", ""));
+        }
+
+        // Without a colon, nothing else may follow the target
+        assert!(parse_diagram("sequenceDiagram\n    A->>B hi\n").is_err());
+    }
+
     // --- participant_decl ---
 
     #[test]
@@ -540,6 +702,45 @@ mod tests {
         assert_eq!(p.alias, Some("Alice".to_string()));
     }
 
+    #[test]
+    fn parse_participant_with_quoted_names() {
+        let mut input = "participant \"Payment Service\" as PS";
+        let p = participant_decl(&mut input).unwrap();
+        assert_eq!(p.id, "PS");
+        assert_eq!(p.alias, Some("Payment Service".to_string()));
+
+        let mut input = "actor \"Order DB\"";
+        let p = participant_decl(&mut input).unwrap();
+        assert_eq!(p.id, "Order DB");
+        assert_eq!(p.alias, None);
+
+        let mut input = "participant \"Order DB\" as the orders";
+        let p = participant_decl(&mut input).unwrap();
+        assert_eq!(p.id, "Order DB");
+        assert_eq!(p.alias, Some("the orders".to_string()));
+    }
+
+    #[test]
+    fn parse_quoted_endpoints() {
+        let input = "sequenceDiagram\n    PS->>\"Order DB\": save\n    Note over \"Order DB\",PS: ok\n    activate \"Order DB\"\n";
+        let diagram = parse_diagram(input).unwrap();
+        match &diagram.statements[0] {
+            Statement::Message(m) => {
+                assert_eq!(m.from, "PS");
+                assert_eq!(m.to, "Order DB");
+            }
+            _ => panic!("expected Message"),
+        }
+        match &diagram.statements[1] {
+            Statement::Note(n) => assert_eq!(
+                n.placement,
+                NotePlacement::OverTwo("Order DB".to_string(), "PS".to_string())
+            ),
+            _ => panic!("expected Note"),
+        }
+        assert_eq!(diagram.statements[2], Statement::Activate("Order DB".to_string()));
+    }
+
     // --- diagram ---
 
     #[test]
@@ -589,6 +790,80 @@ sequenceDiagram
         assert_eq!(diagram.statements.len(), 2);
     }
 
+    #[test]
+    fn parse_title_in_either_form() {
+        let input = "sequenceDiagram\n    title Checkout flow\n    A->>B: hi\n";
+        let diagram = parse_diagram(input).unwrap();
+        assert_eq!(diagram.title.as_deref(), Some("Checkout flow"));
+        assert_eq!(diagram.statements.len(), 1);
+        assert_eq!(diagram.source_lines.0, [3]);
+
+        let diagram = parse_diagram("sequenceDiagram\n    title: Checkout\n").unwrap();
+        assert_eq!(diagram.title.as_deref(), Some("Checkout"));
+        let diagram = parse_diagram("sequenceDiagram\n    title->>B: hi\n").unwrap();
+        assert_eq!(diagram.title, None);
+    }
+
+    #[test]
+    fn parse_error_reports_line_and_column() {
+        let input = "sequenceDiagram\n    Alice->>Bob: Hello\n    ??? oops\n";
+        let err = parse_diagram(input).unwrap_err();
+        assert!(err.starts_with("syntax error at line 3:5:"), "got: {err}");
+        assert!(err.contains("3 |     ??? oops"), "snippet: {err}");
+    }
+
+    #[test]
+    fn parse_error_inside_block_points_at_offending_line() {
+        let input = "sequenceDiagram\n    loop Check\n        A->>B: Ping\n        ???\n    end\n";
+        let err = parse_diagram(input).unwrap_err();
+        assert!(err.starts_with("syntax error at line 4:"), "got: {err}");
+    }
+
+    #[test]
+    fn parse_error_names_trailing_text_after_keyword_statements() {
+        let first_line = |body: &str| {
+            let err = parse_diagram(&format!("sequenceDiagram\n{body}")).unwrap_err();
+            err.lines().next().unwrap().to_string()
+        };
+        assert_eq!(
+            first_line("    deactivate Bob extra junk\n"),
+            "syntax error at line 2:20: unexpected trailing text `extra junk` after `deactivate Bob`"
+        );
+        assert_eq!(
+            first_line("    loop Poll\n        A->>B: hi\n    end loop\n"),
+            "syntax error at line 4:9: unexpected trailing text `loop` after `end`"
+        );
+        assert!(first_line("    participant A B\n").ends_with("`B` after `participant A`"));
+        assert!(first_line("    autonumber on\n").ends_with("`on` after `autonumber`"));
+    }
+
+    #[test]
+    fn parse_keywords_only_as_whole_words() {
+        let input = "sequenceDiagram\n    par a\n        endpoint->>B: hi\n        andrew->>B: yo\n\
+                     \x20   and\n        B->>A: ok\n    end  \n";
+        let diagram = parse_diagram(input).unwrap();
+        let Statement::Par(block) = &diagram.statements[0] else { panic!("{diagram:?}") };
+        assert_eq!(block.body.len(), 2);
+        assert_eq!(block.else_branches.len(), 1);
+    }
+
+    #[test]
+    fn parse_keywords_in_any_case() {
+        let input = "sequenceDiagram\n    Title: Checkout\n    Participant A AS Alice\n    \
+                     Loop every minute\n        A->>B: hi\n        NOTE Right Of B: got it\n    \
+                     END\n    Alt ok\n        Activate B\n    Else\n        note OVER A,B: no\n    \
+                     end\n";
+        let lower = "sequenceDiagram\n    title: Checkout\n    participant A as Alice\n    \
+                     loop every minute\n        A->>B: hi\n        Note right of B: got it\n    \
+                     end\n    alt ok\n        activate B\n    else\n        Note over A,B: no\n    \
+                     end\n";
+        assert_eq!(parse_diagram(input), parse_diagram(lower));
+        assert!(parse_diagram(input).is_ok());
+
+        let err = parse_diagram("sequenceDiagram\n    Loop every\n        A->>B: y\n").unwrap_err();
+        assert!(err.starts_with("unclosed `Loop` started at line 2:5:"), "{err}");
+    }
+
     // --- activate/deactivate ---
 
     #[test]
@@ -748,6 +1023,62 @@ sequenceDiagram
         }
     }
 
+    #[test]
+    fn parse_blocks_without_labels() {
+        let input = "\
+sequenceDiagram
+    loop
+        A->>B: Ping
+    end
+    critical
+        A->>B: Lock
+    option
+        A->>B: Retry
+    end
+    opt
+    end
+    par_over
+        A->>B: One
+    and
+        A->>C: Two
+    and \n        A->>D: Three
+    end
+";
+        let diagram = parse_diagram(input).unwrap();
+        match &diagram.statements[..] {
+            [
+                Statement::Loop(lb),
+                Statement::Critical(ab),
+                Statement::Opt(ob),
+                Statement::Par(pb),
+            ] => {
+                assert_eq!(lb.label, "");
+                assert_eq!(lb.body.len(), 1);
+                assert_eq!(ab.label, "");
+                assert_eq!(ab.else_branches[0].label, "");
+                assert_eq!(ob.label, "");
+                assert_eq!(pb.label, "");
+                let labels: Vec<_> = pb.else_branches.iter().map(|b| b.label.as_str()).collect();
+                assert_eq!(labels, ["", ""]);
+                assert_eq!(pb.else_branches[1].body.len(), 1);
+            }
+            other => panic!("expected loop, critical, opt and par, got {other:?}"),
+        }
+
+        // The keyword still has to stand on its own
+        assert!(parse_diagram("sequenceDiagram\n    loopy\n    end\n").is_err());
+    }
+
+    #[test]
+    fn parse_unclosed_block_reports_where_it_starts() {
+        let input = "sequenceDiagram\n    loop every\n        alt x\n            A->>B: y\n    end\n";
+        let err = parse_diagram(input).unwrap_err();
+        assert!(err.starts_with("unclosed `loop` started at line 2:5:"), "{err}");
+
+        let err = parse_diagram(&input.replace("    end\n", "")).unwrap_err();
+        assert!(err.starts_with("unclosed `alt` started at line 3:9:"), "{err}");
+    }
+
     #[test]
     fn parse_diagram_with_note() {
         let input = "\
@@ -0,0 +1,485 @@
+//! A subset of PlantUML sequence diagrams, parsed into the sequence AST.
+//!
+//! Supported: participant declarations (`participant`, `actor`, `database`
+//! and the other participant keywords, with `as` aliases), messages
+//! (`->`, `-->`, `->>`, `->x` and their reversed `<-` forms, with `++`/`--`
+//! activation suffixes), single- and multi-line notes, `activate`,
+//! `deactivate`, `create`, `destroy`, `autonumber`, and the `alt`/`else`,
+//! `loop`, `opt`, `break`, `par`, `critical` and `group` blocks. Titles,
+//! dividers, delays, spacers, `skinparam` and comments are ignored.
+
+use winnow::prelude::*;
+use winnow::ascii::{Caseless, space0, space1, till_line_ending};
+use winnow::combinator::{alt, eof, opt, peek, preceded, terminated};
+use winnow::token::take_while;
+
+use crate::ast::*;
+use crate::parse_error::syntax_error;
+
+const KIND: &str = "PlantUML sequence diagram";
+
+pub fn parse_plantuml(source: &str) -> Result<Diagram, String> {
+    let mut stack: Vec<OpenBlock> = Vec::new();
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut lines = source_lines(source);
+    let mut last_offset = 0;
+
+    while let Some((offset, line)) = lines.next() {
+        last_offset = offset;
+        let error = || syntax_error(Some(KIND), source, &source[offset..]);
+        let mut input = line.trim();
+        if let Some(rest) = input.strip_prefix("/'") {
+            // Block comment, possibly spanning lines
+            let mut line = rest;
+            while !line.contains("'/") {
+                match lines.next() {
+                    Some((_, next)) => line = next,
+                    None => return Err(format!("{}: comment is missing `'/`", error())),
+                }
+            }
+            continue;
+        }
+        let parsed = match terminated(line_statement, eof).parse_next(&mut input) {
+            Ok(parsed) => parsed,
+            Err(_) => return Err(error()),
+        };
+        let statement = match parsed {
+            Line::Ignored => continue,
+            Line::EndUml => break,
+            Line::Statement(statement) => statement,
+            Line::NoteStart(placement) => {
+                let mut text = Vec::new();
+                loop {
+                    let Some((_, line)) = lines.next() else {
+                        return Err(format!("{}: note is missing `end note`", error()));
+                    };
+                    let line = line.trim();
+                    if is_end_note(line) {
+                        break;
+                    }
+                    text.push(line);
+                }
+                // One row per line, as `<br/>` gives in Mermaid
+                let first = text.iter().position(|l| !l.is_empty()).unwrap_or(text.len());
+                let last = text.iter().rposition(|l| !l.is_empty()).map_or(first, |i| i + 1);
+                Statement::Note(Note { placement, text: text[first..last].join("<br/>") })
+            }
+            Line::Open(kind, label) => {
+                stack.push(OpenBlock {
+                    kind,
+                    label,
+                    body: Vec::new(),
+                    branches: Vec::new(),
+                });
+                continue;
+            }
+            Line::Else(label) => {
+                match stack.last_mut() {
+                    Some(block) if block.kind.divides() => block.branches.push(ElseBranch {
+                        label,
+                        body: Vec::new(),
+                    }),
+                    _ => return Err(error()),
+                }
+                continue;
+            }
+            Line::End => match stack.pop() {
+                Some(block) => block.close(),
+                None => return Err(error()),
+            },
+        };
+        match stack.last_mut() {
+            Some(block) => block.current_body().push(statement),
+            None => statements.push(statement),
+        }
+    }
+
+    if !stack.is_empty() {
+        let rest = &source[last_offset..];
+        return Err(format!("{}: block is missing `end`", syntax_error(Some(KIND), source, rest)));
+    }
+    Ok(Diagram { title: None, statements, source_lines: SourceLines::default() })
+}
+
+/// Whether `source` starts with `@startuml`.
+pub fn looks_like_plantuml(source: &str) -> bool {
+    source.trim_start().starts_with("@startuml")
+}
+
+/// Lines of `source` with their byte offsets.
+fn source_lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    })
+}
+
+fn is_end_note(line: &str) -> bool {
+    let words: Vec<String> = line.split_whitespace().map(str::to_lowercase).collect();
+    matches!(
+        words.iter().map(String::as_str).collect::<Vec<_>>().as_slice(),
+        ["end", "note" | "hnote" | "rnote"] | ["endnote" | "endhnote" | "endrnote"]
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockKind {
+    Alt,
+    Loop,
+    Opt,
+    Break,
+    Par,
+    Critical,
+    Group,
+}
+
+impl BlockKind {
+    /// Whether `else` may divide the block into branches.
+    fn divides(self) -> bool {
+        matches!(self, BlockKind::Alt | BlockKind::Par | BlockKind::Critical)
+    }
+}
+
+struct OpenBlock {
+    kind: BlockKind,
+    label: String,
+    body: Vec<Statement>,
+    branches: Vec<ElseBranch>,
+}
+
+impl OpenBlock {
+    fn current_body(&mut self) -> &mut Vec<Statement> {
+        match self.branches.last_mut() {
+            Some(branch) => &mut branch.body,
+            None => &mut self.body,
+        }
+    }
+
+    fn close(self) -> Statement {
+        let simple = || LoopBlock {
+            label: self.label.clone(),
+            body: self.body.clone(),
+        };
+        let divided = || AltBlock {
+            label: self.label.clone(),
+            body: self.body.clone(),
+            else_branches: self.branches.clone(),
+        };
+        match self.kind {
+            BlockKind::Alt => Statement::Alt(divided()),
+            BlockKind::Par => Statement::Par(divided()),
+            BlockKind::Critical => Statement::Critical(divided()),
+            BlockKind::Loop => Statement::Loop(simple()),
+            BlockKind::Opt => Statement::Opt(simple()),
+            BlockKind::Break => Statement::Break(simple()),
+            BlockKind::Group => Statement::Rect(simple()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    Statement(Statement),
+    /// `note ...` without text; the text follows until `end note`
+    NoteStart(NotePlacement),
+    Open(BlockKind, String),
+    Else(String),
+    End,
+    EndUml,
+    Ignored,
+}
+
+fn line_statement(input: &mut &str) -> winnow::ModalResult<Line> {
+    alt((
+        ignored.value(Line::Ignored),
+        Caseless("@enduml").value(Line::EndUml),
+        note,
+        participant.map(|p| Line::Statement(Statement::ParticipantDecl(p))),
+        preceded((Caseless("create"), space1, opt((participant_keyword, space1))), participant_rest)
+            .map(|p| Line::Statement(Statement::Create(p))),
+        keyword_arg("activate").map(|id| Line::Statement(Statement::Activate(id))),
+        keyword_arg("deactivate").map(|id| Line::Statement(Statement::Deactivate(id))),
+        keyword_arg("destroy").map(|id| Line::Statement(Statement::Destroy(id))),
+        (word("autonumber"), till_line_ending).value(Line::Statement(Statement::AutoNumber)),
+        block_open,
+        (word("else"), till_line_ending).map(|(_, label): (_, &str)| {
+            Line::Else(label.trim().to_string())
+        }),
+        (Caseless("end"), eof).value(Line::End),
+        message.map(|m| Line::Statement(Statement::Message(m))),
+    ))
+    .parse_next(input)
+}
+
+/// Blank lines, comments and layout-only statements.
+fn ignored(input: &mut &str) -> winnow::ModalResult<()> {
+    alt((
+        eof.void(),
+        ("'", till_line_ending).void(),
+        (Caseless("@startuml"), till_line_ending).void(),
+        (alt((word("title"), word("skinparam"), word("hide"))), till_line_ending).void(),
+        (alt(("==", "...", "|||", "!")), till_line_ending).void(),
+    ))
+    .parse_next(input)
+}
+
+/// A case-insensitive keyword ending at a space or the end of the line.
+fn word(keyword: &'static str) -> impl FnMut(&mut &str) -> winnow::ModalResult<()> {
+    move |input: &mut &str| {
+        (Caseless(keyword), peek(alt((space1, eof)))).void().parse_next(input)
+    }
+}
+
+fn identifier<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
+    take_while(1.., |c: char| c.is_alphanumeric() || c == '_').parse_next(input)
+}
+
+fn quoted<'s>(input: &mut &'s str) -> winnow::ModalResult<&'s str> {
+    ("\"", take_while(0.., |c| c != '"'), "\"").map(|(_, s, _)| s).parse_next(input)
+}
+
+fn keyword_arg(keyword: &'static str) -> impl FnMut(&mut &str) -> winnow::ModalResult<String> {
+    move |input: &mut &str| {
+        (Caseless(keyword), space1, identifier)
+            .map(|(_, _, id): (_, _, &str)| id.to_string())
+            .parse_next(input)
+    }
+}
+
+fn participant_keyword(input: &mut &str) -> winnow::ModalResult<()> {
+    alt((
+        Caseless("participant"),
+        Caseless("actor"),
+        Caseless("boundary"),
+        Caseless("control"),
+        Caseless("entity"),
+        Caseless("database"),
+        Caseless("collections"),
+        Caseless("queue"),
+    ))
+    .void()
+    .parse_next(input)
+}
+
+fn participant(input: &mut &str) -> winnow::ModalResult<ParticipantDecl> {
+    (participant_keyword, space1).parse_next(input)?;
+    participant_rest.parse_next(input)
+}
+
+/// `Id`, `Id as "Display"`, `"Display" as Id`, then ignored `order`/colour.
+fn participant_rest(input: &mut &str) -> winnow::ModalResult<ParticipantDecl> {
+    let decl = alt((
+        (quoted, space1, Caseless("as"), space1, identifier).map(
+            |(alias, _, _, _, id): (&str, _, _, _, &str)| ParticipantDecl {
+                id: id.to_string(),
+                alias: Some(alias.to_string()),
+            },
+        ),
+        (identifier, opt(preceded((space1, Caseless("as"), space1), alt((quoted, identifier)))))
+            .map(|(id, alias): (&str, Option<&str>)| ParticipantDecl {
+                id: id.to_string(),
+                alias: alias.map(str::to_string),
+            }),
+    ))
+    .parse_next(input)?;
+    // `order 10`, `#colour` and stereotypes only affect styling
+    till_line_ending.parse_next(input)?;
+    Ok(decl)
+}
+
+fn note(input: &mut &str) -> winnow::ModalResult<Line> {
+    alt((Caseless("note"), Caseless("hnote"), Caseless("rnote"))).parse_next(input)?;
+    space1.parse_next(input)?;
+    let placement = alt((
+        (Caseless("right of"), space1, identifier)
+            .map(|(_, _, id): (_, _, &str)| NotePlacement::RightOf(id.to_string())),
+        (Caseless("left of"), space1, identifier)
+            .map(|(_, _, id): (_, _, &str)| NotePlacement::LeftOf(id.to_string())),
+        (Caseless("over"), space1, identifier, space0, ",", space0, identifier).map(
+            |(_, _, a, _, _, _, b): (_, _, &str, _, _, _, &str)| {
+                NotePlacement::OverTwo(a.to_string(), b.to_string())
+            },
+        ),
+        (Caseless("over"), space1, identifier)
+            .map(|(_, _, id): (_, _, &str)| NotePlacement::Over(id.to_string())),
+    ))
+    .parse_next(input)?;
+    space0.parse_next(input)?;
+    match opt(preceded(":", till_line_ending)).parse_next(input)? {
+        Some(text) => Ok(Line::Statement(Statement::Note(Note {
+            placement,
+            text: text.trim().to_string(),
+        }))),
+        None => Ok(Line::NoteStart(placement)),
+    }
+}
+
+fn block_open(input: &mut &str) -> winnow::ModalResult<Line> {
+    let kind = alt((
+        word("alt").value(BlockKind::Alt),
+        word("loop").value(BlockKind::Loop),
+        word("opt").value(BlockKind::Opt),
+        word("break").value(BlockKind::Break),
+        word("par").value(BlockKind::Par),
+        word("critical").value(BlockKind::Critical),
+        word("group").value(BlockKind::Group),
+    ))
+    .parse_next(input)?;
+    let label = till_line_ending.parse_next(input)?;
+    Ok(Line::Open(kind, label.trim().to_string()))
+}
+
+fn message(input: &mut &str) -> winnow::ModalResult<Message> {
+    let from = identifier.parse_next(input)?;
+    space0.parse_next(input)?;
+    let (line_style, head, reversed) = arrow.parse_next(input)?;
+    space0.parse_next(input)?;
+    let to = identifier.parse_next(input)?;
+    space0.parse_next(input)?;
+    let modifier = opt(alt(("++", "--"))).parse_next(input)?;
+    space0.parse_next(input)?;
+    let text = opt(preceded(":", till_line_ending)).parse_next(input)?;
+
+    let (from, to) = if reversed { (to, from) } else { (from, to) };
+    Ok(Message {
+        from: from.to_string(),
+        to: to.to_string(),
+        arrow: Arrow { line_style, head },
+        text: text.unwrap_or("").trim().to_string(),
+        activate_target: modifier == Some("++"),
+        deactivate_source: modifier == Some("--"),
+    })
+}
+
+/// An arrow's line style, head, and whether it points right to left.
+fn arrow(input: &mut &str) -> winnow::ModalResult<(LineStyle, ArrowHead, bool)> {
+    use ArrowHead::{Arrowhead, Cross, Open};
+    use LineStyle::{Dotted, Solid};
+    alt((
+        "-->>".value((Dotted, Open, false)),
+        "-->x".value((Dotted, Cross, false)),
+        "-->".value((Dotted, Arrowhead, false)),
+        "->>".value((Solid, Open, false)),
+        "->x".value((Solid, Cross, false)),
+        "->".value((Solid, Arrowhead, false)),
+        "<<--".value((Dotted, Open, true)),
+        "x<--".value((Dotted, Cross, true)),
+        "<--".value((Dotted, Arrowhead, true)),
+        "<<-".value((Solid, Open, true)),
+        "x<-".value((Solid, Cross, true)),
+        "<-".value((Solid, Arrowhead, true)),
+    ))
+    .parse_next(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn parse_body(body: &str) -> Vec<Statement> {
+        parse_plantuml(&format!("@startuml\n{body}\n@enduml\n")).unwrap().statements
+    }
+
+    #[test]
+    fn messages_map_arrows_and_reverse() {
+        let statements = parse_body("A -> B: hi\nB --> A : back\nA <- B\nA ->> B ++: async");
+        let messages: Vec<(&str, &str, LineStyle, ArrowHead)> = statements
+            .iter()
+            .map(|s| match s {
+                Statement::Message(m) => (m.from.as_str(), m.to.as_str(), m.arrow.line_style, m.arrow.head),
+                other => panic!("expected message, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("A", "B", LineStyle::Solid, ArrowHead::Arrowhead),
+                ("B", "A", LineStyle::Dotted, ArrowHead::Arrowhead),
+                ("B", "A", LineStyle::Solid, ArrowHead::Arrowhead),
+                ("A", "B", LineStyle::Solid, ArrowHead::Open),
+            ]
+        );
+        let Statement::Message(last) = &statements[3] else { unreachable!() };
+        assert!(last.activate_target);
+        assert_eq!(last.text, "async");
+    }
+
+    #[test]
+    fn participants_with_aliases() {
+        let statements = parse_body("actor User\nparticipant \"Web Server\" as W\ndatabase DB as \"Store\" order 3");
+        assert_eq!(
+            statements,
+            vec![
+                Statement::ParticipantDecl(ParticipantDecl { id: "User".into(), alias: None }),
+                Statement::ParticipantDecl(ParticipantDecl {
+                    id: "W".into(),
+                    alias: Some("Web Server".into()),
+                }),
+                Statement::ParticipantDecl(ParticipantDecl {
+                    id: "DB".into(),
+                    alias: Some("Store".into()),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_single_and_multi_line() {
+        let statements = parse_body("note left of A: one\nnote over A, B\n  two\n  lines\nend note");
+        assert_eq!(
+            statements,
+            vec![
+                Statement::Note(Note {
+                    placement: NotePlacement::LeftOf("A".into()),
+                    text: "one".into(),
+                }),
+                Statement::Note(Note {
+                    placement: NotePlacement::OverTwo("A".into(), "B".into()),
+                    text: "two<br/>lines".into(),
+                }),
+            ]
+        );
+
+        let source = "@startuml\nA -> B: hi\nnote over A, B\n\n  two\n  lines\nend note\n@enduml\n";
+        let output = crate::render(source).unwrap();
+        assert!(output.contains(" │ two        │\n │ lines      │\n"), "{output}");
+    }
+
+    #[test]
+    fn nested_blocks_with_else() {
+        let statements = parse_body(
+            "alt ok\n  A -> B: yes\n  loop 3 times\n    B -> A: ping\n  end\nelse failure\n  A -> B: no\nend\ngroup setup\nactivate A\nend",
+        );
+        let Statement::Alt(alt) = &statements[0] else { panic!("{statements:?}") };
+        assert_eq!(alt.label, "ok");
+        assert!(matches!(alt.body[1], Statement::Loop(_)));
+        assert_eq!(alt.else_branches[0].label, "failure");
+        assert_eq!(
+            statements[1],
+            Statement::Rect(LoopBlock {
+                label: "setup".into(),
+                body: vec![Statement::Activate("A".into())],
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_comments_and_layout_lines() {
+        let statements = parse_body(
+            "title Demo\n' comment\n/' block\ncomment '/\nskinparam monochrome true\n== Phase ==\n...\n|||\nautonumber 10\nA -> B\nelsewhere -> B",
+        );
+        assert_eq!(statements.len(), 3);
+    }
+
+    #[test]
+    fn unmatched_end_and_unclosed_block_are_errors() {
+        let err = parse_plantuml("@startuml\nA -> B\nend\n").unwrap_err();
+        assert!(err.starts_with("syntax error in PlantUML sequence diagram at line 3:"), "{err}");
+        let err = parse_plantuml("@startuml\nloop x\nA -> B\n").unwrap_err();
+        assert!(err.ends_with("block is missing `end`"), "{err}");
+        let err = parse_plantuml("@startuml\nloop x\nelse y\nend\n").unwrap_err();
+        assert!(err.contains("line 3"), "{err}");
+    }
+}
@@ -0,0 +1,53 @@
+//! Python bindings, enabled with the `python` feature and built with
+//! `maturin` (see `pyproject.toml`).
+//!
+//! ```python
+//! import ma
+//! print(ma.render("graph LR\n    A --> B", width=40))
+//! ```
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Render a Mermaid diagram to text.
+///
+/// `width` limits the output to that many columns, and with `wrap` names and
+/// labels that don't fit are wrapped instead of cut short; `ascii` swaps
+/// box-drawing characters for plain ASCII. Raises `ValueError` when the
+/// diagram can't be parsed or laid out.
+#[pyfunction]
+#[pyo3(signature = (text, width=None, ascii=false, wrap=false))]
+fn render(text: &str, width: Option<usize>, ascii: bool, wrap: bool) -> PyResult<String> {
+    let options = crate::RenderOptions {
+        max_width: width,
+        wrap,
+        ascii_only: ascii,
+        ..crate::RenderOptions::default()
+    };
+    crate::render_with(text, &options).map_err(PyValueError::new_err)
+}
+
+#[pymodule]
+fn ma(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(render, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_applies_ascii_and_width() {
+        let output = render("graph LR\n    A --> B\n", Some(40), true, false).unwrap();
+        assert!(output.is_ascii());
+        assert!(output.lines().all(|line| line.len() <= 40));
+    }
+
+    #[test]
+    fn render_errors_become_value_errors() {
+        let err = render("pie", None, false, false).unwrap_err();
+        Python::initialize();
+        Python::attach(|py| assert!(err.is_instance_of::<PyValueError>(py)));
+    }
+}
@@ -0,0 +1,307 @@
+//! The Mermaid diagram types ma renders, each a [`DiagramHandler`] that
+//! recognises its header, parses, lays out and draws it.
+//!
+//! Input is handed to the first handler whose [`sniff`](DiagramHandler::sniff)
+//! accepts it. With the `plugins` feature, crates depending on ma can add
+//! their own diagram types with [`register`]; they are tried after the
+//! built-in ones.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{Computed, Document, RenderOptions, er_parser, graph_parser, parser};
+
+/// A diagram type: how to recognise, parse, lay out and draw it.
+///
+/// Only [`keywords`](Self::keywords) and [`parse`](Self::parse) are required.
+/// The other steps default to ma's own layout and drawing for the kind of
+/// [`Document`] that `parse` returns, so a new type can be added by parsing
+/// it into an existing one, e.g. a state diagram into a flowchart. A type
+/// of its own parses into a [`Document::Custom`] and overrides
+/// [`layout`](Self::layout) and [`render`](Self::render) to handle it.
+pub trait DiagramHandler: Sync {
+    /// Header words that start a diagram of this type, e.g. `graph`. Listed
+    /// in the error for an unknown header.
+    fn keywords(&self) -> &'static [&'static str];
+
+    /// Whether `input` is a diagram of this type: by default, whether its
+    /// first word is one of the [`keywords`](Self::keywords).
+    fn sniff(&self, input: &str) -> bool {
+        input.split_whitespace().next().is_some_and(|word| self.keywords().contains(&word))
+    }
+
+    fn parse(&self, input: &str) -> Result<Document, String>;
+
+    /// Lay out a document returned by [`parse`](Self::parse), whose labels
+    /// have already been sanitized and had [`RenderOptions::rtl`] applied
+    /// (except in a [`Document::Custom`]). The default fails on a custom
+    /// document.
+    fn layout(&self, document: &Document, options: &RenderOptions) -> Result<Computed, String> {
+        crate::layout_document(document, options)
+    }
+
+    /// Draw a layout returned by [`layout`](Self::layout). Line numbers and
+    /// ASCII mode are applied to the result afterwards. The default draws
+    /// nothing for a [`Computed::Custom`].
+    fn render(&self, computed: &Computed, options: &RenderOptions) -> String {
+        crate::draw_layout(computed, options)
+    }
+
+    /// Write what [`render`](Self::render) returns to `out`. The built-in
+    /// types write their grid row by row instead.
+    fn render_to(
+        &self,
+        computed: &Computed,
+        options: &RenderOptions,
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        out.write_all(self.render(computed, options).as_bytes())
+    }
+}
+
+/// What a [`DiagramHandler`] of a type ma doesn't know parsed its input
+/// into, carried in a [`Document::Custom`] along with the handler that lays
+/// it out and draws it.
+#[derive(Clone)]
+pub struct CustomDocument {
+    handler: &'static dyn DiagramHandler,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl CustomDocument {
+    pub fn new(handler: &'static dyn DiagramHandler, value: impl Any + Send + Sync) -> Self {
+        CustomDocument { handler, value: Arc::new(value) }
+    }
+
+    pub fn handler(&self) -> &'static dyn DiagramHandler {
+        self.handler
+    }
+
+    /// The parsed value, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for CustomDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomDocument")
+            .field("keywords", &self.handler.keywords())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Custom documents are equal when they are copies of the same parse, as
+/// ma can't look inside them.
+impl PartialEq for CustomDocument {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+/// A diagram type drawn by ma itself.
+struct BuiltIn {
+    keywords: &'static [&'static str],
+    parse: fn(&str) -> Result<Document, String>,
+}
+
+impl DiagramHandler for BuiltIn {
+    fn keywords(&self) -> &'static [&'static str] {
+        self.keywords
+    }
+
+    fn parse(&self, input: &str) -> Result<Document, String> {
+        (self.parse)(input)
+    }
+
+    fn render_to(
+        &self,
+        computed: &Computed,
+        options: &RenderOptions,
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        crate::stream_layout(computed, options, out)
+    }
+}
+
+static SEQUENCE: BuiltIn = BuiltIn {
+    keywords: &["sequenceDiagram"],
+    parse: |input| parser::parse_diagram(input).map(Document::Sequence),
+};
+
+static FLOWCHART: BuiltIn = BuiltIn {
+    keywords: &["graph", "flowchart"],
+    parse: |input| graph_parser::parse_graph(input).map(Document::Graph),
+};
+
+static ER: BuiltIn = BuiltIn {
+    keywords: &["erDiagram"],
+    parse: |input| er_parser::parse_er(input).map(Document::Er),
+};
+
+static BUILT_IN: [&dyn DiagramHandler; 3] = [&SEQUENCE, &FLOWCHART, &ER];
+
+#[cfg(feature = "plugins")]
+static PLUGINS: std::sync::RwLock<Vec<&'static dyn DiagramHandler>> =
+    std::sync::RwLock::new(Vec::new());
+
+/// Add a diagram type, tried after the built-in ones and those registered
+/// before it.
+#[cfg(feature = "plugins")]
+pub fn register(handler: &'static dyn DiagramHandler) {
+    PLUGINS.write().unwrap_or_else(std::sync::PoisonError::into_inner).push(handler);
+}
+
+/// Every diagram type, in the order they are tried.
+pub fn handlers() -> Vec<&'static dyn DiagramHandler> {
+    let handlers = BUILT_IN.to_vec();
+    #[cfg(feature = "plugins")]
+    let handlers = {
+        let plugins = PLUGINS.read().unwrap_or_else(std::sync::PoisonError::into_inner);
+        [handlers, plugins.clone()].concat()
+    };
+    handlers
+}
+
+/// The handler for `input`'s diagram type.
+pub fn handler_for(input: &str) -> Option<&'static dyn DiagramHandler> {
+    handlers().into_iter().find(|handler| handler.sniff(input))
+}
+
+/// The handler that lays out and draws `document`: the built-in one for its
+/// kind, or the one a custom document was parsed by.
+pub(crate) fn handler_of(document: &Document) -> &'static dyn DiagramHandler {
+    match document {
+        Document::Sequence(_) => &SEQUENCE,
+        Document::Graph(_) => &FLOWCHART,
+        Document::Er(_) => &ER,
+        Document::Custom(custom) => custom.handler(),
+    }
+}
+
+/// The error for a header no handler accepts, suggesting the closest
+/// keyword when `word` looks like a typo of it.
+pub(crate) fn unknown_diagram_type(word: &str) -> String {
+    let keywords: Vec<&str> =
+        handlers().iter().flat_map(|handler| handler.keywords()).copied().collect();
+    let mut message = format!("unknown diagram type: {word}");
+    let closest = keywords
+        .iter()
+        .map(|keyword| (edit_distance(&word.to_lowercase(), &keyword.to_lowercase()), keyword))
+        .min_by_key(|&(distance, _)| distance);
+    if let Some((distance, keyword)) = closest
+        && distance <= keyword.len() / 3
+    {
+        message.push_str(&format!(" (did you mean {keyword}?)"));
+    }
+    message.push_str(&format!("; supported types: {}", keywords.join(", ")));
+    message
+}
+
+/// The number of characters to insert, delete or replace to turn `a` into
+/// `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(ca != cb);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn handler_for_matches_the_first_word() {
+        let keywords = |input| handler_for(input).map(|handler| handler.keywords());
+        assert_eq!(keywords("graph TD\n    A --> B\n"), Some(&["graph", "flowchart"][..]));
+        assert_eq!(keywords("  flowchart LR\n"), Some(&["graph", "flowchart"][..]));
+        assert_eq!(keywords("sequenceDiagram\n"), Some(&["sequenceDiagram"][..]));
+        assert_eq!(keywords("graphs TD\n"), None);
+        assert_eq!(keywords(""), None);
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn registered_handlers_are_tried_after_built_in_ones() {
+        /// `stateDiagram` transitions drawn as a flowchart.
+        struct State;
+
+        impl DiagramHandler for State {
+            fn keywords(&self) -> &'static [&'static str] {
+                &["stateDiagram"]
+            }
+
+            fn parse(&self, input: &str) -> Result<Document, String> {
+                let body = input.trim_start().trim_start_matches("stateDiagram");
+                let graph = format!("graph TD{}", body.replace("[*]", "start"));
+                graph_parser::parse_graph(&graph).map(Document::Graph)
+            }
+        }
+
+        register(&State);
+        let output = crate::render("stateDiagram\n    [*] --> Idle\n").unwrap();
+        assert!(output.contains("start") && output.contains("Idle"), "{output}");
+        let error = crate::render("stateDiagam\n").unwrap_err();
+        assert!(error.contains("(did you mean stateDiagram?)"), "{error}");
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn registered_handlers_can_add_a_kind_of_their_own() {
+        /// `tally` diagrams, drawn as the number of items they list.
+        struct Tally;
+
+        impl DiagramHandler for Tally {
+            fn keywords(&self) -> &'static [&'static str] {
+                &["tally"]
+            }
+
+            fn parse(&self, input: &str) -> Result<Document, String> {
+                let lines = input.lines().skip(1).map(str::trim);
+                let items: Vec<&str> = lines.filter(|l| !l.is_empty()).collect();
+                let items: Vec<String> = items.into_iter().map(String::from).collect();
+                Ok(Document::Custom(CustomDocument::new(&Tally, items)))
+            }
+
+            fn layout(&self, document: &Document, _: &RenderOptions) -> Result<Computed, String> {
+                let Document::Custom(custom) = document else { return Err("not a tally".into()) };
+                let items = custom.downcast_ref::<Vec<String>>().ok_or("not a tally")?;
+                Ok(Computed::Custom(Box::new(items.len())))
+            }
+
+            fn render(&self, computed: &Computed, _: &RenderOptions) -> String {
+                match computed {
+                    Computed::Custom(count) => {
+                        format!("{} items", count.downcast_ref::<usize>().unwrap())
+                    }
+                    _ => String::new(),
+                }
+            }
+        }
+
+        register(&Tally);
+        let input = "tally\n    apples\n    pears\n";
+        assert_eq!(crate::render(input).unwrap(), "2 items");
+        let options = RenderOptions { line_numbers: true, ..RenderOptions::default() };
+        assert_eq!(crate::render_with(input, &options).unwrap(), "1 │ 2 items");
+
+        let document = crate::parse(input).unwrap();
+        assert_eq!(document, document.clone());
+        assert_ne!(document, crate::parse(input).unwrap());
+        let stats = crate::inspect(&document, &RenderOptions::default()).unwrap();
+        assert_eq!((stats.kind, stats.width, stats.height), ("tally", 7, 1));
+        assert_eq!(crate::format(input).unwrap(), input);
+        let error = crate::layout_json(&document, &RenderOptions::default()).unwrap_err();
+        assert_eq!(error, "no layout JSON for custom diagram types");
+    }
+}
@@ -1,191 +1,272 @@
 use crate::ast::*;
-use crate::display_width::{display_width, line_count, split_br};
+use crate::display_width::{display_width, multiline_width, split_br};
+use crate::grid::{AnsiGrid, Canvas, Grid};
 use crate::layout::*;
+use crate::style::Theme;
 
-const BOX_TL: char = '┌';
-const BOX_TR: char = '┐';
-const BOX_BL: char = '└';
-const BOX_BR: char = '┘';
-const BOX_H: char = '─';
-const BOX_V: char = '│';
-const BOX_TD: char = '┬';
-const BOX_TU: char = '┴';
+const LINE_H: char = '─';
+const LINE_DOTTED: char = '╌';
+const LINE_V: char = '│';
+const LOOP_TR: char = '┐';
+const LOOP_BR: char = '┘';
 const ARROW_R: char = '>';
 const ARROW_L: char = '<';
+const OPEN_R: char = '⟩';
+const OPEN_L: char = '⟨';
+const CROSS: char = 'x';
 const HEAVY_V: char = '┃';
-const SELF_LOOP_ARM: usize = 4;
+const DESTROY_MARK: char = '✕';
 
-struct Grid {
-    cells: Vec<Vec<char>>,
-    width: usize,
-    height: usize,
+pub fn render(layout: &Layout) -> String {
+    render_with(layout, &Theme::default())
 }
 
-impl Grid {
-    fn new(width: usize, height: usize) -> Self {
-        Self {
-            cells: vec![vec![' '; width]; height],
-            width,
-            height,
-        }
-    }
+/// Render with the given border theme for participant boxes, notes and block frames.
+pub fn render_with(layout: &Layout, theme: &Theme) -> String {
+    draw(layout, theme).render()
+}
+
+/// Render for a terminal, with the lines dimmed by ANSI escapes.
+pub fn render_ansi(layout: &Layout, theme: &Theme) -> String {
+    let mut grid = AnsiGrid::new(layout.total_width, layout.total_height());
+    draw_on(&mut grid, layout, theme);
+    grid.render()
+}
 
-    fn set(&mut self, row: usize, col: usize, ch: char) {
-        if row < self.height && col < self.width {
-            if self.cells[row][col] == '\0' && col > 0 && self.cells[row][col - 1] != '\0' {
-                self.cells[row][col - 1] = ' ';
+/// Write the rendering to `out` row by row.
+pub fn render_to<W: std::io::Write>(layout: &Layout, theme: &Theme, out: &mut W) -> std::io::Result<()> {
+    draw(layout, theme).write_to(out)
+}
+
+/// Render split into pages of at most `max_height` lines where possible.
+///
+/// Pages break between rows and each page after the first repeats the
+/// participant header boxes (without participants destroyed earlier). The
+/// title heads the first page only and the bottom boxes close the last. A
+/// row taller than the budget still gets a page of its own. With
+/// `keep_blocks_together` a block frame that fits on a page is moved to the
+/// next one rather than split.
+pub fn render_pages(layout: &Layout, theme: &Theme, max_height: usize) -> Vec<String> {
+    pages(layout, theme, max_height, Grid::new, Grid::lines)
+}
+
+/// [`render_pages`] for a terminal, with the lines dimmed by ANSI escapes.
+pub fn render_pages_ansi(layout: &Layout, theme: &Theme, max_height: usize) -> Vec<String> {
+    pages(layout, theme, max_height, AnsiGrid::new, AnsiGrid::lines)
+}
+
+/// Paginate a drawing made on canvases from `new`, read back with `lines_of`.
+fn pages<C: Canvas>(
+    layout: &Layout,
+    theme: &Theme,
+    max_height: usize,
+    new: fn(usize, usize) -> C,
+    lines_of: fn(&C) -> Vec<String>,
+) -> Vec<String> {
+    let mut grid = new(layout.total_width, layout.total_height());
+    draw_on(&mut grid, layout, theme);
+    let lines = lines_of(&grid);
+    let box_height = layout.box_height();
+    let header_height = layout.header_height();
+    let body_height: usize = layout.rows.iter().map(|r| r.height).sum();
+    // Room for the header and, on the last page, the bottom boxes
+    let budget = max_height.saturating_sub(layout.total_height() - body_height).max(1);
+
+    // Body line ranges per page, plus which participants are alive at each start
+    let mut pages: Vec<(usize, usize, Vec<bool>)> = Vec::new();
+    let mut alive = vec![true; layout.participants.len()];
+    let mut start = header_height;
+    let mut y = header_height;
+    let mut page_alive = alive.clone();
+    for (i, row) in layout.rows.iter().enumerate() {
+        let h = row.height;
+        let needed = match row.row {
+            Row::BlockStart(_) if layout.options.keep_blocks_together => {
+                Some(block_height(&layout.rows[i..])).filter(|&b| b <= budget).unwrap_or(h)
             }
-            self.cells[row][col] = ch;
+            _ => h,
+        };
+        if y > start && y + needed - start > budget {
+            pages.push((start, y, page_alive));
+            start = y;
+            page_alive = alive.clone();
+        }
+        for idx in row.row.destroyed() {
+            alive[idx] = false;
         }
+        y += h;
     }
-
-    fn write_str(&mut self, row: usize, col: usize, s: &str) {
-        let mut offset = 0;
-        for ch in s.chars() {
-            self.set(row, col + offset, ch);
-            let w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(1);
-            for j in 1..w {
-                self.set(row, col + offset + j, '\0');
+    pages.push((start, y, page_alive));
+
+    let last = pages.len() - 1;
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start, end, page_alive))| {
+            let mut page = if i == 0 {
+                lines[..header_height].to_vec()
+            } else {
+                let dead: Vec<bool> = page_alive.iter().map(|a| !a).collect();
+                let mut header = new(layout.total_width, box_height);
+                draw_participant_boxes_filtered(&mut header, layout, theme, 0, true, &dead);
+                lines_of(&header)
+            };
+            page.extend_from_slice(&lines[start..end]);
+            if i == last {
+                page.extend_from_slice(&lines[end..]);
             }
-            offset += w;
+            page.join("\n")
+        })
+        .collect()
+}
+
+/// Lines taken by the block starting at `rows[0]`, up to and including its
+/// `end`.
+fn block_height(rows: &[LayoutRow]) -> usize {
+    let mut depth = 0;
+    let mut height = 0;
+    for row in rows {
+        height += row.height;
+        match row.row {
+            Row::BlockStart(_) => depth += 1,
+            Row::BlockEnd(_) => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            break;
         }
     }
+    height
+}
 
-    fn render(&self) -> String {
-        self.cells
-            .iter()
-            .map(|row| {
-                let line: String = row.iter().filter(|&&ch| ch != '\0').collect();
-                line.trim_end().to_string()
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
-    }
+/// Notes the rendering cuts off: boxes reaching past the output width, or
+/// squeezed against the left edge until their text no longer fits.
+pub fn clipped_notes(layout: &Layout) -> Vec<&NoteRow> {
+    layout
+        .rows
+        .iter()
+        .filter_map(|r| match &r.row {
+            Row::Note(note) => Some(note),
+            _ => None,
+        })
+        .filter(|note| {
+            note.box_right >= layout.total_width
+                || note.box_right - note.box_left + 1 < multiline_width(&note.text) + 4
+        })
+        .collect()
 }
 
-fn row_height(row: &Row) -> usize {
-    match row {
-        Row::Message(m) => 2 + line_count(&m.text),
-        Row::Note(n) => 2 + line_count(&n.text),
-        Row::BlockStart(_) | Row::BlockEnd(_) | Row::BlockDivider(_) | Row::Destroy(_) => 1,
-    }
+fn draw(layout: &Layout, theme: &Theme) -> Grid {
+    let mut grid = Grid::new(layout.total_width, layout.total_height());
+    draw_on(&mut grid, layout, theme);
+    grid
 }
 
-pub fn render(layout: &Layout) -> String {
-    let box_height = layout
-        .participants
-        .iter()
-        .map(|p| p.box_height)
-        .max()
-        .unwrap_or(3);
-    let body_height: usize = layout.rows.iter().map(row_height).sum();
-    let height = box_height + body_height + box_height;
-    let mut grid = Grid::new(layout.total_width, height);
+/// Draw onto a canvas of at least `layout.total_width` by
+/// `layout.total_height()` cells.
+pub fn draw_on(grid: &mut impl Canvas, layout: &Layout, theme: &Theme) {
+    let body_height: usize = layout.rows.iter().map(|r| r.height).sum();
 
-    draw_participant_boxes_filtered(&mut grid, layout, 0, true, &[]);
+    if let Some(title) = &layout.title {
+        for (i, line) in split_br(title).iter().enumerate() {
+            let pad_left = layout.total_width.saturating_sub(display_width(line)) / 2;
+            grid.write_str(i, pad_left, line);
+        }
+    }
+    draw_participant_boxes_filtered(grid, layout, theme, layout.title_height(), true, &[]);
 
-    let body_start = box_height;
+    let body_start = layout.header_height();
     let mut y = body_start;
     let mut active_frames: Vec<&BlockRow> = Vec::new();
     let mut alive = vec![true; layout.participants.len()];
-    for (i, row) in layout.rows.iter().enumerate() {
-        let row_activations = layout
-            .activations
-            .get(i)
-            .cloned()
-            .unwrap_or_else(|| vec![false; layout.participants.len()]);
-        let h = row_height(row);
-        match row {
+    for laid in &layout.rows {
+        let active = &laid.active;
+        let h = laid.height;
+        match &laid.row {
             Row::Message(msg) => {
-                draw_lifelines_filtered(&mut grid, layout, y, h, &row_activations, &alive);
-                draw_message(&mut grid, layout, msg, y, &row_activations);
-                draw_frame_sides(&mut grid, layout, &active_frames, y, h);
+                draw_lifelines_filtered(grid, layout, y, h, active, &alive);
+                draw_message(grid, layout, msg, y, active);
+                draw_frame_sides(grid, theme, &active_frames, y, h);
             }
             Row::Note(note) => {
-                draw_lifelines_filtered(&mut grid, layout, y, h, &row_activations, &alive);
-                draw_note(&mut grid, note, y);
-                draw_frame_sides(&mut grid, layout, &active_frames, y, h);
+                draw_lifelines_filtered(grid, layout, y, h, active, &alive);
+                draw_note(grid, theme, note, y);
+                draw_frame_sides(grid, theme, &active_frames, y, h);
             }
             Row::BlockStart(block) => {
-                draw_block_start(&mut grid, layout, block, y);
+                draw_frame_sides(grid, theme, &active_frames, y, h);
+                draw_block_start(grid, layout, theme, block, y, &alive);
                 active_frames.push(block);
+                draw_label_continuation(grid, layout, theme, block, y, active, &alive);
             }
             Row::BlockEnd(block) => {
-                active_frames.retain(|f| f.frame_left != block.frame_left || f.frame_right != block.frame_right);
-                draw_block_end(&mut grid, layout, block, y);
+                // Nested frames can share columns, so close only the innermost match
+                if let Some(pos) = active_frames.iter().rposition(|f| {
+                    f.frame_left == block.frame_left && f.frame_right == block.frame_right
+                }) {
+                    active_frames.remove(pos);
+                }
+                draw_frame_sides(grid, theme, &active_frames, y, h);
+                draw_block_end(grid, layout, theme, block, y, &alive);
             }
             Row::BlockDivider(block) => {
-                draw_block_divider(&mut grid, layout, block, y);
+                draw_frame_sides(grid, theme, &active_frames, y, h);
+                draw_block_divider(grid, layout, theme, block, y, &alive);
+                draw_label_continuation(grid, layout, theme, block, y, active, &alive);
             }
             Row::Destroy(destroy) => {
-                draw_destroy(&mut grid, destroy, y);
+                draw_lifelines_filtered(grid, layout, y, h, active, &alive);
+                draw_destroy(grid, destroy, y);
+                draw_frame_sides(grid, theme, &active_frames, y, h);
                 alive[destroy.participant_idx] = false;
             }
+            Row::Parallel(par) => {
+                draw_parallel(grid, layout, theme, par, (y, h), &mut alive);
+                draw_frame_sides(grid, theme, &active_frames, y, h);
+            }
         }
         y += h;
     }
 
-    let bottom_y = body_start + body_height;
-    draw_participant_boxes_filtered(&mut grid, layout, bottom_y, false, &layout.destroyed);
-
-    grid.render()
+    if layout.options.mirror_participants {
+        let bottom_y = body_start + body_height;
+        let destroyed = &layout.destroyed;
+        draw_participant_boxes_filtered(grid, layout, theme, bottom_y, false, destroyed);
+    }
 }
 
 fn draw_participant_boxes_filtered(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
     layout: &Layout,
+    theme: &Theme,
     y: usize,
     is_top: bool,
     skip: &[bool],
 ) {
-    let max_box_height = layout
-        .participants
-        .iter()
-        .map(|p| p.box_height)
-        .max()
-        .unwrap_or(3);
+    // Every box is as tall as the tallest so the row lines up
+    let max_box_height = layout.box_height();
 
     for (i, p) in layout.participants.iter().enumerate() {
         if skip.get(i).copied().unwrap_or(false) {
             continue;
         }
-        grid.set(y, p.box_left, BOX_TL);
-        for col in (p.box_left + 1)..p.box_right {
-            grid.set(y, col, BOX_H);
-        }
-        grid.set(y, p.box_right, BOX_TR);
-
-        let lines = split_br(&p.name);
-        for (li, line) in lines.iter().enumerate() {
-            let row = y + 1 + li;
-            grid.set(row, p.box_left, BOX_V);
-            grid.write_str(row, p.box_left + 2, line);
-            grid.set(row, p.box_right, BOX_V);
-        }
-        // Fill remaining rows if this box is shorter than the max
-        for li in lines.len()..(max_box_height - 2) {
-            let row = y + 1 + li;
-            grid.set(row, p.box_left, BOX_V);
-            grid.set(row, p.box_right, BOX_V);
+        grid.rect(p.box_left, y, p.box_right - p.box_left + 1, max_box_height, theme);
+        for (li, line) in split_br(&p.name).iter().enumerate() {
+            grid.write_str(y + 1 + li, p.box_left + 1 + layout.options.box_padding, line);
         }
 
         let bottom = y + max_box_height - 1;
-        grid.set(bottom, p.box_left, BOX_BL);
-        for col in (p.box_left + 1)..p.box_right {
-            grid.set(bottom, col, BOX_H);
-        }
-        grid.set(bottom, p.box_right, BOX_BR);
 
         if is_top {
-            grid.set(bottom, p.center_col, BOX_TD);
+            grid.set(bottom, p.center_col, theme.connector_down);
         } else {
-            grid.set(y, p.center_col, BOX_TU);
+            grid.set(y, p.center_col, theme.connector_up);
         }
     }
 }
 
 fn draw_lifelines_filtered(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
     layout: &Layout,
     y: usize,
     count: usize,
@@ -199,7 +280,7 @@ fn draw_lifelines_filtered(
         let ch = if activations.get(i).copied().unwrap_or(false) {
             HEAVY_V
         } else {
-            BOX_V
+            LINE_V
         };
         for dy in 0..count {
             grid.set(y + dy, p.center_col, ch);
@@ -208,7 +289,7 @@ fn draw_lifelines_filtered(
 }
 
 fn draw_message(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
     layout: &Layout,
     msg: &MessageRow,
     y: usize,
@@ -225,30 +306,25 @@ fn draw_message(
         (msg.to_col, msg.from_col)
     };
 
-    let text_col = left_col + 2;
+    draw_message_number(grid, layout, msg, y, left_col + 2);
+    let cols: Vec<usize> = layout
+        .participants
+        .iter()
+        .map(|p| p.center_col)
+        .filter(|col| (left_col..=right_col).contains(col))
+        .collect();
     let lines = split_br(&msg.text);
     for (i, line) in lines.iter().enumerate() {
-        grid.write_str(y + i, text_col, line);
+        let align = layout.options.message_align;
+        for (col, piece) in message_text_pieces(line, &cols, layout.number_width, align) {
+            grid.write_str(y + i, col, piece);
+        }
     }
 
-    let arrow_y = y + lines.len();
+    let arrow_y = y + msg.text_lines();
 
-    match msg.arrow.line_style {
-        LineStyle::Solid => {
-            for col in (left_col + 1)..right_col {
-                grid.set(arrow_y, col, BOX_H);
-            }
-        }
-        LineStyle::Dotted => {
-            for col in (left_col + 1)..right_col {
-                let offset = col - (left_col + 1);
-                if offset % 2 == 0 {
-                    grid.set(arrow_y, col, BOX_H);
-                } else {
-                    grid.set(arrow_y, col, ' ');
-                }
-            }
-        }
+    for col in (left_col + 1)..right_col {
+        grid.set(arrow_y, col, line_char(msg.arrow.line_style));
     }
 
     match msg.direction {
@@ -257,9 +333,6 @@ fn draw_message(
         }
         Direction::RightToLeft => {
             grid.set(arrow_y, left_col + 1, reverse_arrow_head_char(&msg.arrow));
-            if right_col >= 2 {
-                grid.set(arrow_y, right_col - 1, BOX_H);
-            }
         }
     }
 
@@ -275,191 +348,301 @@ fn draw_message(
     let left_ch = if left_idx.is_some_and(|i| activations.get(i).copied().unwrap_or(false)) {
         HEAVY_V
     } else {
-        BOX_V
+        LINE_V
     };
     let right_ch = if right_idx.is_some_and(|i| activations.get(i).copied().unwrap_or(false)) {
         HEAVY_V
     } else {
-        BOX_V
+        LINE_V
     };
 
     grid.set(arrow_y, left_col, left_ch);
     grid.set(arrow_y, right_col, right_ch);
 }
 
+fn draw_message_number(grid: &mut impl Canvas, layout: &Layout, msg: &MessageRow, y: usize, col: usize) {
+    if let Some(n) = msg.number {
+        let label = format!("[{n}]");
+        // Right-align within the gutter so text columns stay put as numbers grow
+        let pad = layout
+            .number_width
+            .saturating_sub(1)
+            .saturating_sub(display_width(&label));
+        grid.write_str(y, col + pad, &label);
+    }
+}
+
 fn draw_self_message(
-    grid: &mut Grid,
+    grid: &mut impl Canvas,
     layout: &Layout,
     msg: &MessageRow,
     y: usize,
     activations: &[bool],
 ) {
     let center = msg.from_col;
-    let arm_end = center + SELF_LOOP_ARM;
+    let arm_end = center + layout.options.self_loop_arm;
     let lines = split_br(&msg.text);
-    let text_rows = lines.len();
+    let text_rows = msg.text_lines();
 
     // text lines
+    draw_message_number(grid, layout, msg, y, center + 2);
     for (i, line) in lines.iter().enumerate() {
-        grid.write_str(y + i, center + 2, line);
+        grid.write_str(y + i, center + 2 + layout.number_width, line);
     }
 
     // outgoing arm ──┐
     let arm_y = y + text_rows;
     for col in (center + 1)..arm_end {
-        grid.set(arm_y, col, BOX_H);
+        grid.set(arm_y, col, line_char(msg.arrow.line_style));
     }
-    grid.set(arm_y, arm_end, BOX_TR);
+    grid.set(arm_y, arm_end, LOOP_TR);
 
     // return arm <─┘
     let return_y = arm_y + 1;
     grid.set(return_y, center + 1, reverse_arrow_head_char(&msg.arrow));
     for col in (center + 2)..arm_end {
-        grid.set(return_y, col, BOX_H);
+        grid.set(return_y, col, line_char(msg.arrow.line_style));
     }
-    grid.set(return_y, arm_end, BOX_BR);
+    grid.set(return_y, arm_end, LOOP_BR);
 
     // Restore lifeline at center
     let idx = layout
         .participants
         .iter()
         .position(|p| p.center_col == center);
-    let ch = if idx.is_some_and(|i| activations.get(i).copied().unwrap_or(false)) {
-        HEAVY_V
-    } else {
-        BOX_V
-    };
-    let h = 2 + text_rows;
-    for dy in 0..h {
-        grid.set(y + dy, center, ch);
+    let active = idx.is_some_and(|i| activations.get(i).copied().unwrap_or(false));
+    for dy in 0..=text_rows {
+        grid.set(y + dy, center, if active { HEAVY_V } else { LINE_V });
     }
+    let return_active = msg.return_active.unwrap_or(active);
+    grid.set(return_y, center, if return_active { HEAVY_V } else { LINE_V });
 }
 
-fn draw_note(grid: &mut Grid, note: &NoteRow, y: usize) {
+fn draw_note(grid: &mut impl Canvas, theme: &Theme, note: &NoteRow, y: usize) {
     let left = note.box_left;
     let right = note.box_right;
     let lines = split_br(&note.text);
 
-    grid.set(y, left, BOX_TL);
-    for col in (left + 1)..right {
-        grid.set(y, col, BOX_H);
-    }
-    grid.set(y, right, BOX_TR);
-
+    grid.rect(left, y, right - left + 1, lines.len() + 2, theme);
     for (i, line) in lines.iter().enumerate() {
         let row = y + 1 + i;
-        grid.set(row, left, BOX_V);
-        for col in (left + 1)..right {
-            grid.set(row, col, ' ');
-        }
+        grid.hline(row, left + 1, right, ' ');
         grid.write_str(row, left + 2, line);
-        grid.set(row, right, BOX_V);
     }
-
-    let bottom = y + 1 + lines.len();
-    grid.set(bottom, left, BOX_BL);
-    for col in (left + 1)..right {
-        grid.set(bottom, col, BOX_H);
-    }
-    grid.set(bottom, right, BOX_BR);
 }
 
-const CROSS: char = '┼';
-
-fn draw_block_start(grid: &mut Grid, layout: &Layout, block: &BlockRow, y: usize) {
-    grid.set(y, block.frame_left, BOX_TL);
-    for col in (block.frame_left + 1)..block.frame_right {
-        grid.set(y, col, BOX_H);
-    }
-    grid.set(y, block.frame_right, BOX_TR);
+fn draw_block_start(
+    grid: &mut impl Canvas,
+    layout: &Layout,
+    theme: &Theme,
+    block: &BlockRow,
+    y: usize,
+    alive: &[bool],
+) {
+    grid.set_merge(y, block.frame_left, theme.top_left);
+    grid.hline(y, block.frame_left + 1, block.frame_right, theme.horizontal);
+    grid.set_merge(y, block.frame_right, theme.top_right);
 
     // Write label
-    grid.write_str(y, block.frame_left + 2, &block.label);
+    let label = split_br(&block.label)[0];
+    grid.write_str(y, block.frame_left + 2, label);
 
     // Draw ┼ at lifeline intersections
-    for p in &layout.participants {
+    for (i, p) in layout.participants.iter().enumerate() {
+        if !alive.get(i).copied().unwrap_or(true) {
+            continue;
+        }
         if p.center_col > block.frame_left && p.center_col < block.frame_right {
             // Only draw ┼ if it's not covered by the label text
-            let label_end = block.frame_left + 2 + display_width(&block.label);
+            let label_end = block.frame_left + 2 + display_width(label);
             if p.center_col > label_end {
-                grid.set(y, p.center_col, CROSS);
+                grid.set(y, p.center_col, theme.crossing);
             }
         }
     }
 }
 
-fn draw_block_end(grid: &mut Grid, layout: &Layout, block: &BlockRow, y: usize) {
-    grid.set(y, block.frame_left, BOX_BL);
-    for col in (block.frame_left + 1)..block.frame_right {
-        grid.set(y, col, BOX_H);
-    }
-    grid.set(y, block.frame_right, BOX_BR);
+fn draw_block_end(
+    grid: &mut impl Canvas,
+    layout: &Layout,
+    theme: &Theme,
+    block: &BlockRow,
+    y: usize,
+    alive: &[bool],
+) {
+    grid.set_merge(y, block.frame_left, theme.bottom_left);
+    grid.hline(y, block.frame_left + 1, block.frame_right, theme.horizontal);
+    grid.set_merge(y, block.frame_right, theme.bottom_right);
 
     // Draw ┼ at lifeline intersections
-    for p in &layout.participants {
+    for (i, p) in layout.participants.iter().enumerate() {
+        if !alive.get(i).copied().unwrap_or(true) {
+            continue;
+        }
         if p.center_col > block.frame_left && p.center_col < block.frame_right {
-            grid.set(y, p.center_col, CROSS);
+            grid.set(y, p.center_col, theme.crossing);
         }
     }
 }
 
-const BOX_DIVIDER_L: char = '├';
-const BOX_DIVIDER_R: char = '┤';
-
-fn draw_block_divider(grid: &mut Grid, layout: &Layout, block: &BlockRow, y: usize) {
-    grid.set(y, block.frame_left, BOX_DIVIDER_L);
-    for col in (block.frame_left + 1)..block.frame_right {
-        grid.set(y, col, BOX_H);
-    }
-    grid.set(y, block.frame_right, BOX_DIVIDER_R);
+fn draw_block_divider(
+    grid: &mut impl Canvas,
+    layout: &Layout,
+    theme: &Theme,
+    block: &BlockRow,
+    y: usize,
+    alive: &[bool],
+) {
+    grid.set_merge(y, block.frame_left, theme.divider_left);
+    grid.hline(y, block.frame_left + 1, block.frame_right, theme.horizontal);
+    grid.set_merge(y, block.frame_right, theme.divider_right);
 
     // Write label
-    grid.write_str(y, block.frame_left + 2, &block.label);
+    let label = split_br(&block.label)[0];
+    grid.write_str(y, block.frame_left + 2, label);
 
     // Draw ┼ at lifeline intersections
-    for p in &layout.participants {
+    for (i, p) in layout.participants.iter().enumerate() {
+        if !alive.get(i).copied().unwrap_or(true) {
+            continue;
+        }
         if p.center_col > block.frame_left && p.center_col < block.frame_right {
-            let label_end = block.frame_left + 2 + display_width(&block.label);
+            let label_end = block.frame_left + 2 + display_width(label);
             if p.center_col > label_end {
-                grid.set(y, p.center_col, CROSS);
+                grid.set(y, p.center_col, theme.crossing);
+            }
+        }
+    }
+}
+
+/// A side-by-side `par` block: the frame with a rule between lanes, and
+/// each lane's rows stacked from the top. Lifelines in a lane follow that
+/// lane's activations down to the bottom border.
+fn draw_parallel(
+    grid: &mut impl Canvas,
+    layout: &Layout,
+    theme: &Theme,
+    par: &ParallelRow,
+    (y, h): (usize, usize),
+    alive: &mut [bool],
+) {
+    let frame = BlockRow {
+        label: par.lanes[0].label.clone(),
+        frame_left: par.frame_left,
+        frame_right: par.frame_right,
+    };
+    let bottom = y + h - 1;
+    draw_block_start(grid, layout, theme, &frame, y, alive);
+
+    for (i, lane) in par.lanes.iter().enumerate() {
+        let right = par.lanes.get(i + 1).map_or(par.frame_right, |next| next.left);
+        let mut in_lane: Vec<bool> = layout
+            .participants
+            .iter()
+            .enumerate()
+            .map(|(p, participant)| {
+                alive[p] && lane.left < participant.center_col && participant.center_col < right
+            })
+            .collect();
+        let mut row_y = y + 1;
+        for laid in &lane.rows {
+            let row_h = laid.height;
+            draw_lifelines_filtered(grid, layout, row_y, row_h, &laid.active, &in_lane);
+            match &laid.row {
+                Row::Message(msg) => draw_message(grid, layout, msg, row_y, &laid.active),
+                Row::Note(note) => draw_note(grid, theme, note, row_y),
+                Row::Destroy(destroy) => {
+                    draw_destroy(grid, destroy, row_y);
+                    in_lane[destroy.participant_idx] = false;
+                    alive[destroy.participant_idx] = false;
+                }
+                _ => {}
             }
+            row_y += row_h;
+        }
+        let after = &lane.active_after;
+        draw_lifelines_filtered(grid, layout, row_y, bottom - row_y, after, &in_lane);
+
+        if i > 0 {
+            grid.set(y, lane.left, theme.connector_down);
+            grid.write_str(y, lane.left + 2, &lane.label);
+            grid.vline(lane.left, y + 1, bottom, theme.vertical);
         }
     }
+    draw_frame_sides(grid, theme, &[&frame], y + 1, h - 2);
+    draw_block_end(grid, layout, theme, &frame, bottom, alive);
+    for lane in &par.lanes[1..] {
+        grid.set(bottom, lane.left, theme.connector_up);
+    }
+}
+
+/// Lines after the first of a wrapped frame label, written inside the frame
+/// over the lifelines.
+fn draw_label_continuation(
+    grid: &mut impl Canvas,
+    layout: &Layout,
+    theme: &Theme,
+    block: &BlockRow,
+    y: usize,
+    activations: &[bool],
+    alive: &[bool],
+) {
+    let lines = split_br(&block.label);
+    let count = lines.len() - 1;
+    if count == 0 {
+        return;
+    }
+    draw_lifelines_filtered(grid, layout, y + 1, count, activations, alive);
+    draw_frame_sides(grid, theme, &[block], y + 1, count);
+    for (i, line) in lines[1..].iter().enumerate() {
+        grid.write_str(y + 1 + i, block.frame_left + 2, line);
+    }
 }
 
 fn draw_frame_sides(
-    grid: &mut Grid,
-    _layout: &Layout,
+    grid: &mut impl Canvas,
+    theme: &Theme,
     active_frames: &[&BlockRow],
     y: usize,
     height: usize,
 ) {
     for frame in active_frames {
         for dy in 0..height {
-            grid.set(y + dy, frame.frame_left, BOX_V);
-            grid.set(y + dy, frame.frame_right, BOX_V);
+            grid.set_merge(y + dy, frame.frame_left, theme.vertical);
+            grid.set_merge(y + dy, frame.frame_right, theme.vertical);
         }
     }
 }
 
+/// The character a message line is drawn with.
+fn line_char(style: LineStyle) -> char {
+    match style {
+        LineStyle::Solid => LINE_H,
+        LineStyle::Dotted => LINE_DOTTED,
+    }
+}
+
+/// The head drawn at the right-hand end of a message pointing right.
+/// Async (`-)`) messages get an open head so they stand apart from `->>`.
 fn arrow_head_char(arrow: &Arrow) -> char {
     match arrow.head {
         ArrowHead::None | ArrowHead::Arrowhead => ARROW_R,
-        ArrowHead::Cross => 'x',
-        ArrowHead::Open => ARROW_R,
+        ArrowHead::Cross => CROSS,
+        ArrowHead::Open => OPEN_R,
     }
 }
 
+/// The head drawn at the left-hand end of a message pointing left.
 fn reverse_arrow_head_char(arrow: &Arrow) -> char {
     match arrow.head {
         ArrowHead::None | ArrowHead::Arrowhead => ARROW_L,
-        ArrowHead::Cross => 'x',
-        ArrowHead::Open => ARROW_L,
+        ArrowHead::Cross => CROSS,
+        ArrowHead::Open => OPEN_L,
     }
 }
 
-fn draw_destroy(grid: &mut Grid, destroy: &DestroyRow, y: usize) {
-    grid.set(y, destroy.col, 'X');
+fn draw_destroy(grid: &mut impl Canvas, destroy: &DestroyRow, y: usize) {
+    grid.set(y, destroy.col, DESTROY_MARK);
 }
 
 #[cfg(test)]
@@ -467,51 +650,6 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    #[test]
-    fn grid_basic_operations() {
-        let mut grid = Grid::new(10, 3);
-        grid.write_str(1, 2, "hello");
-        let output = grid.render();
-        assert!(output.contains("hello"));
-    }
-
-    #[test]
-    fn grid_set_character() {
-        let mut grid = Grid::new(5, 2);
-        grid.set(0, 2, 'X');
-        let output = grid.render();
-        assert!(output.contains("X"));
-    }
-
-    #[test]
-    fn grid_write_wide_chars_correct_offset() {
-        let mut grid = Grid::new(10, 1);
-        grid.write_str(0, 0, "テス");
-        grid.set(0, 4, 'C');
-        let output = grid.render();
-        assert_eq!(output, "テスC");
-    }
-
-    #[test]
-    fn grid_set_overwrites_wide_char_continuation() {
-        let mut grid = Grid::new(10, 1);
-        grid.write_str(0, 0, "テスト");
-        // Overwrite continuation marker of ス (at col 3) with │
-        grid.set(0, 3, '│');
-        let output = grid.render();
-        // ス's base at col 2 should be cleared to space
-        assert_eq!(output, "テ │ト");
-    }
-
-    #[test]
-    fn grid_trims_trailing_spaces() {
-        let mut grid = Grid::new(10, 2);
-        grid.write_str(0, 0, "hi");
-        let output = grid.render();
-        let first_line = output.lines().next().unwrap();
-        assert_eq!(first_line, "hi");
-    }
-
     #[test]
     fn render_two_participants_basic() {
         let input = "sequenceDiagram\n    Alice->>Bob: Hello\n    Bob-->>Alice: Hi!\n";
@@ -632,4 +770,226 @@ mod tests {
         assert!(output.contains("──┐"), "self-message should have loop out");
         assert!(output.contains("┘"), "self-message should have return corner");
     }
+
+    #[test]
+    fn render_self_message_activation_around_loop() {
+        let input = "sequenceDiagram\n    A->>+A: work\n    A-->>-A: done\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        let output = render(&layout);
+
+        let lines: Vec<&str> = output.lines().skip(3).take(6).collect();
+        assert_eq!(lines, ["  │ work", "  │───┐", "  ┃<──┘", "  ┃ done", "  ┃╌╌╌┐", "  │<╌╌┘"]);
+    }
+
+    #[test]
+    fn render_messages_without_text_skip_the_label_line() {
+        let input = "sequenceDiagram\n    A->>B:\n    A->>A:\n    B->>A: hi\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        let output = render(&layout);
+        let lines: Vec<&str> = output.lines().skip(3).take(7).collect();
+        assert_eq!(
+            lines,
+            [
+                "  │────────>│",
+                "  │         │",
+                "  │───┐     │",
+                "  │<──┘     │",
+                "  │ hi      │",
+                "  │<────────│",
+                "  │         │",
+            ]
+        );
+
+        // A number still takes the line
+        let input = "sequenceDiagram\nautonumber\nA->>B:\n";
+        let numbered = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&numbered).unwrap();
+        assert_eq!(render(&layout).lines().nth(3), Some("  │ [1]     │"));
+    }
+
+    #[test]
+    fn render_nested_frames_join_at_shared_sides() {
+        let input = "sequenceDiagram\n    A->>B: 1\n    loop outer\n    alt inner\n    A->>B: 2\n    end\n    end\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        let output = render(&layout);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[6], "┌─loop outer──┐");
+        assert_eq!(lines[7], "├─alt inner─┼─┤");
+        assert_eq!(lines[11], "├─┼─────────┼─┤");
+        assert_eq!(lines[12], "└─┼─────────┼─┘");
+    }
+
+    #[test]
+    fn render_without_mirrored_boxes_ends_at_last_row() {
+        let input = "sequenceDiagram\n    A->>B: hi\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let options = SequenceOptions { mirror_participants: false, ..SequenceOptions::default() };
+        let layout = crate::layout::compute_with(&diagram, &options, None).unwrap();
+        let output = render(&layout);
+
+        assert_eq!(layout.total_height(), 6);
+        assert_eq!(output.lines().count(), 6);
+        assert_eq!(output.lines().last().unwrap(), "  │         │");
+    }
+
+    #[test]
+    fn render_message_text_centered_over_the_arrow() {
+        let input = "sequenceDiagram\n    participant A\n    participant B\n    participant C\n    \
+                     A->>B: a longer label\n    B-->>A: ok\n    A->>C: hi\n    \
+                     A->>C: hello there\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let options =
+            SequenceOptions { message_align: MessageAlign::Center, ..SequenceOptions::default() };
+        let layout = crate::layout::compute_with(&diagram, &options, None).unwrap();
+        let output = render(&layout);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[3], "  │ a longer label  │         │");
+        assert_eq!(lines[6], "  │       ok        │         │");
+        assert_eq!(lines[9], "  │            hi   │         │");
+        // Centered over A->>C it would cross B's lifeline, so it moves to the lane beside it
+        assert_eq!(lines[12], "  │   hello there   │         │");
+    }
+
+    #[test]
+    fn render_frame_around_notes_at_its_sides() {
+        let input = "sequenceDiagram\n    A->>B: hi\n    loop retry\n    Note left of A: wait\n    \
+                     Note right of B: done\n    end\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        let output = render(&layout);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "         ┌───┐     ┌───┐");
+        assert_eq!(
+            lines[6..14],
+            [
+                "┌─loop retry─────────┼──────────┐",
+                "│ ┌──────┐ │         │          │",
+                "│ │ wait │ │         │          │",
+                "│ └──────┘ │         │          │",
+                "│          │         │ ┌──────┐ │",
+                "│          │         │ │ done │ │",
+                "│          │         │ └──────┘ │",
+                "└──────────┼─────────┼──────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn render_par_branches_side_by_side() {
+        let input = "sequenceDiagram\n    par fetch\n    A->>B: get\n    B-->>A: got\n    and log\n    C->>D: write\n    end\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let options = SequenceOptions { par_side_by_side: true, ..SequenceOptions::default() };
+        let layout = crate::layout::compute_with(&diagram, &options, None).unwrap();
+        let output = render(&layout);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[3..11],
+            [
+                "┌─par fetch─┼────┬─and log──────┼─┐",
+                "│ │ get     │    │    │ write   │ │",
+                "│ │────────>│    │    │────────>│ │",
+                "│ │         │    │    │         │ │",
+                "│ │ got     │    │    │         │ │",
+                "│ │<╌╌╌╌╌╌╌╌│    │    │         │ │",
+                "│ │         │    │    │         │ │",
+                "└─┼─────────┼────┴────┼─────────┼─┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn render_wraps_long_frame_labels_under_max_width() {
+        let input = "sequenceDiagram\n    alt retry the request until the server answers\n    A->>B: x\n    else give up\n    B->>A: y\n    end\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute_with_max_width(&diagram, 24).unwrap();
+        let output = render(&layout);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[3], "┌─alt retry the─────┐");
+        assert_eq!(lines[4], "│ request until the │");
+        assert_eq!(lines[5], "│ server answers    │");
+        assert_eq!(lines[6], "│ │ x       │       │");
+        assert_eq!(lines[9], "├─else give up──────┤");
+        assert!(output.lines().all(|l| l.chars().count() <= 24), "{output}");
+    }
+
+    #[test]
+    fn render_pages_repeats_headers_and_keeps_rows_whole() {
+        let mut input = String::from("sequenceDiagram\n");
+        for i in 0..10 {
+            input.push_str(&format!("    Alice->>Bob: m{i}\n"));
+        }
+        let diagram = crate::parser::parse_diagram(&input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        let pages = render_pages(&layout, &Theme::default(), 20);
+
+        // 3-line boxes leave 14 lines per page: four 3-line message rows
+        assert_eq!(pages.len(), 3);
+        for page in &pages {
+            assert!(page.lines().count() <= 20, "{page}");
+            assert!(page.lines().nth(1).unwrap().contains("│ Alice │"), "{page}");
+        }
+        assert!(pages[0].contains("m3") && !pages[0].contains("m4"));
+        assert!(pages[2].contains("m9"));
+        assert_eq!(
+            pages.join("\n").lines().filter(|l| l.contains("──>")).count(),
+            10
+        );
+        assert!(pages[2].ends_with(render(&layout).lines().last().unwrap()));
+    }
+
+    #[test]
+    fn render_pages_can_keep_blocks_together() {
+        let mut input = String::from("sequenceDiagram\n");
+        for i in 0..3 {
+            input.push_str(&format!("    Alice->>Bob: m{i}\n"));
+        }
+        input.push_str("    loop poll\n        Alice->>Bob: ping\n        Bob-->>Alice: pong\n    end\n");
+        let diagram = crate::parser::parse_diagram(&input).unwrap();
+        let mut layout = crate::layout::compute(&diagram).unwrap();
+
+        // 14 lines per page: three messages leave room for the loop's top
+        // border and first message only
+        let split = render_pages(&layout, &Theme::default(), 20);
+        assert_eq!(split.len(), 2);
+        assert!(split[0].contains("loop poll") && split[0].contains("ping"), "{}", split[0]);
+
+        layout.options.keep_blocks_together = true;
+        let kept = render_pages(&layout, &Theme::default(), 20);
+        assert_eq!(kept.len(), 2);
+        assert!(!kept[0].contains("loop poll"), "{}", kept[0]);
+        assert!(kept[1].contains("loop poll") && kept[1].contains("pong"), "{}", kept[1]);
+    }
+
+    #[test]
+    fn render_pages_omits_destroyed_participants_from_later_headers() {
+        let input = "sequenceDiagram\n    A->>B: one\n    A->>C: two\n    destroy C\n    A->>B: three\n    A->>B: four\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        let pages = render_pages(&layout, &Theme::default(), 12);
+        let last = pages.last().unwrap();
+        assert!(last.contains("four"), "{last}");
+        assert!(!last.lines().nth(1).unwrap().contains('C'), "{last}");
+    }
+
+    #[test]
+    fn render_pages_puts_the_title_on_the_first_page_only() {
+        let input = "sequenceDiagram\n    title Polling\n    A->>B: one\n    A->>B: two\n";
+        let diagram = crate::parser::parse_diagram(input).unwrap();
+        let layout = crate::layout::compute(&diagram).unwrap();
+        assert_eq!(layout.header_height(), 5);
+        let pages = render_pages(&layout, &Theme::default(), 11);
+        assert_eq!(pages.len(), 2);
+        let first = &pages[0];
+        assert!(first.starts_with("    Polling\n\n┌───┐") && first.contains("one"), "{first}");
+        assert!(pages[1].starts_with("┌───┐") && pages[1].contains("two"), "{}", pages[1]);
+        assert_eq!(layout.row_source_lines()[0], (5..8, 3));
+    }
 }
@@ -0,0 +1,255 @@
+use crate::display_width::display_width;
+use crate::grid::strip_ansi;
+use crate::{
+    Computed, DiagramHandler, Document, Error, RenderOptions, compute_layout, draw, parse_with,
+};
+
+/// Incremental renderer for a diagram that is edited repeatedly, e.g. an
+/// editor's live preview.
+///
+/// The last parsed document, its layout and the rendered output are kept, so
+/// an [`update`](Session::update) with unchanged text costs a string
+/// comparison, and option changes that don't affect layout only redraw.
+pub struct Session {
+    options: RenderOptions,
+    source: Option<String>,
+    state: Result<Rendered, Error>,
+}
+
+struct Rendered {
+    handler: &'static dyn DiagramHandler,
+    document: Document,
+    layout: Computed,
+    output: String,
+}
+
+/// What an update did to the session's output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderDelta {
+    /// The input differed from the previous update and was re-rendered.
+    pub changed: bool,
+    /// The output's width or height differs from before the update.
+    pub resized: bool,
+    /// Size of the current output in columns and lines (0 on error).
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Session {
+    pub fn new(options: RenderOptions) -> Self {
+        Self {
+            options,
+            source: None,
+            state: Err(Error::from("no input yet".to_string())),
+        }
+    }
+
+    /// Re-render `text` if it differs from the previous update.
+    pub fn update(&mut self, text: &str) -> RenderDelta {
+        let before = self.size();
+        if self.source.as_deref() == Some(text) {
+            return self.delta(false, before);
+        }
+        self.source = Some(text.to_string());
+        self.state = render(text, &self.options);
+        self.delta(true, before)
+    }
+
+    /// Change the render options, redrawing the cached document. The layout
+    /// is recomputed only when the new options affect it.
+    pub fn set_options(&mut self, options: RenderOptions) -> RenderDelta {
+        let before = self.size();
+        let reparse = options.input != self.options.input
+            || options.lenient != self.options.lenient
+            || options.diagram_type != self.options.diagram_type
+            || options.last_shape_wins != self.options.last_shape_wins;
+        if reparse {
+            self.options = options;
+            if let Some(source) = &self.source {
+                self.state = render(source, &self.options);
+            }
+            return self.delta(true, before);
+        }
+        // Labels are measured after the RTL rewrite, with the emoji width
+        let relayout = options.max_width != self.options.max_width
+            || options.wrap != self.options.wrap
+            || options.sequence != self.options.sequence
+            || options.rtl != self.options.rtl
+            || options.emoji_width != self.options.emoji_width;
+        self.options = options;
+        let options = &self.options;
+        match &mut self.state {
+            Ok(rendered) if relayout => {
+                match compute_layout(rendered.handler, &rendered.document, options) {
+                    Ok(layout) => {
+                        rendered.layout = layout;
+                        rendered.output = draw(rendered.handler, &rendered.layout, options);
+                    }
+                    Err(e) => self.state = Err(e.into()),
+                }
+            }
+            Ok(rendered) => {
+                rendered.output = draw(rendered.handler, &rendered.layout, options)
+            }
+            // A failed parse can't be fixed by new options, but a failed
+            // layout (e.g. too narrow) can
+            Err(_) => {
+                if let Some(source) = &self.source {
+                    self.state = render(source, options);
+                }
+            }
+        }
+        self.delta(true, before)
+    }
+
+    pub fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    /// The rendering of the latest input, or why it failed.
+    pub fn output(&self) -> Result<&str, &Error> {
+        self.state.as_ref().map(|r| r.output.as_str())
+    }
+
+    /// The parsed form of the latest input, if it rendered.
+    pub fn document(&self) -> Option<&Document> {
+        self.state.as_ref().ok().map(|r| &r.document)
+    }
+
+    fn size(&self) -> (usize, usize) {
+        match self.output() {
+            Ok(output) => (
+                output.lines().map(|l| display_width(&strip_ansi(l))).max().unwrap_or(0),
+                output.lines().count(),
+            ),
+            Err(_) => (0, 0),
+        }
+    }
+
+    fn delta(&self, changed: bool, before: (usize, usize)) -> RenderDelta {
+        let (width, height) = self.size();
+        RenderDelta {
+            changed,
+            resized: (width, height) != before,
+            width,
+            height,
+        }
+    }
+}
+
+fn render(text: &str, options: &RenderOptions) -> Result<Rendered, Error> {
+    let (handler, document, _) = parse_with(text, options)?;
+    let layout = compute_layout(handler, &document, options)?;
+    let output = draw(handler, &layout, options);
+    Ok(Rendered {
+        handler,
+        document,
+        layout,
+        output,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const GRAPH: &str = "graph LR\n    A --> B\n";
+
+    #[test]
+    fn unchanged_text_is_not_rerendered() {
+        let mut session = Session::new(RenderOptions::default());
+        let first = session.update(GRAPH);
+        assert!(first.changed && first.resized);
+        assert_eq!((first.width, first.height), (15, 3));
+
+        let second = session.update(GRAPH);
+        assert!(!second.changed && !second.resized);
+        assert_eq!(session.output().unwrap(), crate::render(GRAPH).unwrap());
+    }
+
+    #[test]
+    fn edit_reports_resize_only_when_size_changes() {
+        let mut session = Session::new(RenderOptions::default());
+        session.update(GRAPH);
+        let same_size = session.update("graph LR\n    A --> C\n");
+        assert!(same_size.changed && !same_size.resized);
+        let wider = session.update("graph LR\n    A --> Longer\n");
+        assert!(wider.changed && wider.resized);
+    }
+
+    #[test]
+    fn errors_are_kept_until_fixed() {
+        let mut session = Session::new(RenderOptions::default());
+        session.update(GRAPH);
+        let broken = session.update("graph LR\n    A -->\n");
+        assert!(broken.resized);
+        assert_eq!((broken.width, broken.height), (0, 0));
+        assert!(session.output().is_err());
+        assert!(session.document().is_none());
+        assert!(session.update(GRAPH).resized);
+        assert!(session.output().is_ok());
+    }
+
+    #[test]
+    fn option_change_redraws_cached_layout() {
+        let mut session = Session::new(RenderOptions::default());
+        session.update(GRAPH);
+        let delta = session.set_options(RenderOptions {
+            ascii_only: true,
+            ..RenderOptions::default()
+        });
+        assert!(!delta.resized);
+        assert_eq!(session.output().unwrap(), "+---+     +---+\n| A |---->| B |\n+---+     +---+");
+    }
+
+    #[test]
+    fn diagram_type_change_reparses() {
+        let snippet = "    A --> B\n";
+        let mut session = Session::new(RenderOptions::default());
+        session.update(snippet);
+        assert!(session.output().is_err());
+
+        let diagram_type = crate::DiagramType::Flowchart;
+        let options = RenderOptions { diagram_type, ..RenderOptions::default() };
+        session.set_options(options.clone());
+        assert_eq!(session.output().unwrap(), crate::render_with(snippet, &options).unwrap());
+
+        let diagram_type = crate::DiagramType::Sequence;
+        session.set_options(RenderOptions { diagram_type, ..options });
+        assert!(matches!(session.document(), Some(crate::Document::Sequence(_))));
+    }
+
+    #[test]
+    fn emoji_width_change_relays_out_like_a_fresh_render() {
+        let source = "graph LR\n    A[❤ ok] --> B\n";
+        let mut session = Session::new(RenderOptions::default());
+        session.update(source);
+        let emoji_width = crate::display_width::EmojiWidth::Wide;
+        let options = RenderOptions { emoji_width, ..RenderOptions::default() };
+        assert!(session.set_options(options.clone()).resized);
+        assert_eq!(session.output().unwrap(), crate::render_with(source, &options).unwrap());
+    }
+
+    #[test]
+    fn color_change_redraws_without_resizing() {
+        let source = "graph LR\n    A --> B\n";
+        let mut session = Session::new(RenderOptions::default());
+        session.update(source);
+        let options = RenderOptions { color: true, ..RenderOptions::default() };
+        let delta = session.set_options(options.clone());
+        assert!(delta.changed && !delta.resized);
+        assert_eq!(session.output().unwrap(), crate::render_with(source, &options).unwrap());
+    }
+
+    #[test]
+    fn rtl_change_relays_out_like_a_fresh_render() {
+        let source = "graph LR\n    A[שלום (1)] --> B\n";
+        let mut session = Session::new(RenderOptions::default());
+        session.update(source);
+        let rtl = crate::bidi::RtlMode::Reverse;
+        let options = RenderOptions { rtl, ..RenderOptions::default() };
+        session.set_options(options.clone());
+        assert_eq!(session.output().unwrap(), crate::render_with(source, &options).unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::ast::Statement;
+
+/// Size and shape summary of a diagram, computed from its layout without
+/// drawing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// `sequence`, `graph` or `er`.
+    pub kind: &'static str,
+    /// Participants, nodes or entities.
+    pub elements: usize,
+    /// Messages, edges or relationships.
+    pub connections: usize,
+    /// Deepest nesting of blocks or subgraphs (0 when there are none).
+    pub max_depth: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (elements, connections) = match self.kind {
+            "sequence" => ("participants", "messages"),
+            "graph" => ("nodes", "edges"),
+            _ => ("entities", "relationships"),
+        };
+        writeln!(f, "kind: {}", self.kind)?;
+        writeln!(f, "{elements}: {}", self.elements)?;
+        writeln!(f, "{connections}: {}", self.connections)?;
+        writeln!(f, "max depth: {}", self.max_depth)?;
+        writeln!(f, "width: {}", self.width)?;
+        write!(f, "height: {}", self.height)
+    }
+}
+
+/// Message count and deepest block nesting of a sequence diagram body.
+pub fn sequence_counts(statements: &[Statement]) -> (usize, usize) {
+    let mut messages = 0;
+    let mut depth = 0;
+    for stmt in statements {
+        let bodies: Vec<&[Statement]> = match stmt {
+            Statement::Message(_) => {
+                messages += 1;
+                continue;
+            }
+            Statement::Loop(lb) | Statement::Opt(lb) | Statement::Break(lb) | Statement::Rect(lb) => {
+                vec![&lb.body]
+            }
+            Statement::Alt(ab) | Statement::Par(ab) | Statement::Critical(ab) => {
+                std::iter::once(ab.body.as_slice())
+                    .chain(ab.else_branches.iter().map(|b| b.body.as_slice()))
+                    .collect()
+            }
+            _ => continue,
+        };
+        for body in bodies {
+            let (m, d) = sequence_counts(body);
+            messages += m;
+            depth = depth.max(d + 1);
+        }
+    }
+    (messages, depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_diagram;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sequence_counts_nested_blocks() {
+        let diagram = parse_diagram(
+            "sequenceDiagram\n    A->>B: one\n    loop outer\n        alt yes\n            A->>B: two\n        else no\n            B->>A: three\n        end\n    end\n",
+        )
+        .unwrap();
+        assert_eq!(sequence_counts(&diagram.statements), (3, 2));
+    }
+
+    #[test]
+    fn display_uses_kind_labels() {
+        let stats = Stats {
+            kind: "graph",
+            elements: 2,
+            connections: 1,
+            max_depth: 0,
+            width: 15,
+            height: 3,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "kind: graph\nnodes: 2\nedges: 1\nmax depth: 0\nwidth: 15\nheight: 3"
+        );
+    }
+}
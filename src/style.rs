@@ -0,0 +1,249 @@
+use std::cell::Cell;
+
+/// Border glyphs for boxes and frames.
+///
+/// Edges, arrows and lifelines are always drawn with light lines; the
+/// `connector_*` and `crossing` glyphs are where those meet a border.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub top_left: char,
+    pub top_right: char,
+    pub bottom_left: char,
+    pub bottom_right: char,
+    pub horizontal: char,
+    pub vertical: char,
+    /// Where a horizontal divider meets the left / right side (`├`, `┤`).
+    pub divider_left: char,
+    pub divider_right: char,
+    /// Where a light line leaves the border downward / upward (`┬`, `┴`).
+    pub connector_down: char,
+    pub connector_up: char,
+    /// Where a light line leaves a side to the left / right (`┤`, `├`).
+    pub connector_left: char,
+    pub connector_right: char,
+    /// Where a light vertical line crosses a horizontal border (`┼`).
+    pub crossing: char,
+}
+
+impl Theme {
+    pub const LIGHT: Theme = Theme {
+        top_left: '┌',
+        top_right: '┐',
+        bottom_left: '└',
+        bottom_right: '┘',
+        horizontal: '─',
+        vertical: '│',
+        divider_left: '├',
+        divider_right: '┤',
+        connector_down: '┬',
+        connector_up: '┴',
+        connector_left: '┤',
+        connector_right: '├',
+        crossing: '┼',
+    };
+
+    pub const ROUNDED: Theme = Theme {
+        top_left: '╭',
+        top_right: '╮',
+        bottom_left: '╰',
+        bottom_right: '╯',
+        ..Theme::LIGHT
+    };
+
+    pub const HEAVY: Theme = Theme {
+        top_left: '┏',
+        top_right: '┓',
+        bottom_left: '┗',
+        bottom_right: '┛',
+        horizontal: '━',
+        vertical: '┃',
+        divider_left: '┣',
+        divider_right: '┫',
+        connector_down: '┯',
+        connector_up: '┷',
+        connector_left: '┨',
+        connector_right: '┠',
+        crossing: '┿',
+    };
+
+    pub const DOUBLE: Theme = Theme {
+        top_left: '╔',
+        top_right: '╗',
+        bottom_left: '╚',
+        bottom_right: '╝',
+        horizontal: '═',
+        vertical: '║',
+        divider_left: '╠',
+        divider_right: '╣',
+        connector_down: '╤',
+        connector_up: '╧',
+        connector_left: '╢',
+        connector_right: '╟',
+        crossing: '╪',
+    };
+
+    /// Names accepted by [`Theme::named`].
+    pub const NAMES: &[&str] = &["light", "rounded", "heavy", "double"];
+
+    pub fn named(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::LIGHT),
+            "rounded" => Some(Theme::ROUNDED),
+            "heavy" => Some(Theme::HEAVY),
+            "double" => Some(Theme::DOUBLE),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::LIGHT
+    }
+}
+
+/// Box-drawing glyphs and marks emitted by the renderers and their
+/// pure-ASCII stand-ins.
+const ASCII_TABLE: &[(char, char)] = &[
+    ('─', '-'),
+    ('╌', '-'),
+    ('━', '-'),
+    ('═', '='),
+    ('│', '|'),
+    ('┃', '|'),
+    ('║', '|'),
+    ('┊', ':'),
+    ('┌', '+'),
+    ('┐', '+'),
+    ('└', '+'),
+    ('┘', '+'),
+    ('╭', '+'),
+    ('╮', '+'),
+    ('╰', '+'),
+    ('╯', '+'),
+    ('┏', '+'),
+    ('┓', '+'),
+    ('┗', '+'),
+    ('┛', '+'),
+    ('╔', '+'),
+    ('╗', '+'),
+    ('╚', '+'),
+    ('╝', '+'),
+    ('├', '+'),
+    ('┤', '+'),
+    ('┬', '+'),
+    ('┴', '+'),
+    ('┼', '+'),
+    ('┣', '+'),
+    ('┫', '+'),
+    ('┯', '+'),
+    ('┷', '+'),
+    ('┨', '+'),
+    ('┠', '+'),
+    ('┿', '+'),
+    ('╠', '+'),
+    ('╣', '+'),
+    ('╤', '+'),
+    ('╧', '+'),
+    ('╢', '+'),
+    ('╟', '+'),
+    ('╪', '+'),
+    ('╱', '/'),
+    ('╲', '\\'),
+    ('▼', 'v'),
+    ('▲', '^'),
+    ('►', '>'),
+    ('◄', '<'),
+    ('⟩', ')'),
+    ('⟨', '('),
+    ('✕', 'x'),
+    ('×', 'x'),
+    // The cut-off mark of names and labels shortened to fit
+    ('…', '.'),
+];
+
+/// ASCII replacement for a box-drawing glyph; other characters pass through.
+pub fn to_ascii(ch: char) -> char {
+    ASCII_TABLE
+        .iter()
+        .find(|(from, _)| *from == ch)
+        .map(|&(_, to)| to)
+        .unwrap_or(ch)
+}
+
+/// ASCII replacement for a character of label text: only the marks ma adds
+/// to labels itself, `…` for a cut-off and `×` for a repeat count, are
+/// replaced.
+pub fn text_to_ascii(ch: char) -> char {
+    match ch {
+        '…' | '×' => to_ascii(ch),
+        _ => ch,
+    }
+}
+
+/// Replace every box-drawing glyph in rendered output with its ASCII stand-in.
+pub fn ascii_only(text: &str) -> String {
+    text.chars().map(to_ascii).collect()
+}
+
+thread_local! {
+    static ASCII_ONLY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` with the [`Grid`](crate::grid::Grid)s it creates drawing boxes,
+/// lines and arrow heads with their ASCII stand-ins on this thread.
+pub fn with_ascii_only<T>(ascii_only: bool, f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            ASCII_ONLY.set(self.0);
+        }
+    }
+    let _restore = Restore(ASCII_ONLY.replace(ascii_only));
+    f()
+}
+
+/// Whether grids created now draw in ASCII; see [`with_ascii_only`].
+pub(crate) fn is_ascii_only() -> bool {
+    ASCII_ONLY.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn ascii_only_maps_box_drawing() {
+        let boxed = "┌───┐\n│ A │──>╭╮\n└─┬─┘  ▼◄";
+        assert_eq!(ascii_only(boxed), "+---+\n| A |-->++\n+-+-+  v<");
+    }
+
+    #[test]
+    fn ascii_only_covers_every_theme() {
+        for name in Theme::NAMES {
+            let t = Theme::named(name).unwrap();
+            let glyphs = [
+                t.top_left,
+                t.top_right,
+                t.bottom_left,
+                t.bottom_right,
+                t.horizontal,
+                t.vertical,
+                t.divider_left,
+                t.divider_right,
+                t.connector_down,
+                t.connector_up,
+                t.connector_left,
+                t.connector_right,
+                t.crossing,
+            ];
+            assert!(glyphs.iter().all(|&g| to_ascii(g).is_ascii()), "{name}");
+        }
+    }
+
+    #[test]
+    fn ascii_only_keeps_labels() {
+        assert_eq!(ascii_only("│ テスト │"), "| テスト |");
+    }
+}
@@ -0,0 +1,105 @@
+//! JavaScript bindings, enabled with the `wasm` feature.
+//!
+//! ```js
+//! import init, { render, RenderOptions } from "ma";
+//! await init();
+//! const options = new RenderOptions();
+//! options.maxWidth = 80;
+//! options.style = "rounded";
+//! pre.textContent = render(source, options);
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::style::Theme;
+
+/// Options for [`render`]; every field starts at its library default.
+#[wasm_bindgen(js_name = RenderOptions)]
+#[derive(Debug, Clone)]
+pub struct JsRenderOptions {
+    #[wasm_bindgen(js_name = maxWidth)]
+    pub max_width: Option<u32>,
+    #[wasm_bindgen(js_name = maxHeight)]
+    pub max_height: Option<u32>,
+    /// Wrap names and labels that don't fit `maxWidth` instead of cutting
+    /// them short.
+    pub wrap: bool,
+    pub ascii: bool,
+    #[wasm_bindgen(js_name = lineNumbers)]
+    pub line_numbers: bool,
+    /// Border style name, one of `light`, `rounded`, `heavy` or `double`.
+    #[wasm_bindgen(getter_with_clone)]
+    pub style: String,
+}
+
+#[wasm_bindgen(js_class = RenderOptions)]
+impl JsRenderOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for JsRenderOptions {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            max_height: None,
+            wrap: false,
+            ascii: false,
+            line_numbers: false,
+            style: "light".to_string(),
+        }
+    }
+}
+
+impl JsRenderOptions {
+    fn to_options(&self) -> Result<crate::RenderOptions, String> {
+        let theme = Theme::named(&self.style).ok_or_else(|| {
+            format!("unknown style: {} (expected one of {})", self.style, Theme::NAMES.join(", "))
+        })?;
+        Ok(crate::RenderOptions {
+            max_width: self.max_width.map(|w| w as usize),
+            max_height: self.max_height.map(|h| h as usize),
+            wrap: self.wrap,
+            ascii_only: self.ascii,
+            theme,
+            line_numbers: self.line_numbers,
+            ..crate::RenderOptions::default()
+        })
+    }
+}
+
+/// Render a Mermaid diagram to text. Throws an `Error` carrying the parse or
+/// layout message on failure.
+#[wasm_bindgen]
+pub fn render(input: &str, options: Option<JsRenderOptions>) -> Result<String, JsError> {
+    let options = options.unwrap_or_default().to_options().map_err(|e| JsError::new(&e))?;
+    crate::render_with(input, &options).map_err(|e| JsError::new(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn options_map_onto_render_options() {
+        let js = JsRenderOptions {
+            max_width: Some(40),
+            wrap: true,
+            style: "heavy".to_string(),
+            ..JsRenderOptions::default()
+        };
+        let options = js.to_options().unwrap();
+        assert_eq!(options.max_width, Some(40));
+        assert!(options.wrap);
+        assert_eq!(options.theme, Theme::HEAVY);
+    }
+
+    #[test]
+    fn unknown_style_is_rejected() {
+        let js = JsRenderOptions { style: "wavy".to_string(), ..JsRenderOptions::default() };
+        assert!(js.to_options().unwrap_err().starts_with("unknown style: wavy"));
+    }
+}
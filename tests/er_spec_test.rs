@@ -6,7 +6,7 @@ fn spec_er_single_relationship() {
     let output = ma::render(input).unwrap();
     let expected = "\
 ┌──────────┐              ┌───────┐
-│ CUSTOMER │||──places──o{│ ORDER │
+│ CUSTOMER ├||──places──o{┤ ORDER │
 └──────────┘              └───────┘";
     assert_eq!(output, expected);
 }
@@ -146,8 +146,8 @@ erDiagram
 fn spec_er_entity_no_attributes_unchanged() {
     let with_attrs = ma::render("erDiagram\n    A ||--|| B : rel\n").unwrap();
     // Entities without attribute blocks should render the same as before
-    assert!(with_attrs.contains("│ A │"), "simple entity unchanged");
-    assert!(with_attrs.contains("│ B │"), "simple entity unchanged");
+    assert!(with_attrs.contains("│ A ├"), "simple entity unchanged");
+    assert!(with_attrs.contains("┤ B │"), "simple entity unchanged");
 }
 
 #[test]
@@ -168,3 +168,22 @@ erDiagram
     assert!(output.contains("id"), "attribute visible");
     assert!(output.contains("places"), "relationship label visible");
 }
+
+#[test]
+fn spec_er_entities_without_relationships() {
+    let input = "erDiagram\n    USER {\n        int id PK\n    }\n    TAG\n    LOG {\n    }\n";
+    let output = ma::render(input).unwrap();
+    assert_eq!(
+        output,
+        "\
+┌───────────┐  ┌─────┐
+│ USER      │  │ TAG │
+├───────────┤  └─────┘
+│ int id PK │
+└───────────┘
+
+┌─────┐
+│ LOG │
+└─────┘"
+    );
+}
@@ -0,0 +1,70 @@
+//! Golden snapshots: every `tests/golden/*.mmd` fixture is rendered and
+//! compared with the `.txt` file next to it.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1` to (re)write the expected outputs after an
+//! intentional layout change, then review the result with `git diff`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pretty_assertions::StrComparison;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(golden_dir())
+        .expect("tests/golden exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mmd"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn render_fixture(path: &Path) -> String {
+    let input = fs::read_to_string(path).unwrap();
+    match ma::render(&input) {
+        Ok(output) => output + "\n",
+        Err(e) => panic!("{}: {e}", path.display()),
+    }
+}
+
+#[test]
+fn golden_snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|v| v != "0");
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", golden_dir().display());
+
+    let mut failures = Vec::new();
+    for fixture in &fixtures {
+        let actual = render_fixture(fixture);
+        let snapshot = fixture.with_extension("txt");
+        let name = fixture.file_stem().unwrap().to_string_lossy();
+
+        if update {
+            if fs::read_to_string(&snapshot).ok().as_deref() != Some(actual.as_str()) {
+                fs::write(&snapshot, &actual).unwrap();
+                eprintln!("updated {name}");
+            }
+            continue;
+        }
+
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{name}:\n{}",
+                StrComparison::new(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!("{name}: missing {}", snapshot.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} snapshot(s) differ (rerun with UPDATE_SNAPSHOTS=1 to accept):\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
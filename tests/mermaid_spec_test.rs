@@ -20,7 +20,7 @@ sequenceDiagram
     │────────>│
     │         │
     │ Hi!     │
-    │< ─ ─ ─ ─│
+    │<╌╌╌╌╌╌╌╌│
     │         │
 ┌───┴───┐  ┌──┴──┐
 │ Alice │  │ Bob │
@@ -85,7 +85,7 @@ sequenceDiagram
     │────────>┃
     │         ┃
     │ Hi!     ┃
-    │< ─ ─ ─ ─┃
+    │<╌╌╌╌╌╌╌╌┃
     │         ┃
 ┌───┴───┐  ┌──┴──┐
 │ Alice │  │ Bob │
@@ -129,7 +129,7 @@ sequenceDiagram
     │         │ │ Got it! │
     │         │ └─────────┘
     │ Hi!     │
-    │< ─ ─ ─ ─│
+    │<╌╌╌╌╌╌╌╌│
     │         │
 ┌───┴───┐  ┌──┴──┐
 │ Alice │  │ Bob │
@@ -323,8 +323,24 @@ sequenceDiagram
 ";
     let output = ma::render(input).unwrap();
 
-    assert!(output.contains("1."), "first message numbered");
-    assert!(output.contains("2."), "second message numbered");
+    assert!(output.contains("[1] Hello"), "first message numbered");
+    assert!(output.contains("[2] Hi!"), "second message numbered");
+}
+
+#[test]
+fn spec_autonumber_keeps_text_column_stable() {
+    let mut input = String::from("sequenceDiagram\n    autonumber\n");
+    for i in 0..10 {
+        input.push_str(&format!("    Alice->>Bob: msg{i}\n"));
+    }
+    let output = ma::render(&input).unwrap();
+
+    let lines: Vec<&str> = output.lines().collect();
+    let first = lines.iter().find(|l| l.contains("msg0")).unwrap();
+    let last = lines.iter().find(|l| l.contains("msg9")).unwrap();
+    assert!(first.contains(" [1] msg0"), "single digit right-aligned: {first}");
+    assert!(last.contains("[10] msg9"), "two digits: {last}");
+    assert_eq!(first.find("msg0"), last.find("msg9"), "text starts at the same column");
 }
 
 // --- nested blocks ---
@@ -476,7 +492,7 @@ sequenceDiagram
 
     assert!(output.contains("Hello"));
     assert!(output.contains("Goodbye"));
-    assert!(output.contains('X'), "destroy marker X visible");
+    assert!(output.contains('✕'), "destroy marker ✕ visible");
 }
 
 #[test]
@@ -518,5 +534,53 @@ sequenceDiagram
     assert!(output.contains("Hello"));
     assert!(output.contains("Init"));
     assert!(output.contains("Done"));
-    assert!(output.contains('X'), "destroy marker visible");
+    assert!(output.contains('✕'), "destroy marker visible");
+}
+
+#[test]
+fn spec_destroy_snapshot() {
+    let input = "\
+sequenceDiagram
+    A->>B: Hello
+    destroy B
+    B->>A: Goodbye
+    A->>C: Next
+";
+    let output = ma::render(input).unwrap();
+    let expected = "\
+┌───┐      ┌───┐     ┌───┐
+│ A │      │ B │     │ C │
+└─┬─┘      └─┬─┘     └─┬─┘
+  │ Hello    │         │
+  │─────────>│         │
+  │          │         │
+  │          ✕         │
+  │ Goodbye            │
+  │<─────────│         │
+  │                    │
+  │ Next               │
+  │───────────────────>│
+  │                    │
+┌─┴─┐                ┌─┴─┐
+│ A │                │ C │
+└───┘                └───┘";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn spec_destroy_inside_block_keeps_other_lifelines() {
+    let input = "\
+sequenceDiagram
+    A->>B: Hello
+    loop Check
+        destroy B
+    end
+    A->>C: Next
+";
+    let output = ma::render(input).unwrap();
+    let destroy_line = output.lines().find(|l| l.contains('✕')).unwrap();
+    assert!(destroy_line.starts_with("│ │"), "frame side and A's lifeline drawn: {destroy_line}");
+    assert!(destroy_line.trim_end().ends_with("│ │"), "C's lifeline and frame side drawn: {destroy_line}");
+    let end_line = output.lines().find(|l| l.starts_with('└') && l.contains('┼')).unwrap();
+    assert_eq!(end_line.matches('┼').count(), 2, "no crossing for destroyed B: {end_line}");
 }
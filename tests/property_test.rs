@@ -0,0 +1,260 @@
+use ma::display_width::display_width;
+use proptest::prelude::*;
+
+const IDS: &[&str] = &["A", "B", "Carol", "D1", "テスト"];
+
+fn id() -> impl Strategy<Value = &'static str> {
+    prop::sample::select(IDS)
+}
+
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9 日本語の表示幅テスト]{0,24}"
+}
+
+fn arrow() -> impl Strategy<Value = &'static str> {
+    prop::sample::select(&["->>", "-->>", "->", "-->", "-x", "--x", "-)", "--)"][..])
+}
+
+fn message() -> impl Strategy<Value = String> {
+    (id(), arrow(), id(), text()).prop_map(|(from, arrow, to, text)| {
+        format!("{from}{arrow}{to}: {text}")
+    })
+}
+
+fn note() -> impl Strategy<Value = String> {
+    prop_oneof![
+        (id(), text()).prop_map(|(p, t)| format!("Note right of {p}: {t}")),
+        (id(), text()).prop_map(|(p, t)| format!("Note left of {p}: {t}")),
+        (id(), text()).prop_map(|(p, t)| format!("Note over {p}: {t}")),
+        (id(), id(), text()).prop_map(|(a, b, t)| format!("Note over {a},{b}: {t}")),
+    ]
+}
+
+fn sequence_statement() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![4 => message(), 1 => note()];
+    leaf.prop_recursive(3, 24, 4, |inner| {
+        prop_oneof![
+            (text(), prop::collection::vec(inner.clone(), 1..4)).prop_map(|(label, body)| {
+                format!("loop {label}\n{}\nend", body.join("\n"))
+            }),
+            (
+                text(),
+                prop::collection::vec(inner.clone(), 1..3),
+                text(),
+                prop::collection::vec(inner, 1..3),
+            )
+                .prop_map(|(l1, b1, l2, b2)| {
+                    format!("alt {l1}\n{}\nelse {l2}\n{}\nend", b1.join("\n"), b2.join("\n"))
+                }),
+        ]
+    })
+}
+
+/// Sequence diagrams with every participant declared up front.
+fn sequence_diagram() -> impl Strategy<Value = String> {
+    prop::collection::vec(sequence_statement(), 1..12).prop_map(|statements| {
+        let mut src = String::from("sequenceDiagram\n");
+        for id in IDS {
+            src.push_str(&format!("participant {id}\n"));
+        }
+        for stmt in statements {
+            src.push_str(&stmt);
+            src.push('\n');
+        }
+        src
+    })
+}
+
+fn node() -> impl Strategy<Value = String> {
+    (id(), text(), 0..4usize).prop_map(|(id, label, shape)| {
+        let id = format!("N{}", id.len());
+        if label.trim().is_empty() {
+            return id;
+        }
+        match shape {
+            0 => format!("{id}[{label}]"),
+            1 => format!("{id}({label})"),
+            2 => format!("{id}{{{label}}}"),
+            _ => id,
+        }
+    })
+}
+
+fn graph_diagram() -> impl Strategy<Value = String> {
+    let edge = prop::sample::select(&["-->", "---", "-.->", "==>"][..]);
+    (
+        prop::sample::select(&["TD", "LR"][..]),
+        prop::collection::vec((node(), edge, node(), prop::option::of("[a-z]{1,8}")), 1..16),
+    )
+        .prop_map(|(dir, edges)| {
+            let mut src = format!("graph {dir}\n");
+            for (from, edge, to, label) in edges {
+                match label {
+                    Some(label) => src.push_str(&format!("    {from} {edge}|{label}| {to}\n")),
+                    None => src.push_str(&format!("    {from} {edge} {to}\n")),
+                }
+            }
+            src
+        })
+}
+
+fn er_diagram() -> impl Strategy<Value = String> {
+    let left = prop::sample::select(&["||", "|o", "}|", "}o"][..]);
+    let right = prop::sample::select(&["||", "o|", "|{", "o{"][..]);
+    prop::collection::vec((0..5usize, left, right, 0..5usize, "[a-z]{1,10}"), 1..10).prop_map(
+        |relationships| {
+            let mut src = String::from("erDiagram\n");
+            for (from, l, r, to, label) in relationships {
+                src.push_str(&format!("    E{from} {l}--{r} E{to} : {label}\n"));
+            }
+            src
+        },
+    )
+}
+
+/// A generated diagram after its body lines were dropped, repeated or cut
+/// short, as in a half-edited file. Dropping lines loses participant
+/// declarations and unbalances blocks.
+fn mangled_diagram() -> impl Strategy<Value = String> {
+    let edit = (0..3usize, any::<prop::sample::Index>(), any::<prop::sample::Index>());
+    (
+        prop_oneof![sequence_diagram(), graph_diagram(), er_diagram()],
+        prop::collection::vec(edit, 1..6),
+    )
+        .prop_map(|(src, edits)| {
+            let mut lines: Vec<String> = src.lines().map(str::to_string).collect();
+            for (kind, at, cut) in edits {
+                if lines.len() < 2 {
+                    break;
+                }
+                let i = 1 + at.index(lines.len() - 1);
+                match kind {
+                    0 => {
+                        lines.remove(i);
+                    }
+                    1 => lines.insert(i, lines[i].clone()),
+                    _ => {
+                        let line = &lines[i];
+                        let end = (0..=cut.index(line.len() + 1))
+                            .rev()
+                            .find(|&end| line.is_char_boundary(end))
+                            .unwrap_or(0);
+                        lines[i].truncate(end);
+                    }
+                }
+            }
+            lines.join("\n") + "\n"
+        })
+}
+
+fn assert_fits(output: &str, max_width: usize) -> Result<(), TestCaseError> {
+    for line in output.lines() {
+        prop_assert!(
+            display_width(line) <= max_width,
+            "line wider than {max_width}: {line:?}\n{output}"
+        );
+    }
+    Ok(())
+}
+
+/// `to_mermaid` output parses back to the same document.
+fn assert_round_trips(src: &str) -> Result<(), TestCaseError> {
+    let document = ma::parse(src).map_err(|e| TestCaseError::reject(e.to_string()))?;
+    let emitted = ma::to_mermaid(&document);
+    prop_assert_eq!(ma::parse(&emitted).ok(), Some(document), "emitted:\n{}", emitted);
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn sequence_renders_without_panicking(src in sequence_diagram()) {
+        prop_assert!(ma::render(&src).is_ok(), "{src}");
+    }
+
+    #[test]
+    fn sequence_respects_max_width(src in sequence_diagram(), max_width in 20..120usize) {
+        if let Ok(output) = ma::render_with_options(&src, Some(max_width)) {
+            assert_fits(&output, max_width)?;
+        }
+    }
+
+    #[test]
+    fn graph_renders_without_panicking(src in graph_diagram()) {
+        prop_assert!(ma::render(&src).is_ok(), "{src}");
+    }
+
+    #[test]
+    fn graph_respects_max_width(src in graph_diagram(), max_width in 20..120usize) {
+        if let Ok(output) = ma::render_with_options(&src, Some(max_width)) {
+            assert_fits(&output, max_width)?;
+        }
+    }
+
+    #[test]
+    fn er_renders_without_panicking(src in er_diagram()) {
+        prop_assert!(ma::render(&src).is_ok(), "{src}");
+    }
+
+    #[test]
+    fn er_respects_max_width(src in er_diagram(), max_width in 20..120usize) {
+        if let Ok(output) = ma::render_with_options(&src, Some(max_width)) {
+            assert_fits(&output, max_width)?;
+        }
+    }
+
+    #[test]
+    fn sequence_round_trips(src in sequence_diagram()) {
+        assert_round_trips(&src)?;
+    }
+
+    #[test]
+    fn graph_round_trips(src in graph_diagram()) {
+        assert_round_trips(&src)?;
+    }
+
+    #[test]
+    fn er_round_trips(src in er_diagram()) {
+        assert_round_trips(&src)?;
+    }
+
+    #[test]
+    fn mangled_input_never_panics(src in mangled_diagram(), max_width in 20..120usize) {
+        let lenient = ma::RenderOptions { lenient: true, ..Default::default() };
+        let _ = ma::render(&src);
+        let _ = ma::render_with_options(&src, Some(max_width));
+        let _ = ma::render_with(&src, &lenient);
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics(src in "(sequenceDiagram|graph TD|erDiagram|digraph \\{|@startuml)?\n[ -~\n]{0,200}") {
+        let _ = ma::render(&src);
+    }
+}
+
+/// Every file in `tests/malformed` renders or fails with an error, whatever
+/// the width and strictness.
+#[test]
+fn malformed_corpus_never_panics() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("malformed");
+    let mut panicked = Vec::new();
+    for entry in std::fs::read_dir(&dir).expect("tests/malformed exists") {
+        let path = entry.unwrap().path();
+        let src = std::fs::read_to_string(&path).unwrap();
+        for max_width in [None, Some(12), Some(40)] {
+            for lenient in [false, true] {
+                let options = ma::RenderOptions { max_width, lenient, ..Default::default() };
+                let rendered = std::panic::catch_unwind(|| {
+                    let _ = ma::render_all_with(&src, &options);
+                    let _ = ma::layout_json_all(&src, &options);
+                });
+                if rendered.is_err() {
+                    let name = path.file_name().unwrap().to_string_lossy();
+                    panicked.push(format!("{name} (max width {max_width:?}, lenient {lenient})"));
+                }
+            }
+        }
+    }
+    assert!(panicked.is_empty(), "panicked on:\n{}", panicked.join("\n"));
+}
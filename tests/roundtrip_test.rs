@@ -0,0 +1,155 @@
+//! `to_mermaid` round trips: parsing its output gives back the same document.
+
+use std::fs;
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+
+fn assert_round_trip(input: &str) {
+    let document = ma::parse(input).unwrap_or_else(|e| panic!("{e}\n{input}"));
+    let emitted = ma::to_mermaid(&document);
+    let reparsed = ma::parse(&emitted).unwrap_or_else(|e| panic!("{e}\n{emitted}"));
+    assert_eq!(reparsed, document, "emitted:\n{emitted}");
+    assert_eq!(ma::to_mermaid(&reparsed), emitted, "emitting is not stable");
+}
+
+#[test]
+fn golden_fixtures_round_trip() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "mmd") {
+            assert_round_trip(&fs::read_to_string(&path).unwrap());
+        }
+    }
+}
+
+#[test]
+fn sequence_statements_round_trip() {
+    assert_round_trip(
+        "sequenceDiagram
+    autonumber
+    actor U as The User
+    participant S
+    create participant W as Worker
+    U->S: solid
+    S-->U: dotted
+    U-xS: cross
+    S--xU: dotted cross
+    U-)S: open
+    S--)U: dotted open
+    U->>+S: activate
+    S-->>-U: deactivate
+    activate U
+    deactivate U
+    destroy W
+    Note left of U: left
+    Note right of S: right
+    Note over U,S: both
+",
+    );
+}
+
+#[test]
+fn quoted_participants_round_trip() {
+    assert_round_trip(
+        "sequenceDiagram
+    participant \"Payment Service\" as PS
+    participant \"Order DB\"
+    PS->>\"Order DB\": save
+    Note over \"Order DB\",PS: saved
+    destroy \"Order DB\"
+    auth-service-xS: svc.api
+    svc.api-->>-auth-service: ok
+",
+    );
+}
+
+#[test]
+fn sequence_blocks_round_trip() {
+    assert_round_trip(
+        "sequenceDiagram
+    loop forever
+        opt maybe
+            break stop
+                A->>B: x
+            end
+        end
+    end
+    rect rgb(0, 0, 255)
+        A->>B: y
+    end
+    rect
+        A->>B: z
+    end
+    alt one
+        A->>B: 1
+    else two
+        A->>B: 2
+    else
+        A->>B: 3
+    end
+    par a
+        A->>B: p
+    and b
+        B->>A: q
+    end
+    critical c
+        A->>B: r
+    option o
+        B->>A: s
+    end
+",
+    );
+}
+
+#[test]
+fn graph_shapes_and_links_round_trip() {
+    assert_round_trip(
+        "flowchart LR
+    A[Box] --> B(Round)
+    B --- C{Diamond}
+    C -.-> D((Circle))
+    D -.- E[\"quoted ] label\"]
+    E ==>|thick| F(\"(parens)\")
+    F === G{\"}\"}
+    A -- alt label --> G
+    G -->|spaced label | A
+    H
+    H -->|\"a|b\"| A
+    A -->|#quot;q#quot; &amp; say \"hi\"| H
+",
+    );
+}
+
+#[test]
+fn graph_declaration_order_round_trips() {
+    // Node order comes from first mention, which the emitter must preserve
+    // even when a node is declared before the edge that first uses it
+    assert_round_trip("graph TD\n    Z[last]\n    Y --> Z\n    X\n    X --> Y\n    Z --> Z\n");
+    assert_round_trip("graph TD\n    subgraph One Two\n        B --> A\n    end\n    A --> C & B\n");
+    assert_round_trip("graph TD\n    subgraph api [Public API]\n        B --> A\n    end\n");
+    assert_round_trip("graph TD\n    A --> |go| B %% weight: 3\n    A -- alt --> C %%note\n");
+}
+
+#[test]
+fn er_round_trips() {
+    assert_round_trip(
+        "erDiagram
+    LINE-ITEM {
+        int qty
+    }
+    CUSTOMER ||--o{ ORDER : places
+    ORDER ||--|{ LINE-ITEM : contains
+    CUSTOMER }|--|| ADDRESS : lives at
+    ORDER o|--|o CUSTOMER : billed to
+    CUSTOMER {
+        string name
+        int id PK
+    }
+    LONER {
+        string x FK
+    }
+",
+    );
+}
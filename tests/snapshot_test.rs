@@ -16,7 +16,7 @@ sequenceDiagram
     │────────>│
     │         │
     │ Hi!     │
-    │< ─ ─ ─ ─│
+    │<╌╌╌╌╌╌╌╌│
     │         │
 ┌───┴───┐  ┌──┴──┐
 │ Alice │  │ Bob │
@@ -42,7 +42,8 @@ sequenceDiagram
     assert!(output.contains("Charlie"));
     assert!(output.contains("Hello Bob!"));
     assert!(output.contains("Hey Charlie"));
-    assert!(output.contains("Hi everyone!"));
+    // Charlie's reply to Alice steps around Bob's lifeline
+    assert!(output.contains("│ Hi          │ everyone!    │"), "{output}");
 
     let alice_count = output.matches("Alice").count();
     assert_eq!(alice_count, 2, "Alice appears in top and bottom boxes");
@@ -89,7 +90,7 @@ sequenceDiagram
     │────────>┃
     │         ┃
     │ Hi!     ┃
-    │< ─ ─ ─ ─┃
+    │<╌╌╌╌╌╌╌╌┃
     │         ┃
 ┌───┴───┐  ┌──┴──┐
 │ Alice │  │ Bob │
@@ -139,7 +140,7 @@ sequenceDiagram
     │         │ │ Got it! │
     │         │ └─────────┘
     │ Hi!     │
-    │< ─ ─ ─ ─│
+    │<╌╌╌╌╌╌╌╌│
     │         │
 ┌───┴───┐  ┌──┴──┐
 │ Alice │  │ Bob │